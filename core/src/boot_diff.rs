@@ -0,0 +1,146 @@
+//! What the customization changed in the boot partition files that decide
+//! how the card boots. Their contents are taken before the customization is
+//! written and compared line by line afterwards, so the Finished screen and
+//! the changes log show exactly what the tool edited.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Files whose edits are reported.
+const WATCHED: [&str; 2] = ["cmdline.txt", "config.txt"];
+
+/// A line of a watched file, as it was, as it is, or both.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+/// The lines of one watched file, changed or not, in file order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub file: String,
+    pub lines: Vec<DiffLine>,
+}
+
+/// The watched files as they are now; missing ones as None.
+pub fn snapshot(boot: &Path) -> Vec<(&'static str, Option<String>)> {
+    WATCHED
+        .iter()
+        .map(|file| (*file, std::fs::read_to_string(boot.join(file)).ok()))
+        .collect()
+}
+
+/// The watched files that differ from `before`.
+pub fn changes(before: &[(&str, Option<String>)], boot: &Path) -> Vec<FileDiff> {
+    before
+        .iter()
+        .filter_map(|(file, old)| {
+            let new = std::fs::read_to_string(boot.join(file)).ok();
+            (new != *old).then(|| FileDiff {
+                file: file.to_string(),
+                lines: diff(
+                    old.as_deref().unwrap_or_default(),
+                    new.as_deref().unwrap_or_default(),
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Line diff through the longest common subsequence; boot files are a few
+/// dozen lines at most.
+fn diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // common[i][j]: length of the LCS of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+impl FileDiff {
+    /// The diff in the unified style, without hunk headers.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("--- a/{0}\n+++ b/{0}\n", self.file);
+        for line in &self.lines {
+            let (prefix, line) = match line {
+                DiffLine::Same(line) => (' ', line),
+                DiffLine::Removed(line) => ('-', line),
+                DiffLine::Added(line) => ('+', line),
+            };
+            text.push(prefix);
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_edited_files() {
+        let boot = tempfile::tempdir().unwrap();
+        std::fs::write(boot.path().join("cmdline.txt"), "console=tty1 quiet\n").unwrap();
+        std::fs::write(boot.path().join("config.txt"), "[all]\narm_64bit=1\n").unwrap();
+        let before = snapshot(boot.path());
+
+        std::fs::write(
+            boot.path().join("cmdline.txt"),
+            "console=tty1 quiet systemd.run=/boot/firstrun.sh\n",
+        )
+        .unwrap();
+        std::fs::write(boot.path().join("config.txt"), "[all]\narm_64bit=1\n").unwrap();
+        let changes = changes(&before, boot.path());
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0].to_text(),
+            "--- a/cmdline.txt\n+++ b/cmdline.txt\n\
+             -console=tty1 quiet\n\
+             +console=tty1 quiet systemd.run=/boot/firstrun.sh\n"
+        );
+    }
+
+    #[test]
+    fn keeps_common_lines_in_place() {
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nx\nc\nd\n"),
+            [
+                DiffLine::Same("a".to_string()),
+                DiffLine::Removed("b".to_string()),
+                DiffLine::Added("x".to_string()),
+                DiffLine::Same("c".to_string()),
+                DiffLine::Added("d".to_string()),
+            ]
+        );
+    }
+}
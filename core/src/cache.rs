@@ -0,0 +1,327 @@
+//! Download cache for the selected image. The TUI prefetches into
+//! `<name>.part` while the user is still configuring the write; the worker
+//! then reads the local prefix and only downloads the rest. Downloads are
+//! named after the image's sha256 in the OS list (after the URL for images
+//! without one), so a URL that now serves a newer image misses the cache.
+//! Complete downloads keep their sha256 next to them, so an image can be
+//! written offline even when the OS list now points to another URL for it.
+//! Images stay until they are removed from the Cache screen.
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use reqwest::{Client, StatusCode};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+// Set from the `cache_dir` option, replacing the XDG location
+static CUSTOM_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Uses `dir` for the cache instead of the default location.
+pub fn set_cache_dir(dir: Option<PathBuf>) {
+    *CUSTOM_DIR.lock().unwrap_or_else(|e| e.into_inner()) = dir;
+}
+
+/// The default cache location, `$XDG_CACHE_HOME/rpi-imager-tui`.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("rpi-imager-tui"))
+}
+
+/// Where downloads are cached: the directory set for this run, or the
+/// default one.
+pub fn cache_dir() -> Option<PathBuf> {
+    let custom = CUSTOM_DIR.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    custom.or_else(default_cache_dir)
+}
+
+/// Where a fully downloaded `url` is stored, given the sha256 the OS list
+/// expects for it.
+fn complete_path(url: &str, sha256: Option<&str>) -> Option<PathBuf> {
+    Some(cache_dir()?.join(file_name(url, sha256)))
+}
+
+fn file_name(url: &str, sha256: Option<&str>) -> String {
+    let key = sha256.map_or_else(|| url.to_string(), str::to_ascii_lowercase);
+    let hash = hex::encode(Sha256::digest(key.as_bytes()));
+    let name = url.rsplit('/').next().unwrap_or("image");
+    format!("{}-{}", &hash[..16], name)
+}
+
+/// Whether a file name has the `<hash>-<name>` form of [`complete_path`].
+fn is_cached_download(name: &str) -> bool {
+    name.len() > 17
+        && name.as_bytes()[16] == b'-'
+        && name.as_bytes()[..16].iter().all(u8::is_ascii_hexdigit)
+}
+
+fn part_path(url: &str, sha256: Option<&str>) -> Option<PathBuf> {
+    let mut path = complete_path(url, sha256)?.into_os_string();
+    path.push(".part");
+    Some(path.into())
+}
+
+/// The cached download of `url`, complete or partial, if there is one.
+pub fn lookup(url: &str, sha256: Option<&str>) -> Option<PathBuf> {
+    [complete_path(url, sha256)?, part_path(url, sha256)?]
+        .into_iter()
+        .find(|p| p.is_file())
+}
+
+/// Where the sha256 of a complete download is kept.
+fn checksum_path(complete: &Path) -> PathBuf {
+    let mut path = complete.to_path_buf().into_os_string();
+    path.push(".sha256");
+    path.into()
+}
+
+/// The complete download of `url`, or any complete download whose sha256 is
+/// `sha256`. Writing from it needs no network. With a `sha256`, the
+/// checksum kept next to a download has to match it.
+pub fn offline_image(url: Option<&str>, sha256: Option<&str>) -> Option<PathBuf> {
+    offline_image_in(&cache_dir()?, url, sha256)
+}
+
+fn offline_image_in(dir: &Path, url: Option<&str>, sha256: Option<&str>) -> Option<PathBuf> {
+    let recorded = |path: &Path| std::fs::read_to_string(checksum_path(path)).ok();
+    if let Some(path) = url
+        .map(|url| dir.join(file_name(url, sha256)))
+        .filter(|p| p.is_file())
+        && sha256.is_none_or(|sha256| {
+            recorded(&path).is_some_and(|r| r.trim().eq_ignore_ascii_case(sha256))
+        })
+    {
+        return Some(path);
+    }
+    let sha256 = sha256?;
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| is_cached_download(&entry.file_name().to_string_lossy()))
+        .find_map(|entry| {
+            let path = entry.path();
+            let image = path.to_str()?.strip_suffix(".sha256")?;
+            let recorded = std::fs::read_to_string(&path).ok()?;
+            (recorded.trim().eq_ignore_ascii_case(sha256) && Path::new(image).is_file())
+                .then(|| PathBuf::from(image))
+        })
+}
+
+/// Hashes a complete download and stores the checksum next to it.
+fn record_checksum(complete: &Path) -> std::io::Result<()> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(complete)?, &mut hasher)?;
+    std::fs::write(
+        checksum_path(complete),
+        hex::encode(hasher.finalize()) + "\n",
+    )
+}
+
+/// A download in the cache, as listed on the Cache screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedImage {
+    pub path: PathBuf,
+    /// The file name of the image at its URL.
+    pub name: String,
+    pub size: u64,
+    pub partial: bool,
+}
+
+/// The downloads in the cache, by name. The directory may be user-chosen,
+/// so only files named like our own downloads are listed.
+pub fn list() -> Vec<CachedImage> {
+    cache_dir().map(|dir| list_in(&dir)).unwrap_or_default()
+}
+
+fn list_in(dir: &Path) -> Vec<CachedImage> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut images: Vec<CachedImage> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !is_cached_download(&file_name) || file_name.ends_with(".sha256") {
+                return None;
+            }
+            let path = entry.path();
+            let partial = is_partial(&path);
+            let name = file_name[17..].trim_end_matches(".part").to_string();
+            Some(CachedImage {
+                size: entry.metadata().ok()?.len(),
+                path,
+                name,
+                partial,
+            })
+        })
+        .collect();
+    images.sort_by(|a, b| a.name.cmp(&b.name));
+    images
+}
+
+/// Deletes a download and the checksum kept next to it.
+pub fn remove(image: &CachedImage) -> Result<()> {
+    std::fs::remove_file(&image.path)
+        .with_context(|| format!("Failed to remove {}", image.path.display()))?;
+    let _ = std::fs::remove_file(checksum_path(&image.path));
+    Ok(())
+}
+
+/// Whether a path returned by [`lookup`] only holds the start of the image.
+pub fn is_partial(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "part")
+}
+
+/// Progress of a prefetch: bytes on disk and the total, if known.
+pub type PrefetchProgress = (u64, Option<u64>);
+
+/// Downloads `url` into the cache, resuming an earlier partial download of
+/// the same image. Once `stop` fires it returns with the partial download
+/// flushed, so a worker can go on from its length.
+pub async fn prefetch(
+    client: &Client,
+    url: &str,
+    sha256: Option<&str>,
+    mut stop: tokio::sync::oneshot::Receiver<()>,
+    progress: impl Fn(PrefetchProgress),
+) -> Result<()> {
+    let (Some(dir), Some(complete), Some(part)) = (
+        cache_dir(),
+        complete_path(url, sha256),
+        part_path(url, sha256),
+    ) else {
+        return Ok(());
+    };
+    if complete.is_file() {
+        let size = complete.metadata()?.len();
+        progress((size, Some(size)));
+        return Ok(());
+    }
+
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let mut offset = tokio::fs::metadata(&part)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let res = tokio::select! {
+        res = crate::http::get_from(client, url, offset) => res?,
+        _ = &mut stop => return Ok(()),
+    };
+    if res.status() != StatusCode::PARTIAL_CONTENT {
+        // The server ignored the range, start over
+        offset = 0;
+    }
+    let total = res.content_length().map(|len| len + offset);
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(offset > 0)
+        .truncate(offset == 0)
+        .open(&part)
+        .await
+        .with_context(|| format!("Failed to open {}", part.display()))?;
+
+    let mut stream = res.bytes_stream();
+    let mut last_update = Instant::now();
+    loop {
+        // Only waiting for the network is cancelled, never a write
+        let chunk = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = &mut stop => {
+                file.flush().await?;
+                return Ok(());
+            }
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        let chunk = chunk.context("Prefetch interrupted")?;
+        file.write_all(&chunk).await?;
+        offset += chunk.len() as u64;
+        if last_update.elapsed() >= Duration::from_millis(500) {
+            progress((offset, total));
+            last_update = Instant::now();
+        }
+    }
+    file.flush().await?;
+    drop(file);
+
+    tokio::fs::rename(&part, &complete).await?;
+    let checksummed = complete.clone();
+    tokio::task::spawn_blocking(move || record_checksum(&checksummed))
+        .await?
+        .with_context(|| format!("Failed to checksum {}", complete.display()))?;
+    progress((offset, total));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_and_removes_only_own_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let complete = dir.path().join("0123456789abcdef-raspios.img.xz");
+        std::fs::write(&complete, b"image").unwrap();
+        std::fs::write(checksum_path(&complete), "00\n").unwrap();
+        std::fs::write(
+            dir.path().join("fedcba9876543210-ubuntu.img.xz.part"),
+            b"im",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("os_list.json"), b"{}").unwrap();
+
+        let images = list_in(dir.path());
+        let names: Vec<(&str, u64, bool)> = images
+            .iter()
+            .map(|i| (i.name.as_str(), i.size, i.partial))
+            .collect();
+        assert_eq!(
+            names,
+            [("raspios.img.xz", 5, false), ("ubuntu.img.xz", 2, true)]
+        );
+
+        remove(&images[0]).unwrap();
+        assert!(!checksum_path(&complete).exists());
+        assert_eq!(list_in(dir.path()).len(), 1);
+        assert!(dir.path().join("os_list.json").exists());
+    }
+
+    #[test]
+    fn offline_images_match_the_expected_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = "https://downloads.raspberrypi.com/raspios_lite_arm64_latest";
+        let (old, new) = ("aa".repeat(32), "bb".repeat(32));
+        assert_ne!(file_name(url, Some(&old)), file_name(url, Some(&new)));
+
+        let image = dir.path().join(file_name(url, Some(&old)));
+        std::fs::write(&image, b"image").unwrap();
+        std::fs::write(checksum_path(&image), format!("{}\n", old)).unwrap();
+        assert_eq!(
+            offline_image_in(dir.path(), Some(url), Some(&old.to_uppercase())),
+            Some(image.clone())
+        );
+        // The URL now serves another image
+        assert_eq!(offline_image_in(dir.path(), Some(url), Some(&new)), None);
+        // Found by its checksum under another URL
+        assert_eq!(
+            offline_image_in(dir.path(), Some("https://mirror/os.img.xz"), Some(&old)),
+            Some(image.clone())
+        );
+
+        // A download named for `new` whose bytes turned out to be something else
+        let stale = dir.path().join(file_name(url, Some(&new)));
+        std::fs::write(&stale, b"other").unwrap();
+        std::fs::write(checksum_path(&stale), format!("{}\n", "cc".repeat(32))).unwrap();
+        assert_eq!(offline_image_in(dir.path(), Some(url), Some(&new)), None);
+    }
+}
@@ -0,0 +1,296 @@
+//! Telling how an image is compressed from its first bytes. Local files get
+//! renamed, and URLs don't always end in the extension, so names are not
+//! trusted.
+//!
+//! The writer decodes images through the [`Decompressor`] that recognizes
+//! them. Plain images, xz, gzip, zstd, bzip2 and ZIP archives are built in; tools
+//! using the library [`register`] more formats.
+
+use anyhow::Result;
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZstdDecoder};
+use futures::future::BoxFuture;
+use std::io::Read;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, PoisonError, RwLock};
+use tokio::io::{AsyncBufRead, AsyncRead};
+
+/// The image as it arrives, starting at its magic bytes.
+pub type Input = Box<dyn AsyncBufRead + Unpin + Send>;
+/// The image as it goes to the card.
+pub type Output = Box<dyn AsyncRead + Unpin + Send>;
+
+/// How decoders may run.
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    /// Threads for decoders that can use several.
+    pub threads: NonZeroU32,
+}
+
+/// A format images come in.
+pub trait Decompressor: Send + Sync {
+    /// Short name, e.g. "xz".
+    fn name(&self) -> &str;
+
+    /// Whether `head`, the first bytes of the image, start this format. It
+    /// holds what the first read returned: usually kilobytes, but at least
+    /// [`Compression::MAGIC_LEN`] bytes only for files.
+    fn detect(&self, head: &[u8]) -> bool;
+
+    /// Starts decoding `input`. Formats that have to read a header first,
+    /// like archives, may do it here.
+    fn decode(&self, input: Input, settings: Settings) -> BoxFuture<'static, Result<Output>>;
+
+    /// Whether the image is smaller than what it decodes to; the writer
+    /// shows the input rate along with the writing speed then.
+    fn is_compressed(&self) -> bool {
+        true
+    }
+}
+
+struct Plain;
+struct Xz;
+struct Gzip;
+struct Zstd;
+struct Bzip2;
+struct Zip;
+
+impl Decompressor for Plain {
+    fn name(&self) -> &str {
+        "plain"
+    }
+
+    fn detect(&self, _head: &[u8]) -> bool {
+        true
+    }
+
+    fn decode(&self, input: Input, _settings: Settings) -> BoxFuture<'static, Result<Output>> {
+        Box::pin(async move { Ok(Box::new(input) as Output) })
+    }
+
+    fn is_compressed(&self) -> bool {
+        false
+    }
+}
+
+impl Decompressor for Xz {
+    fn name(&self) -> &str {
+        "xz"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        Compression::detect(head) == Compression::Xz
+    }
+
+    fn decode(&self, input: Input, settings: Settings) -> BoxFuture<'static, Result<Output>> {
+        // Blocks of multi-block images (xz -T) are decoded in parallel
+        Box::pin(
+            async move { Ok(Box::new(XzDecoder::parallel(input, settings.threads)) as Output) },
+        )
+    }
+}
+
+impl Decompressor for Gzip {
+    fn name(&self) -> &str {
+        "gzip"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        Compression::detect(head) == Compression::Gzip
+    }
+
+    fn decode(&self, input: Input, _settings: Settings) -> BoxFuture<'static, Result<Output>> {
+        Box::pin(async move { Ok(Box::new(GzipDecoder::new(input)) as Output) })
+    }
+}
+
+impl Decompressor for Zstd {
+    fn name(&self) -> &str {
+        "zstd"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        Compression::detect(head) == Compression::Zstd
+    }
+
+    fn decode(&self, input: Input, _settings: Settings) -> BoxFuture<'static, Result<Output>> {
+        Box::pin(async move { Ok(Box::new(ZstdDecoder::new(input)) as Output) })
+    }
+}
+
+impl Decompressor for Bzip2 {
+    fn name(&self) -> &str {
+        "bzip2"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        Compression::detect(head) == Compression::Bzip2
+    }
+
+    fn decode(&self, input: Input, _settings: Settings) -> BoxFuture<'static, Result<Output>> {
+        Box::pin(async move { Ok(Box::new(BzDecoder::new(input)) as Output) })
+    }
+}
+
+impl Decompressor for Zip {
+    fn name(&self) -> &str {
+        "zip"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        Compression::detect(head) == Compression::Zip
+    }
+
+    fn decode(&self, input: Input, _settings: Settings) -> BoxFuture<'static, Result<Output>> {
+        Box::pin(crate::unzip::image_entry(input))
+    }
+}
+
+/// Tried last to first; plain images match anything, so they come first.
+static DECOMPRESSORS: LazyLock<RwLock<Vec<Arc<dyn Decompressor>>>> = LazyLock::new(|| {
+    RwLock::new(vec![
+        Arc::new(Plain),
+        Arc::new(Xz),
+        Arc::new(Gzip),
+        Arc::new(Zstd),
+        Arc::new(Bzip2),
+        Arc::new(Zip),
+    ])
+});
+
+/// Adds a format. It is tried before the ones registered earlier and the
+/// built-in ones, so it can also take over one of those.
+pub fn register(decompressor: impl Decompressor + 'static) {
+    DECOMPRESSORS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(Arc::new(decompressor));
+}
+
+/// The decompressor for an image starting with `head`.
+pub fn for_image(head: &[u8]) -> Arc<dyn Decompressor> {
+    let decompressors = DECOMPRESSORS.read().unwrap_or_else(PoisonError::into_inner);
+    decompressors
+        .iter()
+        .rev()
+        .find(|decompressor| decompressor.detect(head))
+        .cloned()
+        .unwrap_or_else(|| Arc::new(Plain))
+}
+
+/// The built-in formats, as told by their magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Xz,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Zip,
+}
+
+impl Compression {
+    /// How many leading bytes [`Compression::detect`] looks at.
+    pub const MAGIC_LEN: usize = 6;
+
+    /// The format `head`, the first bytes of an image, starts; `None` for
+    /// anything else, plain images included.
+    pub fn detect(head: &[u8]) -> Self {
+        if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Self::Xz
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else if head.starts_with(b"BZh") {
+            Self::Bzip2
+        } else if head.starts_with(b"PK\x03\x04") {
+            Self::Zip
+        } else {
+            Self::None
+        }
+    }
+
+    /// The format of the file at `path`.
+    pub fn of_file(path: &Path) -> std::io::Result<Self> {
+        let mut head = Vec::with_capacity(Self::MAGIC_LEN);
+        std::fs::File::open(path)?
+            .take(Self::MAGIC_LEN as u64)
+            .read_to_end(&mut head)?;
+        Ok(Self::detect(&head))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_by_magic_bytes() {
+        assert_eq!(
+            Compression::detect(&[0xfd, b'7', b'z', b'X', b'Z', 0, 0]),
+            Compression::Xz
+        );
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 8]), Compression::Gzip);
+        assert_eq!(
+            Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd]),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::detect(b"BZh91AY"), Compression::Bzip2);
+        assert_eq!(Compression::detect(b"PK\x03\x04\x14\x00"), Compression::Zip);
+        // An MBR boot sector, or too little to tell
+        assert_eq!(
+            Compression::detect(&[0xfa, 0xb8, 0, 0x10]),
+            Compression::None
+        );
+        assert_eq!(Compression::detect(&[0xfd]), Compression::None);
+    }
+
+    /// Images behind a four byte header.
+    struct Wrapped;
+
+    impl Decompressor for Wrapped {
+        fn name(&self) -> &str {
+            "wrapped"
+        }
+
+        fn detect(&self, head: &[u8]) -> bool {
+            head.starts_with(b"WRAP")
+        }
+
+        fn decode(
+            &self,
+            mut input: Input,
+            _settings: Settings,
+        ) -> BoxFuture<'static, Result<Output>> {
+            Box::pin(async move {
+                use tokio::io::AsyncReadExt;
+                input.read_exact(&mut [0; 4]).await?;
+                Ok(Box::new(input) as Output)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn decodes_with_registered_formats_first() {
+        assert_eq!(for_image(&[0x1f, 0x8b, 8]).name(), "gzip");
+        assert!(!for_image(&[0xfa, 0xb8, 0, 0x10]).is_compressed());
+
+        register(Wrapped);
+        let image = b"WRAP\xfa\xb8image".to_vec();
+        let decompressor = for_image(&image);
+        assert_eq!(decompressor.name(), "wrapped");
+        let settings = Settings {
+            threads: NonZeroU32::MIN,
+        };
+        let mut output = decompressor
+            .decode(Box::new(std::io::Cursor::new(image)), settings)
+            .await
+            .unwrap();
+        let mut decoded = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut output, &mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, b"\xfa\xb8image");
+    }
+}
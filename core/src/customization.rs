@@ -0,0 +1,1420 @@
+use crate::eeprom::BootloaderConfig;
+use crate::os_list::OsSource;
+use crate::secrets::Secrets;
+use glob::glob;
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Everything the user sets: the image settings written onto the card
+/// (hostname, localization, user, Wi-Fi, SSH, network), and the options of
+/// the tool itself (downloads, verification, cache, appearance).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomizationOptions {
+    pub hostname: String,
+    pub timezone: String,
+    pub keyboard_layout: String,
+    #[serde(default)]
+    pub keyboard_variant: String, // XKB variant of the layout, e.g. "dvorak"
+
+    // User settings
+    pub user_name: String,
+    pub password: Option<String>, // Plain text password, to be hashed later
+
+    // SSH
+    pub ssh_enabled: bool,
+    pub ssh_password_auth: bool,
+    pub ssh_public_keys: String,
+
+    // WiFi
+    pub wifi_ssid: String,
+    pub wifi_password: String,
+    pub wifi_country: String,
+    pub wifi_hidden: bool,
+
+    // Static addresses of the wired and the wireless interface; DHCP when unset
+    #[serde(default)]
+    pub eth0: StaticIp,
+    #[serde(default)]
+    pub wlan0: StaticIp,
+
+    // Locale
+    pub locale: String,
+
+    // Options Tab
+    // Opt-in; stored under a new key so configs saved while the old,
+    // unused flag defaulted to on don't count as consent.
+    #[serde(default, rename = "telemetry_opt_in")]
+    pub telemetry: bool,
+    pub eject_finished: bool,
+    #[serde(default)]
+    pub remember_secrets: bool, // Keep passwords in config.toml between runs
+    // Remembered passwords encrypted with a passphrase (see secrets.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_secrets: Option<String>,
+    // Known once the user unlocked or set it; never written anywhere
+    #[serde(skip)]
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub verification: Verification,
+    #[serde(default)]
+    pub wipe_device: bool, // Zero the card's first and last MiBs before writing
+    #[serde(default)]
+    pub require_ac_power: bool, // Don't start writing while the host runs on battery
+    // Also write the decompressed image to this file; chosen per write on
+    // the confirmation screen, so it is never saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tee_file: Option<String>,
+    #[serde(default)]
+    pub theme: Theme,
+    #[serde(default)]
+    pub cache_dir: Option<String>, // Replaces ~/.cache/rpi-imager-tui for prefetched images
+    #[serde(default)]
+    pub decompression_threads: Option<u32>, // For .xz images; all cores when unset
+    // JSON file with counters of the writes, for provisioning station dashboards
+    #[serde(default)]
+    pub stats_file: Option<String>,
+
+    // Network
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    #[serde(default)]
+    pub ca_bundle: Option<String>, // Extra PEM bundle trusted in addition to the built-in roots
+    // Pins for downloads.raspberrypi.com: SHA-256 fingerprints of a
+    // certificate in its chain, or sha256/<base64> of a public key
+    #[serde(default)]
+    pub pinned_certificates: Vec<String>,
+    // Extra OS lists shown alongside the official one
+    #[serde(default)]
+    pub os_sources: Vec<OsSource>,
+    // Base URLs standing in for downloads.raspberrypi.com, offered in order
+    // when a download is slow
+    #[serde(default)]
+    pub download_mirrors: Vec<String>,
+    // Parallel ranged connections per download, for high-latency links; a
+    // single stream when unset
+    #[serde(default)]
+    pub download_connections: Option<u32>,
+
+    // Shell command run against the Pi once it is reachable, e.g.
+    // ansible-playbook -i "$PI_HOST," -u "$PI_USER" site.yml
+    #[serde(default)]
+    pub bootstrap_command: Option<String>,
+
+    // EEPROM settings, only used for bootloader recovery images
+    #[serde(default)]
+    pub bootloader: BootloaderConfig,
+
+    // Copied onto the boot partition after customizing, e.g. a custom
+    // config.txt or a provisioning token
+    #[serde(default)]
+    pub extra_files: Vec<ExtraFile>,
+}
+
+impl Default for CustomizationOptions {
+    fn default() -> Self {
+        let host = crate::host_locale::detected();
+        let or = |detected: &Option<String>, fallback: &str| {
+            detected.clone().unwrap_or_else(|| fallback.to_string())
+        };
+        Self {
+            hostname: "raspberrypi".to_string(),
+            timezone: or(&host.timezone, "Europe/London"),
+            keyboard_layout: or(&host.keyboard_layout, "gb"),
+            keyboard_variant: String::new(),
+            user_name: "pi".to_string(),
+            password: None,
+            ssh_enabled: false,
+            ssh_password_auth: true,
+            ssh_public_keys: String::new(),
+            wifi_ssid: String::new(),
+            wifi_password: String::new(),
+            wifi_country: or(&host.wifi_country, "GB"),
+            wifi_hidden: false,
+            eth0: StaticIp::default(),
+            wlan0: StaticIp::default(),
+            locale: or(&host.locale, "en_GB.UTF-8"),
+            telemetry: false,
+            eject_finished: true,
+            remember_secrets: false,
+            sealed_secrets: None,
+            passphrase: None,
+            verification: Verification::default(),
+            wipe_device: false,
+            require_ac_power: false,
+            tee_file: None,
+            theme: Theme::default(),
+            cache_dir: None,
+            decompression_threads: None,
+            stats_file: None,
+            http_proxy: None,
+            ca_bundle: None,
+            pinned_certificates: Vec::new(),
+            os_sources: Vec::new(),
+            download_mirrors: Vec::new(),
+            download_connections: None,
+            bootstrap_command: None,
+            bootloader: BootloaderConfig::default(),
+            extra_files: Vec::new(),
+        }
+    }
+}
+
+/// Earlier and undone states of the options, for Ctrl+Z and Ctrl+Y in the
+/// Customization view.
+#[derive(Default)]
+pub struct History {
+    seen: Option<CustomizationOptions>,
+    undo: Vec<CustomizationOptions>,
+    redo: Vec<CustomizationOptions>,
+}
+
+/// Changes that can be undone; older ones are forgotten.
+const HISTORY_LEN: usize = 50;
+
+impl History {
+    /// Records the options if they changed since the last call. Changes made
+    /// outside of editing (loading, unlocking, inspecting a card) only move
+    /// the starting point, so they can't be undone.
+    pub fn observe(&mut self, options: &CustomizationOptions, editing: bool) {
+        if self.seen.as_ref() == Some(options) {
+            return;
+        }
+        if editing && let Some(previous) = self.seen.take() {
+            self.undo.push(previous);
+            if self.undo.len() > HISTORY_LEN {
+                self.undo.remove(0);
+            }
+            self.redo.clear();
+        }
+        self.seen = Some(options.clone());
+    }
+
+    /// Puts back the options before the last change; false if there is none.
+    pub fn undo(&mut self, options: &mut CustomizationOptions) -> bool {
+        Self::step(&mut self.undo, &mut self.redo, &mut self.seen, options)
+    }
+
+    /// Puts back the change undone last; false if there is none.
+    pub fn redo(&mut self, options: &mut CustomizationOptions) -> bool {
+        Self::step(&mut self.redo, &mut self.undo, &mut self.seen, options)
+    }
+
+    fn step(
+        from: &mut Vec<CustomizationOptions>,
+        to: &mut Vec<CustomizationOptions>,
+        seen: &mut Option<CustomizationOptions>,
+        options: &mut CustomizationOptions,
+    ) -> bool {
+        let Some(state) = from.pop() else {
+            return false;
+        };
+        to.push(std::mem::replace(options, state));
+        *seen = Some(options.clone());
+        true
+    }
+}
+
+/// A file copied onto the boot partition, registered in the config file.
+/// `destination` is relative to the partition's root; an empty `source`
+/// creates an empty marker file such as `ssh`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtraFile {
+    #[serde(default)]
+    pub source: String,
+    pub destination: String,
+}
+
+/// A fixed address for one interface, for networks without DHCP. Left
+/// empty, a family is still configured by DHCP (IPv4) or router
+/// advertisements (IPv6). Addresses carry their prefix length, e.g.
+/// 192.168.1.20/24, and DNS servers of both families share one list.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaticIp {
+    pub address: String,
+    pub gateway: String,
+    pub address6: String,
+    pub gateway6: String,
+    pub dns: String,
+}
+
+type Validator = fn(&str) -> Result<(), String>;
+
+impl StaticIp {
+    /// The settings as the menu lists them, with their validators.
+    pub const FIELDS: [(&'static str, Validator); 5] = [
+        ("IPv4 address", validate_ipv4_address),
+        ("IPv4 gateway", validate_ipv4_gateway),
+        ("IPv6 address", validate_ipv6_address),
+        ("IPv6 gateway", validate_ipv6_gateway),
+        ("DNS servers", validate_dns_servers),
+    ];
+
+    /// The setting at `index` of [`Self::FIELDS`].
+    pub fn field(&self, index: usize) -> &str {
+        match index {
+            0 => &self.address,
+            1 => &self.gateway,
+            2 => &self.address6,
+            3 => &self.gateway6,
+            _ => &self.dns,
+        }
+    }
+
+    /// The setting at `index` of [`Self::FIELDS`], for editing.
+    pub fn field_mut(&mut self, index: usize) -> &mut String {
+        match index {
+            0 => &mut self.address,
+            1 => &mut self.gateway,
+            2 => &mut self.address6,
+            3 => &mut self.gateway6,
+            _ => &mut self.dns,
+        }
+    }
+
+    /// Whether either family gets a fixed address; gateways and DNS
+    /// servers alone are ignored.
+    pub fn is_set(&self) -> bool {
+        !self.address.is_empty() || !self.address6.is_empty()
+    }
+
+    /// The DNS servers, IPv4 ones first.
+    fn dns_servers(&self) -> (Vec<&str>, Vec<&str>) {
+        self.dns
+            .split([',', ' '])
+            .filter(|s| !s.is_empty())
+            .partition(|s| !s.contains(':'))
+    }
+
+    /// Checks every setting with its validator from [`Self::FIELDS`]; the
+    /// values end up in scripts, so they are never taken unchecked.
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, (name, validate)) in Self::FIELDS.iter().enumerate() {
+            validate(self.field(index)).map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// The `[ipv4]` and `[ipv6]` groups of a NetworkManager keyfile. A
+    /// family without an address stays automatic.
+    fn keyfile_groups(&self) -> String {
+        let (dns4, dns6) = self.dns_servers();
+        let mut groups = String::new();
+        for (family, address, gateway, dns) in [
+            ("ipv4", &self.address, &self.gateway, dns4),
+            ("ipv6", &self.address6, &self.gateway6, dns6),
+        ] {
+            groups.push_str(&format!("\n[{}]\n", family));
+            if address.is_empty() {
+                groups.push_str("method=auto\n");
+            } else {
+                groups.push_str("method=manual\n");
+                if gateway.is_empty() {
+                    groups.push_str(&format!("address1={}\n", address));
+                } else {
+                    groups.push_str(&format!("address1={},{}\n", address, gateway));
+                }
+            }
+            if !dns.is_empty() {
+                groups.push_str(&format!("dns={};\n", dns.join(";")));
+            }
+        }
+        groups
+    }
+
+    /// A dhcpcd.conf profile for `interface`. dhcpcd has no static IPv6
+    /// route, so the IPv6 gateway comes from router advertisements there.
+    fn dhcpcd_profile(&self, interface: &str) -> String {
+        let mut profile = format!("interface {}\n", interface);
+        if !self.address.is_empty() {
+            profile.push_str(&format!("static ip_address={}\n", self.address));
+        }
+        if !self.gateway.is_empty() && !self.address.is_empty() {
+            profile.push_str(&format!("static routers={}\n", self.gateway));
+        }
+        if !self.address6.is_empty() {
+            profile.push_str(&format!("static ip6_address={}\n", self.address6));
+        }
+        if !self.dns.is_empty() {
+            let (dns4, dns6) = self.dns_servers();
+            profile.push_str(&format!(
+                "static domain_name_servers={}\n",
+                [dns4, dns6].concat().join(" ")
+            ));
+        }
+        profile
+    }
+
+    /// The addresses in a netplan interface, each line indented by `indent`.
+    /// Values are quoted, as IPv6 addresses are full of colons.
+    pub fn netplan(&self, indent: &str) -> String {
+        let quoted = |values: &[&str]| {
+            let values: Vec<String> = values.iter().map(|v| format!("\"{}\"", v)).collect();
+            values.join(", ")
+        };
+        let mut yaml = String::new();
+        let addresses: Vec<&str> = [&self.address, &self.address6]
+            .into_iter()
+            .filter(|a| !a.is_empty())
+            .map(String::as_str)
+            .collect();
+        if !addresses.is_empty() {
+            yaml.push_str(&format!("{}addresses: [{}]\n", indent, quoted(&addresses)));
+        }
+        let routes: Vec<&str> = [
+            (&self.address, &self.gateway),
+            (&self.address6, &self.gateway6),
+        ]
+        .into_iter()
+        .filter(|(a, g)| !a.is_empty() && !g.is_empty())
+        .map(|(_, g)| g.as_str())
+        .collect();
+        if !routes.is_empty() {
+            yaml.push_str(&format!("{}routes:\n", indent));
+            for via in routes {
+                yaml.push_str(&format!(
+                    "{}  - to: default\n{}    via: \"{}\"\n",
+                    indent, indent, via
+                ));
+            }
+        }
+        let (dns4, dns6) = self.dns_servers();
+        if !self.dns.is_empty() {
+            yaml.push_str(&format!(
+                "{}nameservers:\n{}  addresses: [{}]\n",
+                indent,
+                indent,
+                quoted(&[dns4, dns6].concat())
+            ));
+        }
+        yaml
+    }
+}
+
+/// Whether the card is read back and compared after writing.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verification {
+    #[default]
+    Always,
+    /// Decided on the confirmation screen of each write
+    Ask,
+    Never,
+}
+
+impl Verification {
+    /// The next mode, as the menu cycles through them.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Always => Self::Ask,
+            Self::Ask => Self::Never,
+            Self::Never => Self::Always,
+        }
+    }
+
+    /// The name shown in the menu.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Always => "Always",
+            Self::Ask => "Ask",
+            Self::Never => "Never",
+        }
+    }
+}
+
+/// Tabs of the customization screen, kept for layouts that group the
+/// settings.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CustomizationTab {
+    General,
+    Services,
+    Options,
+}
+
+#[allow(dead_code)]
+impl CustomizationTab {
+    /// The tab to the right, wrapping around.
+    pub fn next(&self) -> Self {
+        match self {
+            Self::General => Self::Services,
+            Self::Services => Self::Options,
+            Self::Options => Self::General,
+        }
+    }
+
+    /// The tab to the left, wrapping around.
+    pub fn prev(&self) -> Self {
+        match self {
+            Self::General => Self::Options,
+            Self::Services => Self::General,
+            Self::Options => Self::Services,
+        }
+    }
+}
+
+/// The image-side sections of the customization menu, in menu order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CustomizationSection {
+    Hostname,
+    Localization,
+    User,
+    Wifi,
+    RemoteAccess,
+    Network,
+}
+
+impl CustomizationSection {
+    /// Every section, in menu order.
+    pub const ALL: [Self; 6] = [
+        Self::Hostname,
+        Self::Localization,
+        Self::User,
+        Self::Wifi,
+        Self::RemoteAccess,
+        Self::Network,
+    ];
+
+    /// The section at `index` of the customization menu; None for the
+    /// entries that aren't sections (Options and after).
+    pub fn from_menu_index(index: usize) -> Option<Self> {
+        match index {
+            0 => Some(Self::Hostname),
+            1 => Some(Self::Localization),
+            2 => Some(Self::User),
+            3 => Some(Self::Wifi),
+            4 => Some(Self::RemoteAccess),
+            5 => Some(Self::Network),
+            _ => None,
+        }
+    }
+
+    /// os_list capability marking an image that ignores this section.
+    pub fn unsupported_capability(&self) -> &'static str {
+        match self {
+            Self::Hostname => "no_hostname",
+            Self::Localization => "no_localization",
+            Self::User => "no_user",
+            Self::Wifi => "no_wifi",
+            Self::RemoteAccess => "no_ssh",
+            Self::Network => "no_network",
+        }
+    }
+}
+
+/// Accent color of the interface, picked in the setup wizard. The colors
+/// themselves are the interface's business.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Raspberry,
+    Ocean,
+    Forest,
+    Mono,
+}
+
+impl Theme {
+    /// Every theme, in the order the option cycles through them.
+    pub const ALL: [Self; 4] = [Self::Raspberry, Self::Ocean, Self::Forest, Self::Mono];
+
+    /// The theme after this one.
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    /// The name shown in the options.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Raspberry => "Raspberry",
+            Self::Ocean => "Ocean",
+            Self::Forest => "Forest",
+            Self::Mono => "Monochrome",
+        }
+    }
+}
+
+impl CustomizationOptions {
+    /// The config is TOML, so it can be edited by hand. Older versions kept
+    /// it as config.json next to it, which is read while there is no
+    /// config.toml yet and removed on the next save.
+    pub fn config_path() -> Option<std::path::PathBuf> {
+        if let Ok(home) = std::env::var("HOME") {
+            let path = std::path::Path::new(&home).join(".config/rpi-imager-tui/config.toml");
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// The saved options, or the defaults. A config that doesn't parse is
+    /// reported by [`config_error`].
+    pub fn load() -> Self {
+        match Self::config_path() {
+            Some(path) => Self::load_from(&path),
+            None => Self::default(),
+        }
+    }
+
+    fn load_from(path: &Path) -> Self {
+        if let Ok(text) = std::fs::read_to_string(path) {
+            return Self::from_toml(&text).unwrap_or_else(|e| {
+                *CONFIG_ERROR.lock().unwrap_or_else(|e| e.into_inner()) = Some((
+                    path.to_path_buf(),
+                    format!(
+                        "{} is not valid, so the defaults are used and nothing is saved \
+                         over it until it is fixed: {:#}",
+                        path.display(),
+                        e
+                    ),
+                ));
+                Self::default()
+            });
+        }
+        std::fs::File::open(path.with_extension("json"))
+            .ok()
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    /// Persists the options so they are preloaded on the next run. Passwords
+    /// stay in memory only, unless the user opted in to remembering them,
+    /// and are encrypted when a passphrase is set. Nothing is saved once
+    /// [`disable_saving`] was called, or over a config that didn't parse.
+    pub fn save(&self) {
+        if let Some(path) = Self::config_path() {
+            self.save_to(&path);
+        }
+    }
+
+    fn save_to(&self, path: &Path) {
+        let broken = CONFIG_ERROR
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_some_and(|(broken, _)| broken == path);
+        if saving_disabled() || broken {
+            return;
+        }
+        let Ok(text) = self.to_toml() else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if std::fs::write(path, text).is_ok() {
+            let _ = std::fs::remove_file(path.with_extension("json"));
+        }
+    }
+
+    /// The options as they are saved, in the config file or a preset.
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(&self.persisted())?)
+    }
+
+    /// Options from the text of a config file or preset, as [`Self::to_toml`]
+    /// writes them.
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Passwords left out or sealed, as [`Self::save`] describes.
+    fn persisted(&self) -> Self {
+        let mut persisted = self.clone();
+        if !self.remember_secrets {
+            persisted.password = None;
+            persisted.wifi_password.clear();
+            persisted.sealed_secrets = None;
+        } else if self.encrypts_secrets() {
+            // Without the passphrase (not unlocked this run) the sealed copy
+            // is kept as it is
+            if let Some(passphrase) = &self.passphrase {
+                let secrets = Secrets {
+                    password: self.password.clone(),
+                    wifi_password: self.wifi_password.clone(),
+                };
+                persisted.sealed_secrets = crate::secrets::seal(&secrets, passphrase).ok();
+            }
+            persisted.password = None;
+            persisted.wifi_password.clear();
+        }
+        persisted
+    }
+
+    /// Whether remembered passwords are saved encrypted.
+    pub fn encrypts_secrets(&self) -> bool {
+        self.passphrase.is_some() || self.sealed_secrets.is_some()
+    }
+
+    /// Decrypts the remembered passwords, keeping the passphrase for saving.
+    pub fn unlock(&mut self, passphrase: String) -> anyhow::Result<()> {
+        if let Some(sealed) = &self.sealed_secrets {
+            let secrets = crate::secrets::open(sealed, &passphrase)?;
+            if self.password.is_none() {
+                self.password = secrets.password;
+            }
+            if self.wifi_password.is_empty() {
+                self.wifi_password = secrets.wifi_password;
+            }
+        }
+        self.passphrase = Some(passphrase);
+        Ok(())
+    }
+
+    /// Clears a section so nothing for it ends up in the generated script.
+    pub fn clear_section(&mut self, section: CustomizationSection) {
+        match section {
+            CustomizationSection::Hostname => self.hostname.clear(),
+            CustomizationSection::Localization => {
+                self.timezone.clear();
+                self.keyboard_layout.clear();
+                self.keyboard_variant.clear();
+                self.locale = "en_GB.UTF-8".to_string();
+            }
+            CustomizationSection::User => {
+                self.user_name = "pi".to_string();
+                self.password = None;
+            }
+            CustomizationSection::Wifi => {
+                self.wifi_ssid.clear();
+                self.wifi_password.clear();
+                self.wifi_country.clear();
+            }
+            CustomizationSection::RemoteAccess => self.ssh_enabled = false,
+            CustomizationSection::Network => {
+                self.eth0 = StaticIp::default();
+                self.wlan0 = StaticIp::default();
+            }
+        }
+    }
+
+    /// Whether any value of `section` differs from its default, so the menu
+    /// can show what will actually be applied.
+    pub fn section_modified(&self, section: CustomizationSection) -> bool {
+        let defaults = Self::default();
+        match section {
+            CustomizationSection::Hostname => self.hostname != defaults.hostname,
+            CustomizationSection::Localization => {
+                self.timezone != defaults.timezone
+                    || self.keyboard_layout != defaults.keyboard_layout
+                    || self.keyboard_variant != defaults.keyboard_variant
+                    || self.locale != defaults.locale
+            }
+            CustomizationSection::User => {
+                self.user_name != defaults.user_name || self.password.is_some()
+            }
+            CustomizationSection::Wifi => {
+                self.wifi_ssid != defaults.wifi_ssid
+                    || !self.wifi_password.is_empty()
+                    || self.wifi_country != defaults.wifi_country
+                    || self.wifi_hidden != defaults.wifi_hidden
+            }
+            CustomizationSection::RemoteAccess => {
+                self.ssh_enabled != defaults.ssh_enabled
+                    || self.ssh_password_auth != defaults.ssh_password_auth
+                    || self.ssh_public_keys != defaults.ssh_public_keys
+            }
+            CustomizationSection::Network => {
+                self.eth0 != defaults.eth0 || self.wlan0 != defaults.wlan0
+            }
+        }
+    }
+
+    /// Puts every value of `section` back to its default.
+    pub fn reset_section(&mut self, section: CustomizationSection) {
+        self.copy_section(&Self::default(), section);
+    }
+
+    /// Takes every value of `section` from `other`.
+    pub fn copy_section(&mut self, other: &Self, section: CustomizationSection) {
+        match section {
+            CustomizationSection::Hostname => self.hostname = other.hostname.clone(),
+            CustomizationSection::Localization => {
+                self.timezone = other.timezone.clone();
+                self.keyboard_layout = other.keyboard_layout.clone();
+                self.keyboard_variant = other.keyboard_variant.clone();
+                self.locale = other.locale.clone();
+            }
+            CustomizationSection::User => {
+                self.user_name = other.user_name.clone();
+                self.password = other.password.clone();
+            }
+            CustomizationSection::Wifi => {
+                self.wifi_ssid = other.wifi_ssid.clone();
+                self.wifi_password = other.wifi_password.clone();
+                self.wifi_country = other.wifi_country.clone();
+                self.wifi_hidden = other.wifi_hidden;
+            }
+            CustomizationSection::RemoteAccess => {
+                self.ssh_enabled = other.ssh_enabled;
+                self.ssh_password_auth = other.ssh_password_auth;
+                self.ssh_public_keys = other.ssh_public_keys.clone();
+            }
+            CustomizationSection::Network => {
+                self.eth0 = other.eth0.clone();
+                self.wlan0 = other.wlan0.clone();
+            }
+        }
+    }
+
+    /// Like [`Self::section_modified`], for the tool options of the menu.
+    pub fn tool_options_modified(&self) -> bool {
+        let defaults = Self::default();
+        self.telemetry != defaults.telemetry
+            || self.remember_secrets != defaults.remember_secrets
+            || self.verification != defaults.verification
+            || self.wipe_device != defaults.wipe_device
+            || self.require_ac_power != defaults.require_ac_power
+            || self.eject_finished != defaults.eject_finished
+    }
+
+    /// Puts the tool options of the menu back to their defaults, leaving the
+    /// image settings as they are.
+    pub fn reset_tool_options(&mut self) {
+        let defaults = Self::default();
+        self.telemetry = defaults.telemetry;
+        self.remember_secrets = defaults.remember_secrets;
+        self.verification = defaults.verification;
+        self.wipe_device = defaults.wipe_device;
+        self.require_ac_power = defaults.require_ac_power;
+        self.eject_finished = defaults.eject_finished;
+    }
+
+    /// Whether anything has to be written onto the card after the image.
+    pub fn needs_customization(&self) -> bool {
+        // Check if any option is non-default (empty means cleared, see clear_section)
+        let differs = |value: &str, default: &str| !value.is_empty() && value != default;
+        differs(&self.hostname, "raspberrypi")
+            || self.ssh_enabled
+            || !self.wifi_ssid.is_empty()
+            || self.user_name != "pi"
+            || self.password.is_some()
+            || differs(&self.timezone, "Europe/London")
+            || differs(&self.keyboard_layout, "gb")
+            || !self.keyboard_variant.is_empty()
+            || self.locale != "en_GB.UTF-8"
+            || self.eth0.is_set()
+            || self.wlan0.is_set()
+            || !self.extra_files.is_empty()
+    }
+
+    /// The firstrun.sh that Raspberry Pi OS runs on its first boot to apply
+    /// the image settings. Fails if the password can't be hashed or a
+    /// static address isn't valid.
+    pub fn generate_firstrun_script(&self) -> anyhow::Result<String> {
+        for (interface, ip) in [("eth0", &self.eth0), ("wlan0", &self.wlan0)] {
+            ip.validate()
+                .map_err(|e| anyhow::anyhow!("Static address of {}: {}", interface, e))?;
+        }
+        let mut script = String::from("#!/bin/bash\n");
+
+        // Better safety (disable for some commands that might fail harmlessly)
+        script.push_str("set +e\n");
+
+        // 1. Hostname
+        if !self.hostname.is_empty() {
+            script.push_str(&format!(
+                "CURRENT_HOSTNAME=$(cat /etc/hostname | tr -d \" \\t\\n\\r\")\n\
+                 if [ -f /usr/lib/raspberrypi-sys-mods/imager_custom ]; then\n\
+                     /usr/lib/raspberrypi-sys-mods/imager_custom set_hostname {}\n\
+                 else\n\
+                     echo {} > /etc/hostname\n\
+                     sed -i \"s/127.0.1.1.*$CURRENT_HOSTNAME/127.0.1.1\\t{}/g\" /etc/hosts\n\
+                 fi\n",
+                shell_escape(&self.hostname),
+                shell_escape(&self.hostname),
+                self.hostname
+            ));
+        }
+
+        // Determine first user (uid 1000) and home
+        script.push_str("FIRSTUSER=$(getent passwd 1000 | cut -d: -f1)\n");
+        script.push_str("FIRSTUSERHOME=$(getent passwd 1000 | cut -d: -f6)\n");
+
+        // 2. SSH
+        if self.ssh_enabled {
+            if !self.ssh_public_keys.is_empty() {
+                script.push_str("if [ -f /usr/lib/raspberrypi-sys-mods/imager_custom ]; then\n");
+                script.push_str(&format!(
+                    "   /usr/lib/raspberrypi-sys-mods/imager_custom enable_ssh -k '{}'\n",
+                    self.ssh_public_keys
+                ));
+                script.push_str("else\n");
+                script.push_str("   install -o \"$FIRSTUSER\" -m 700 -d \"$FIRSTUSERHOME/.ssh\"\n");
+                script.push_str("   cat > \"$FIRSTUSERHOME/.ssh/authorized_keys\" <<'EOF'\n");
+                script.push_str(&self.ssh_public_keys);
+                script.push_str("\nEOF\n");
+                script.push_str(
+                    "   chown \"$FIRSTUSER:$FIRSTUSER\" \"$FIRSTUSERHOME/.ssh/authorized_keys\"\n",
+                );
+                script.push_str("   chmod 600 \"$FIRSTUSERHOME/.ssh/authorized_keys\"\n");
+
+                if !self.ssh_password_auth {
+                    script.push_str("   echo 'PasswordAuthentication no' >>/etc/ssh/sshd_config\n");
+                }
+
+                script.push_str("   systemctl enable ssh\n");
+                script.push_str("fi\n");
+            } else if self.ssh_password_auth {
+                script.push_str("if [ -f /usr/lib/raspberrypi-sys-mods/imager_custom ]; then\n");
+                script.push_str("   /usr/lib/raspberrypi-sys-mods/imager_custom enable_ssh\n");
+                script.push_str("else\n");
+                script.push_str("   systemctl enable ssh\n");
+                script.push_str("fi\n");
+            }
+        }
+
+        // 3. User Account
+
+        let user = &self.user_name;
+
+        let pwd = self.password.as_deref().unwrap_or("");
+
+        if !user.is_empty() && !pwd.is_empty() {
+            let pwd_hash = hash_password(pwd)?;
+
+            script.push_str("if [ -f /usr/lib/userconf-pi/userconf ]; then\n");
+
+            script.push_str(&format!(
+                "   /usr/lib/userconf-pi/userconf {} {}\n",
+                shell_escape(user),
+                shell_escape(&pwd_hash)
+            ));
+
+            script.push_str("else\n");
+
+            // Legacy/Manual fallback
+
+            script.push_str(&format!(
+                "   echo \"$FIRSTUSER:{}\" | chpasswd -e\n",
+                shell_escape(&pwd_hash)
+            ));
+
+            script.push_str(&format!("   if [ \"$FIRSTUSER\" != \"{}\" ]; then\n", user));
+
+            script.push_str(&format!("      usermod -l \"{}\" \"$FIRSTUSER\"\n", user));
+            script.push_str(&format!(
+                "      usermod -m -d \"/home/{}\" \"{}\"\n",
+                user, user
+            ));
+            script.push_str(&format!("      groupmod -n \"{}\" \"$FIRSTUSER\"\n", user));
+
+            // Fix autologin and sudoers
+            script.push_str(
+                "      if grep -q \"^autologin-user=\" /etc/lightdm/lightdm.conf ; then\n",
+            );
+            script.push_str(&format!(
+                "         sed /etc/lightdm/lightdm.conf -i -e \"s/^autologin-user=.*/autologin-user={}/\"\n",
+                user
+            ));
+            script.push_str("      fi\n");
+
+            script.push_str(
+                "      if [ -f /etc/systemd/system/getty@tty1.service.d/autologin.conf ]; then\n",
+            );
+            script.push_str(&format!(
+                "         sed /etc/systemd/system/getty@tty1.service.d/autologin.conf -i -e \"s/$FIRSTUSER/{}/\"\n",
+                user
+            ));
+            script.push_str("      fi\n");
+
+            script.push_str("      if [ -f /etc/sudoers.d/010_pi-nopasswd ]; then\n");
+            script.push_str(&format!(
+                "         sed -i \"s/^$FIRSTUSER /{} /\" /etc/sudoers.d/010_pi-nopasswd\n",
+                user
+            ));
+            script.push_str("      fi\n");
+            script.push_str("   fi\n");
+            script.push_str("fi\n");
+        }
+
+        // 4. WiFi
+        if !self.wifi_ssid.is_empty() {
+            let scan_ssid = if self.wifi_hidden { "scan_ssid=1" } else { "" };
+
+            script.push_str("if [ -f /usr/lib/raspberrypi-sys-mods/imager_custom ]; then\n");
+            let hidden_flag = if self.wifi_hidden { "-h" } else { "" };
+            script.push_str(&format!(
+                "   /usr/lib/raspberrypi-sys-mods/imager_custom set_wlan {} {} {} {}\n",
+                hidden_flag,
+                shell_escape(&self.wifi_ssid),
+                shell_escape(&self.wifi_password),
+                shell_escape(&self.wifi_country)
+            ));
+            script.push_str("else\n");
+
+            script.push_str("cat >/etc/wpa_supplicant/wpa_supplicant.conf <<'WPAEOF'\n");
+            if !self.wifi_country.is_empty() {
+                script.push_str(&format!("country={}\n", self.wifi_country));
+            }
+            script.push_str("ctrl_interface=DIR=/var/run/wpa_supplicant GROUP=netdev\n");
+            script.push_str("update_config=1\n");
+            script.push_str("network={\n");
+            script.push_str(&format!("    ssid=\"{}\"\n", self.wifi_ssid)); // Simple quoting for now
+            script.push_str(&format!("    psk=\"{}\"\n", self.wifi_password));
+            script.push_str(&format!("    {}\n", scan_ssid));
+            script.push_str("}\n");
+            script.push_str("WPAEOF\n");
+
+            script.push_str("   chmod 600 /etc/wpa_supplicant/wpa_supplicant.conf\n");
+            script.push_str("   rfkill unblock wifi || true\n");
+            script.push_str("   for filename in /var/lib/systemd/rfkill/*:wlan ; do\n");
+            script.push_str("       echo 0 > $filename\n");
+            script.push_str("   done\n");
+            script.push_str("fi\n");
+        } else if !self.wifi_country.is_empty() {
+            script.push_str("rfkill unblock wifi || true\n");
+            script.push_str("for filename in /var/lib/systemd/rfkill/*:wlan ; do\n");
+            script.push_str("  echo 0 > $filename\n");
+            script.push_str("done\n");
+        }
+
+        // 5. Static addresses. NetworkManager isn't running yet, so its
+        // keyfiles are written directly: a new one for eth0, and the Wi-Fi
+        // connection imager_custom wrote above gets its address groups
+        // replaced for wlan0. Older releases configure both in dhcpcd.conf.
+        if self.eth0.is_set() || self.wlan0.is_set() {
+            script.push_str(
+                "if command -v nmcli >/dev/null && [ -d /etc/NetworkManager/system-connections ]; then\n",
+            );
+            if self.eth0.is_set() {
+                script
+                    .push_str("   CONN=/etc/NetworkManager/system-connections/eth0.nmconnection\n");
+                script.push_str("cat >\"$CONN\" <<'NMEOF'\n");
+                script.push_str("[connection]\nid=eth0\ntype=ethernet\ninterface-name=eth0\n");
+                script.push_str(&self.eth0.keyfile_groups());
+                script.push_str("NMEOF\n");
+                script.push_str("   chmod 600 \"$CONN\"\n");
+            }
+            if self.wlan0.is_set() {
+                script.push_str(
+                    "   CONN=/etc/NetworkManager/system-connections/preconfigured.nmconnection\n",
+                );
+                script.push_str("   if [ -f \"$CONN\" ]; then\n");
+                script.push_str(
+                    "      awk '/^\\[/ { skip = ($0 == \"[ipv4]\" || $0 == \"[ipv6]\") } !skip' \"$CONN\" >\"$CONN.new\"\n",
+                );
+                script.push_str("cat >>\"$CONN.new\" <<'NMEOF'\n");
+                script.push_str(&self.wlan0.keyfile_groups());
+                script.push_str("NMEOF\n");
+                script.push_str("      mv \"$CONN.new\" \"$CONN\"\n");
+                script.push_str("      chmod 600 \"$CONN\"\n");
+                script.push_str("   fi\n");
+            }
+            script.push_str("else\n");
+            script.push_str("cat >>/etc/dhcpcd.conf <<'DHCPCDEOF'\n");
+            for (interface, ip) in [("eth0", &self.eth0), ("wlan0", &self.wlan0)] {
+                if ip.is_set() {
+                    script.push('\n');
+                    script.push_str(&ip.dhcpcd_profile(interface));
+                }
+            }
+            script.push_str("DHCPCDEOF\n");
+            script.push_str("fi\n");
+        }
+
+        // 6. Locale / Timezone / Keyboard
+        if !self.keyboard_layout.is_empty() || !self.timezone.is_empty() || !self.locale.is_empty()
+        {
+            // imager_custom only takes a layout, so variants always go through
+            // /etc/default/keyboard
+            let mut keyboard_file = String::new();
+            keyboard_file.push_str("cat >/etc/default/keyboard <<'KBEOF'\n");
+            keyboard_file.push_str("XKBMODEL=\"pc105\"\n");
+            keyboard_file.push_str(&format!("XKBLAYOUT=\"{}\"\n", self.keyboard_layout));
+            keyboard_file.push_str(&format!("XKBVARIANT=\"{}\"\n", self.keyboard_variant));
+            keyboard_file.push_str("XKBOPTIONS=\"\"\n");
+            keyboard_file.push('\n');
+            keyboard_file.push_str("KBEOF\n");
+            keyboard_file
+                .push_str("   dpkg-reconfigure -f noninteractive keyboard-configuration\n");
+            script.push_str("if [ -f /usr/lib/raspberrypi-sys-mods/imager_custom ]; then\n");
+            if !self.keyboard_layout.is_empty() && !self.keyboard_variant.is_empty() {
+                script.push_str(&keyboard_file);
+            } else if !self.keyboard_layout.is_empty() {
+                script.push_str(&format!(
+                    "   /usr/lib/raspberrypi-sys-mods/imager_custom set_keymap {}\n",
+                    shell_escape(&self.keyboard_layout)
+                ));
+            }
+            if !self.timezone.is_empty() {
+                script.push_str(&format!(
+                    "   /usr/lib/raspberrypi-sys-mods/imager_custom set_timezone {}\n",
+                    shell_escape(&self.timezone)
+                ));
+            }
+            script.push_str("else\n");
+
+            // Fallback
+            if !self.timezone.is_empty() {
+                script.push_str("   rm -f /etc/localtime\n");
+                script.push_str(&format!("   echo \"{}\" >/etc/timezone\n", self.timezone));
+                script.push_str("   dpkg-reconfigure -f noninteractive tzdata\n");
+            }
+
+            if !self.keyboard_layout.is_empty() {
+                script.push_str(&keyboard_file);
+            }
+
+            // Locale generation (from previous implementation, compatible)
+            if self.locale != "en_GB.UTF-8" {
+                script.push_str(&format!(
+                    "sed -i 's/^# *{} /{} /' /etc/locale.gen\n",
+                    regex_escape(&self.locale),
+                    self.locale
+                ));
+                script.push_str("locale-gen\n");
+                script.push_str(&format!("update-locale LANG={}\n", self.locale));
+            }
+
+            script.push_str("fi\n");
+        }
+
+        // Cleanup
+        script.push_str("rm -f /boot/firstrun.sh\n");
+        script.push_str("sed -i 's| systemd.run.*||g' /boot/cmdline.txt\n");
+        script.push_str("exit 0\n");
+
+        Ok(script)
+    }
+}
+
+static SAVING_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// The config file that didn't parse when loaded, and why.
+static CONFIG_ERROR: Mutex<Option<(PathBuf, String)>> = Mutex::new(None);
+
+/// Why the config couldn't be loaded, if it couldn't. The defaults are used
+/// then, and the file is kept as it is, so a typo made by hand doesn't cost
+/// the rest of it.
+pub fn config_error() -> Option<String> {
+    CONFIG_ERROR
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|(_, error)| error.clone())
+}
+
+/// Keeps the config and the other files of the user's settings from being
+/// written for the rest of the process. The interface does so when it runs
+/// as root after restarting itself elevated: sudo may keep HOME, and the
+/// files would end up owned by root.
+pub fn disable_saving() {
+    SAVING_DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether [`disable_saving`] was called.
+pub fn saving_disabled() -> bool {
+    SAVING_DISABLED.load(Ordering::Relaxed)
+}
+
+/// Whether the process runs as root.
+#[cfg(unix)]
+pub fn is_root() -> bool {
+    nix::unistd::Uid::effective().is_root()
+}
+
+/// Only unix has a root user.
+#[cfg(not(unix))]
+pub fn is_root() -> bool {
+    false
+}
+
+/// Validators return the message to show under an invalid entry.
+///
+/// A single DNS label, as the Pi announces itself by it.
+pub fn validate_hostname(value: &str) -> Result<(), String> {
+    let valid = !value.is_empty()
+        && value.len() <= 63
+        && !value.starts_with('-')
+        && !value.ends_with('-')
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err("Use 1-63 letters, digits or '-', not starting or ending with '-'".to_string())
+    }
+}
+
+/// A Linux user name, other than root.
+pub fn validate_username(value: &str) -> Result<(), String> {
+    let valid = value.len() <= 32
+        && value.starts_with(|c: char| c.is_ascii_lowercase())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if value == "root" {
+        Err("'root' cannot be used".to_string())
+    } else if valid {
+        Ok(())
+    } else {
+        Err("Use lowercase letters, digits, '-' or '_', starting with a letter".to_string())
+    }
+}
+
+/// An ISO 3166 country code, which sets the allowed Wi-Fi channels.
+pub fn validate_wifi_country(value: &str) -> Result<(), String> {
+    let value = value.trim();
+    if value.len() == 2 && value.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(())
+    } else {
+        Err("Use a two-letter country code, e.g. GB".to_string())
+    }
+}
+
+/// OpenSSH public keys, one per line.
+pub fn validate_ssh_keys(value: &str) -> Result<(), String> {
+    let invalid = value
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .any(|l| !["ssh-", "ecdsa-", "sk-"].iter().any(|p| l.starts_with(p)));
+    if invalid {
+        Err("Not an OpenSSH public key".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// An IPv4 address with its prefix length, or empty.
+pub fn validate_ipv4_address(value: &str) -> Result<(), String> {
+    validate_address::<std::net::Ipv4Addr>(value, 32, "192.168.1.20/24")
+}
+
+/// An IPv6 address with its prefix length, or empty.
+pub fn validate_ipv6_address(value: &str) -> Result<(), String> {
+    validate_address::<std::net::Ipv6Addr>(value, 128, "2001:db8::20/64")
+}
+
+/// An address with its prefix length; empty for none.
+fn validate_address<T: std::str::FromStr>(
+    value: &str,
+    max_prefix: u8,
+    example: &str,
+) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    let valid = value.split_once('/').is_some_and(|(address, prefix)| {
+        address.parse::<T>().is_ok() && prefix.parse::<u8>().is_ok_and(|p| p <= max_prefix)
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Use an address with its prefix length, e.g. {}",
+            example
+        ))
+    }
+}
+
+/// An IPv4 address, or empty.
+pub fn validate_ipv4_gateway(value: &str) -> Result<(), String> {
+    if value.is_empty() || value.parse::<std::net::Ipv4Addr>().is_ok() {
+        Ok(())
+    } else {
+        Err("Not an IPv4 address".to_string())
+    }
+}
+
+/// An IPv6 address, or empty.
+pub fn validate_ipv6_gateway(value: &str) -> Result<(), String> {
+    if value.is_empty() || value.parse::<std::net::Ipv6Addr>().is_ok() {
+        Ok(())
+    } else {
+        Err("Not an IPv6 address".to_string())
+    }
+}
+
+/// IP addresses of either family, separated by commas or spaces.
+pub fn validate_dns_servers(value: &str) -> Result<(), String> {
+    let valid = value
+        .split([',', ' '])
+        .filter(|s| !s.is_empty())
+        .all(|s| s.parse::<std::net::IpAddr>().is_ok());
+    if valid {
+        Ok(())
+    } else {
+        Err("Use IP addresses separated by commas, e.g. 1.1.1.1, 2606:4700::1111".to_string())
+    }
+}
+
+pub(crate) fn shell_escape(s: &str) -> String {
+    s.replace("\"", "\\\"").replace("$", "\\$")
+}
+
+fn regex_escape(s: &str) -> String {
+    s.replace(".", "\\.")
+}
+
+/// SHA-512 crypt ("$6$..."), the only form userconf-pi, chpasswd -e,
+/// cloud-init and custom.toml are given, so the plain password never
+/// reaches the card. A failure is an error rather than an empty hash, which
+/// would leave the account without a password.
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    pwhash::sha512_crypt::hash(password)
+        .map_err(|e| anyhow::anyhow!("Failed to hash the password: {}", e))
+}
+
+/// The public keys of the user running the tool, from ~/.ssh, to offer
+/// for the card.
+pub fn discover_ssh_keys() -> Vec<String> {
+    let mut keys = Vec::new();
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    let ssh_dir = std::path::Path::new(&home).join(".ssh");
+
+    // 1. Scan for .pub files
+    let pattern = ssh_dir.join("*.pub");
+    if let Some(pattern_str) = pattern.to_str()
+        && let Ok(paths) = glob(pattern_str)
+    {
+        for entry in paths.filter_map(Result::ok) {
+            if let Ok(content) = std::fs::read_to_string(&entry) {
+                keys.push(content.trim().to_string());
+            }
+        }
+    }
+
+    // 2. Read authorized_keys
+    let auth_keys = ssh_dir.join("authorized_keys");
+    if let Ok(file) = std::fs::File::open(auth_keys) {
+        let reader = std::io::BufReader::new(file);
+        for line in reader.lines().map_while(Result::ok) {
+            let trimmed = line.trim();
+            if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                keys.push(trimmed.to_string());
+            }
+        }
+    }
+
+    // Deduplicate
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_a_config_that_does_not_parse() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let saved = CustomizationOptions {
+            hostname: "kiosk".to_string(),
+            ..CustomizationOptions::default()
+        };
+        saved.save_to(&path);
+        assert_eq!(CustomizationOptions::load_from(&path).hostname, "kiosk");
+
+        let typo = std::fs::read_to_string(&path)
+            .unwrap()
+            .replace("hostname = ", "hostname ");
+        std::fs::write(&path, &typo).unwrap();
+        let loaded = CustomizationOptions::load_from(&path);
+        assert_eq!(loaded.hostname, CustomizationOptions::default().hostname);
+        assert!(config_error().unwrap().contains("config.toml is not valid"));
+
+        loaded.save_to(&path);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), typo);
+    }
+
+    #[test]
+    fn undoes_and_redoes_edits_only() {
+        let mut options = CustomizationOptions::default();
+        let mut history = History::default();
+        history.observe(&options, false);
+        // Not an edit, e.g. passwords decrypted after unlocking
+        options.password = Some("secret".to_string());
+        history.observe(&options, false);
+
+        options.wifi_password = "long and precious".to_string();
+        history.observe(&options, true);
+        options.wifi_password = "oops".to_string();
+        history.observe(&options, true);
+
+        assert!(history.undo(&mut options));
+        assert_eq!(options.wifi_password, "long and precious");
+        assert!(history.redo(&mut options));
+        assert_eq!(options.wifi_password, "oops");
+        assert!(!history.redo(&mut options));
+
+        assert!(history.undo(&mut options));
+        assert!(history.undo(&mut options));
+        assert!(options.wifi_password.is_empty());
+        assert_eq!(options.password.as_deref(), Some("secret"));
+        assert!(!history.undo(&mut options));
+    }
+
+    #[test]
+    fn saves_as_toml_without_secrets_unless_remembered() {
+        let mut options = CustomizationOptions {
+            password: Some("secret".to_string()),
+            wifi_ssid: "lab".to_string(),
+            wifi_password: "long and precious".to_string(),
+            download_connections: Some(4),
+            os_sources: vec![crate::os_list::OsSource {
+                name: "Lab images".to_string(),
+                url: "https://example.com/os_list.json".to_string(),
+            }],
+            extra_files: vec![ExtraFile {
+                source: "/srv/lab/motd".to_string(),
+                destination: "motd".to_string(),
+            }],
+            ..CustomizationOptions::default()
+        };
+
+        let saved = CustomizationOptions::from_toml(&options.to_toml().unwrap()).unwrap();
+        assert_eq!(saved.password, None);
+        assert!(saved.wifi_password.is_empty());
+        assert_eq!(saved.extra_files, options.extra_files);
+
+        options.remember_secrets = true;
+        let saved = CustomizationOptions::from_toml(&options.to_toml().unwrap()).unwrap();
+        assert_eq!(saved, options);
+    }
+
+    #[test]
+    fn configures_static_addresses_for_both_network_stacks() {
+        let options = CustomizationOptions {
+            eth0: StaticIp {
+                address: "192.168.1.20/24".to_string(),
+                gateway: "192.168.1.1".to_string(),
+                address6: "2001:db8::20/64".to_string(),
+                gateway6: "2001:db8::1".to_string(),
+                dns: "1.1.1.1, 2606:4700::1111".to_string(),
+            },
+            ..CustomizationOptions::default()
+        };
+        assert!(options.needs_customization());
+        assert!(options.section_modified(CustomizationSection::Network));
+
+        let script = options.generate_firstrun_script().unwrap();
+        assert!(script.contains(
+            "[connection]\nid=eth0\ntype=ethernet\ninterface-name=eth0\n\n\
+             [ipv4]\nmethod=manual\naddress1=192.168.1.20/24,192.168.1.1\ndns=1.1.1.1;\n\n\
+             [ipv6]\nmethod=manual\naddress1=2001:db8::20/64,2001:db8::1\n\
+             dns=2606:4700::1111;\nNMEOF\n"
+        ));
+        assert!(script.contains(
+            "interface eth0\nstatic ip_address=192.168.1.20/24\nstatic routers=192.168.1.1\n\
+             static ip6_address=2001:db8::20/64\n\
+             static domain_name_servers=1.1.1.1 2606:4700::1111\nDHCPCDEOF\n"
+        ));
+        assert!(!script.contains("wlan0"));
+
+        assert!(validate_ipv4_address("192.168.1.20").is_err());
+        assert!(validate_ipv4_address("192.168.1.20/33").is_err());
+        assert!(validate_ipv6_address("2001:db8::20/64").is_ok());
+        assert!(validate_dns_servers("1.1.1.1 8.8.8.8").is_ok());
+        assert!(validate_dns_servers("one.one.one.one").is_err());
+
+        let mut injected = options.clone();
+        injected.eth0.dns = "1.1.1.1\nNMEOF\nreboot".to_string();
+        assert!(injected.generate_firstrun_script().is_err());
+    }
+}
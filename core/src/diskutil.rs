@@ -0,0 +1,145 @@
+//! Parsing of `diskutil list -plist physical` and `diskutil info -plist`
+//! output, the macOS counterpart of [`crate::lsblk`].
+//!
+//! The list only names the physical disks and their partitions; size, bus
+//! and removability come from `info` on each whole disk. The boot volume
+//! lives in a synthesized APFS container, so the physical disk holding it is
+//! found through the physical stores `info /` reports.
+
+// The backend only runs on macOS, the parsing is tested everywhere
+#![cfg_attr(not(target_os = "macos"), allow(dead_code))]
+
+use serde::Deserialize;
+
+/// `diskutil list -plist`: every disk with its partitions.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DiskList {
+    pub all_disks_and_partitions: Vec<ListedDisk>,
+}
+
+/// A whole disk as `diskutil list` reports it.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ListedDisk {
+    pub device_identifier: String,
+    /// Set when a file system spans the whole disk, without partitions.
+    #[serde(default)]
+    pub mount_point: Option<String>,
+    #[serde(default)]
+    pub volume_name: Option<String>,
+    #[serde(default)]
+    pub partitions: Vec<Partition>,
+}
+
+/// A partition of a [`ListedDisk`].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Partition {
+    #[serde(default)]
+    pub mount_point: Option<String>,
+    #[serde(default)]
+    pub volume_name: Option<String>,
+}
+
+/// `diskutil info -plist DISK`: what the drive list shows about a disk.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DiskInfo {
+    pub device_identifier: String,
+    pub device_node: String,
+    #[serde(default)]
+    pub media_name: Option<String>,
+    #[serde(default)]
+    pub total_size: u64,
+    #[serde(default)]
+    pub removable_media: bool,
+    #[serde(default)]
+    pub ejectable: bool,
+    #[serde(default = "writable")]
+    pub writable_media: bool,
+    #[serde(default)]
+    pub internal: bool,
+    #[serde(default)]
+    pub solid_state: bool,
+    #[serde(default)]
+    pub bus_protocol: Option<String>,
+    #[serde(default)]
+    pub parent_whole_disk: Option<String>,
+    #[serde(default, rename = "APFSPhysicalStores")]
+    pub apfs_physical_stores: Vec<PhysicalStore>,
+}
+
+/// A disk an APFS container lives on.
+#[derive(Debug, Deserialize)]
+pub struct PhysicalStore {
+    #[serde(rename = "APFSPhysicalStore")]
+    pub identifier: String,
+}
+
+fn writable() -> bool {
+    true
+}
+
+impl ListedDisk {
+    /// Mount points of the disk itself and all of its partitions.
+    pub fn mountpoints(&self) -> Vec<String> {
+        self.mount_point
+            .iter()
+            .chain(
+                self.partitions
+                    .iter()
+                    .filter_map(|p| p.mount_point.as_ref()),
+            )
+            .filter(|mp| !mp.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// The first volume name, like lsblk's LABEL.
+    pub fn label(&self) -> Option<&str> {
+        self.volume_name
+            .iter()
+            .chain(
+                self.partitions
+                    .iter()
+                    .filter_map(|p| p.volume_name.as_ref()),
+            )
+            .map(|name| name.trim())
+            .find(|name| !name.is_empty())
+    }
+}
+
+impl DiskInfo {
+    /// The whole disks the volume described by this info lives on: the
+    /// physical stores of an APFS container, or the parent disk otherwise.
+    pub fn whole_disks(&self) -> Vec<String> {
+        if self.apfs_physical_stores.is_empty() {
+            self.parent_whole_disk.iter().cloned().collect()
+        } else {
+            self.apfs_physical_stores
+                .iter()
+                .map(|store| whole_disk(&store.identifier).to_string())
+                .collect()
+        }
+    }
+}
+
+/// "disk0" for the partition "disk0s2".
+pub fn whole_disk(identifier: &str) -> &str {
+    let digits = identifier.trim_start_matches("disk");
+    match digits.find('s') {
+        Some(at) => &identifier[..identifier.len() - digits.len() + at],
+        None => identifier,
+    }
+}
+
+/// Parses the output of `diskutil list -plist`.
+pub fn parse_list(plist: &[u8]) -> Result<DiskList, plist::Error> {
+    plist::from_bytes(plist)
+}
+
+/// Parses the output of `diskutil info -plist`.
+pub fn parse_info(plist: &[u8]) -> Result<DiskInfo, plist::Error> {
+    plist::from_bytes(plist)
+}
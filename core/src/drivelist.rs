@@ -0,0 +1,450 @@
+use crate::diskutil::{self, DiskInfo, DiskList};
+use crate::lsblk::{self, LsblkDevice};
+use crate::windisk::{self, Disks};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Lists a fake card backed by a file besides the real drives, to try
+/// writing without one.
+static FAKE_DRIVE: AtomicBool = AtomicBool::new(false);
+
+/// Lists the fake card along with the drives from now on.
+pub fn set_fake_drive(enabled: bool) {
+    FAKE_DRIVE.store(enabled, Ordering::Relaxed);
+}
+
+/// A drive an image can be written to, as [`get_drives`] lists it.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Drive {
+    pub name: String,          // e.g., /dev/sda
+    pub description: String,   // e.g., "Samsung SSD 860 (500 GB)"
+    pub model: Option<String>, // Reader or drive model, for write speed history
+    pub size: u64,
+    pub removable: bool,
+    pub readonly: bool,
+    pub mountpoints: Vec<String>,
+    pub serial: Option<String>,
+    pub profile: TargetProfile,
+}
+
+/// How a target should be treated while writing. SSDs (e.g. Pi 5 NVMe boot
+/// drives) are large and fast, so they get bigger buffers and extra cleanup.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetProfile {
+    #[default]
+    SdCard,
+    Ssd,
+}
+
+impl TargetProfile {
+    /// How much is written to the target at once.
+    pub fn buffer_size(&self) -> usize {
+        match self {
+            Self::SdCard => 4 * 1024 * 1024,
+            Self::Ssd => 16 * 1024 * 1024,
+        }
+    }
+
+    /// The name the worker is given on its command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SdCard => "sd",
+            Self::Ssd => "ssd",
+        }
+    }
+
+    /// The profile named by [`Self::as_str`]; an SD card for anything else.
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "ssd" => Self::Ssd,
+            _ => Self::SdCard,
+        }
+    }
+}
+
+impl Drive {
+    /// Whether the host runs from this drive. The backends mark such drives
+    /// with "/" among their mountpoints; they are never offered for writing.
+    pub fn is_system(&self) -> bool {
+        // Heuristic: if it contains root mountpoint "/", it is likely the system drive.
+        self.mountpoints.iter().any(|mp| mp == "/")
+    }
+}
+
+/// Where the attached drives are listed from, one per platform.
+trait Backend {
+    fn drives(&self) -> Result<Vec<Drive>, Box<dyn Error>>;
+}
+
+/// `lsblk`, on Linux.
+#[cfg_attr(any(target_os = "macos", windows), allow(dead_code))]
+struct Lsblk;
+
+/// `diskutil`, on macOS.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+struct Diskutil;
+
+/// `Get-Disk` through PowerShell, on Windows.
+#[cfg_attr(not(windows), allow(dead_code))]
+struct PowerShell;
+
+#[cfg(target_os = "macos")]
+fn backend() -> impl Backend {
+    Diskutil
+}
+
+#[cfg(windows)]
+fn backend() -> impl Backend {
+    PowerShell
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn backend() -> impl Backend {
+    Lsblk
+}
+
+impl Backend for Lsblk {
+    fn drives(&self) -> Result<Vec<Drive>, Box<dyn Error>> {
+        let output = Command::new("lsblk")
+            .args([
+                "-J",
+                "-b",
+                "-o",
+                "NAME,SIZE,MODEL,TYPE,MOUNTPOINT,LABEL,RM,RO,SERIAL,TRAN,ROTA",
+            ])
+            .output()?;
+
+        if !output.status.success() {
+            return Err(
+                format!("lsblk failed: {}", String::from_utf8_lossy(&output.stderr)).into(),
+            );
+        }
+
+        let output_str = String::from_utf8(output.stdout)?;
+        Ok(drives_from(lsblk::parse(&output_str)?))
+    }
+}
+
+impl Backend for Diskutil {
+    fn drives(&self) -> Result<Vec<Drive>, Box<dyn Error>> {
+        let run = |args: &[&str]| -> Result<Vec<u8>, Box<dyn Error>> {
+            let output = Command::new("diskutil").args(args).output()?;
+            if !output.status.success() {
+                return Err(format!(
+                    "diskutil {} failed: {}",
+                    args[0],
+                    String::from_utf8_lossy(&output.stderr)
+                )
+                .into());
+            }
+            Ok(output.stdout)
+        };
+
+        let list = diskutil::parse_list(&run(&["list", "-plist", "physical"])?)?;
+        let infos = list
+            .all_disks_and_partitions
+            .iter()
+            .map(|disk| {
+                let info = run(&["info", "-plist", &disk.device_identifier])?;
+                Ok(diskutil::parse_info(&info)?)
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        // Without it nothing is marked as the system drive, so it must load
+        let root = diskutil::parse_info(&run(&["info", "-plist", "/"])?)?;
+        Ok(drives_from_diskutil(&list, &infos, &root))
+    }
+}
+
+impl Backend for PowerShell {
+    fn drives(&self) -> Result<Vec<Drive>, Box<dyn Error>> {
+        let output = Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", windisk::SCRIPT])
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "Get-Disk failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        let output_str = String::from_utf8(output.stdout)?;
+        Ok(drives_from_windows(&windisk::parse(&output_str)?))
+    }
+}
+
+/// Every drive on the host, system drives included, through lsblk,
+/// diskutil or PowerShell.
+pub fn get_drives() -> Result<Vec<Drive>, Box<dyn Error>> {
+    let debug = FAKE_DRIVE.load(Ordering::Relaxed);
+
+    let mut drives = backend().drives()?;
+
+    if debug {
+        let fake_path = "fake_sd_card.img";
+        if !std::path::Path::new(fake_path).exists() {
+            let f = std::fs::File::create(fake_path)?;
+            f.set_len(4 * 1024 * 1024 * 1024)?; // 4 GB
+        }
+        crate::workspace::track_file(fake_path);
+
+        drives.push(Drive {
+            name: fake_path.to_string(),
+            description: "Fake SD Card (Debug)".to_string(),
+            model: None,
+            size: 4 * 1024 * 1024 * 1024,
+            removable: true,
+            readonly: false,
+            mountpoints: vec![],
+            serial: None,
+            profile: TargetProfile::SdCard,
+        });
+    }
+
+    Ok(drives)
+}
+
+/// Turns parsed lsblk devices into selectable drives.
+fn drives_from(devices: Vec<LsblkDevice>) -> Vec<Drive> {
+    devices
+        .into_iter()
+        // We only care about physical disks, not partitions or loop devices at the top level
+        .filter(|device| device.device_type == "disk")
+        .map(|device| {
+            let model = device.model.as_deref().unwrap_or("Unknown");
+
+            // Create a friendly description
+            let description = if let Some(lbl) = &device.label {
+                format!("{} - {} ({})", model, lbl, format_size(device.size))
+            } else {
+                format!("{} ({})", model, format_size(device.size))
+            };
+
+            Drive {
+                name: format!("/dev/{}", device.name),
+                description,
+                model: device.model.clone(),
+                size: device.size,
+                removable: device.rm,
+                readonly: device.ro,
+                mountpoints: device.all_mountpoints(),
+                serial: device.serial.clone(),
+                profile: detect_profile(&device),
+            }
+        })
+        .collect()
+}
+
+/// Turns the physical disks diskutil lists into selectable drives. The disk
+/// holding `root`, the info of "/", gets "/" as a mountpoint so it is
+/// treated as the system drive like on Linux.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn drives_from_diskutil(list: &DiskList, infos: &[DiskInfo], root: &DiskInfo) -> Vec<Drive> {
+    let system = root.whole_disks();
+    list.all_disks_and_partitions
+        .iter()
+        .filter_map(|disk| {
+            let info = infos
+                .iter()
+                .find(|i| i.device_identifier == disk.device_identifier)?;
+            let model = info.media_name.as_deref().unwrap_or("Unknown");
+            let description = match disk.label() {
+                Some(label) => format!("{} - {} ({})", model, label, format_size(info.total_size)),
+                None => format!("{} ({})", model, format_size(info.total_size)),
+            };
+            let mut mountpoints = disk.mountpoints();
+            if system.contains(&disk.device_identifier) {
+                mountpoints.insert(0, "/".to_string());
+            }
+            Some(Drive {
+                name: info.device_node.clone(),
+                description,
+                model: info.media_name.clone(),
+                size: info.total_size,
+                // Built-in SD slots are internal but have removable media
+                removable: info.removable_media || info.ejectable || !info.internal,
+                readonly: !info.writable_media,
+                mountpoints,
+                serial: None,
+                profile: diskutil_profile(info),
+            })
+        })
+        .collect()
+}
+
+/// Turns the disks Windows reports into selectable drives. The boot and
+/// system disks get "/" as a mountpoint so they are treated as the system
+/// drive like on Linux.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn drives_from_windows(disks: &Disks) -> Vec<Drive> {
+    disks
+        .disks
+        .iter()
+        .map(|disk| {
+            let model = disk.friendly_name.as_deref().unwrap_or("Unknown");
+            let mut mountpoints = disks.mountpoints(disk);
+            if disk.is_boot || disk.is_system {
+                mountpoints.insert(0, "/".to_string());
+            }
+            Drive {
+                name: disk.path(),
+                description: format!("{} ({})", model, format_size(disk.size)),
+                model: disk.friendly_name.clone(),
+                size: disk.size,
+                removable: disk.is_removable(),
+                readonly: disk.is_read_only,
+                mountpoints,
+                serial: disk
+                    .serial_number
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string),
+                profile: if disk.bus_type == "NVMe" {
+                    TargetProfile::Ssd
+                } else {
+                    TargetProfile::SdCard
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn diskutil_profile(info: &DiskInfo) -> TargetProfile {
+    let bus = info.bus_protocol.as_deref().unwrap_or("");
+    let large = info.total_size >= 100 * 1024 * 1024 * 1024;
+    if bus == "PCI-Express" || (info.solid_state && large && (bus == "USB" || bus == "SATA")) {
+        TargetProfile::Ssd
+    } else {
+        TargetProfile::SdCard
+    }
+}
+
+fn detect_profile(device: &LsblkDevice) -> TargetProfile {
+    let transport = device.tran.as_deref().unwrap_or("");
+    // SD card readers also report "usb", so only treat large non-rotational
+    // USB/SATA disks as SSDs.
+    let large = device.size >= 100 * 1024 * 1024 * 1024;
+    if transport == "nvme" || (!device.rota && large && (transport == "usb" || transport == "sata"))
+    {
+        TargetProfile::Ssd
+    } else {
+        TargetProfile::SdCard
+    }
+}
+
+/// `bytes` in binary units for people, e.g. "29.7 GB".
+pub fn format_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+    const TB: u64 = GB * 1024;
+
+    if bytes >= TB {
+        format!("{:.2} TB", bytes as f64 / TB as f64)
+    } else if bytes >= GB {
+        format!("{:.2} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.0} MB", bytes as f64 / MB as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One line per drive, compared against `<fixture>.golden`.
+    fn summarize(json: &str) -> String {
+        let devices = lsblk::parse(json).expect("fixture should parse");
+        summarize_drives(&drives_from(devices))
+    }
+
+    fn summarize_drives(drives: &[Drive]) -> String {
+        drives
+            .iter()
+            .map(|d| {
+                format!(
+                    "{} | {} | size={} rm={} ro={} | {} | serial={} | mounts={:?}\n",
+                    d.name,
+                    d.description,
+                    d.size,
+                    d.removable,
+                    d.readonly,
+                    d.profile.as_str(),
+                    d.serial.as_deref().unwrap_or("-"),
+                    d.mountpoints,
+                )
+            })
+            .collect()
+    }
+
+    macro_rules! golden {
+        ($name:ident, $file:literal) => {
+            #[test]
+            fn $name() {
+                let json = include_str!(concat!("../tests/fixtures/lsblk/", $file, ".json"));
+                let expected = include_str!(concat!("../tests/fixtures/lsblk/", $file, ".golden"));
+                assert_eq!(summarize(json), expected);
+            }
+        };
+    }
+
+    golden!(util_linux_2_31_strings, "util-linux-2.31");
+    golden!(util_linux_2_34_nested_lvm, "util-linux-2.34");
+    golden!(util_linux_2_38_booleans, "util-linux-2.38");
+    golden!(util_linux_2_39_mountpoints, "util-linux-2.39-mountpoints");
+    golden!(numeric_flags, "numeric-flags");
+
+    #[test]
+    fn diskutil_marks_the_disk_holding_the_boot_volume() {
+        let info = |plist: &[u8]| diskutil::parse_info(plist).expect("fixture should parse");
+        let list = diskutil::parse_list(include_bytes!("../tests/fixtures/diskutil/list.plist"))
+            .expect("fixture should parse");
+        let infos = [
+            info(include_bytes!("../tests/fixtures/diskutil/disk0.plist")),
+            info(include_bytes!("../tests/fixtures/diskutil/disk4.plist")),
+        ];
+        let root = info(include_bytes!("../tests/fixtures/diskutil/root.plist"));
+
+        let drives = drives_from_diskutil(&list, &infos, &root);
+
+        assert_eq!(
+            summarize_drives(&drives),
+            include_str!("../tests/fixtures/diskutil/macos-14.golden")
+        );
+        assert!(drives[0].is_system() && !drives[1].is_system());
+        assert_eq!(diskutil::whole_disk("disk12s3"), "disk12");
+        assert_eq!(diskutil::whole_disk("disk4"), "disk4");
+    }
+
+    #[test]
+    fn windows_disks_from_both_powershell_versions() {
+        for fixture in [
+            include_str!("../tests/fixtures/windisk/powershell-5.json"),
+            include_str!("../tests/fixtures/windisk/powershell-7.json"),
+        ] {
+            let disks = windisk::parse(fixture).expect("fixture should parse");
+            assert_eq!(
+                summarize_drives(&drives_from_windows(&disks)),
+                include_str!("../tests/fixtures/windisk/windows-11.golden")
+            );
+        }
+        assert!(windisk::is_physical_drive(r"\\.\PHYSICALDRIVE2"));
+        assert!(!windisk::is_physical_drive("/dev/sda"));
+    }
+
+    #[test]
+    fn rejects_non_lsblk_json() {
+        assert!(lsblk::parse(r#"{"devices": []}"#).is_err());
+        assert!(
+            lsblk::parse(r#"{"blockdevices": [{"name": "sda", "size": "big", "type": "disk"}]}"#)
+                .is_err()
+        );
+    }
+}
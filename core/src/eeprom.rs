@@ -0,0 +1,118 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Subset of the EEPROM boot.conf settings exposed in the editor. Defaults
+/// match the firmware defaults, so an untouched config leaves the image as-is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BootloaderConfig {
+    pub boot_order: String,
+    pub power_off_on_halt: bool,
+    pub boot_uart: bool,
+    pub wake_on_gpio: bool,
+    pub net_install_enabled: bool,
+}
+
+impl Default for BootloaderConfig {
+    fn default() -> Self {
+        Self {
+            boot_order: "0xf41".to_string(),
+            power_off_on_halt: false,
+            boot_uart: false,
+            wake_on_gpio: true,
+            net_install_enabled: true,
+        }
+    }
+}
+
+impl BootloaderConfig {
+    /// Whether nothing was changed, so the image's own settings are kept.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// BOOT_ORDER is a hex number of boot modes, read right to left.
+    pub fn is_valid_boot_order(value: &str) -> bool {
+        value.strip_prefix("0x").is_some_and(|digits| {
+            !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit())
+        })
+    }
+
+    /// [`Self::is_valid_boot_order`] for the text input.
+    pub fn validate_boot_order(value: &str) -> Result<(), String> {
+        if Self::is_valid_boot_order(value.trim()) {
+            Ok(())
+        } else {
+            Err("Expected a hex value such as 0xf41".to_string())
+        }
+    }
+
+    /// The settings as a boot.conf for the recovery image.
+    pub fn to_boot_conf(&self) -> String {
+        let flag = |b: bool| if b { 1 } else { 0 };
+        format!(
+            "[all]\nBOOT_UART={}\nPOWER_OFF_ON_HALT={}\nWAKE_ON_GPIO={}\nNET_INSTALL_ENABLED={}\nBOOT_ORDER={}\n",
+            flag(self.boot_uart),
+            flag(self.power_off_on_halt),
+            flag(self.wake_on_gpio),
+            flag(self.net_install_enabled),
+            self.boot_order
+        )
+    }
+
+    /// Embeds this config into the pieeprom.bin of a mounted recovery image and
+    /// regenerates pieeprom.sig, as described in the rpi-eeprom documentation.
+    pub fn apply(&self, boot: &Path) -> Result<()> {
+        let eeprom = boot.join("pieeprom.bin");
+        if !eeprom.exists() {
+            return Err(anyhow!(
+                "pieeprom.bin not found; is this a bootloader image?"
+            ));
+        }
+
+        let conf_path = boot.join("boot.conf");
+        fs::write(&conf_path, self.to_boot_conf()).context("Failed to write boot.conf")?;
+
+        let updated = boot.join("pieeprom.upd");
+        let status = Command::new("rpi-eeprom-config")
+            .arg("--config")
+            .arg(&conf_path)
+            .arg("--out")
+            .arg(&updated)
+            .arg(&eeprom)
+            .status()
+            .map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    anyhow!("rpi-eeprom-config not found. Install the rpi-eeprom package to customize bootloader images.")
+                } else {
+                    anyhow!(e).context("Failed to run rpi-eeprom-config")
+                }
+            })?;
+        if !status.success() {
+            return Err(anyhow!(
+                "rpi-eeprom-config failed. Exit code: {:?}",
+                status.code()
+            ));
+        }
+        fs::rename(&updated, &eeprom).context("Failed to replace pieeprom.bin")?;
+
+        let bytes = fs::read(&eeprom).context("Failed to read pieeprom.bin")?;
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let sig = format!("{}\nts: {}\n", hex::encode(Sha256::digest(&bytes)), ts);
+        fs::write(boot.join("pieeprom.sig"), sig).context("Failed to write pieeprom.sig")?;
+
+        Ok(())
+    }
+}
+
+/// Bootloader recovery images are published as rpi-boot-eeprom-recovery-*.
+pub fn is_bootloader_image(url: &str) -> bool {
+    url.contains("boot-eeprom")
+}
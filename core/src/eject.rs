@@ -0,0 +1,82 @@
+//! Making a written card safe to pull out. Desktops mount the fresh
+//! partitions as soon as the write ends, so they are unmounted first; then
+//! the drive is powered off through udisks2 where it runs, which also spins
+//! down the reader, or ejected otherwise.
+
+use anyhow::{Context, Result, bail};
+use std::process::Command;
+
+/// Unmounts the partitions of `device` and ejects it.
+#[cfg(not(any(target_os = "macos", windows)))]
+pub fn eject(device: &str) -> Result<()> {
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    crate::in_use::unmount(device, &mountpoints(&mounts, device))?;
+    if crate::udisks::available() {
+        return crate::udisks::power_off(device);
+    }
+    run(Command::new("eject").arg(device))
+}
+
+/// Ejects the disk and its volumes.
+#[cfg(target_os = "macos")]
+pub fn eject(device: &str) -> Result<()> {
+    run(Command::new("diskutil").args(["eject", device]))
+}
+
+/// Not implemented; Windows users eject from the tray.
+#[cfg(windows)]
+pub fn eject(_device: &str) -> Result<()> {
+    bail!("Ejecting isn't supported on Windows yet; use \"Safely Remove Hardware\"")
+}
+
+#[cfg_attr(windows, allow(dead_code))]
+fn run(command: &mut Command) -> Result<()> {
+    let output = command
+        .output()
+        .context("Failed to run the eject command")?;
+    if !output.status.success() {
+        bail!(
+            "Ejecting failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Where the partitions of `device` are mounted according to /proc/mounts.
+#[cfg_attr(any(target_os = "macos", windows), allow(dead_code))]
+fn mountpoints(mounts: &str, device: &str) -> Vec<String> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (source, mountpoint) = (fields.next()?, fields.next()?);
+            let partition = source.strip_prefix(device)?;
+            // /dev/sdb1 or /dev/mmcblk0p1, but not /dev/sdba1
+            partition
+                .trim_start_matches('p')
+                .chars()
+                .all(|c| c.is_ascii_digit())
+                .then(|| mountpoint.replace("\\040", " "))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_mounted_partitions_of_the_card() {
+        let mounts = "/dev/sda2 / ext4 rw 0 0\n\
+                      /dev/sdb1 /media/pi/bootfs vfat rw 0 0\n\
+                      /dev/sdb2 /media/pi/root\\040fs ext4 rw 0 0\n\
+                      /dev/sdba1 /media/pi/other vfat rw 0 0\n\
+                      /dev/mmcblk0p1 /boot/firmware vfat rw 0 0\n";
+        assert_eq!(
+            mountpoints(mounts, "/dev/sdb"),
+            ["/media/pi/bootfs", "/media/pi/root fs"]
+        );
+        assert_eq!(mountpoints(mounts, "/dev/mmcblk0"), ["/boot/firmware"]);
+    }
+}
@@ -0,0 +1,149 @@
+//! What a write failed at. The writer tags its errors with an [`ErrorKind`]
+//! where they happen, the worker sends the kind along with the message, and
+//! the interface offers what helps with that kind of failure: writing again
+//! after a dropped download or a bad read-back, another card when this one
+//! can't be opened.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The stage of the write an error comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ErrorKind {
+    /// The image couldn't be fetched or opened, or its checksum is wrong.
+    Download,
+    /// The image isn't readable: a damaged archive or compressed stream.
+    Decompress,
+    /// The device is missing, in use, or can't be opened for writing.
+    DeviceOpen,
+    DeviceWrite,
+    /// What was read back from the device differs from what was written.
+    Verify,
+    Customize,
+    Cancelled,
+    /// The copy of the image next to the write couldn't be created or written.
+    TeeFile,
+    /// Anything that wasn't tagged.
+    Other,
+}
+
+impl ErrorKind {
+    /// The kind `error` was tagged with, through any context added later.
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|e| {
+                // Readers can only fail with io::Error, so tags travel inside
+                e.downcast_ref::<Tagged>().or_else(|| {
+                    e.downcast_ref::<std::io::Error>()?
+                        .get_ref()?
+                        .downcast_ref::<Tagged>()
+                })
+            })
+            .map_or(Self::Other, |tagged| tagged.kind)
+    }
+
+    /// Whether writing again with the same selections may succeed.
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            Self::Download | Self::Decompress | Self::DeviceWrite | Self::Verify
+        )
+    }
+
+    /// What to tell the user below the error message.
+    pub fn hint(self) -> Option<&'static str> {
+        Some(match self {
+            Self::Download => "The download failed. Press 'r' to try again.",
+            Self::Decompress => {
+                "The image may be damaged. Press 'r' to download and write it again."
+            }
+            Self::DeviceOpen => {
+                "Check that the device is connected and not in use, or choose another one."
+            }
+            Self::DeviceWrite => {
+                "The device may have been removed or be failing. Press 'r' to write it again."
+            }
+            Self::Verify => {
+                "The device didn't read back what was written and may be failing. Press 'r' \
+                 to write it again, or try another one."
+            }
+            Self::Customize => "The image was written, but its settings couldn't be applied.",
+            Self::TeeFile => {
+                "The card wasn't finished because the copy of the image couldn't be saved. \
+                 Check its path and the free space there."
+            }
+            Self::Cancelled | Self::Other => return None,
+        })
+    }
+
+    /// `error` as an io::Error that [`Self::of`] still finds the kind in,
+    /// for failures inside a reader.
+    pub fn io(self, error: impl Into<anyhow::Error>) -> std::io::Error {
+        std::io::Error::other(Tagged {
+            kind: self,
+            error: error.into(),
+        })
+    }
+}
+
+/// An error and its kind; shows as the error alone.
+#[derive(Debug)]
+struct Tagged {
+    kind: ErrorKind,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Tagged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for Tagged {}
+
+/// Tags errors of a `Result` with their [`ErrorKind`].
+pub trait Tag<T> {
+    /// Tags the error with `kind`, unless it already has one: the first tag
+    /// is the closest to where it happened.
+    fn tag(self, kind: ErrorKind) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> Tag<T> for Result<T, E> {
+    fn tag(self, kind: ErrorKind) -> anyhow::Result<T> {
+        self.map_err(|e| {
+            let error = e.into();
+            if ErrorKind::of(&error) == ErrorKind::Other {
+                anyhow::Error::new(Tagged { kind, error })
+            } else {
+                error
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, anyhow};
+
+    #[test]
+    fn keeps_the_first_tag_through_context() {
+        let download: anyhow::Result<()> = Err(anyhow!("connection reset"));
+        let err = download
+            .tag(ErrorKind::Download)
+            .context("Failed to read/decompress image stream")
+            .tag(ErrorKind::Decompress)
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Download);
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to read/decompress image stream: connection reset"
+        );
+
+        let read: std::io::Result<()> = Err(ErrorKind::Verify.io(anyhow!("EOF")));
+        let err = read.context("Failed to read").unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Verify);
+        assert_eq!(ErrorKind::of(&anyhow!("untagged")), ErrorKind::Other);
+    }
+}
@@ -0,0 +1,187 @@
+//! The boot partition is FAT, which takes less than the Linux tools writing
+//! to it: some characters can't be in a name, a full partition leaves files
+//! cut short, and scripts edited on Windows keep CRLF line endings that the
+//! shell running them on first boot chokes on. These checks run before
+//! anything is written, so a customization fails with the reason instead of
+//! leaving a card that doesn't boot as configured.
+
+use anyhow::{Result, bail};
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Characters FAT doesn't allow in names; control characters neither.
+const RESERVED: &[char] = &['"', '*', ':', '<', '>', '?', '\\', '|'];
+
+/// Extensions of the files the first boot reads as text.
+const TEXT_EXTENSIONS: [&str; 10] = [
+    "sh",
+    "txt",
+    "conf",
+    "cfg",
+    "toml",
+    "yaml",
+    "yml",
+    "json",
+    "service",
+    "nmconnection",
+];
+
+/// Text files known by name, without an extension.
+const TEXT_NAMES: [&str; 3] = ["user-data", "meta-data", "network-config"];
+
+/// Space left after everything but the extra files is written: the
+/// generated files are a few KiB, but FAT takes at least a cluster for each.
+pub const CUSTOMIZATION_RESERVE: u64 = 256 * 1024;
+
+/// Fails if a component of `destination` can't be a FAT name.
+pub fn check_name(destination: &str) -> Result<()> {
+    for name in destination.split('/').filter(|name| !name.is_empty()) {
+        if let Some(c) = name
+            .chars()
+            .find(|c| RESERVED.contains(c) || c.is_control())
+        {
+            bail!(
+                "{} can't be on the boot partition: FAT names can't contain {:?}",
+                destination,
+                c
+            );
+        }
+        if name.ends_with(['.', ' ']) && name != "." && name != ".." {
+            bail!(
+                "{} can't be on the boot partition: FAT drops dots and spaces at the end of names",
+                destination
+            );
+        }
+        if name.encode_utf16().count() > 255 {
+            bail!(
+                "{} can't be on the boot partition: the name is too long for FAT",
+                destination
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Whether the first boot reads the file at `path` as text.
+pub fn is_text(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| TEXT_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        || path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| TEXT_NAMES.contains(&n))
+}
+
+/// `contents` with CRLF line endings turned into LF. Anything that isn't
+/// UTF-8 is left as it is.
+pub fn normalize_newlines(contents: &[u8]) -> Cow<'_, [u8]> {
+    match std::str::from_utf8(contents) {
+        Ok(text) if text.contains("\r\n") => Cow::Owned(text.replace("\r\n", "\n").into_bytes()),
+        _ => Cow::Borrowed(contents),
+    }
+}
+
+/// Fails unless the filesystem of `dir` has room for files of `sizes`, each
+/// rounded up to whole blocks, plus `reserve` bytes. Passes if the free
+/// space can't be told.
+pub fn ensure_space(dir: &Path, sizes: impl IntoIterator<Item = u64>, reserve: u64) -> Result<()> {
+    let Some((free, block)) = free_space(dir) else {
+        return Ok(());
+    };
+    let needed = sizes
+        .into_iter()
+        .map(|size| size.div_ceil(block) * block)
+        .sum::<u64>()
+        + reserve;
+    if needed > free {
+        bail!(
+            "The boot partition has {} free, but the customization needs {}",
+            crate::drivelist::format_size(free),
+            crate::drivelist::format_size(needed)
+        );
+    }
+    Ok(())
+}
+
+/// The free bytes of the filesystem of `dir` and its block size.
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<(u64, u64)> {
+    let stat = nix::sys::statvfs::statvfs(dir).ok()?;
+    let block = stat.fragment_size().max(1);
+    Some((stat.blocks_available() * block, block))
+}
+
+/// The free bytes of the volume of `dir` and its cluster size.
+#[cfg(windows)]
+fn free_space(dir: &Path) -> Option<(u64, u64)> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            available: *mut u64,
+            total: *mut u64,
+            free: *mut u64,
+        ) -> i32;
+        fn GetVolumePathNameW(file: *const u16, volume: *mut u16, length: u32) -> i32;
+        fn GetDiskFreeSpaceW(
+            root: *const u16,
+            sectors_per_cluster: *mut u32,
+            bytes_per_sector: *mut u32,
+            free_clusters: *mut u32,
+            total_clusters: *mut u32,
+        ) -> i32;
+    }
+
+    let dir: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut root = [0u16; 261];
+    let (mut available, mut sectors, mut bytes, mut free, mut total) = (0u64, 0u32, 0u32, 0, 0);
+    // SAFETY: the paths are NUL-terminated, `root` is as long as passed and
+    // the out-pointers are valid for the calls
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            dir.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        ) != 0
+            && GetVolumePathNameW(dir.as_ptr(), root.as_mut_ptr(), root.len() as u32) != 0
+            && GetDiskFreeSpaceW(
+                root.as_ptr(),
+                &mut sectors,
+                &mut bytes,
+                &mut free,
+                &mut total,
+            ) != 0
+    };
+    ok.then(|| (available, (u64::from(sectors) * u64::from(bytes)).max(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_against_fat_quirks() {
+        assert!(check_name("provisioning/token.txt").is_ok());
+        for name in ["wifi:home.txt", "notes/todo?", "firstrun.sh.", "setup /x"] {
+            assert!(check_name(name).is_err(), "{}", name);
+        }
+
+        assert_eq!(
+            normalize_newlines(b"#!/bin/bash\r\necho hi\r\n").as_ref(),
+            b"#!/bin/bash\necho hi\n"
+        );
+        let binary = [0xff, b'\r', b'\n'];
+        assert_eq!(normalize_newlines(&binary).as_ref(), binary);
+        assert!(is_text(Path::new("scripts/Setup.SH")));
+        assert!(is_text(Path::new("user-data")));
+        assert!(!is_text(Path::new("overlays/custom.dtbo")));
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(ensure_space(dir.path(), [1024], 0).is_ok());
+        assert!(ensure_space(dir.path(), [u64::MAX / 2], 0).is_err());
+    }
+}
@@ -0,0 +1,769 @@
+//! Customization backends. Which files configure an image on its first boot
+//! depends on the image: firstrun.sh run by systemd from cmdline.txt,
+//! cloud-init's user-data and network-config, the custom.toml read by the
+//! raspberrypi-sys-mods firstboot script, Armbian's first-run files, or the
+//! few settings Home Assistant OS and LibreELEC take from the boot partition.
+
+use crate::customization::{CustomizationOptions, hash_password, shell_escape};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// One line of the checklist [`Backend::checks`] reports.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Check {
+    pub label: String,
+    pub passed: bool,
+}
+
+impl Check {
+    fn new(label: &str, passed: bool) -> Self {
+        Self {
+            label: label.to_string(),
+            passed,
+        }
+    }
+}
+
+/// The failed checks as an error message, if any failed.
+pub fn failures(checks: &[Check]) -> Option<String> {
+    let failed: Vec<&str> = checks
+        .iter()
+        .filter(|c| !c.passed)
+        .map(|c| c.label.as_str())
+        .collect();
+    (!failed.is_empty()).then(|| format!("Boot partition check failed: {}", failed.join(", ")))
+}
+
+/// How an image applies its first-boot settings, told from its
+/// `init_format` and what is on its boot partition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Firstrun,
+    CloudInit,
+    CustomToml,
+    Armbian,
+    HomeAssistant,
+    LibreElec,
+}
+
+impl Backend {
+    /// Picks the backend from the OS list's `init_format`. Images that don't
+    /// declare one (local files) or use an unknown one are recognized by
+    /// their boot partition.
+    pub fn select(init_format: Option<&str>, boot: &Path) -> Self {
+        match init_format {
+            Some("systemd") => Self::Firstrun,
+            Some("cloudinit" | "cloudinit-rpi") => Self::CloudInit,
+            Some("haos") => Self::HomeAssistant,
+            Some("libreelec") => Self::LibreElec,
+            _ => Self::detect(boot),
+        }
+    }
+
+    fn detect(boot: &Path) -> Self {
+        let cmdline = fs::read_to_string(boot.join("cmdline.txt")).unwrap_or_default();
+        if armbian_boot_dir(boot).is_some() {
+            Self::Armbian
+        } else if boot.join("KERNEL").exists() && boot.join("SYSTEM").exists() {
+            Self::LibreElec
+        } else if boot.join("user-data").exists() || boot.join("meta-data").exists() {
+            Self::CloudInit
+        } else if cmdline.contains("raspberrypi-sys-mods/firstboot") {
+            Self::CustomToml
+        } else {
+            Self::Firstrun
+        }
+    }
+
+    /// Writes the customization onto a mounted boot partition.
+    pub fn apply(self, boot: &Path, options: &CustomizationOptions) -> Result<()> {
+        match self {
+            Self::Firstrun => apply_firstrun(boot, options),
+            Self::CloudInit => {
+                fs::write(boot.join("user-data"), user_data(options)?)
+                    .context("Failed to write user-data")?;
+                let existing = fs::read_to_string(boot.join("meta-data")).unwrap_or_default();
+                fs::write(boot.join("meta-data"), meta_data(&existing, &random_uuid()))
+                    .context("Failed to write meta-data")?;
+                if let Some(config) = network_config(options) {
+                    fs::write(boot.join("network-config"), config)
+                        .context("Failed to write network-config")?;
+                }
+                Ok(())
+            }
+            Self::CustomToml => fs::write(boot.join("custom.toml"), custom_toml(options)?)
+                .context("Failed to write custom.toml"),
+            Self::Armbian => apply_armbian(boot, options),
+            Self::HomeAssistant => apply_home_assistant(boot, options),
+            Self::LibreElec => apply_libreelec(boot, options),
+        }
+    }
+
+    /// Checks that the files [`Self::apply`] wrote are where and what the
+    /// first boot expects, as far as that can be told from the outside.
+    pub fn checks(self, boot: &Path, options: &CustomizationOptions) -> Vec<Check> {
+        let read = |name: &str| fs::read_to_string(boot.join(name)).ok();
+        let yaml = |name: &str| {
+            read(name).is_some_and(|text| {
+                serde_yaml::from_str::<serde_yaml::Value>(&text).is_ok_and(|v| v.is_mapping())
+            })
+        };
+        let mut checks = Vec::new();
+        match self {
+            Self::Firstrun => {
+                let script = fs::metadata(boot.join("firstrun.sh"));
+                checks.push(Check::new("firstrun.sh exists", script.is_ok()));
+                checks.push(Check::new(
+                    "firstrun.sh is executable",
+                    script.is_ok_and(|m| executable(&m)),
+                ));
+                checks.push(Check::new(
+                    "cmdline.txt runs firstrun.sh",
+                    read("cmdline.txt")
+                        .is_some_and(|c| c.contains("systemd.run=/boot/firstrun.sh")),
+                ));
+            }
+            Self::CloudInit => {
+                checks.push(Check::new("user-data parses as YAML", yaml("user-data")));
+                checks.push(Check::new(
+                    "meta-data has an instance-id",
+                    read("meta-data").is_some_and(|m| m.contains("instance-id:")),
+                ));
+                if network_config(options).is_some() {
+                    checks.push(Check::new(
+                        "network-config parses as YAML",
+                        yaml("network-config"),
+                    ));
+                }
+            }
+            Self::CustomToml => checks.push(Check::new(
+                "custom.toml exists",
+                read("custom.toml").is_some_and(|t| t.starts_with("config_version")),
+            )),
+            Self::Armbian => {
+                let boot_dir = armbian_boot_dir(boot).unwrap_or_else(|| boot.to_path_buf());
+                checks.push(Check::new(
+                    "armbian_first_run.txt exists",
+                    boot_dir.join("armbian_first_run.txt").exists(),
+                ));
+                if boot.join("root").is_dir() {
+                    checks.push(Check::new(
+                        ".not_logged_in_yet exists",
+                        boot.join("root/.not_logged_in_yet").exists(),
+                    ));
+                }
+            }
+            Self::HomeAssistant => {
+                if !options.wifi_ssid.is_empty() {
+                    checks.push(Check::new(
+                        "CONFIG/network/my-network exists",
+                        boot.join("CONFIG/network/my-network").exists(),
+                    ));
+                }
+                if options.ssh_enabled && !public_keys(options).is_empty() {
+                    checks.push(Check::new(
+                        "CONFIG/authorized_keys exists",
+                        boot.join("CONFIG/authorized_keys").exists(),
+                    ));
+                }
+            }
+            Self::LibreElec => checks.push(Check::new(
+                "cmdline.txt has the expected ssh setting",
+                read("cmdline.txt").is_some_and(|c| {
+                    c.split_whitespace().any(|p| p == "ssh") == options.ssh_enabled
+                }),
+            )),
+        }
+        checks
+    }
+}
+
+#[cfg(unix)]
+fn executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+/// FAT has no executable bit; Linux mounts every file on it executable.
+#[cfg(not(unix))]
+fn executable(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+fn apply_firstrun(boot: &Path, options: &CustomizationOptions) -> Result<()> {
+    // 1. Write firstrun.sh
+    let script_content = options.generate_firstrun_script()?;
+    let script_path = boot.join("firstrun.sh");
+    fs::write(&script_path, script_content).context("Failed to write firstrun.sh")?;
+
+    // Make executable (chmod +x) - though FAT doesn't store permissions, it helps if it's ext4
+    let _ = Command::new("chmod")
+        .arg("+x")
+        .arg(script_path.to_str().unwrap())
+        .status();
+
+    // 2. Modify cmdline.txt
+    let cmdline_path = boot.join("cmdline.txt");
+    if cmdline_path.exists() {
+        let mut cmdline =
+            fs::read_to_string(&cmdline_path).context("Failed to read cmdline.txt")?;
+
+        // Remove old entries if any (sanity check)
+        cmdline = cmdline.replace(" systemd.run=/boot/firstrun.sh", "");
+        cmdline = cmdline.replace(" systemd.run_success_action=reboot", "");
+        cmdline = cmdline.replace(" systemd.unit=kernel-command-line.target", "");
+
+        // Append new ones
+        // Ensure we append to the single line, space separated
+        let trimmed = cmdline.trim();
+        let new_cmdline = format!(
+            "{} systemd.run=/boot/firstrun.sh systemd.run_success_action=reboot systemd.unit=kernel-command-line.target",
+            trimmed
+        );
+
+        fs::write(&cmdline_path, new_cmdline).context("Failed to update cmdline.txt")?;
+    } else {
+        // If cmdline.txt doesn't exist, this might not be RPi OS or partition structure is different.
+        // We warn but continue.
+        eprintln!("Warning: cmdline.txt not found in boot partition.");
+    }
+
+    Ok(())
+}
+
+/// Where Armbian keeps armbianEnv.txt: the mounted partition itself when the
+/// image has a separate boot partition, its /boot otherwise.
+fn armbian_boot_dir(boot: &Path) -> Option<std::path::PathBuf> {
+    [boot.to_path_buf(), boot.join("boot")]
+        .into_iter()
+        .find(|dir| {
+            dir.join("armbianEnv.txt").exists()
+                || dir.join("armbian_first_run.txt.template").exists()
+        })
+}
+
+/// armbian_first_run.txt configures the network on the first boot, and the
+/// presets in /root/.not_logged_in_yet answer the first-login prompts for
+/// the user, passwords and locale. The latter needs the root filesystem, so
+/// images with a separate boot partition only get the network settings.
+fn apply_armbian(boot: &Path, options: &CustomizationOptions) -> Result<()> {
+    let boot_dir = armbian_boot_dir(boot).unwrap_or_else(|| boot.to_path_buf());
+    fs::write(
+        boot_dir.join("armbian_first_run.txt"),
+        armbian_first_run(options),
+    )
+    .context("Failed to write armbian_first_run.txt")?;
+
+    let root = boot.join("root");
+    if !root.is_dir() {
+        eprintln!("Warning: root filesystem not found, only the network is customized.");
+        return Ok(());
+    }
+    fs::write(root.join(".not_logged_in_yet"), armbian_presets(options))
+        .context("Failed to write .not_logged_in_yet")?;
+
+    let keys = public_keys(options);
+    if options.ssh_enabled && !keys.is_empty() {
+        let ssh_dir = root.join(".ssh");
+        fs::create_dir_all(&ssh_dir).context("Failed to create /root/.ssh")?;
+        fs::write(ssh_dir.join("authorized_keys"), keys.join("\n") + "\n")
+            .context("Failed to write authorized_keys")?;
+    }
+    if options.ssh_enabled && !options.ssh_password_auth {
+        let conf_dir = boot.join("etc/ssh/sshd_config.d");
+        fs::create_dir_all(&conf_dir).context("Failed to create sshd_config.d")?;
+        fs::write(
+            conf_dir.join("rpi-imager.conf"),
+            "PasswordAuthentication no\n",
+        )
+        .context("Failed to write sshd_config.d/rpi-imager.conf")?;
+    }
+    Ok(())
+}
+
+fn armbian_first_run(options: &CustomizationOptions) -> String {
+    let wifi = !options.wifi_ssid.is_empty();
+    let mut text = String::from("FR_general_delete_this_file_after_completion=1\n");
+    text.push_str(&format!("FR_net_change_defaults={}\n", u8::from(wifi)));
+    if wifi {
+        text.push_str("FR_net_ethernet_enabled=1\n");
+        text.push_str("FR_net_wifi_enabled=1\n");
+        text.push_str(&format!(
+            "FR_net_wifi_ssid=\"{}\"\n",
+            shell_escape(&options.wifi_ssid)
+        ));
+        text.push_str(&format!(
+            "FR_net_wifi_key=\"{}\"\n",
+            shell_escape(&options.wifi_password)
+        ));
+        text.push_str(&format!(
+            "FR_net_wifi_countrycode=\"{}\"\n",
+            shell_escape(&options.wifi_country)
+        ));
+        text.push_str("FR_net_use_static=0\n");
+    }
+    text
+}
+
+/// Armbian's armbian-firstlogin sources this file; unset presets are still
+/// asked for interactively.
+fn armbian_presets(options: &CustomizationOptions) -> String {
+    let mut presets = Vec::new();
+    if !options.wifi_ssid.is_empty() {
+        presets.push(("PRESET_NET_CHANGE_DEFAULTS", "1".to_string()));
+        presets.push(("PRESET_NET_ETHERNET_ENABLED", "1".to_string()));
+        presets.push(("PRESET_NET_WIFI_ENABLED", "1".to_string()));
+        presets.push(("PRESET_NET_WIFI_SSID", options.wifi_ssid.clone()));
+        presets.push(("PRESET_NET_WIFI_KEY", options.wifi_password.clone()));
+        presets.push(("PRESET_NET_WIFI_COUNTRYCODE", options.wifi_country.clone()));
+        presets.push(("PRESET_NET_USE_STATIC", "0".to_string()));
+    }
+    if !options.locale.is_empty() || !options.timezone.is_empty() {
+        presets.push(("SET_LANG_BASED_ON_LOCATION", "n".to_string()));
+    }
+    if !options.locale.is_empty() {
+        presets.push(("PRESET_LOCALE", options.locale.clone()));
+    }
+    if !options.timezone.is_empty() {
+        presets.push(("PRESET_TIMEZONE", options.timezone.clone()));
+    }
+    if let Some(password) = options.password.as_deref().filter(|p| !p.is_empty()) {
+        // armbian-firstlogin requires a root password as well
+        presets.push(("PRESET_ROOT_PASSWORD", password.to_string()));
+        if !options.user_name.is_empty() {
+            presets.push(("PRESET_USER_NAME", options.user_name.clone()));
+            presets.push(("PRESET_USER_PASSWORD", password.to_string()));
+            presets.push(("PRESET_DEFAULT_REALNAME", options.user_name.clone()));
+            presets.push(("PRESET_USER_SHELL", "bash".to_string()));
+        }
+    }
+
+    presets
+        .into_iter()
+        .map(|(name, value)| format!("{}=\"{}\"\n", name, shell_escape(&value)))
+        .collect()
+}
+
+/// Home Assistant OS imports a CONFIG folder on the boot partition at
+/// startup: NetworkManager connections from network/, and authorized_keys,
+/// which opens the debug SSH server on port 22222.
+fn apply_home_assistant(boot: &Path, options: &CustomizationOptions) -> Result<()> {
+    let config = boot.join("CONFIG");
+    if !options.wifi_ssid.is_empty() {
+        let network = config.join("network");
+        fs::create_dir_all(&network).context("Failed to create CONFIG/network")?;
+        fs::write(
+            network.join("my-network"),
+            network_manager_connection(options),
+        )
+        .context("Failed to write CONFIG/network/my-network")?;
+    }
+    let keys = public_keys(options);
+    if options.ssh_enabled && !keys.is_empty() {
+        fs::create_dir_all(&config).context("Failed to create CONFIG")?;
+        fs::write(config.join("authorized_keys"), keys.join("\n") + "\n")
+            .context("Failed to write CONFIG/authorized_keys")?;
+    }
+    Ok(())
+}
+
+fn network_manager_connection(options: &CustomizationOptions) -> String {
+    let mut ini = String::from("[connection]\nid=my-network\n");
+    ini.push_str(&format!("uuid={}\n", random_uuid()));
+    ini.push_str("type=802-11-wireless\n\n[802-11-wireless]\nmode=infrastructure\n");
+    ini.push_str(&format!("ssid={}\n", options.wifi_ssid));
+    if options.wifi_hidden {
+        ini.push_str("hidden=true\n");
+    }
+    if !options.wifi_password.is_empty() {
+        ini.push_str("\n[802-11-wireless-security]\nauth-alg=open\nkey-mgmt=wpa-psk\n");
+        ini.push_str(&format!("psk={}\n", options.wifi_password));
+    }
+    ini.push_str("\n[ipv4]\nmethod=auto\n\n[ipv6]\naddr-gen-mode=stable-privacy\nmethod=auto\n");
+    ini
+}
+
+fn random_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// LibreELEC starts its SSH server when `ssh` is on the kernel command line.
+fn apply_libreelec(boot: &Path, options: &CustomizationOptions) -> Result<()> {
+    let cmdline_path = boot.join("cmdline.txt");
+    let cmdline = fs::read_to_string(&cmdline_path).context("Failed to read cmdline.txt")?;
+    let mut params: Vec<&str> = cmdline.split_whitespace().filter(|p| *p != "ssh").collect();
+    if options.ssh_enabled {
+        params.push("ssh");
+    }
+    fs::write(&cmdline_path, params.join(" ") + "\n").context("Failed to update cmdline.txt")
+}
+
+/// A double-quoted string, valid in both YAML and TOML.
+fn quote(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+fn public_keys(options: &CustomizationOptions) -> Vec<&str> {
+    options
+        .ssh_public_keys
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// cloud-init user-data. Empty (cleared) values are left out.
+fn user_data(options: &CustomizationOptions) -> Result<String> {
+    let mut yaml = String::from("#cloud-config\n");
+    if !options.hostname.is_empty() {
+        yaml.push_str(&format!("hostname: {}\n", quote(&options.hostname)));
+        yaml.push_str("manage_etc_hosts: true\n");
+    }
+    if !options.timezone.is_empty() {
+        yaml.push_str(&format!("timezone: {}\n", quote(&options.timezone)));
+    }
+    if !options.locale.is_empty() {
+        yaml.push_str(&format!("locale: {}\n", quote(&options.locale)));
+    }
+    if !options.keyboard_layout.is_empty() {
+        yaml.push_str("keyboard:\n  model: pc105\n");
+        yaml.push_str(&format!("  layout: {}\n", quote(&options.keyboard_layout)));
+        if !options.keyboard_variant.is_empty() {
+            yaml.push_str(&format!(
+                "  variant: {}\n",
+                quote(&options.keyboard_variant)
+            ));
+        }
+    }
+
+    if !options.user_name.is_empty() {
+        yaml.push_str("users:\n");
+        yaml.push_str(&format!("- name: {}\n", quote(&options.user_name)));
+        yaml.push_str("  groups: users,adm,dialout,audio,netdev,video,plugdev,cdrom,games,input,gpio,spi,i2c,render,sudo\n");
+        yaml.push_str("  shell: /bin/bash\n");
+        match options.password.as_deref().filter(|p| !p.is_empty()) {
+            Some(password) => {
+                yaml.push_str("  lock_passwd: false\n");
+                yaml.push_str(&format!("  passwd: {}\n", quote(&hash_password(password)?)));
+            }
+            None => yaml.push_str("  lock_passwd: true\n"),
+        }
+        let keys = public_keys(options);
+        if options.ssh_enabled && !keys.is_empty() {
+            yaml.push_str("  ssh_authorized_keys:\n");
+            for key in keys {
+                yaml.push_str(&format!("    - {}\n", quote(key)));
+            }
+        }
+        yaml.push_str("  sudo: ALL=(ALL) NOPASSWD:ALL\n");
+    }
+
+    if options.ssh_enabled {
+        yaml.push_str("enable_ssh: true\n");
+        yaml.push_str(&format!("ssh_pwauth: {}\n", options.ssh_password_auth));
+    }
+    Ok(yaml)
+}
+
+/// cloud-init meta-data: what the image shipped, with a new instance-id.
+/// The NoCloud datasource needs the file to exist, and cloud-init only runs
+/// user-data again for an instance it hasn't seen, so a card that already
+/// booted once picks up the new settings too.
+fn meta_data(existing: &str, instance_id: &str) -> String {
+    let mut yaml: String = existing
+        .lines()
+        .filter(|line| !line.starts_with("instance-id:") && !line.starts_with("instance_id:"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    yaml.push_str(&format!("instance-id: rpi-imager-{}\n", instance_id));
+    yaml
+}
+
+/// cloud-init network-config (netplan v2), only needed for Wi-Fi and static
+/// addresses. The wlan0 address is that of the Wi-Fi network, so it is
+/// left out without one.
+fn network_config(options: &CustomizationOptions) -> Option<String> {
+    if options.wifi_ssid.is_empty() && !options.eth0.is_set() {
+        return None;
+    }
+    let mut yaml = String::from("network:\n  version: 2\n");
+    if options.eth0.is_set() {
+        yaml.push_str("  ethernets:\n    eth0:\n");
+        yaml.push_str(&format!(
+            "      dhcp4: {}\n      optional: true\n",
+            options.eth0.address.is_empty()
+        ));
+        yaml.push_str(&options.eth0.netplan("      "));
+    }
+    if options.wifi_ssid.is_empty() {
+        return Some(yaml);
+    }
+    yaml.push_str("  wifis:\n    renderer: networkd\n");
+    yaml.push_str(&format!(
+        "    wlan0:\n      dhcp4: {}\n      optional: true\n",
+        options.wlan0.address.is_empty()
+    ));
+    yaml.push_str(&options.wlan0.netplan("      "));
+    if !options.wifi_country.is_empty() {
+        yaml.push_str(&format!(
+            "      regulatory-domain: {}\n",
+            quote(&options.wifi_country)
+        ));
+    }
+    yaml.push_str("      access-points:\n");
+    yaml.push_str(&format!("        {}:\n", quote(&options.wifi_ssid)));
+    yaml.push_str(&format!(
+        "          password: {}\n",
+        quote(&options.wifi_password)
+    ));
+    if options.wifi_hidden {
+        yaml.push_str("          hidden: true\n");
+    }
+    Some(yaml)
+}
+
+/// custom.toml as read by raspberrypi-sys-mods' init_config.
+fn custom_toml(options: &CustomizationOptions) -> Result<String> {
+    let mut toml = String::from("config_version = 1\n");
+    if !options.hostname.is_empty() {
+        toml.push_str(&format!(
+            "\n[system]\nhostname = {}\n",
+            quote(&options.hostname)
+        ));
+    }
+
+    if let Some(password) = options.password.as_deref().filter(|p| !p.is_empty())
+        && !options.user_name.is_empty()
+    {
+        toml.push_str("\n[user]\n");
+        toml.push_str(&format!("name = {}\n", quote(&options.user_name)));
+        toml.push_str(&format!(
+            "password = {}\n",
+            quote(&hash_password(password)?)
+        ));
+        toml.push_str("password_encrypted = true\n");
+    }
+
+    if options.ssh_enabled {
+        toml.push_str("\n[ssh]\nenabled = true\n");
+        toml.push_str(&format!(
+            "password_authentication = {}\n",
+            options.ssh_password_auth
+        ));
+        let keys: Vec<String> = public_keys(options).into_iter().map(quote).collect();
+        if !keys.is_empty() {
+            toml.push_str(&format!("authorized_keys = [ {} ]\n", keys.join(", ")));
+        }
+    }
+
+    if !options.wifi_ssid.is_empty() {
+        toml.push_str("\n[wlan]\n");
+        toml.push_str(&format!("ssid = {}\n", quote(&options.wifi_ssid)));
+        toml.push_str(&format!("password = {}\n", quote(&options.wifi_password)));
+        toml.push_str("password_encrypted = false\n");
+        toml.push_str(&format!("hidden = {}\n", options.wifi_hidden));
+        if !options.wifi_country.is_empty() {
+            toml.push_str(&format!("country = {}\n", quote(&options.wifi_country)));
+        }
+    }
+
+    if !options.keyboard_layout.is_empty() || !options.timezone.is_empty() {
+        toml.push_str("\n[locale]\n");
+        if !options.keyboard_layout.is_empty() {
+            toml.push_str(&format!("keymap = {}\n", quote(&options.keyboard_layout)));
+        }
+        if !options.timezone.is_empty() {
+            toml.push_str(&format!("timezone = {}\n", quote(&options.timezone)));
+        }
+    }
+    Ok(toml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> CustomizationOptions {
+        CustomizationOptions {
+            hostname: "lab-pi".to_string(),
+            timezone: "Europe/Berlin".to_string(),
+            keyboard_layout: "de".to_string(),
+            user_name: "alice".to_string(),
+            password: Some("secret".to_string()),
+            ssh_enabled: true,
+            ssh_password_auth: false,
+            ssh_public_keys: "ssh-ed25519 AAAA alice@laptop".to_string(),
+            wifi_ssid: "lab \"5G\"".to_string(),
+            wifi_password: "hunter2".to_string(),
+            wifi_country: "DE".to_string(),
+            ..CustomizationOptions::default()
+        }
+    }
+
+    #[test]
+    fn selects_backend_from_init_format_then_boot_files() {
+        let boot = tempfile::tempdir().unwrap();
+        fs::write(
+            boot.path().join("cmdline.txt"),
+            "console=tty1 init=/usr/lib/raspberrypi-sys-mods/firstboot",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Backend::select(Some("systemd"), boot.path()),
+            Backend::Firstrun
+        );
+        assert_eq!(
+            Backend::select(Some("cloudinit-rpi"), boot.path()),
+            Backend::CloudInit
+        );
+        assert_eq!(Backend::select(None, boot.path()), Backend::CustomToml);
+
+        fs::write(boot.path().join("meta-data"), "").unwrap();
+        assert_eq!(Backend::select(None, boot.path()), Backend::CloudInit);
+
+        fs::create_dir(boot.path().join("boot")).unwrap();
+        fs::write(boot.path().join("boot/armbianEnv.txt"), "verbosity=1").unwrap();
+        assert_eq!(Backend::select(None, boot.path()), Backend::Armbian);
+    }
+
+    #[test]
+    fn home_assistant_and_libreelec_take_network_and_ssh() {
+        let boot = tempfile::tempdir().unwrap();
+        Backend::select(Some("haos"), boot.path())
+            .apply(boot.path(), &options())
+            .unwrap();
+
+        let connection = fs::read_to_string(boot.path().join("CONFIG/network/my-network")).unwrap();
+        assert!(connection.contains("ssid=lab \"5G\"\n"), "{}", connection);
+        assert!(connection.contains("psk=hunter2\n"), "{}", connection);
+        let keys = fs::read_to_string(boot.path().join("CONFIG/authorized_keys")).unwrap();
+        assert_eq!(keys, "ssh-ed25519 AAAA alice@laptop\n");
+
+        fs::write(boot.path().join("cmdline.txt"), "boot=UUID=1234 quiet\n").unwrap();
+        Backend::LibreElec.apply(boot.path(), &options()).unwrap();
+        Backend::LibreElec.apply(boot.path(), &options()).unwrap();
+        let cmdline = fs::read_to_string(boot.path().join("cmdline.txt")).unwrap();
+        assert_eq!(cmdline, "boot=UUID=1234 quiet ssh\n");
+    }
+
+    #[test]
+    fn armbian_presets_go_to_the_root_filesystem() {
+        let rootfs = tempfile::tempdir().unwrap();
+        fs::create_dir_all(rootfs.path().join("boot")).unwrap();
+        fs::create_dir_all(rootfs.path().join("root")).unwrap();
+        fs::write(rootfs.path().join("boot/armbianEnv.txt"), "").unwrap();
+
+        Backend::Armbian.apply(rootfs.path(), &options()).unwrap();
+
+        let first_run =
+            fs::read_to_string(rootfs.path().join("boot/armbian_first_run.txt")).unwrap();
+        assert!(
+            first_run.contains("FR_net_wifi_ssid=\"lab \\\"5G\\\"\"\n"),
+            "{}",
+            first_run
+        );
+        let presets = fs::read_to_string(rootfs.path().join("root/.not_logged_in_yet")).unwrap();
+        assert!(
+            presets.contains("PRESET_USER_NAME=\"alice\"\n"),
+            "{}",
+            presets
+        );
+        assert!(
+            presets.contains("PRESET_TIMEZONE=\"Europe/Berlin\"\n"),
+            "{}",
+            presets
+        );
+        let keys = fs::read_to_string(rootfs.path().join("root/.ssh/authorized_keys")).unwrap();
+        assert_eq!(keys, "ssh-ed25519 AAAA alice@laptop\n");
+    }
+
+    #[test]
+    fn checks_catch_a_missing_hook() {
+        let boot = tempfile::tempdir().unwrap();
+        fs::write(boot.path().join("cmdline.txt"), "console=tty1").unwrap();
+
+        Backend::Firstrun.apply(boot.path(), &options()).unwrap();
+        let checks = Backend::Firstrun.checks(boot.path(), &options());
+        assert!(checks.iter().all(|c| c.passed), "{:?}", checks);
+
+        fs::write(boot.path().join("cmdline.txt"), "console=tty1").unwrap();
+        let failed: Vec<String> = Backend::Firstrun
+            .checks(boot.path(), &options())
+            .into_iter()
+            .filter(|c| !c.passed)
+            .map(|c| c.label)
+            .collect();
+        assert_eq!(failed, ["cmdline.txt runs firstrun.sh"]);
+
+        Backend::CloudInit.apply(boot.path(), &options()).unwrap();
+        let checks = Backend::CloudInit.checks(boot.path(), &options());
+        assert!(checks.iter().all(|c| c.passed), "{:?}", checks);
+    }
+
+    #[test]
+    fn cloud_init_output_reads_back_as_the_same_options() {
+        let boot = tempfile::tempdir().unwrap();
+
+        Backend::CloudInit.apply(boot.path(), &options()).unwrap();
+        let read = crate::inspect::read_customization(boot.path()).unwrap();
+
+        assert_eq!(read.hostname, "lab-pi");
+        assert_eq!(read.timezone, "Europe/Berlin");
+        assert_eq!(read.keyboard_layout, "de");
+        assert_eq!(read.user_name, "alice");
+        assert!(read.ssh_enabled && !read.ssh_password_auth);
+        assert_eq!(read.ssh_public_keys, "ssh-ed25519 AAAA alice@laptop");
+        let network = fs::read_to_string(boot.path().join("network-config")).unwrap();
+        assert!(network.contains(r#""lab \"5G\"":"#), "{}", network);
+    }
+
+    #[test]
+    fn meta_data_gets_a_new_instance_id() {
+        let shipped = "# Ubuntu's NoCloud seed\ninstance_id: cloud-image\nlocal-hostname: ubuntu\n";
+
+        assert_eq!(
+            meta_data(shipped, "1234"),
+            "# Ubuntu's NoCloud seed\nlocal-hostname: ubuntu\ninstance-id: rpi-imager-1234\n"
+        );
+        assert_eq!(meta_data("", "1234"), "instance-id: rpi-imager-1234\n");
+    }
+
+    #[test]
+    fn custom_toml_hashes_the_password() {
+        let toml = custom_toml(&options()).unwrap();
+
+        assert!(toml.contains("hostname = \"lab-pi\""), "{}", toml);
+        assert!(toml.contains("password = \"$6$"), "{}", toml);
+        assert!(!toml.contains("\"secret\""), "{}", toml);
+        assert!(toml.contains("authorized_keys = [ \"ssh-ed25519 AAAA alice@laptop\" ]"));
+        assert!(toml.contains("ssid = \"lab \\\"5G\\\"\""), "{}", toml);
+    }
+
+    #[test]
+    fn firstrun_passes_only_the_hash_to_userconf() {
+        let script = options().generate_firstrun_script().unwrap();
+
+        let userconf = script
+            .lines()
+            .find(|l| l.trim_start().starts_with("/usr/lib/userconf-pi/userconf "))
+            .unwrap();
+        assert!(userconf.contains(" alice \\$6\\$"), "{}", userconf);
+        assert!(script.contains("chpasswd -e"), "{}", script);
+        assert!(!script.contains("secret"), "{}", script);
+    }
+}
@@ -0,0 +1,112 @@
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Localization settings detected on the host, used as customization
+/// defaults. Values the image wouldn't understand are dropped.
+#[derive(Debug, Default)]
+pub struct HostLocale {
+    pub timezone: Option<String>,
+    pub keyboard_layout: Option<String>,
+    pub locale: Option<String>,
+    pub wifi_country: Option<String>,
+}
+
+/// Detection runs `localectl` and `iw`, so it is done once per process.
+pub fn detected() -> &'static HostLocale {
+    static DETECTED: OnceLock<HostLocale> = OnceLock::new();
+    DETECTED.get_or_init(HostLocale::detect)
+}
+
+impl HostLocale {
+    fn detect() -> Self {
+        let localectl = command_output("localectl", &["status"]).unwrap_or_default();
+        let locale = detect_locale(&localectl);
+        let wifi_country = detect_wifi_country().or_else(|| {
+            // Fall back to the locale's territory, e.g. en_US.UTF-8 -> US
+            locale
+                .as_deref()
+                .and_then(|l| l.split(['_', '.']).nth(1))
+                .map(str::to_string)
+        });
+        Self {
+            timezone: detect_timezone(),
+            keyboard_layout: detect_keyboard(&localectl),
+            locale,
+            wifi_country,
+        }
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Value of a "Key: value" line in `localectl status` output.
+fn localectl_field<'a>(status: &'a str, field: &str) -> Option<&'a str> {
+    status
+        .lines()
+        .find_map(|l| l.trim().strip_prefix(field)?.trim().strip_prefix(':'))
+        .map(str::trim)
+}
+
+fn detect_timezone() -> Option<String> {
+    let timezone = std::fs::read_to_string("/etc/timezone")
+        .ok()
+        .map(|tz| tz.trim().to_string())
+        .or_else(|| {
+            let target = std::fs::read_link("/etc/localtime").ok()?;
+            let target = target.to_string_lossy();
+            target.split_once("zoneinfo/").map(|(_, tz)| tz.to_string())
+        })?;
+    crate::static_data::get_timezones()
+        .contains(&timezone.as_str())
+        .then_some(timezone)
+}
+
+fn detect_keyboard(localectl: &str) -> Option<String> {
+    let layouts = localectl_field(localectl, "X11 Layout")
+        .map(str::to_string)
+        .or_else(|| {
+            let keyboard = std::fs::read_to_string("/etc/default/keyboard").ok()?;
+            keyboard.lines().find_map(|l| {
+                l.strip_prefix("XKBLAYOUT=")
+                    .map(|v| v.trim_matches('"').to_string())
+            })
+        })?;
+    // Multiple layouts are comma-separated; the first is the primary one
+    let layout = layouts.split(',').next()?.trim();
+    crate::keyboards::available()
+        .iter()
+        .any(|k| k.variant.is_none() && k.layout == layout)
+        .then(|| layout.to_string())
+}
+
+fn detect_locale(localectl: &str) -> Option<String> {
+    let lang = std::env::var("LANG")
+        .ok()
+        .filter(|l| !l.is_empty())
+        .or_else(|| {
+            localectl_field(localectl, "System Locale")
+                .and_then(|v| v.strip_prefix("LANG="))
+                .map(str::to_string)
+        })?;
+    // Hosts often spell the encoding "utf8"; the image expects "UTF-8"
+    let (name, _) = lang.split_once('.').unwrap_or((&lang, ""));
+    let locale = format!("{}.UTF-8", name);
+    crate::static_data::get_locales()
+        .contains(&locale.as_str())
+        .then_some(locale)
+}
+
+/// The regulatory domain the host's Wi-Fi is operating under.
+fn detect_wifi_country() -> Option<String> {
+    let reg = command_output("iw", &["reg", "get"])?;
+    reg.lines()
+        .find_map(|l| l.strip_prefix("country ")?.split(':').next())
+        .filter(|c| c.len() == 2 && *c != "00")
+        .map(str::to_string)
+}
@@ -0,0 +1,659 @@
+use crate::customization::CustomizationOptions;
+use anyhow::{Context, Result, anyhow};
+use futures::StreamExt;
+use reqwest::{Certificate, Client, Response};
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::server::ParsedCertificate;
+use rustls::{CertificateError, DigitallySignedStruct, OtherError, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Set by the application, see [`set_user_agent`].
+static USER_AGENT: OnceLock<String> = OnceLock::new();
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Host whose certificate is checked against `pinned_certificates`.
+const PINNED_HOST: &str = "downloads.raspberrypi.com";
+
+/// Names the application in the User-Agent of every request, instead of
+/// the library. Only the first call counts.
+pub fn set_user_agent(agent: String) {
+    let _ = USER_AGENT.set(agent);
+}
+
+fn user_agent() -> &'static str {
+    USER_AGENT.get().map_or(
+        concat!("rpi-imager-core/", env!("CARGO_PKG_VERSION")),
+        String::as_str,
+    )
+}
+
+/// Builds the HTTP client shared by OS list fetching and image downloads.
+pub fn client(options: &CustomizationOptions) -> Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent(user_agent())
+        .connect_timeout(Duration::from_secs(30));
+
+    if let Some(proxy) = options.http_proxy.as_deref().filter(|p| !p.is_empty()) {
+        let proxy = reqwest::Proxy::all(proxy).context(format!("Invalid proxy URL {}", proxy))?;
+        builder = builder.proxy(proxy);
+    }
+
+    if !options.pinned_certificates.is_empty() {
+        builder = builder.use_preconfigured_tls(pinned_tls_config(options)?);
+    } else if let Some(path) = options.ca_bundle.as_deref().filter(|p| !p.is_empty()) {
+        let pem = std::fs::read(path).context(format!("Failed to read CA bundle {}", path))?;
+        for cert in Certificate::from_pem_bundle(&pem)
+            .context(format!("Failed to parse CA bundle {}", path))?
+        {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    builder.build().context("Failed to build HTTP client")
+}
+
+fn pinned_tls_config(options: &CustomizationOptions) -> Result<rustls::ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(path) = options.ca_bundle.as_deref().filter(|p| !p.is_empty()) {
+        let pem =
+            std::fs::read_to_string(path).context(format!("Failed to read CA bundle {}", path))?;
+        for cert in rustls::pki_types::pem::PemObject::pem_slice_iter(pem.as_bytes()) {
+            let cert: CertificateDer =
+                cert.context(format!("Failed to parse CA bundle {}", path))?;
+            roots
+                .add(cert)
+                .context(format!("Invalid certificate in CA bundle {}", path))?;
+        }
+    }
+
+    let verifier = PinningVerifier::new(roots, &options.pinned_certificates, provider.clone())?;
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS")?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// An entry of `pinned_certificates`: the SHA-256 fingerprint of a
+/// certificate in hex, or `sha256/<base64>` of its public key as curl and
+/// HPKP write them. A key pin survives the certificate being renewed with
+/// the same key, and pinning an intermediate survives new leaves.
+#[derive(Debug, Clone, PartialEq)]
+enum Pin {
+    Certificate(Vec<u8>),
+    PublicKey(Vec<u8>),
+}
+
+impl Pin {
+    fn parse(pin: &str) -> Result<Self> {
+        use base64::Engine;
+        let pin = pin.trim();
+        let parsed = match pin.strip_prefix("sha256/") {
+            Some(key) => base64::engine::general_purpose::STANDARD
+                .decode(key)
+                .ok()
+                .map(Pin::PublicKey),
+            None => hex::decode(pin.replace(':', "")).ok().map(Pin::Certificate),
+        };
+        parsed
+            .filter(|pin| match pin {
+                Pin::Certificate(digest) | Pin::PublicKey(digest) => digest.len() == 32,
+            })
+            .ok_or_else(|| anyhow!("Invalid certificate pin {}", pin))
+    }
+
+    fn matches(&self, cert: &CertificateDer<'_>) -> bool {
+        match self {
+            Pin::Certificate(digest) => Sha256::digest(cert.as_ref()).as_slice() == digest,
+            Pin::PublicKey(digest) => ParsedCertificate::try_from(cert).is_ok_and(|cert| {
+                Sha256::digest(cert.subject_public_key_info().as_ref()).as_slice() == digest
+            }),
+        }
+    }
+}
+
+/// The certificates [`PINNED_HOST`] presented matched none of the pins.
+#[derive(Debug)]
+pub struct PinMismatch {
+    /// SHA-256 fingerprint of the server's own certificate.
+    pub fingerprint: String,
+}
+
+impl std::fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The certificate of {} (SHA-256 {}) matches none of the pinned certificates or keys",
+            PINNED_HOST, self.fingerprint
+        )
+    }
+}
+
+impl std::error::Error for PinMismatch {}
+
+/// Performs normal WebPKI validation, then additionally requires a
+/// certificate of the chain `PINNED_HOST` presents to match one of the pins.
+#[derive(Debug)]
+struct PinningVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<Pin>,
+}
+
+impl PinningVerifier {
+    fn new(
+        roots: RootCertStore,
+        pins: &[String],
+        provider: Arc<rustls::crypto::CryptoProvider>,
+    ) -> Result<Self> {
+        let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .context("Failed to build certificate verifier")?;
+        let pins = pins.iter().map(|p| Pin::parse(p)).collect::<Result<_>>()?;
+        Ok(Self { inner, pins })
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        if server_name.to_str() == PINNED_HOST
+            && !std::iter::once(end_entity)
+                .chain(intermediates)
+                .any(|cert| self.pins.iter().any(|pin| pin.matches(cert)))
+        {
+            let mismatch = PinMismatch {
+                fingerprint: hex::encode(Sha256::digest(end_entity.as_ref())),
+            };
+            return Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                OtherError(Arc::new(mismatch)),
+            )));
+        }
+
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// `url` on the mirror at `base`, which keeps the official layout. Only
+/// images on the official download host are mirrored.
+pub fn mirror_url(url: &str, base: &str) -> Option<String> {
+    let path = url.strip_prefix("https://")?.strip_prefix(PINNED_HOST)?;
+    path.starts_with('/')
+        .then(|| format!("{}{}", base.trim_end_matches('/'), path))
+}
+
+/// Longest Retry-After that is waited for before giving up.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Sends a GET request, retrying connection failures, server errors and
+/// rate limiting.
+pub async fn get(client: &Client, url: &str) -> Result<Response> {
+    get_from(client, url, 0).await
+}
+
+/// Like [`get`], but asks for the body starting at `offset`. Servers that
+/// ignore the range answer 200 with the whole body instead of 206.
+pub async fn get_from(client: &Client, url: &str, offset: u64) -> Result<Response> {
+    let range = (offset > 0).then(|| format!("bytes={}-", offset));
+    send(client, url, range).await
+}
+
+async fn send(client: &Client, url: &str, range: Option<String>) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let mut request = client.get(url);
+        if let Some(range) = &range {
+            request = request.header(reqwest::header::RANGE, range);
+        }
+        let mut delay = Duration::from_secs(attempt as u64);
+        match request.send().await {
+            Ok(res) if res.status().is_success() => return Ok(res),
+            Ok(res)
+                if (res.status().is_server_error()
+                    || res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS)
+                    && attempt < MAX_ATTEMPTS
+                    && retry_after(&res).is_none_or(|after| after <= MAX_RETRY_AFTER) =>
+            {
+                delay = retry_after(&res).unwrap_or(delay);
+            }
+            Ok(res) => {
+                return Err(anyhow!(
+                    "Request to {} failed with status: {}",
+                    url,
+                    res.status()
+                ));
+            }
+            Err(e) if (e.is_connect() || e.is_timeout()) && attempt < MAX_ATTEMPTS => {}
+            Err(e) => return Err(e).context(format!("Failed to download from {}", url)),
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Size of the ranges a parallel download is split into. Each connection
+/// holds one in memory until the ones before it have been passed on.
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Whether `res` can be continued with ranged requests by
+/// [`parallel_download`]: the server says it supports ranges, and the size
+/// of the body is known.
+pub fn supports_ranges(res: &Response) -> bool {
+    let ranges = res
+        .headers()
+        .get(reqwest::header::ACCEPT_RANGES)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim() == "bytes");
+    (ranges || res.status() == reqwest::StatusCode::PARTIAL_CONTENT)
+        && res.content_length().is_some()
+}
+
+/// Downloads the file of `res` from `offset` to `end` over up to
+/// `connections` ranged requests at once. High-latency links cap a single
+/// stream well below their line rate; chunks are still yielded in order.
+/// Like [`resuming`], a range that fails is asked for again after a wait,
+/// and If-Range makes sure every range is of the same file.
+pub fn parallel_download(
+    client: Client,
+    res: Response,
+    offset: u64,
+    end: u64,
+    connections: usize,
+) -> impl futures::Stream<Item = std::io::Result<bytes::Bytes>> {
+    let url = res.url().to_string();
+    let validator = validator(&res);
+    drop(res);
+    let ranges = (offset..end)
+        .step_by(CHUNK_SIZE as usize)
+        .map(move |from| (from, (from + CHUNK_SIZE).min(end) - 1));
+    futures::stream::iter(ranges)
+        .map(move |(from, to)| {
+            let (client, url, validator) = (client.clone(), url.clone(), validator.clone());
+            async move {
+                let mut failures = 0;
+                loop {
+                    let error = match fetch_range(&client, &url, validator.as_ref(), from, to).await
+                    {
+                        Ok(chunk) => return Ok(chunk),
+                        Err(RangeError::Changed) => {
+                            anyhow!("{} changed on the server during the download", url)
+                        }
+                        Err(RangeError::Failed(_)) if failures < MAX_RESUMES => {
+                            tokio::time::sleep(Duration::from_secs(1 << failures)).await;
+                            failures += 1;
+                            continue;
+                        }
+                        Err(RangeError::Failed(e)) => e,
+                    };
+                    let error = error.context(format!(
+                        "Download of {} failed at bytes {}-{}",
+                        url, from, to
+                    ));
+                    return Err(std::io::Error::other(error));
+                }
+            }
+        })
+        .buffered(connections.max(1))
+}
+
+/// Why a range of a [`parallel_download`] couldn't be fetched.
+enum RangeError {
+    /// The server sent the whole file, so it isn't the one the download
+    /// started with any more, or ranges stopped working.
+    Changed,
+    /// Anything asking again may fix.
+    Failed(anyhow::Error),
+}
+
+async fn fetch_range(
+    client: &Client,
+    url: &str,
+    validator: Option<&reqwest::header::HeaderValue>,
+    from: u64,
+    to: u64,
+) -> std::result::Result<bytes::Bytes, RangeError> {
+    let failed = |e: anyhow::Error| RangeError::Failed(e);
+    let mut request = client
+        .get(url)
+        .header(reqwest::header::RANGE, format!("bytes={}-{}", from, to));
+    if let Some(validator) = validator {
+        request = request.header(reqwest::header::IF_RANGE, validator.clone());
+    }
+    let res = request
+        .send()
+        .await
+        .and_then(Response::error_for_status)
+        .map_err(|e| failed(e.into()))?;
+    if res.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(RangeError::Changed);
+    }
+    let chunk = res.bytes().await.map_err(|e| failed(e.into()))?;
+    if chunk.len() as u64 != to - from + 1 {
+        return Err(failed(anyhow!("{} bytes were sent", chunk.len())));
+    }
+    Ok(chunk)
+}
+
+/// Times in a row a dropped download is continued before giving up. The
+/// waits in between double from a second.
+const MAX_RESUMES: u32 = 5;
+
+/// Where a [`resuming`] download is.
+struct Resuming<F> {
+    client: Client,
+    url: String,
+    /// ETag or Last-Modified of the first response, sent as If-Range
+    validator: Option<reqwest::header::HeaderValue>,
+    position: u64,
+    body: Option<futures::stream::BoxStream<'static, reqwest::Result<bytes::Bytes>>>,
+    failures: u32,
+    failed: bool,
+    resumed: F,
+}
+
+impl<F> Resuming<F> {
+    /// Asks for the rest. Only the request is borrowed from `self`, as the
+    /// state isn't shared between threads.
+    fn reopen(&self) -> impl Future<Output = Result<Response>> + Send + use<F> {
+        let mut request = self
+            .client
+            .get(&self.url)
+            .header(reqwest::header::RANGE, format!("bytes={}-", self.position));
+        if let Some(validator) = &self.validator {
+            request = request.header(reqwest::header::IF_RANGE, validator.clone());
+        }
+        let failure = format!("{} can't be continued at byte {}", self.url, self.position);
+        async move {
+            let res = request.send().await?.error_for_status()?;
+            // A changed file comes whole, as does one from a server without ranges
+            if res.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+                return Err(anyhow!(failure));
+            }
+            Ok(res)
+        }
+    }
+}
+
+/// The body of `res`, which starts at `offset` of the file. When the
+/// connection drops, the rest is asked for with a ranged request from where
+/// it broke off, so a reset doesn't cost what was already downloaded.
+/// If-Range makes sure the rest is of the same file. `resumed` is called
+/// with the offset each time.
+pub fn resuming(
+    client: Client,
+    res: Response,
+    offset: u64,
+    resumed: impl Fn(u64) + Send + 'static,
+) -> impl futures::Stream<Item = std::io::Result<bytes::Bytes>> {
+    let validator = validator(&res);
+    let state = Resuming {
+        client,
+        url: res.url().to_string(),
+        validator,
+        position: offset,
+        body: Some(res.bytes_stream().boxed()),
+        failures: 0,
+        failed: false,
+        resumed,
+    };
+    futures::stream::unfold(state, |mut state| async move {
+        if state.failed {
+            return None;
+        }
+        loop {
+            let error = match state.body.as_mut() {
+                Some(body) => match body.next().await {
+                    Some(Ok(chunk)) => {
+                        state.position += chunk.len() as u64;
+                        state.failures = 0;
+                        return Some((Ok(chunk), state));
+                    }
+                    Some(Err(e)) => anyhow::Error::new(e),
+                    None => return None,
+                },
+                None => match state.reopen().await {
+                    Ok(res) => {
+                        state.body = Some(res.bytes_stream().boxed());
+                        continue;
+                    }
+                    Err(e) => e,
+                },
+            };
+            state.body = None;
+            if state.failures >= MAX_RESUMES {
+                state.failed = true;
+                let error = error.context(format!(
+                    "Download of {} broke off at byte {}",
+                    state.url, state.position
+                ));
+                return Some((Err(std::io::Error::other(error)), state));
+            }
+            tokio::time::sleep(Duration::from_secs(1 << state.failures)).await;
+            state.failures += 1;
+            (state.resumed)(state.position);
+        }
+    })
+    // Readers may ask again after the end
+    .fuse()
+}
+
+/// The ETag of `res`, or its Last-Modified, to send as If-Range. Weak ETags
+/// can't be used for ranges.
+fn validator(res: &Response) -> Option<reqwest::header::HeaderValue> {
+    let headers = res.headers();
+    headers
+        .get(reqwest::header::ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(reqwest::header::LAST_MODIFIED))
+        .cloned()
+}
+
+/// The Retry-After header in its seconds form; HTTP dates are not used by
+/// the mirrors.
+fn retry_after(res: &Response) -> Option<Duration> {
+    res.headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_only_official_downloads() {
+        let url = "https://downloads.raspberrypi.com/raspios_lite_arm64/images/os.img.xz";
+        assert_eq!(
+            mirror_url(url, "https://mirror.example.org/raspberrypi/").as_deref(),
+            Some("https://mirror.example.org/raspberrypi/raspios_lite_arm64/images/os.img.xz")
+        );
+        assert!(
+            mirror_url(
+                "https://example.com/os.img.xz",
+                "https://mirror.example.org"
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn accepts_only_pinned_certificates() {
+        use rustls::pki_types::pem::PemObject;
+
+        let chain: Vec<CertificateDer> =
+            CertificateDer::pem_slice_iter(include_bytes!("../tests/fixtures/tls/chain.pem"))
+                .collect::<std::result::Result<_, _>>()
+                .unwrap();
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(
+                CertificateDer::from_pem_slice(include_bytes!("../tests/fixtures/tls/root.pem"))
+                    .unwrap(),
+            )
+            .unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let host = ServerName::try_from(PINNED_HOST).unwrap();
+        // The fixtures are valid from 2026 to 2126
+        let now = UnixTime::since_unix_epoch(Duration::from_secs(1_900_000_000));
+        let verify = |pins: &[&str]| {
+            let pins: Vec<String> = pins.iter().map(|p| p.to_string()).collect();
+            PinningVerifier::new(roots.clone(), &pins, provider.clone())
+                .unwrap()
+                .verify_server_cert(&chain[0], &chain[1..], &host, &[], now)
+        };
+
+        let leaf = "A3:87:05:A5:2B:3D:6D:A2:ED:7C:14:35:A3:4D:56:54:\
+                    1D:5C:AE:A4:A4:88:60:95:15:E4:A7:6A:69:C1:A1:B4";
+        assert!(verify(&[leaf]).is_ok());
+        // The public key of the intermediate
+        assert!(verify(&["sha256/djG+0AeBBzUtE3IwL7MEYS3K8oMLy4IGiyuOTpcrIbE="]).is_ok());
+
+        let error = verify(&[&"00".repeat(32)]).unwrap_err();
+        let rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(error))) = error
+        else {
+            panic!("unexpected error {:?}", error);
+        };
+        let mismatch = error.downcast_ref::<PinMismatch>().unwrap();
+        assert_eq!(mismatch.fingerprint, leaf.replace(':', "").to_lowercase());
+
+        assert!(Pin::parse("sha256/not base64").is_err());
+        assert!(Pin::parse("a387").is_err());
+    }
+
+    #[tokio::test]
+    async fn continues_a_dropped_download_where_it_broke_off() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/os.img", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in [
+                // Promises ten bytes, then the connection drops after five
+                "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nETag: \"v1\"\r\n\r\n01234",
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: 5\r\n\
+                 Content-Range: bytes 5-9/10\r\n\r\n56789",
+            ] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let n = socket.read(&mut request).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).to_lowercase());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let client = Client::new();
+        let res = client.get(&url).send().await.unwrap();
+        let offsets = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let resumed = offsets.clone();
+        let chunks: Vec<_> = resuming(client, res, 0, move |offset| {
+            resumed.lock().unwrap().push(offset)
+        })
+        .collect()
+        .await;
+        let body: Vec<u8> = chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect();
+
+        assert_eq!(body, b"0123456789");
+        assert_eq!(*offsets.lock().unwrap(), [5]);
+        let requests = server.await.unwrap();
+        assert!(requests[1].contains("range: bytes=5-"));
+        assert!(requests[1].contains("if-range: \"v1\""));
+    }
+
+    #[tokio::test]
+    async fn asks_again_for_a_range_that_failed() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/os.img", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in [
+                "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nAccept-Ranges: bytes\r\n\
+                 ETag: \"v1\"\r\n\r\n0123456789",
+                // Drops the connection halfway through the range
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: 6\r\n\
+                 Content-Range: bytes 4-9/10\r\n\r\n456",
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: 6\r\n\
+                 Content-Range: bytes 4-9/10\r\n\r\n456789",
+            ] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let n = socket.read(&mut request).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).to_lowercase());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let client = Client::builder().pool_max_idle_per_host(0).build().unwrap();
+        let res = client.get(&url).send().await.unwrap();
+        assert!(supports_ranges(&res));
+        let chunks: Vec<_> = parallel_download(client, res, 4, 10, 2).collect().await;
+        let body: Vec<u8> = chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect();
+
+        assert_eq!(body, b"456789");
+        let requests = server.await.unwrap();
+        for request in &requests[1..] {
+            assert!(request.contains("range: bytes=4-9"));
+            assert!(request.contains("if-range: \"v1\""));
+        }
+    }
+}
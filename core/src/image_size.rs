@@ -0,0 +1,214 @@
+//! Guessing how large an image is once decompressed, for progress and ETA
+//! when the OS list has no extract_size (custom URLs, local files, some
+//! third-party entries). Local files are asked first: xz keeps exact sizes in
+//! its index, zstd in the frame header if the encoder knew it, gzip modulo
+//! 4 GiB in its trailer. Otherwise the size is extrapolated from how much of
+//! the compressed input was consumed.
+
+use crate::compression::Compression;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// The decompressed size of the image at `path`, telling the format by its
+/// magic bytes like the writer does.
+pub fn from_file(path: &Path) -> Option<u64> {
+    let compression = Compression::of_file(path).ok()?;
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    match compression {
+        Compression::Xz => xz_size(&mut file, len),
+        Compression::Gzip => gzip_size(&mut file, len),
+        Compression::Zstd => zstd_size(&mut file),
+        Compression::Bzip2 | Compression::Zip => None,
+        Compression::None => Some(len),
+    }
+}
+
+/// Scales `written` decompressed bytes by how much of the compressed input
+/// they took, once enough was read for the ratio to mean something.
+pub fn extrapolate(written: u64, consumed: u64, compressed_total: u64) -> Option<u64> {
+    if consumed < 1024 * 1024 || compressed_total == 0 {
+        return None;
+    }
+    Some((written as f64 * compressed_total as f64 / consumed as f64) as u64)
+}
+
+/// Sums the uncompressed sizes in the index of every stream, walking back
+/// from the end of the file.
+fn xz_size(file: &mut File, len: u64) -> Option<u64> {
+    let mut end = len;
+    let mut total = 0u64;
+    while end > 0 {
+        // Stream padding is a multiple of four zero bytes
+        let mut word = [0u8; 4];
+        read_at(file, end.checked_sub(4)?, &mut word)?;
+        if word == [0; 4] {
+            end -= 4;
+            continue;
+        }
+
+        let mut footer = [0u8; 12];
+        read_at(file, end.checked_sub(12)?, &mut footer)?;
+        if &footer[10..] != b"YZ" {
+            return None;
+        }
+        let index_size = (u32::from_le_bytes(footer[4..8].try_into().ok()?) as u64 + 1) * 4;
+        let index_start = end.checked_sub(12 + index_size)?;
+        let mut index = vec![0u8; index_size as usize];
+        read_at(file, index_start, &mut index)?;
+
+        let (uncompressed, blocks) = parse_xz_index(&index)?;
+        total += uncompressed;
+        end = index_start.checked_sub(blocks + 12)?;
+    }
+    Some(total)
+}
+
+/// Returns the uncompressed size and the padded size of all blocks.
+fn parse_xz_index(index: &[u8]) -> Option<(u64, u64)> {
+    if index.first() != Some(&0) {
+        return None;
+    }
+    let mut pos = 1;
+    let records = varint(index, &mut pos)?;
+    let (mut uncompressed, mut blocks) = (0u64, 0u64);
+    for _ in 0..records {
+        let unpadded = varint(index, &mut pos)?;
+        blocks += unpadded.div_ceil(4) * 4;
+        uncompressed += varint(index, &mut pos)?;
+    }
+    Some((uncompressed, blocks))
+}
+
+fn varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// The trailer only holds the size modulo 4 GiB; images compress to less
+/// than their size, so the smallest candidate above the compressed length is
+/// taken.
+fn gzip_size(file: &mut File, len: u64) -> Option<u64> {
+    let mut trailer = [0u8; 4];
+    read_at(file, len.checked_sub(4)?, &mut trailer)?;
+    let mut size = u32::from_le_bytes(trailer) as u64;
+    while size < len {
+        size += 1 << 32;
+    }
+    Some(size)
+}
+
+/// Frame_Content_Size of the first frame, if the encoder wrote one.
+fn zstd_size(file: &mut File) -> Option<u64> {
+    let mut header = [0u8; 18];
+    file.read_exact(&mut header).ok()?;
+    parse_zstd_header(&header)
+}
+
+fn parse_zstd_header(header: &[u8]) -> Option<u64> {
+    if header.get(..4)? != [0x28, 0xb5, 0x2f, 0xfd] {
+        return None;
+    }
+    let descriptor = *header.get(4)?;
+    let single_segment = descriptor & 0x20 != 0;
+    let mut pos = 5 + usize::from(!single_segment);
+    pos += [0, 1, 2, 4][(descriptor & 0x03) as usize];
+    let field = match descriptor >> 6 {
+        0 if single_segment => 1,
+        0 => return None,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let mut bytes = [0u8; 8];
+    bytes[..field].copy_from_slice(header.get(pos..pos + field)?);
+    let size = u64::from_le_bytes(bytes);
+    Some(if field == 2 { size + 256 } else { size })
+}
+
+fn read_at(file: &mut File, offset: u64, buf: &mut [u8]) -> Option<()> {
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(buf).ok()
+}
+
+/// Counts the bytes read through it, to tell how far into the compressed
+/// input the decoder is.
+pub struct Counting<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R> Counting<R> {
+    /// Adds what is read from `inner` to `count`.
+    pub fn new(inner: R, count: Arc<AtomicU64>) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Counting<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = buf.filled().len() - before;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::bufread::{GzipEncoder, XzEncoder};
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn reads_sizes_from_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let image: Vec<u8> = (0..3 * 1024 * 1024 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        let mut xz = Vec::new();
+        XzEncoder::new(&image[..])
+            .read_to_end(&mut xz)
+            .await
+            .unwrap();
+        let mut gz = Vec::new();
+        GzipEncoder::new(&image[..])
+            .read_to_end(&mut gz)
+            .await
+            .unwrap();
+        for (name, data) in [
+            ("image.img.xz", &xz),
+            ("image.img.gz", &gz),
+            ("image.img", &image),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            assert_eq!(from_file(&path), Some(image.len() as u64), "{}", name);
+        }
+
+        // Single segment frame with a four byte content size
+        let zstd = [0x28, 0xb5, 0x2f, 0xfd, 0xa0, 0x40, 0x42, 0x0f, 0x00];
+        assert_eq!(parse_zstd_header(&zstd), Some(1_000_000));
+        assert_eq!(extrapolate(4 << 20, 1 << 20, 2 << 20), Some(8 << 20));
+    }
+}
@@ -0,0 +1,266 @@
+//! Refusing to write to a drive something else still uses. Writing under a
+//! mounted filesystem, active swap, or a dm-crypt/LVM/RAID device stacked on
+//! the drive corrupts data without any error, so the drive and each of its
+//! partitions are looked up in /proc/mounts, /proc/swaps and the holders
+//! directories in sysfs first.
+//!
+//! Filesystems the desktop mounted on its own are unmounted beforehand, so
+//! a card that was just plugged in doesn't need a trip to the file manager.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Unmounts the filesystems of `device` at `mountpoints`, as listed with the
+/// drive. The root filesystem is never touched; [`check`] reports it.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn unmount(device: &str, mountpoints: &[String]) -> Result<()> {
+    let mounts = fs::read_to_string("/proc/mounts").unwrap_or_default();
+    for (partition, mountpoint) in mounted(&mounts, mountpoints) {
+        // Without root, the worker writes through udisks2, which can unmount
+        // what the desktop mounted as well
+        let result = if crate::customization::is_root() {
+            Command::new("umount")
+                .arg(&mountpoint)
+                .status()
+                .context("Failed to run umount")
+                .and_then(|status| match status.success() {
+                    true => Ok(()),
+                    false => Err(anyhow::anyhow!("umount exited with {}", status)),
+                })
+        } else {
+            crate::udisks::unmount(&partition)
+        };
+        result.with_context(|| {
+            format!(
+                "Failed to unmount {} from {} before writing to {}. \
+                 Close any program using it and try again.",
+                partition, mountpoint, device
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// The volumes of a disk are unmounted together on macOS.
+#[cfg(target_os = "macos")]
+pub fn unmount(device: &str, mountpoints: &[String]) -> Result<()> {
+    if mountpoints.iter().all(|mp| mp == "/") {
+        return Ok(());
+    }
+    let output = Command::new("diskutil")
+        .args(["unmountDisk", device])
+        .output()
+        .context("Failed to run diskutil")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to unmount {} before writing to it: {}. Close any program using it and try again.",
+            device,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Windows has no unmount for a volume in use; its volumes are locked and
+/// dismounted instead, and stay so until the write is done.
+#[cfg(windows)]
+pub fn unmount(device: &str, mountpoints: &[String]) -> Result<()> {
+    crate::windisk::dismount(mountpoints).with_context(|| {
+        format!(
+            "Failed to dismount the volumes of {} before writing to it. \
+             Close any program using them and try again.",
+            device
+        )
+    })
+}
+
+/// The devices mounted at `mountpoints` according to /proc/mounts, nested
+/// mounts first.
+#[cfg_attr(any(target_os = "macos", windows), allow(dead_code))]
+fn mounted(mounts: &str, mountpoints: &[String]) -> Vec<(String, String)> {
+    let mut mounted: Vec<(String, String)> = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?.to_string(), unescape(fields.next()?)))
+        })
+        .filter(|(_, mountpoint)| mountpoint != "/" && mountpoints.contains(mountpoint))
+        .collect();
+    mounted.sort_by_key(|(_, mountpoint)| std::cmp::Reverse(mountpoint.len()));
+    mounted
+}
+
+/// /proc/mounts writes spaces and other separators as octal escapes.
+#[cfg_attr(any(target_os = "macos", windows), allow(dead_code))]
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(byte) = field
+                .get(i + 1..i + 4)
+                .and_then(|octal| u8::from_str_radix(octal, 8).ok())
+        {
+            unescaped.push(byte);
+            i += 4;
+        } else {
+            unescaped.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Fails with an explanation if `device` or one of its partitions is in use.
+pub fn check(device: &str) -> Result<()> {
+    let name = fs::canonicalize(device)
+        .ok()
+        .and_then(|path| path.file_name().map(|n| n.to_string_lossy().to_string()));
+    let Some(name) = name else {
+        return Ok(());
+    };
+    let users = users(&name, Path::new("/sys"), Path::new("/proc"));
+    if users.is_empty() {
+        return Ok(());
+    }
+    bail!(
+        "{} is in use: {}. Writing to it now would corrupt data without any error. \
+         Unmount it, turn off the swap or close the device mapper first \
+         (umount, swapoff, cryptsetup close, vgchange -an, mdadm --stop).",
+        device,
+        users.join("; ")
+    )
+}
+
+/// What uses the block device `name` (e.g. "sdb") or its partitions.
+fn users(name: &str, sys: &Path, proc: &Path) -> Vec<String> {
+    let mut devices = vec![name.to_string()];
+    if let Ok(entries) = fs::read_dir(sys.join("block").join(name)) {
+        let mut partitions: Vec<String> = entries
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|entry| entry.starts_with(name) && entry.len() > name.len())
+            .collect();
+        partitions.sort();
+        devices.extend(partitions);
+    }
+
+    let mounts = fs::read_to_string(proc.join("mounts")).unwrap_or_default();
+    let swaps = fs::read_to_string(proc.join("swaps")).unwrap_or_default();
+    let mut users = Vec::new();
+    for device in &devices {
+        let path = format!("/dev/{}", device);
+        for line in mounts.lines() {
+            let mut fields = line.split_whitespace();
+            if fields.next() == Some(path.as_str())
+                && let Some(mountpoint) = fields.next()
+            {
+                users.push(format!("{} is mounted at {}", path, mountpoint));
+            }
+        }
+        if swaps
+            .lines()
+            .skip(1)
+            .any(|line| line.split_whitespace().next() == Some(path.as_str()))
+        {
+            users.push(format!("{} is active swap", path));
+        }
+        for holder in holders(device, sys) {
+            users.push(format!("{} is held by {}", path, holder));
+        }
+    }
+    users
+}
+
+/// Devices stacked on `device`, by their device-mapper or md name if known.
+fn holders(device: &str, sys: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(sys.join("class/block").join(device).join("holders")) else {
+        return Vec::new();
+    };
+    let mut holders: Vec<String> = entries
+        .flatten()
+        .map(|e| {
+            let holder = e.file_name().to_string_lossy().to_string();
+            match fs::read_to_string(sys.join("class/block").join(&holder).join("dm/name")) {
+                Ok(dm_name) => format!("{} ({})", holder, dm_name.trim()),
+                Err(_) => holder,
+            }
+        })
+        .collect();
+    holders.sort();
+    holders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_listed_mounts_to_unmount() {
+        let mounts = "/dev/sda2 / ext4 rw 0 0\n\
+                      /dev/sdb1 /media/pi/boot\\040fs vfat rw 0 0\n\
+                      /dev/sdb2 /media/pi/rootfs ext4 rw 0 0\n\
+                      /dev/sdb1 /media/pi/rootfs/boot vfat rw 0 0\n";
+        let listed = [
+            "/".to_string(),
+            "/media/pi/boot fs".to_string(),
+            "/media/pi/rootfs".to_string(),
+            "/media/pi/rootfs/boot".to_string(),
+        ];
+
+        let mounted = mounted(mounts, &listed);
+
+        let mountpoints: Vec<&str> = mounted.iter().map(|(_, mp)| mp.as_str()).collect();
+        assert_eq!(
+            mountpoints,
+            [
+                "/media/pi/rootfs/boot",
+                "/media/pi/boot fs",
+                "/media/pi/rootfs"
+            ]
+        );
+        assert_eq!(mounted[1].0, "/dev/sdb1");
+    }
+
+    #[test]
+    fn finds_mounts_swap_and_holders_of_partitions() {
+        let root = tempfile::tempdir().unwrap();
+        let (sys, proc) = (root.path().join("sys"), root.path().join("proc"));
+        for dir in [
+            "block/sda/sda2",
+            "block/sda/sda3",
+            "block/sdb/sdb1",
+            "block/sdb/sdb2",
+            "block/sdb/queue",
+        ] {
+            fs::create_dir_all(sys.join(dir)).unwrap();
+        }
+        fs::create_dir_all(sys.join("class/block/sdb2/holders/dm-0")).unwrap();
+        fs::create_dir_all(sys.join("class/block/dm-0/dm")).unwrap();
+        fs::write(sys.join("class/block/dm-0/dm/name"), "luks-data\n").unwrap();
+        fs::create_dir_all(&proc).unwrap();
+        fs::write(
+            proc.join("mounts"),
+            "/dev/sda2 / ext4 rw 0 0\n/dev/sdb1 /media/pi/bootfs vfat rw 0 0\n",
+        )
+        .unwrap();
+        fs::write(
+            proc.join("swaps"),
+            "Filename Type Size Used Priority\n/dev/sda3 partition 1024 0 -2\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            users("sdb", &sys, &proc),
+            [
+                "/dev/sdb1 is mounted at /media/pi/bootfs",
+                "/dev/sdb2 is held by dm-0 (luks-data)",
+            ]
+        );
+        assert_eq!(users("sda", &sys, &proc).len(), 2);
+        assert!(users("sdc", &sys, &proc).is_empty());
+    }
+}
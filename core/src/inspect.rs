@@ -0,0 +1,250 @@
+use crate::customization::CustomizationOptions;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const IMAGER_CUSTOM: &str = "/usr/lib/raspberrypi-sys-mods/imager_custom";
+const USERCONF: &str = "/usr/lib/userconf-pi/userconf";
+
+/// Text files larger than this are listed without their contents.
+const MAX_PREVIEW_SIZE: u64 = 64 * 1024;
+
+/// An entry at the top of the boot partition, with the text of small text
+/// files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootFile {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+    pub content: Option<String>,
+}
+
+/// Lists the top level of a mounted boot partition, including the contents of
+/// small text files so they can be previewed without mounting again.
+pub fn list_boot_files(boot: &Path) -> Result<Vec<BootFile>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(boot).context("Failed to read boot partition")? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let content = if metadata.is_file() && metadata.len() <= MAX_PREVIEW_SIZE {
+            std::fs::read(entry.path())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+        } else {
+            None
+        };
+        files.push(BootFile {
+            name: entry.file_name().to_string_lossy().to_string(),
+            size: metadata.len(),
+            is_dir: metadata.is_dir(),
+            content,
+        });
+    }
+    files.sort_by_key(|f| f.name.to_lowercase());
+    Ok(files)
+}
+
+/// Reconstructs customization options from the files found on a mounted boot
+/// partition. Passwords are only stored as hashes on the card and cannot be
+/// recovered, so `password` is always left unset.
+pub fn read_customization(boot: &Path) -> Result<CustomizationOptions> {
+    let read = |name: &str| std::fs::read_to_string(boot.join(name)).ok();
+
+    let firstrun = read("firstrun.sh");
+    let userconf = read("userconf.txt").or_else(|| read("userconf"));
+    let user_data = read("user-data");
+
+    if firstrun.is_none() && userconf.is_none() && user_data.is_none() {
+        return Err(anyhow!(
+            "No customization found on this card (no firstrun.sh, userconf.txt or user-data)"
+        ));
+    }
+
+    let mut options = CustomizationOptions {
+        ssh_password_auth: false,
+        ..CustomizationOptions::default()
+    };
+
+    if let Some(script) = &firstrun {
+        parse_firstrun(script, &mut options);
+    }
+    if let Some(user_data) = &user_data {
+        parse_user_data(user_data, &mut options);
+    }
+    if let Some(userconf) = &userconf
+        && let Some((user, _hash)) = userconf.trim().split_once(':')
+    {
+        options.user_name = user.to_string();
+    }
+    if boot.join("ssh").exists() || boot.join("ssh.txt").exists() {
+        options.ssh_enabled = true;
+        options.ssh_password_auth = true;
+    }
+
+    Ok(options)
+}
+
+/// Parses a firstrun.sh written either by this tool or by the official imager.
+fn parse_firstrun(script: &str, options: &mut CustomizationOptions) {
+    let mut in_authorized_keys = false;
+    let mut keys = Vec::new();
+
+    for line in script.lines() {
+        let trimmed = line.trim();
+
+        if in_authorized_keys {
+            if trimmed == "EOF" {
+                in_authorized_keys = false;
+            } else if !trimmed.is_empty() {
+                keys.push(trimmed.to_string());
+            }
+            continue;
+        }
+        if trimmed.contains(".ssh/authorized_keys\" <<'EOF'") {
+            in_authorized_keys = true;
+            continue;
+        }
+
+        let words = shell_words(trimmed);
+        let args: Vec<&str> = words.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            [IMAGER_CUSTOM, "set_hostname", host] => options.hostname = host.to_string(),
+            [IMAGER_CUSTOM, "enable_ssh", rest @ ..] => {
+                options.ssh_enabled = true;
+                match rest {
+                    ["-k", key, ..] => keys.push(key.to_string()),
+                    _ => options.ssh_password_auth = true,
+                }
+            }
+            [IMAGER_CUSTOM, "set_wlan", rest @ ..] => {
+                let (hidden, rest) = match rest {
+                    ["-h", rest @ ..] => (true, rest),
+                    _ => (false, rest),
+                };
+                if let [ssid, password, country, ..] = rest {
+                    options.wifi_hidden = hidden;
+                    options.wifi_ssid = ssid.to_string();
+                    options.wifi_password = password.to_string();
+                    options.wifi_country = country.to_string();
+                }
+            }
+            [IMAGER_CUSTOM, "set_keymap", layout] => options.keyboard_layout = layout.to_string(),
+            [IMAGER_CUSTOM, "set_timezone", tz] => options.timezone = tz.to_string(),
+            [USERCONF, user, ..] => options.user_name = user.to_string(),
+            ["update-locale", lang] => {
+                if let Some(locale) = lang.strip_prefix("LANG=") {
+                    options.locale = locale.to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !keys.is_empty() {
+        keys.dedup();
+        options.ssh_public_keys = keys.join("\n");
+    }
+}
+
+/// Extracts the handful of cloud-init user-data keys the customization maps to.
+/// This is a line-based reader rather than a full YAML parser.
+fn parse_user_data(user_data: &str, options: &mut CustomizationOptions) {
+    let mut section = "";
+    let mut seen_user = false;
+
+    for line in user_data.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let top_level = !line.starts_with(' ') && !line.starts_with('-');
+        if top_level {
+            section = trimmed.split(':').next().unwrap_or("");
+        }
+
+        let entry = trimmed.trim_start_matches("- ");
+        if trimmed.starts_with("- ") && is_public_key(unquote(entry)) {
+            push_key(options, entry);
+            continue;
+        }
+        let Some((key, value)) = entry.split_once(':') else {
+            continue;
+        };
+        let value = unquote(value.trim());
+
+        match (section, key.trim()) {
+            ("hostname", "hostname") => options.hostname = value.to_string(),
+            ("timezone", "timezone") => options.timezone = value.to_string(),
+            ("locale", "locale") => options.locale = value.to_string(),
+            ("keyboard", "layout") => options.keyboard_layout = value.to_string(),
+            ("users", "name") if !seen_user => {
+                options.user_name = value.to_string();
+                seen_user = true;
+            }
+            ("users", "lock_passwd") => options.ssh_password_auth = value != "true",
+            ("ssh_pwauth", "ssh_pwauth") => {
+                options.ssh_enabled = true;
+                options.ssh_password_auth = value == "true";
+            }
+            ("enable_ssh", "enable_ssh") => options.ssh_enabled = value == "true",
+            _ => {}
+        }
+    }
+}
+
+fn is_public_key(s: &str) -> bool {
+    ["ssh-", "ecdsa-", "sk-"].iter().any(|p| s.starts_with(p))
+}
+
+fn push_key(options: &mut CustomizationOptions, key: &str) {
+    options.ssh_enabled = true;
+    if !options.ssh_public_keys.is_empty() {
+        options.ssh_public_keys.push('\n');
+    }
+    options.ssh_public_keys.push_str(unquote(key.trim()));
+}
+
+fn unquote(s: &str) -> &str {
+    s.trim_matches('"').trim_matches('\'')
+}
+
+/// Splits a shell command line into words, honouring single quotes, double
+/// quotes and backslash escapes. Good enough for the lines we generate.
+fn shell_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('"'), '"') => quote = None,
+            (Some('"') | None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_word = true;
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (_, c) => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    words
+}
@@ -0,0 +1,383 @@
+//! Progress protocol shared by the writer, the privileged worker and the TUI.
+//! The worker prints each event as one JSON line on stdout, wrapped in an
+//! [`Envelope`], and ends with a [`Summary`]; the TUI parses them back. New
+//! phases only need a variant here.
+
+use crate::boot_diff::FileDiff;
+use crate::customization::CustomizationOptions;
+use crate::error::ErrorKind;
+use crate::first_boot::Check;
+use crate::inspect::BootFile;
+use serde::{Deserialize, Serialize};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The stages of a write, in order.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum WritingPhase {
+    /// Waiting for the image to arrive, before anything is written.
+    Downloading,
+    Writing,
+    /// Flushing the page cache to the card after the last byte was written.
+    Syncing,
+    Verifying,
+    /// Writing the customization onto the boot partition.
+    Customizing,
+}
+
+/// What the writer reports while it runs, sent from the worker to the
+/// interface as JSON lines.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum ProgressEvent {
+    /// `fraction` of `phase` done, from 0.0 to 1.0.
+    Progress {
+        phase: WritingPhase,
+        fraction: f64,
+    },
+    /// Fraction of the image confirmed on the device by the last fdatasync,
+    /// trailing the writing progress by whatever is still buffered.
+    Synced(f64),
+    Status(String),
+    /// The host serving the image, after redirects.
+    Source(String),
+    /// The download has been too slow for a while; another mirror may help.
+    SlowDownload,
+    Error {
+        kind: ErrorKind,
+        message: String,
+    },
+    /// The card wasn't read back, as asked with [`WorkerCommand::SkipVerify`].
+    /// Comes before Finished.
+    VerificationSkipped,
+    /// The card was ejected and can be pulled out. Comes before Finished.
+    Ejected,
+    Finished,
+    /// The image was written but applying the customization failed.
+    CustomizationFailed(String),
+    Customization(Box<CustomizationOptions>),
+    /// What the boot partition looked like after customizing it.
+    Checklist(Vec<Check>),
+    /// The edits the customization made to cmdline.txt and config.txt.
+    BootChanges(Vec<FileDiff>),
+    BootFiles(Vec<BootFile>),
+    EmmcReady,
+    /// [`crate::writer::fingerprint`] of the card once written. Not printed
+    /// on its own but carried by the [`Summary`].
+    Fingerprint(String),
+    /// Always the last line of a worker run.
+    Summary(Summary),
+}
+
+/// What the worker's parent can ask for, one JSON line per command on the
+/// worker's stdin, e.g. `{"command":"pause"}`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum WorkerCommand {
+    Abort,
+    /// Finish without reading the card back.
+    SkipVerify,
+    Pause,
+    Resume,
+}
+
+impl WorkerCommand {
+    /// A command as the worker reads it from stdin.
+    pub fn parse(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok()
+    }
+
+    /// The command as a line for the worker's stdin.
+    pub fn to_line(self) -> String {
+        serde_json::to_string(&self).unwrap_or_default() + "\n"
+    }
+}
+
+/// One line of worker output.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope<E> {
+    /// Starts at 0 and grows by one per line, so gaps show lost lines.
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch.
+    pub time: u64,
+    /// The phase the worker was in when the line was printed.
+    pub phase: Option<WritingPhase>,
+    #[serde(flatten)]
+    pub event: E,
+}
+
+/// The last line of worker output: how the run went and how long each
+/// phase took.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    /// Type of the last event other than a status or progress update, e.g.
+    /// "Finished" or "Error".
+    pub outcome: Option<String>,
+    pub elapsed_ms: u64,
+    /// Milliseconds spent in each phase, in the order they were entered.
+    pub phases: Vec<(WritingPhase, u64)>,
+    /// [`ProgressEvent::Fingerprint`] of the written card.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+/// Status lines closer together than this within a phase are merged: only
+/// the latest is printed, once the interval is over or before the next
+/// other event, so the status that ends a burst (e.g. "Paused") is never
+/// lost.
+const STATUS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Numbering and phase tracking of the lines this process printed.
+struct Reporter {
+    seq: u64,
+    started: Instant,
+    phases: Vec<(WritingPhase, Instant)>,
+    last_status: Option<Instant>,
+    /// The latest status that came too soon after the previous one.
+    held_status: Option<String>,
+    /// Whether a thread is already waiting to print the held status.
+    flush_scheduled: bool,
+    outcome: Option<String>,
+    fingerprint: Option<String>,
+}
+
+impl Reporter {
+    fn new() -> Self {
+        Self {
+            seq: 0,
+            started: Instant::now(),
+            phases: Vec::new(),
+            last_status: None,
+            held_status: None,
+            flush_scheduled: false,
+            outcome: None,
+            fingerprint: None,
+        }
+    }
+
+    fn envelope<'a>(&mut self, event: &'a ProgressEvent) -> Option<Envelope<&'a ProgressEvent>> {
+        let now = Instant::now();
+        match event {
+            ProgressEvent::Status(status) => {
+                if self
+                    .last_status
+                    .is_some_and(|last| now.duration_since(last) < STATUS_INTERVAL)
+                {
+                    self.held_status = Some(status.clone());
+                    return None;
+                }
+                self.held_status = None;
+                self.last_status = Some(now);
+            }
+            ProgressEvent::Progress { phase, .. } => {
+                if self
+                    .phases
+                    .last()
+                    .is_none_or(|(current, _)| current != phase)
+                {
+                    self.phases.push((*phase, now));
+                    // The first status of a phase always gets through
+                    self.last_status = None;
+                }
+            }
+            ProgressEvent::Fingerprint(fingerprint) => {
+                self.fingerprint = Some(fingerprint.clone());
+                return None;
+            }
+            ProgressEvent::Synced(_)
+            | ProgressEvent::Source(_)
+            | ProgressEvent::SlowDownload
+            | ProgressEvent::Summary(_) => {}
+            other => {
+                self.outcome = serde_json::to_value(other)
+                    .ok()
+                    .and_then(|v| v["type"].as_str().map(str::to_string));
+            }
+        }
+
+        let envelope = Envelope {
+            seq: self.seq,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            phase: self.phases.last().map(|(phase, _)| *phase),
+            event,
+        };
+        self.seq += 1;
+        Some(envelope)
+    }
+
+    /// The JSON lines for `event`: the held status first unless `event` is
+    /// a status itself, which replaces it.
+    fn lines(&mut self, event: &ProgressEvent) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !matches!(event, ProgressEvent::Status(_)) {
+            lines.extend(self.flush());
+        }
+        lines.extend(
+            self.envelope(event)
+                .and_then(|envelope| serde_json::to_string(&envelope).ok()),
+        );
+        lines
+    }
+
+    /// The line of the held status, if there is one.
+    fn flush(&mut self) -> Option<String> {
+        let status = ProgressEvent::Status(self.held_status.take()?);
+        self.last_status = None;
+        self.envelope(&status)
+            .and_then(|envelope| serde_json::to_string(&envelope).ok())
+    }
+
+    fn summary(&self) -> Summary {
+        let now = Instant::now();
+        let ends = self.phases.iter().skip(1).map(|(_, start)| *start);
+        let phases = self
+            .phases
+            .iter()
+            .zip(ends.chain(std::iter::once(now)))
+            .map(|((phase, start), end)| (*phase, end.duration_since(*start).as_millis() as u64))
+            .collect();
+        Summary {
+            outcome: self.outcome.clone(),
+            elapsed_ms: now.duration_since(self.started).as_millis() as u64,
+            phases,
+            fingerprint: self.fingerprint.clone(),
+        }
+    }
+}
+
+static REPORTER: LazyLock<Mutex<Reporter>> = LazyLock::new(|| Mutex::new(Reporter::new()));
+
+/// Prints the [`Summary`] line. Called once, when the worker is done.
+pub fn emit_summary() {
+    let summary = REPORTER.lock().unwrap_or_else(|e| e.into_inner()).summary();
+    ProgressEvent::Summary(summary).emit();
+}
+
+/// Prints the held status once [`STATUS_INTERVAL`] is over, unless another
+/// line printed it first.
+fn schedule_flush(reporter: &mut Reporter) {
+    if reporter.held_status.is_none() || reporter.flush_scheduled {
+        return;
+    }
+    reporter.flush_scheduled = true;
+    std::thread::spawn(|| {
+        std::thread::sleep(STATUS_INTERVAL);
+        let mut reporter = REPORTER.lock().unwrap_or_else(|e| e.into_inner());
+        reporter.flush_scheduled = false;
+        if let Some(line) = reporter.flush() {
+            println!("{}", line);
+        }
+    });
+}
+
+impl ProgressEvent {
+    /// Writes the event as a JSON line to stdout. A status following the
+    /// previous one too closely is held back and printed a little later.
+    pub fn emit(&self) {
+        let mut reporter = REPORTER.lock().unwrap_or_else(|e| e.into_inner());
+        for line in reporter.lines(self) {
+            println!("{}", line);
+        }
+        schedule_flush(&mut reporter);
+    }
+
+    /// An event from a line of worker output, dropping the envelope.
+    pub fn parse(line: &str) -> Option<Self> {
+        serde_json::from_str::<Envelope<Self>>(line)
+            .ok()
+            .map(|envelope| envelope.event)
+    }
+
+    /// The event for a failed run, with the kind `error` was tagged with.
+    pub fn error(error: &anyhow::Error) -> Self {
+        Self::Error {
+            kind: ErrorKind::of(error),
+            message: format!("{:#}", error),
+        }
+    }
+
+    /// An error that can't be told apart from others.
+    pub fn other_error(message: String) -> Self {
+        Self::Error {
+            kind: ErrorKind::Other,
+            message,
+        }
+    }
+
+    /// Whether no further events follow this one.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Self::Finished | Self::Error { .. } | Self::CustomizationFailed(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_lines_and_tracks_phases() {
+        let mut reporter = Reporter::new();
+        let events = [
+            ProgressEvent::Status("Starting download...".to_string()),
+            ProgressEvent::Status("Continuing prefetched download...".to_string()),
+            ProgressEvent::Progress {
+                phase: WritingPhase::Writing,
+                fraction: 0.5,
+            },
+            ProgressEvent::Progress {
+                phase: WritingPhase::Verifying,
+                fraction: 0.0,
+            },
+            ProgressEvent::Fingerprint("f00d".to_string()),
+            ProgressEvent::Finished,
+        ];
+        let lines: Vec<String> = events.iter().flat_map(|e| reporter.lines(e)).collect();
+
+        // The second status came too soon after the first, so it waited
+        // for the progress update
+        assert_eq!(lines.len(), 5);
+        let last: Envelope<ProgressEvent> = serde_json::from_str(&lines[4]).unwrap();
+        assert_eq!(last.seq, 4);
+        assert_eq!(last.phase, Some(WritingPhase::Verifying));
+        assert!(matches!(
+            ProgressEvent::parse(&lines[1]),
+            Some(ProgressEvent::Status(status)) if status == "Continuing prefetched download..."
+        ));
+        assert!(matches!(
+            ProgressEvent::parse(&lines[2]),
+            Some(ProgressEvent::Progress {
+                phase: WritingPhase::Writing,
+                ..
+            })
+        ));
+
+        let summary = reporter.summary();
+        assert_eq!(summary.outcome.as_deref(), Some("Finished"));
+        assert_eq!(summary.fingerprint.as_deref(), Some("f00d"));
+        let phases: Vec<WritingPhase> = summary.phases.iter().map(|(p, _)| *p).collect();
+        assert_eq!(phases, [WritingPhase::Writing, WritingPhase::Verifying]);
+    }
+
+    #[test]
+    fn keeps_the_status_that_ends_a_burst() {
+        let mut reporter = Reporter::new();
+        let status = |s: &str| ProgressEvent::Status(s.to_string());
+        assert_eq!(reporter.lines(&status("Writing... 10%")).len(), 1);
+        assert!(reporter.lines(&status("Writing... 11%")).is_empty());
+        assert!(reporter.lines(&status("Paused")).is_empty());
+
+        // Nothing else follows while paused; the interval ending prints it
+        let held = reporter.flush().unwrap();
+        assert!(matches!(
+            ProgressEvent::parse(&held),
+            Some(ProgressEvent::Status(s)) if s == "Paused"
+        ));
+        assert_eq!(reporter.flush(), None);
+    }
+}
@@ -0,0 +1,148 @@
+//! Keyboard layouts and variants offered by the picker. The host's XKB rules
+//! list everything the image's keyboard-configuration knows about, so it is
+//! used when present; the embedded table is the fallback.
+
+use std::sync::OnceLock;
+
+const RULES: [&str; 2] = [
+    "/usr/share/X11/xkb/rules/evdev.lst",
+    "/usr/share/X11/xkb/rules/base.lst",
+];
+
+/// A keyboard layout with its variant, as the picker lists it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyboard {
+    pub layout: String,
+    pub variant: Option<String>,
+    pub name: String,
+}
+
+impl Keyboard {
+    /// XKB notation, "us" or "us(dvorak)".
+    pub fn code(&self) -> String {
+        match &self.variant {
+            Some(variant) => format!("{}({})", self.layout, variant),
+            None => self.layout.clone(),
+        }
+    }
+}
+
+/// Splits a code from [`Keyboard::code`] into layout and variant.
+pub fn split_code(code: &str) -> (String, String) {
+    match code.split_once('(') {
+        Some((layout, variant)) => (
+            layout.to_string(),
+            variant.trim_end_matches(')').to_string(),
+        ),
+        None => (code.to_string(), String::new()),
+    }
+}
+
+/// The code for a layout and a variant that may be empty, the inverse of
+/// [`split_code`].
+pub fn join_code(layout: &str, variant: &str) -> String {
+    if variant.is_empty() {
+        layout.to_string()
+    } else {
+        format!("{}({})", layout, variant)
+    }
+}
+
+/// Read once per process, like the other host data.
+pub fn available() -> &'static [Keyboard] {
+    static KEYBOARDS: OnceLock<Vec<Keyboard>> = OnceLock::new();
+    KEYBOARDS.get_or_init(|| {
+        RULES
+            .iter()
+            .filter_map(|path| std::fs::read_to_string(path).ok())
+            .map(|text| parse_rules(&text))
+            .find(|keyboards| !keyboards.is_empty())
+            .unwrap_or_else(|| {
+                crate::static_data::get_keyboards()
+                    .into_iter()
+                    .map(|(layout, name)| Keyboard {
+                        layout: layout.to_string(),
+                        variant: None,
+                        name: name.to_string(),
+                    })
+                    .collect()
+            })
+    })
+}
+
+/// Parses an XKB `.lst` file: "! layout" lines are "us  English (US)",
+/// "! variant" lines are "dvorak  us: English (Dvorak)". Each layout is
+/// followed by its variants.
+fn parse_rules(text: &str) -> Vec<Keyboard> {
+    let mut layouts: Vec<Keyboard> = Vec::new();
+    let mut variants: Vec<Keyboard> = Vec::new();
+    let mut section = "";
+    for line in text.lines() {
+        if let Some(name) = line.strip_prefix("! ") {
+            section = name.trim();
+            continue;
+        }
+        let Some((code, description)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let description = description.trim();
+        match section {
+            "layout" => layouts.push(Keyboard {
+                layout: code.to_string(),
+                variant: None,
+                name: description.to_string(),
+            }),
+            "variant" => {
+                if let Some((layout, name)) = description.split_once(": ") {
+                    variants.push(Keyboard {
+                        layout: layout.to_string(),
+                        variant: Some(code.to_string()),
+                        name: name.to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut keyboards = Vec::with_capacity(layouts.len() + variants.len());
+    for layout in layouts {
+        let code = layout.layout.clone();
+        keyboards.push(layout);
+        keyboards.extend(variants.iter().filter(|v| v.layout == code).cloned());
+    }
+    keyboards
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_layouts_with_their_variants() {
+        let text = "\
+! model
+  pc105           Generic 105-key PC
+! layout
+  us              English (US)
+  gb              English (UK)
+! variant
+  dvorak          us: English (Dvorak)
+  extd            gb: English (UK, extended, Windows)
+  intl            us: English (US, intl., with dead keys)
+! option
+  grp             Switching to another layout
+";
+        let codes: Vec<String> = parse_rules(text).iter().map(Keyboard::code).collect();
+
+        assert_eq!(codes, ["us", "us(dvorak)", "us(intl)", "gb", "gb(extd)"]);
+        assert_eq!(parse_rules(text)[1].name, "English (Dvorak)");
+        assert_eq!(
+            split_code("us(dvorak)"),
+            ("us".to_string(), "dvorak".to_string())
+        );
+        assert_eq!(split_code("gb"), ("gb".to_string(), String::new()));
+        assert_eq!(join_code("us", "dvorak"), "us(dvorak)");
+        assert_eq!(join_code("gb", ""), "gb");
+    }
+}
@@ -0,0 +1,80 @@
+//! The imaging engine of rpi-imager-tui, for tools that write cards without
+//! its terminal interface, such as graphical frontends or provisioning
+//! daemons.
+//!
+//! - [`os_list`] fetches the list of operating systems to choose from.
+//! - [`drivelist`] lists the drives an image can be written to.
+//! - [`customization`] holds what the first boot sets up: hostname, user,
+//!   Wi-Fi, SSH and the rest.
+//! - [`writer`] downloads, decompresses, writes and verifies an image, and
+//!   writes the customization onto its boot partition ([`post_process`]).
+//! - [`compression`] decodes images; more formats can be registered there.
+//!
+//! Writing opens the device itself, so it needs the privileges for that;
+//! the interface runs it in a worker process started through sudo, pkexec
+//! or with a device handed over by udisks2. Progress arrives on a channel as
+//! [`ipc::ProgressEvent`]s, and [`writer::Control`] pauses or aborts a write
+//! from another task.
+//!
+//! ```no_run
+//! use rpi_imager_core::customization::CustomizationOptions;
+//! use rpi_imager_core::{drivelist, http, os_list, writer};
+//! use std::sync::Arc;
+//!
+//! # async fn provision() -> anyhow::Result<()> {
+//! http::set_user_agent("kiosk-provisioner/1.0".to_string());
+//! let options = CustomizationOptions {
+//!     hostname: "kiosk".to_string(),
+//!     ..CustomizationOptions::default()
+//! };
+//! let client = http::client(&options)?;
+//! let os = os_list::fetch(&client).await?.os_list.remove(0);
+//! // A removable drive, never the disk the host runs from
+//! let drive = drivelist::get_drives()
+//!     .map_err(|e| anyhow::anyhow!("{}", e))?
+//!     .into_iter()
+//!     .find(|d| d.removable && !d.is_system())
+//!     .ok_or_else(|| anyhow::anyhow!("No card to write to"))?;
+//!
+//! let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+//! let control = Arc::new(writer::Control::default());
+//! let job = writer::WriteJob::new(os, drive, options);
+//! let write = tokio::spawn(writer::write_image(job, control, tx));
+//! while let Some(event) = rx.recv().await {
+//!     println!("{:?}", event);
+//! }
+//! write.await??;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod boot_diff;
+pub mod cache;
+pub mod compression;
+pub mod customization;
+pub mod diskutil;
+pub mod drivelist;
+pub mod eeprom;
+pub mod eject;
+pub mod error;
+pub mod fat;
+pub mod first_boot;
+pub mod host_locale;
+pub mod http;
+pub mod image_size;
+pub mod in_use;
+pub mod inspect;
+pub mod ipc;
+pub mod keyboards;
+pub mod lsblk;
+pub mod os_list;
+pub mod post_process;
+pub mod secrets;
+pub mod static_data;
+pub mod target;
+#[cfg(unix)]
+pub mod udisks;
+pub mod unzip;
+pub mod windisk;
+pub mod workspace;
+pub mod writer;
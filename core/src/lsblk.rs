@@ -0,0 +1,96 @@
+//! Parsing of `lsblk -J -b` output across util-linux versions.
+//!
+//! Older releases (< 2.33) print every column as a string ("1", "0",
+//! "15931539456"); newer ones use numbers and booleans. Mountpoints come as
+//! "mountpoint" or, with the MOUNTPOINTS column (2.37+), as a "mountpoints"
+//! array that may contain nulls.
+
+use serde::{Deserialize, Deserializer};
+
+/// The JSON printed by `lsblk --json`.
+#[derive(Debug, Deserialize)]
+pub struct LsblkOutput {
+    pub blockdevices: Vec<LsblkDevice>,
+}
+
+/// A block device, with its partitions as children.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LsblkDevice {
+    pub name: String,
+    #[serde(deserialize_with = "size")]
+    pub size: u64,
+    #[serde(default, deserialize_with = "non_empty")]
+    pub model: Option<String>,
+    #[serde(rename = "type")]
+    pub device_type: String,
+    #[serde(default)]
+    pub mountpoint: Option<String>,
+    #[serde(default)]
+    pub mountpoints: Vec<Option<String>>,
+    #[serde(default, deserialize_with = "non_empty")]
+    pub label: Option<String>,
+    #[serde(default, deserialize_with = "non_empty")]
+    pub serial: Option<String>,
+    #[serde(default, deserialize_with = "non_empty")]
+    pub tran: Option<String>,
+    #[serde(default, deserialize_with = "flag")]
+    pub rota: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub rm: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub ro: bool,
+    #[serde(default)]
+    pub children: Vec<LsblkDevice>,
+}
+
+impl LsblkDevice {
+    /// Mountpoints of the device itself and all of its partitions.
+    pub fn all_mountpoints(&self) -> Vec<String> {
+        let mut mountpoints: Vec<String> = self
+            .mountpoint
+            .iter()
+            .chain(self.mountpoints.iter().flatten())
+            .filter(|mp| !mp.is_empty())
+            .cloned()
+            .collect();
+        mountpoints.dedup();
+        for child in &self.children {
+            mountpoints.extend(child.all_mountpoints());
+        }
+        mountpoints
+    }
+}
+
+/// The devices in the output of `lsblk --json --bytes`.
+pub fn parse(json: &str) -> serde_json::Result<Vec<LsblkDevice>> {
+    serde_json::from_str::<LsblkOutput>(json).map(|out| out.blockdevices)
+}
+
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(n) => n
+            .as_u64()
+            .ok_or_else(|| serde::de::Error::custom("Invalid size number")),
+        serde_json::Value::String(s) => s.trim().parse::<u64>().map_err(serde::de::Error::custom),
+        // Empty card readers report no size at all
+        serde_json::Value::Null => Ok(0),
+        _ => Err(serde::de::Error::custom("Invalid size format")),
+    }
+}
+
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(b) => b,
+        serde_json::Value::String(s) => s.trim() == "1" || s.trim().eq_ignore_ascii_case("true"),
+        serde_json::Value::Number(n) => n.as_i64() == Some(1),
+        _ => false,
+    })
+}
+
+/// Old releases pad some columns with spaces or print "" instead of null.
+fn non_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty()))
+}
@@ -0,0 +1,772 @@
+use crate::compression::Compression;
+use crate::customization::CustomizationSection;
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The official list of operating systems.
+pub const OS_LIST_URL: &str = "https://downloads.raspberrypi.com/os_list_imagingutility_v4.json";
+
+/// An os_list JSON, the official one or that of another source.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsList {
+    // Community lists often only carry os_list
+    #[serde(default)]
+    pub imager: ImagerInfo,
+    pub os_list: Vec<OsListItem>,
+}
+
+/// What the list says about Raspberry Pi Imager itself and the boards.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImagerInfo {
+    #[serde(default)]
+    pub latest_version: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub devices: Vec<Device>,
+}
+
+/// An additional os_list JSON (e.g. a community list for other SBCs),
+/// registered in the config file. `url` may also be a local path.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OsSource {
+    pub name: String,
+    pub url: String,
+}
+
+/// A board images are filtered by, e.g. "Raspberry Pi 5".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub matching_type: Option<String>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    #[serde(default)]
+    pub default: bool,
+}
+
+/// An image, or a category of images in `subitems`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsListItem {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub random: bool,
+
+    // Subitems (for categories)
+    #[serde(default)]
+    pub subitems: Vec<OsListItem>,
+    /// A category whose entries are in a list of their own, fetched when it
+    /// is highlighted.
+    #[serde(default)]
+    pub subitems_url: Option<String>,
+
+    // Image specific fields
+    pub url: Option<String>,
+    pub extract_size: Option<u64>,
+    pub extract_sha256: Option<String>,
+    pub image_download_size: Option<u64>,
+    pub image_download_sha256: Option<String>,
+    pub release_date: Option<String>,
+    pub init_format: Option<String>,
+
+    #[serde(default)]
+    pub devices: Vec<String>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+
+    pub website: Option<String>,
+    pub tooltip: Option<String>,
+    pub architecture: Option<String>,
+    #[serde(default, rename = "enable_rpi_connect")]
+    pub enable_rpi_connect: bool,
+}
+
+/// Fetches the official list.
+pub async fn fetch(client: &Client) -> Result<OsList> {
+    fetch_from(client, OS_LIST_URL).await
+}
+
+/// Fetches the list at `url`, which may also be a local path. Lists may
+/// be compressed.
+pub async fn fetch_from(client: &Client, url: &str) -> Result<OsList> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        let body = crate::http::get(client, url)
+            .await?
+            .bytes()
+            .await
+            .context(format!("Failed to download OS list from {}", url))?;
+        let json = decompress(&body)
+            .await
+            .context(format!("Failed to decompress OS list from {}", url))?;
+        let list = parse(&json).context(format!("Failed to parse OS list from {}", url))?;
+        if url == OS_LIST_URL
+            && let Some(path) = cache_path()
+        {
+            let _ = std::fs::create_dir_all(path.parent().unwrap_or(&path));
+            let _ = std::fs::write(path, &json);
+        }
+        Ok(list)
+    } else {
+        let data = tokio::fs::read(url)
+            .await
+            .context(format!("Failed to read OS list {}", url))?;
+        parse(&data).context(format!("Failed to parse OS list {}", url))
+    }
+}
+
+/// The os_list layouts in use. Lists don't state their version, so it is
+/// told by shape: a bare array of items in the first lists, devices without
+/// capabilities or matching_type in v3, and an explicit `version` above 4
+/// for anything newer.
+#[derive(Debug, PartialEq)]
+enum Schema {
+    Bare,
+    V3,
+    V4,
+    Newer(u64),
+}
+
+impl Schema {
+    fn detect(doc: &Value) -> Self {
+        if doc.is_array() {
+            return Self::Bare;
+        }
+        if let Some(version) = doc
+            .get("version")
+            .or_else(|| doc.get("schema_version"))
+            .and_then(Value::as_u64)
+            && version > 4
+        {
+            return Self::Newer(version);
+        }
+        match doc.pointer("/imager/devices").and_then(Value::as_array) {
+            Some(devices)
+                if !devices.is_empty()
+                    && devices.iter().all(|d| {
+                        d.get("capabilities").is_none() && d.get("matching_type").is_none()
+                    }) =>
+            {
+                Self::V3
+            }
+            _ => Self::V4,
+        }
+    }
+}
+
+/// Parses an os_list of any schema version into the v4 model. v4 lists are
+/// parsed strictly, so a broken official list is reported. Older lists get
+/// their differences converted first, and older and newer lists skip the
+/// entries that still don't fit instead of failing as a whole.
+pub fn parse(json: &[u8]) -> Result<OsList> {
+    let mut doc: Value = serde_json::from_slice(json)?;
+    let schema = Schema::detect(&doc);
+    if schema == Schema::V4 {
+        return Ok(serde_json::from_value(doc)?);
+    }
+    if schema == Schema::Bare {
+        doc = serde_json::json!({ "os_list": doc });
+    }
+    if matches!(schema, Schema::Bare | Schema::V3) {
+        upgrade_v3(&mut doc);
+    }
+    if let Ok(list) = serde_json::from_value(doc.clone()) {
+        return Ok(list);
+    }
+
+    let imager = doc
+        .get("imager")
+        .and_then(|imager| serde_json::from_value(imager.clone()).ok())
+        .unwrap_or_default();
+    let items = doc
+        .get("os_list")
+        .and_then(Value::as_array)
+        .context("No os_list in the document")?;
+    let os_list: Vec<OsListItem> = items.iter().filter_map(lenient_item).collect();
+    if os_list.is_empty() && !items.is_empty() {
+        anyhow::bail!("None of the {} entries could be read", items.len());
+    }
+    Ok(OsList { imager, os_list })
+}
+
+/// The item with the subitems that can be read, or None if the item itself
+/// can't be.
+fn lenient_item(value: &Value) -> Option<OsListItem> {
+    let mut item = value.clone();
+    let subitems = match item.as_object_mut()?.remove("subitems") {
+        Some(Value::Array(subitems)) => subitems.iter().filter_map(lenient_item).collect(),
+        _ => Vec::new(),
+    };
+    let mut item: OsListItem = serde_json::from_value(item).ok()?;
+    item.subitems = subitems;
+    Some(item)
+}
+
+/// Converts what v3 and earlier lists write differently: sizes as strings,
+/// null subitems and devices without tags.
+fn upgrade_v3(doc: &mut Value) {
+    fn upgrade_item(item: &mut Value) {
+        let Some(fields) = item.as_object_mut() else {
+            return;
+        };
+        for key in ["extract_size", "image_download_size"] {
+            if let Some(Value::String(size)) = fields.get(key)
+                && let Ok(size) = size.trim().parse::<u64>()
+            {
+                fields.insert(key.to_string(), size.into());
+            }
+        }
+        if fields.get("subitems").is_some_and(Value::is_null) {
+            fields.remove("subitems");
+        }
+        if let Some(Value::Array(subitems)) = fields.get_mut("subitems") {
+            subitems.iter_mut().for_each(upgrade_item);
+        }
+    }
+
+    if let Some(Value::Array(items)) = doc.get_mut("os_list") {
+        items.iter_mut().for_each(upgrade_item);
+    }
+    if let Some(Value::Array(devices)) = doc.pointer_mut("/imager/devices") {
+        for device in devices.iter_mut().filter_map(Value::as_object_mut) {
+            device
+                .entry("tags")
+                .or_insert_with(|| Value::Array(Vec::new()));
+        }
+    }
+}
+
+/// Mirrors sometimes serve the list gzip or xz compressed without saying
+/// so in Content-Encoding, so this goes by the magic bytes.
+async fn decompress(body: &[u8]) -> Result<Vec<u8>> {
+    use async_compression::tokio::bufread::{GzipDecoder, XzDecoder};
+    use tokio::io::AsyncReadExt;
+
+    let mut json = Vec::new();
+    match Compression::detect(body) {
+        Compression::Gzip => {
+            GzipDecoder::new(body).read_to_end(&mut json).await?;
+        }
+        Compression::Xz => {
+            XzDecoder::new(body).read_to_end(&mut json).await?;
+        }
+        _ => json.extend_from_slice(body),
+    }
+    Ok(json)
+}
+
+/// The last official list that was fetched successfully.
+fn cache_path() -> Option<std::path::PathBuf> {
+    crate::cache::cache_dir().map(|dir| dir.join("os_list.json"))
+}
+
+/// The cached official list and when it was fetched, for when the server
+/// can't be reached or keeps failing.
+pub fn cached() -> Option<(OsList, std::time::SystemTime)> {
+    let path = cache_path()?;
+    let fetched = std::fs::metadata(&path).ok()?.modified().ok()?;
+    let list = parse(&std::fs::read(&path).ok()?).ok()?;
+    Some((list, fetched))
+}
+
+/// Fetches the official list and merges in every configured extra source.
+/// Sources that fail to load are skipped so they cannot block the official list.
+/// When the official list can't be fetched, the cached copy is used instead
+/// and the second value says why, for a banner.
+pub async fn fetch_all(client: &Client, sources: &[OsSource]) -> Result<(OsList, Option<String>)> {
+    let (mut list, degraded) = match fetch(client).await {
+        Ok(list) => (list, None),
+        Err(e) => {
+            let Some((list, fetched)) = cached() else {
+                return Err(e);
+            };
+            let age = fetched.elapsed().unwrap_or_default().as_secs() / 3600;
+            let notice = format!(
+                "Offline: showing the OS list cached {} ago ({})",
+                if age < 48 {
+                    format!("{} hours", age)
+                } else {
+                    format!("{} days", age / 24)
+                },
+                e
+            );
+            (list, Some(notice))
+        }
+    };
+    for source in sources {
+        if let Ok(extra) = fetch_from(client, &source.url).await {
+            list.merge(source, extra);
+        }
+    }
+    Ok((list, degraded))
+}
+
+const CUSTOM_ENTRY: &str = "Use custom image";
+
+impl OsListItem {
+    /// An image that isn't in any OS list, known only by its location.
+    pub fn custom(name: String, url: String) -> Self {
+        Self {
+            name,
+            description: String::new(),
+            icon: None,
+            random: false,
+            subitems: Vec::new(),
+            subitems_url: None,
+            url: Some(url),
+            extract_size: None,
+            extract_sha256: None,
+            image_download_size: None,
+            image_download_sha256: None,
+            release_date: None,
+            init_format: None,
+            devices: Vec::new(),
+            capabilities: Vec::new(),
+            website: None,
+            tooltip: None,
+            architecture: None,
+            enable_rpi_connect: false,
+        }
+    }
+
+    /// An image given by the user as a URL or a path; paths are made
+    /// absolute so the worker finds them from any directory.
+    pub fn from_location(location: &str) -> Self {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            let name = location.rsplit('/').next().unwrap_or(location).to_string();
+            return Self {
+                description: format!("Image from {}", location),
+                ..Self::custom(name, location.to_string())
+            };
+        }
+        let path = std::path::Path::new(location);
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Custom Image".to_string());
+        Self {
+            description: format!("Local Image: {}", path.display()),
+            ..Self::custom(name, path.to_string_lossy().to_string())
+        }
+    }
+
+    /// The entry at the end of the OS list that asks for a local file.
+    pub fn custom_entry() -> Self {
+        Self {
+            description: "Write an image file from this computer: .img, or compressed as \
+                .img.xz, .img.gz, .img.zst, .img.bz2 or .zip."
+                .to_string(),
+            url: None,
+            ..Self::custom(CUSTOM_ENTRY.to_string(), String::new())
+        }
+    }
+
+    /// Whether this is the "Use custom" entry from [`Self::custom_entry`].
+    pub fn is_custom_entry(&self) -> bool {
+        self.url.is_none() && self.subitems.is_empty() && self.name == CUSTOM_ENTRY
+    }
+
+    /// Whether writing this image can honour the given customization section.
+    /// Listed images without an init_format don't run firstrun.sh or
+    /// cloud-init at all; local images are assumed to support everything.
+    /// Home Assistant OS only takes the network and SSH keys, LibreELEC only
+    /// the SSH switch.
+    pub fn supports(&self, section: CustomizationSection) -> bool {
+        let listed = self.url.as_deref().is_some_and(|u| u.starts_with("http"));
+        let supported = match self.customization_format().as_deref() {
+            None => !listed,
+            Some("haos") => matches!(
+                section,
+                CustomizationSection::Wifi | CustomizationSection::RemoteAccess
+            ),
+            Some("libreelec") => section == CustomizationSection::RemoteAccess,
+            Some(_) => true,
+        };
+        supported
+            && !self
+                .capabilities
+                .iter()
+                .any(|c| c == section.unsupported_capability())
+    }
+
+    /// The image's `init_format`. Home Assistant OS and LibreELEC don't
+    /// declare one but have their own configuration files, so they are
+    /// recognized by name.
+    pub fn customization_format(&self) -> Option<String> {
+        if self.init_format.is_some() {
+            return self.init_format.clone();
+        }
+        if self.name.starts_with("Home Assistant") {
+            Some("haos".to_string())
+        } else if self.name.starts_with("LibreELEC") {
+            Some("libreelec".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+impl Device {
+    /// Whether `item` is offered for this board, decided like the official
+    /// imager does: entries listing one of the board's tags are, and entries
+    /// listing none are too unless the board's matching is "exclusive".
+    /// Boards without tags ("No filtering") take everything. Categories and
+    /// tools like Erase don't list boards and are never hidden for it.
+    pub fn supports(&self, item: &OsListItem) -> bool {
+        if self.tags.is_empty() {
+            return true;
+        }
+        if !item.devices.is_empty() {
+            return item.devices.iter().any(|tag| self.tags.contains(tag));
+        }
+        let image = item.url.as_deref().is_some_and(|u| u.starts_with("http"));
+        !image || self.matching_type.as_deref() != Some("exclusive")
+    }
+
+    /// The entries of `items` offered for this board, without categories
+    /// that end up empty.
+    pub fn filter(&self, items: &[OsListItem]) -> Vec<OsListItem> {
+        items
+            .iter()
+            .filter(|item| self.supports(item))
+            .filter_map(|item| {
+                if item.subitems.is_empty() {
+                    return Some(item.clone());
+                }
+                let subitems = self.filter(&item.subitems);
+                (!subitems.is_empty()).then(|| OsListItem {
+                    subitems,
+                    ..item.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+impl OsList {
+    /// Adds another source's devices, and its images as a category named
+    /// after the source.
+    pub fn merge(&mut self, source: &OsSource, other: OsList) {
+        if other.imager.devices.is_empty() {
+            self.imager.devices.push(Device {
+                name: source.name.clone(),
+                tags: Vec::new(),
+                icon: None,
+                description: format!("Boards from {}", source.url),
+                matching_type: None,
+                capabilities: Vec::new(),
+                default: false,
+            });
+        } else {
+            self.imager.devices.extend(other.imager.devices);
+        }
+
+        self.os_list.push(OsListItem {
+            name: source.name.clone(),
+            description: format!("Images from {}", source.url),
+            icon: None,
+            random: false,
+            subitems: other.os_list,
+            subitems_url: None,
+            url: None,
+            extract_size: None,
+            extract_sha256: None,
+            image_download_size: None,
+            image_download_sha256: None,
+            release_date: None,
+            init_format: None,
+            devices: Vec::new(),
+            capabilities: Vec::new(),
+            website: None,
+            tooltip: None,
+            architecture: None,
+            enable_rpi_connect: false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::{Map, Value, json};
+
+    const SAMPLE: &str = include_str!("../tests/fixtures/os_list_v4_sample.json");
+
+    #[test]
+    fn filters_by_device_tags() {
+        let list: OsList = serde_json::from_str(SAMPLE).unwrap();
+        let names = |device: &Device| -> Vec<String> {
+            fn walk(items: &[OsListItem], names: &mut Vec<String>) {
+                for item in items {
+                    names.push(item.name.clone());
+                    walk(&item.subitems, names);
+                }
+            }
+            let mut names = Vec::new();
+            walk(&device.filter(&list.os_list), &mut names);
+            names
+        };
+        let devices = &list.imager.devices;
+
+        // Everything that doesn't list boards stays for an inclusive board
+        let pi4 = names(&devices[1]);
+        assert!(pi4.contains(&"Raspberry Pi OS (Legacy, 32-bit) Lite".to_string()));
+        assert!(pi4.contains(&"LibreELEC".to_string()));
+        assert!(!pi4.contains(&"Bootloader (Pi 5 family)".to_string()));
+
+        let pi5 = names(&devices[0]);
+        assert!(!pi5.contains(&"Raspberry Pi OS (Legacy, 32-bit) Lite".to_string()));
+        assert!(pi5.contains(&"SD Card Boot".to_string()));
+        assert!(pi5.contains(&"Erase".to_string()));
+
+        assert_eq!(names(&devices[3]).len(), 13);
+    }
+
+    #[test]
+    fn parses_v4_feed_sample() {
+        let list: OsList = serde_json::from_str(SAMPLE).unwrap();
+
+        assert_eq!(list.imager.latest_version, "1.9.6");
+        assert_eq!(list.imager.devices.len(), 4);
+        assert!(list.imager.devices[3].default);
+        assert_eq!(list.imager.devices[2].capabilities, Vec::<String>::new());
+
+        assert_eq!(list.os_list.len(), 7);
+        let recommended = &list.os_list[0];
+        assert_eq!(recommended.init_format.as_deref(), Some("systemd"));
+        assert_eq!(recommended.extract_size, Some(6006243328));
+        assert!(recommended.enable_rpi_connect);
+
+        // Categories pointing at another list (subitems_url) have no subitems
+        let ubuntu = &list.os_list[2].subitems[0];
+        assert_eq!(ubuntu.name, "Ubuntu");
+        assert!(ubuntu.subitems.is_empty() && ubuntu.url.is_none());
+        assert_eq!(
+            ubuntu.subitems_url.as_deref(),
+            Some("https://downloads.raspberrypi.com/os_list_imagingutility_ubuntu.json")
+        );
+
+        let bootloader = &list.os_list[4].subitems[0].subitems[0];
+        assert!(crate::eeprom::is_bootloader_image(
+            bootloader.url.as_deref().unwrap()
+        ));
+        assert!(bootloader.init_format.is_none());
+    }
+
+    #[tokio::test]
+    async fn decompresses_by_magic_bytes() {
+        use async_compression::tokio::bufread::GzipEncoder;
+        use tokio::io::AsyncReadExt;
+
+        let mut gzipped = Vec::new();
+        GzipEncoder::new(SAMPLE.as_bytes())
+            .read_to_end(&mut gzipped)
+            .await
+            .unwrap();
+        assert_eq!(decompress(&gzipped).await.unwrap(), SAMPLE.as_bytes());
+        assert_eq!(
+            decompress(SAMPLE.as_bytes()).await.unwrap(),
+            SAMPLE.as_bytes()
+        );
+    }
+
+    #[test]
+    fn sample_round_trips() {
+        let list: OsList = serde_json::from_str(SAMPLE).unwrap();
+        let once = serde_json::to_value(&list).unwrap();
+        let twice =
+            serde_json::to_value(serde_json::from_value::<OsList>(once.clone()).unwrap()).unwrap();
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn converts_other_schema_versions() {
+        let v3 = json!({
+            "imager": {
+                "latest_version": "1.7.5",
+                "url": "https://www.raspberrypi.com/software/",
+                "devices": [{ "name": "Raspberry Pi 4", "icon": null }]
+            },
+            "os_list": [{
+                "name": "Raspberry Pi OS (32-bit)",
+                "url": "https://downloads.raspberrypi.com/raspios.img.xz",
+                "extract_size": "3980394496",
+                "subitems": null
+            }]
+        });
+        assert_eq!(Schema::detect(&v3), Schema::V3);
+        let list = parse(v3.to_string().as_bytes()).unwrap();
+        assert_eq!(list.os_list[0].extract_size, Some(3980394496));
+        assert!(list.imager.devices[0].tags.is_empty());
+
+        let bare = json!([{ "name": "Raspbian" }]);
+        assert_eq!(parse(bare.to_string().as_bytes()).unwrap().os_list.len(), 1);
+
+        // Entries a newer schema changed are skipped, the rest still show
+        let newer = json!({
+            "version": 5,
+            "os_list": [
+                { "name": { "en": "Renamed" } },
+                { "name": "Category", "subitems": [
+                    { "name": "Kept", "url": "https://example.com/kept.img.xz" },
+                    { "name": "Dropped", "extract_size": { "bytes": 1 } }
+                ]}
+            ]
+        });
+        assert_eq!(Schema::detect(&newer), Schema::Newer(5));
+        let list = parse(newer.to_string().as_bytes()).unwrap();
+        assert_eq!(list.os_list.len(), 1);
+        assert_eq!(list.os_list[0].subitems.len(), 1);
+        assert_eq!(list.os_list[0].subitems[0].name, "Kept");
+
+        assert_eq!(
+            Schema::detect(&serde_json::from_str(SAMPLE).unwrap()),
+            Schema::V4
+        );
+    }
+
+    #[test]
+    fn community_list_without_imager_block() {
+        let list: OsList =
+            serde_json::from_value(json!({ "os_list": [{ "name": "Armbian" }] })).unwrap();
+        assert!(list.imager.devices.is_empty());
+        assert_eq!(list.os_list[0].name, "Armbian");
+    }
+
+    const ITEM_FIELDS: &[&str] = &[
+        "name",
+        "description",
+        "icon",
+        "random",
+        "subitems",
+        "subitems_url",
+        "url",
+        "extract_size",
+        "extract_sha256",
+        "image_download_size",
+        "release_date",
+        "init_format",
+        "devices",
+        "capabilities",
+        "enable_rpi_connect",
+    ];
+
+    /// Arbitrary JSON, biased towards the keys the parser looks at.
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            ".{0,16}".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            let key = prop_oneof![
+                prop::sample::select(ITEM_FIELDS).prop_map(str::to_string),
+                Just("os_list".to_string()),
+                Just("imager".to_string()),
+                "[a-z_]{1,12}",
+            ];
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..6).prop_map(Value::Array),
+                prop::collection::btree_map(key, inner, 0..8)
+                    .prop_map(|m| Value::Object(m.into_iter().collect())),
+            ]
+        })
+    }
+
+    /// Well-formed items with a random subset of the optional fields.
+    fn os_item() -> impl Strategy<Value = Value> {
+        let leaf = (
+            "[A-Za-z0-9 ()-]{1,30}",
+            prop::option::of("https://[a-z]{1,10}\\.com/[a-z0-9_-]{1,20}\\.img\\.(xz|gz|zst)"),
+            prop::option::of(any::<u64>()),
+            prop::option::of("[0-9a-f]{64}"),
+            prop::option::of(prop::sample::select(vec![
+                "systemd",
+                "cloudinit",
+                "cloudinit-rpi",
+            ])),
+            prop::collection::vec("pi[0-9]-(32|64)bit", 0..4),
+            prop::option::of("https://[a-z]{1,10}\\.com/[a-z_]{1,20}\\.json"),
+        )
+            .prop_map(|(name, url, size, sha, init, devices, subitems_url)| {
+                let mut item = Map::new();
+                item.insert("name".into(), name.into());
+                let mut opt = |key: &str, value: Option<Value>| {
+                    if let Some(v) = value {
+                        item.insert(key.into(), v);
+                    }
+                };
+                opt("url", url.map(Value::from));
+                opt("extract_size", size.map(Value::from));
+                opt("extract_sha256", sha.map(Value::from));
+                opt("init_format", init.map(Value::from));
+                opt("subitems_url", subitems_url.map(Value::from));
+                if !devices.is_empty() {
+                    item.insert("devices".into(), devices.into());
+                }
+                Value::Object(item)
+            });
+        leaf.prop_recursive(3, 32, 4, |inner| {
+            (inner.clone(), prop::collection::vec(inner, 0..4)).prop_map(|(mut item, subitems)| {
+                item["subitems"] = Value::Array(subitems);
+                item
+            })
+        })
+    }
+
+    fn count_items(items: &[OsListItem]) -> usize {
+        items.iter().map(|i| 1 + count_items(&i.subitems)).sum()
+    }
+
+    fn count_values(item: &Value) -> usize {
+        1 + item["subitems"]
+            .as_array()
+            .map_or(0, |s| s.iter().map(count_values).sum())
+    }
+
+    proptest! {
+        #[test]
+        fn parsing_arbitrary_json_never_panics(value in json_value()) {
+            let _ = serde_json::from_value::<OsList>(value.clone());
+            let _ = serde_json::from_value::<OsListItem>(value);
+        }
+
+        #[test]
+        fn well_formed_lists_parse(items in prop::collection::vec(os_item(), 0..6)) {
+            let expected: usize = items.iter().map(count_values).sum();
+            let list: OsList = serde_json::from_value(json!({ "os_list": items })).unwrap();
+            prop_assert_eq!(count_items(&list.os_list), expected);
+        }
+
+        #[test]
+        fn wrong_field_types_are_rejected(
+            item in os_item(),
+            (field, bad) in prop_oneof![
+                Just(("name", json!(42))),
+                Just(("extract_size", json!("big"))),
+                Just(("extract_size", json!(-1))),
+                Just(("subitems", json!({}))),
+                Just(("devices", json!("pi5-64bit"))),
+                Just(("url", json!(["a", "b"]))),
+                Just(("random", json!("yes"))),
+            ],
+        ) {
+            let mut item = item;
+            item[field] = bad;
+            prop_assert!(serde_json::from_value::<OsListItem>(item).is_err());
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use crate::boot_diff::FileDiff;
+use crate::customization::{CustomizationOptions, ExtraFile};
+use crate::first_boot::{Backend, Check};
+use crate::workspace::Workspace;
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::{Component, Path};
+use std::process::Command;
+
+/// Writes the customization onto the boot partition, in the format the
+/// image's first boot understands (see [`Backend`]), copies the extra files,
+/// then mounts it again read-only and returns the checklist of what the first
+/// boot will find, along with the edits to cmdline.txt and config.txt.
+pub fn apply_customization(
+    device_path: &str,
+    options: &CustomizationOptions,
+    init_format: Option<&str>,
+) -> Result<(Vec<Check>, Vec<FileDiff>)> {
+    if !options.needs_customization() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let changes = with_boot_partition(device_path, |boot| {
+        let before = crate::boot_diff::snapshot(boot);
+        let sizes = options
+            .extra_files
+            .iter()
+            .map(|file| fs::metadata(&file.source).map_or(0, |metadata| metadata.len()));
+        crate::fat::ensure_space(boot, sizes, crate::fat::CUSTOMIZATION_RESERVE)?;
+        Backend::select(init_format, boot).apply(boot, options)?;
+        copy_extra_files(boot, &options.extra_files)?;
+        Ok(crate::boot_diff::changes(&before, boot))
+    })?;
+    let checks = with_partition(device_path, true, |boot| {
+        let mut checks = Backend::select(init_format, boot).checks(boot, options);
+        checks.extend(options.extra_files.iter().map(|file| Check {
+            label: format!("{} is on the boot partition", file.destination),
+            passed: boot.join(&file.destination).is_file(),
+        }));
+        Ok(checks)
+    })?;
+    Ok((checks, changes))
+}
+
+/// Copies each extra file to its destination on the mounted boot partition,
+/// creating directories as needed. Destinations can't leave the partition.
+/// Text files get LF line endings, as the first boot reads them on Linux.
+fn copy_extra_files(boot: &Path, files: &[ExtraFile]) -> Result<()> {
+    for file in files {
+        crate::fat::check_name(&file.destination)?;
+        let destination = Path::new(&file.destination);
+        if destination
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            bail!(
+                "Extra file destination {} must be a relative path inside the boot partition",
+                file.destination
+            );
+        }
+        let target = boot.join(destination);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if file.source.is_empty() {
+            fs::write(&target, "")
+        } else if crate::fat::is_text(destination) {
+            fs::read(&file.source)
+                .and_then(|contents| fs::write(&target, crate::fat::normalize_newlines(&contents)))
+        } else {
+            fs::copy(&file.source, &target).map(|_| ())
+        }
+        .with_context(|| {
+            format!(
+                "Failed to copy {} to {} on the boot partition",
+                file.source, file.destination
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Mounts the boot (first) partition of `device_path`, runs `f` against the
+/// mount point and unmounts again, even if `f` fails.
+pub fn with_boot_partition<T>(device_path: &str, f: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+    with_partition(device_path, false, f)
+}
+
+fn with_partition<T>(
+    device_path: &str,
+    read_only: bool,
+    f: impl FnOnce(&Path) -> Result<T>,
+) -> Result<T> {
+    let boot_partition = get_boot_partition(device_path);
+    let workspace = Workspace::new()?;
+
+    // Wait a moment for kernel to refresh partition table after write
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    // Refresh partition table just in case
+    let _ = Command::new("partprobe").arg(device_path).output();
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    // Started without root, through udisks2
+    #[cfg(unix)]
+    if !crate::customization::is_root() {
+        let path = crate::udisks::mount(&boot_partition, read_only)?;
+        let result = f(&path);
+        crate::udisks::unmount(&boot_partition)?;
+        return result;
+    }
+
+    // The mount guard unmounts even if `f` panics or returns early
+    let mount = if read_only {
+        workspace.mount_read_only(&boot_partition)
+    } else {
+        workspace.mount(&boot_partition)
+    }
+    .context("Failed to mount boot partition")?;
+    let result = f(mount.path());
+    mount.unmount()?;
+
+    result
+}
+
+/// Post-write cleanup for SSD targets. Images carry a GPT sized for the image,
+/// so the backup header is moved to the real end of the disk, and the space
+/// past the image is discarded. Both steps are best effort.
+pub fn finalize_ssd(device_path: &str, image_size: u64) {
+    if is_gpt(device_path) {
+        let _ = Command::new("sgdisk").arg("-e").arg(device_path).output();
+    }
+
+    // The backup GPT now lives at the end of the disk, so only discard up to
+    // the last MiB to leave it intact.
+    let Some(device_size) = Command::new("blockdev")
+        .arg("--getsize64")
+        .arg(device_path)
+        .output()
+        .ok()
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+    else {
+        return;
+    };
+    let reserved = 1024 * 1024;
+    let offset = image_size.div_ceil(reserved) * reserved;
+    if device_size > offset + reserved {
+        let _ = Command::new("blkdiscard")
+            .arg("-o")
+            .arg(offset.to_string())
+            .arg("-l")
+            .arg((device_size - offset - reserved).to_string())
+            .arg(device_path)
+            .output();
+    }
+}
+
+fn is_gpt(device_path: &str) -> bool {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut signature = [0u8; 8];
+    fs::File::open(device_path)
+        .and_then(|mut f| {
+            f.seek(SeekFrom::Start(512))?;
+            f.read_exact(&mut signature)
+        })
+        .is_ok()
+        && &signature == b"EFI PART"
+}
+
+fn get_boot_partition(device_path: &str) -> String {
+    // Heuristic for partition name
+    if device_path.chars().last().unwrap().is_numeric() {
+        format!("{}p1", device_path)
+    } else {
+        format!("{}1", device_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_extra_files_inside_the_partition() {
+        let boot = tempfile::tempdir().unwrap();
+        let source = tempfile::NamedTempFile::new().unwrap();
+        fs::write(source.path(), "dtparam=audio=on\r\n").unwrap();
+        let file = |source: &str, destination: &str| ExtraFile {
+            source: source.to_string(),
+            destination: destination.to_string(),
+        };
+
+        copy_extra_files(
+            boot.path(),
+            &[
+                file(source.path().to_str().unwrap(), "config.txt"),
+                file("", "ssh"),
+                file(source.path().to_str().unwrap(), "provisioning/token"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(boot.path().join("config.txt")).unwrap(),
+            "dtparam=audio=on\n"
+        );
+        assert!(boot.path().join("ssh").is_file());
+        assert_eq!(
+            fs::read(boot.path().join("provisioning/token")).unwrap(),
+            b"dtparam=audio=on\r\n"
+        );
+
+        for escaping in ["../etc/passwd", "/etc/passwd", "wifi:home.txt"] {
+            assert!(copy_extra_files(boot.path(), &[file("", escaping)]).is_err());
+        }
+    }
+}
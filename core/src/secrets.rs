@@ -0,0 +1,72 @@
+//! Passphrase encryption of the passwords kept in config.toml, so a copy of
+//! the file doesn't leak them. Argon2id derives the key from the passphrase
+//! and ChaCha20-Poly1305 seals the secrets.
+
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// The passwords [`seal`] protects with a passphrase.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Secrets {
+    pub password: Option<String>,
+    pub wifi_password: String,
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Failed to derive key: {}", e))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// Encrypts the secrets into base64 of salt, nonce and ciphertext.
+pub fn seal(secrets: &Secrets, passphrase: &str) -> Result<String> {
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let plaintext = serde_json::to_vec(secrets)?;
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| anyhow!("Failed to encrypt secrets"))?;
+    Ok(STANDARD.encode([&salt[..], &nonce[..], &ciphertext[..]].concat()))
+}
+
+/// Decrypts what [`seal`] returned. Fails with a wrong passphrase.
+pub fn open(sealed: &str, passphrase: &str) -> Result<Secrets> {
+    let data = STANDARD.decode(sealed)?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err(anyhow!("Encrypted secrets are truncated"));
+    }
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let plaintext = cipher(passphrase, salt)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Wrong passphrase"))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_only_with_the_same_passphrase() {
+        let secrets = Secrets {
+            password: Some("hunter2".to_string()),
+            wifi_password: "lab wifi".to_string(),
+        };
+
+        let sealed = seal(&secrets, "correct horse").unwrap();
+
+        assert!(!sealed.contains("lab wifi"));
+        assert_eq!(open(&sealed, "correct horse").unwrap(), secrets);
+        assert!(open(&sealed, "battery staple").is_err());
+    }
+}
@@ -2,14 +2,28 @@ static TIMEZONES_DATA: &str = include_str!("../resources/timezones.txt");
 static KEYBOARDS_DATA: &str = include_str!("../resources/keyboards.csv");
 static LOCALES_DATA: &str = include_str!("../resources/locales.txt");
 
+/// The IANA time zones, e.g. "Europe/London".
 pub fn get_timezones() -> Vec<&'static str> {
     TIMEZONES_DATA.lines().filter(|l| !l.is_empty()).collect()
 }
 
+/// The locales offered in the picker, e.g. "en_GB.UTF-8".
 pub fn get_locales() -> Vec<&'static str> {
     LOCALES_DATA.lines().filter(|l| !l.is_empty()).collect()
 }
 
+/// Country codes taken from the territories of the known locales.
+pub fn get_countries() -> Vec<&'static str> {
+    let mut countries: Vec<&'static str> = get_locales()
+        .into_iter()
+        .filter_map(|l| l.split(['_', '.']).nth(1))
+        .collect();
+    countries.sort_unstable();
+    countries.dedup();
+    countries
+}
+
+/// The keyboard layouts as (XKB layout, country) pairs.
 pub fn get_keyboards() -> Vec<(&'static str, &'static str)> {
     KEYBOARDS_DATA
         .lines()
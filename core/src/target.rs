@@ -0,0 +1,109 @@
+//! What an image is written to. Cards and disks are block devices, but the
+//! writer can also produce an image file, or stream into a pipe where
+//! nothing can be read back. The writer asks the target what it can do
+//! instead of assuming a block device.
+
+use anyhow::{Context, Result};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+use tokio::fs::{File, OpenOptions};
+
+/// The kinds of target, told by [`Target::detect`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    BlockDevice,
+    RegularFile,
+    /// `-`, or any other stream (pipe, character device) that can only be
+    /// written front to back.
+    Stdout,
+}
+
+impl Target {
+    /// What `path` is; anything that isn't a device or stream yet becomes an
+    /// image file.
+    pub fn detect(path: &str) -> Self {
+        if path == "-" {
+            return Self::Stdout;
+        }
+        Self::detect_path(path)
+    }
+
+    #[cfg(unix)]
+    fn detect_path(path: &str) -> Self {
+        match std::fs::metadata(path).map(|m| m.file_type()) {
+            Ok(t) if t.is_block_device() => Self::BlockDevice,
+            Ok(t) if t.is_fifo() || t.is_char_device() || t.is_socket() => Self::Stdout,
+            // A file that doesn't exist yet is created
+            _ => Self::RegularFile,
+        }
+    }
+
+    /// Disks are only reachable as \\.\PhysicalDriveN, which has no metadata.
+    #[cfg(windows)]
+    fn detect_path(path: &str) -> Self {
+        if crate::windisk::is_physical_drive(path) {
+            Self::BlockDevice
+        } else {
+            Self::RegularFile
+        }
+    }
+
+    /// Whether the target can be seeked and read back, for wiping, delta
+    /// writes and verification.
+    pub fn can_read_back(self) -> bool {
+        self != Self::Stdout
+    }
+
+    /// Whether fsync means anything; pipes reject it.
+    pub fn can_sync(self) -> bool {
+        self != Self::Stdout
+    }
+
+    /// Whether the kernel exposes the image's partitions as device nodes, for
+    /// customization, bootloader settings and SSD finalization.
+    pub fn has_partitions(self) -> bool {
+        self == Self::BlockDevice
+    }
+
+    /// Opens `path` for writing as this kind of target.
+    pub async fn open(self, path: &str) -> Result<File> {
+        let result = match self {
+            #[cfg(windows)]
+            Self::BlockDevice => crate::windisk::open(path).await,
+            #[cfg(not(windows))]
+            Self::BlockDevice => OpenOptions::new().write(true).read(true).open(path).await,
+            Self::RegularFile => {
+                OpenOptions::new()
+                    .write(true)
+                    .read(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(path)
+                    .await
+            }
+            Self::Stdout if path == "-" => OpenOptions::new().write(true).open("/dev/stdout").await,
+            Self::Stdout => OpenOptions::new().write(true).open(path).await,
+        };
+        result.context(format!(
+            "Failed to open device {}. Ensure you are running with root privileges (sudo).",
+            path
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_targets() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let target = Target::detect(file.path().to_str().unwrap());
+        assert_eq!(target, Target::RegularFile);
+        assert!(target.can_read_back() && !target.has_partitions());
+
+        assert_eq!(Target::detect("-"), Target::Stdout);
+        assert_eq!(Target::detect("/dev/null"), Target::Stdout);
+        assert!(!Target::Stdout.can_read_back());
+    }
+}
@@ -0,0 +1,164 @@
+//! Writing without root. udisks2 hands out file descriptors of block devices
+//! to users polkit allows, usually whoever sits at the desktop, asking the
+//! desktop's polkit agent for confirmation if needed. The TUI obtains one and
+//! passes it on to an unprivileged worker; partitions are mounted
+//! through udisks2 as well. When udisks2 says no, the worker is started via
+//! sudo/pkexec as before.
+
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+const SERVICE: &str = "org.freedesktop.UDisks2";
+
+type Options<'a> = HashMap<&'a str, Value<'a>>;
+
+fn resolve(conn: &Connection, device: &str) -> Result<OwnedObjectPath> {
+    let spec: Options = HashMap::from([("path", Value::from(device))]);
+    let reply = conn.call_method(
+        Some(SERVICE),
+        "/org/freedesktop/UDisks2/Manager",
+        Some("org.freedesktop.UDisks2.Manager"),
+        "ResolveDevice",
+        &(spec, Options::new()),
+    )?;
+    let objects: Vec<OwnedObjectPath> = reply.body().deserialize()?;
+    objects
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("udisks2 doesn't know {}", device))
+}
+
+fn call(
+    device: &str,
+    interface: &str,
+    method: &str,
+    body: &(impl serde::Serialize + zbus::zvariant::DynamicType),
+) -> Result<zbus::Message> {
+    let conn = Connection::system().context("Failed to connect to the system bus")?;
+    let object = resolve(&conn, device)?;
+    conn.call_method(Some(SERVICE), &object, Some(interface), method, body)
+        .with_context(|| format!("udisks2 {} of {} failed", method, device))
+}
+
+/// Whether udisks2 runs on this machine, looked up once per process.
+pub fn available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        Connection::system()
+            .ok()
+            .and_then(|conn| {
+                zbus::blocking::fdo::DBusProxy::new(&conn)
+                    .ok()?
+                    .name_has_owner(SERVICE.try_into().ok()?)
+                    .ok()
+            })
+            .unwrap_or(false)
+    })
+}
+
+/// Opens `device` for reading and writing.
+pub fn open_device(device: &str) -> Result<OwnedFd> {
+    open(device, "rw", Options::new())
+}
+
+/// Opens `device` for reading, only if polkit allows it without asking.
+pub fn read_device(device: &str) -> Result<OwnedFd> {
+    let options = HashMap::from([("auth.no_user_interaction", Value::from(true))]);
+    open(device, "r", options)
+}
+
+fn open(device: &str, mode: &str, options: Options) -> Result<OwnedFd> {
+    let reply = call(
+        device,
+        "org.freedesktop.UDisks2.Block",
+        "OpenDevice",
+        &(mode, options),
+    )?;
+    let fd: zbus::zvariant::OwnedFd = reply.body().deserialize()?;
+    Ok(fd.into())
+}
+
+/// Mounts `partition` where udisks2 chooses, under /media or /run/media.
+pub fn mount(partition: &str, read_only: bool) -> Result<PathBuf> {
+    // The desktop may have mounted the fresh partitions already
+    let _ = unmount(partition);
+
+    let mut options = Options::new();
+    if read_only {
+        options.insert("options", Value::from("ro"));
+    }
+    let reply = call(
+        partition,
+        "org.freedesktop.UDisks2.Filesystem",
+        "Mount",
+        &(options,),
+    )?;
+    let path: String = reply.body().deserialize()?;
+    Ok(PathBuf::from(path))
+}
+
+/// Unmounts `partition` through udisks2.
+pub fn unmount(partition: &str) -> Result<()> {
+    call(
+        partition,
+        "org.freedesktop.UDisks2.Filesystem",
+        "Unmount",
+        &(Options::new(),),
+    )?;
+    Ok(())
+}
+
+/// Powers off the drive `device` is on, like `udisksctl power-off`.
+pub fn power_off(device: &str) -> Result<()> {
+    let conn = Connection::system().context("Failed to connect to the system bus")?;
+    let block = resolve(&conn, device)?;
+    let reply = conn.call_method(
+        Some(SERVICE),
+        &block,
+        Some("org.freedesktop.DBus.Properties"),
+        "Get",
+        &("org.freedesktop.UDisks2.Block", "Drive"),
+    )?;
+    let drive = OwnedObjectPath::try_from(reply.body().deserialize::<OwnedValue>()?)?;
+    conn.call_method(
+        Some(SERVICE),
+        &drive,
+        Some("org.freedesktop.UDisks2.Drive"),
+        "PowerOff",
+        &(Options::new(),),
+    )
+    .with_context(|| format!("udisks2 PowerOff of {} failed", device))?;
+    Ok(())
+}
+
+/// Lets a child process inherit `fd`, passed to it as `--device-fd`.
+pub fn inheritable(fd: &OwnedFd) -> Result<()> {
+    use nix::fcntl::{FcntlArg, FdFlag, fcntl};
+    fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty()))?;
+    Ok(())
+}
+
+// Set by the worker when it was started with `--device-fd`
+static DEVICE_FD: Mutex<Option<(String, OwnedFd)>> = Mutex::new(None);
+
+/// Takes ownership of the descriptor the TUI passed for `device`.
+pub fn adopt(device: String, fd: RawFd) {
+    // SAFETY: the TUI opened this descriptor for the worker alone, and
+    // `--device-fd` is parsed once, so nothing else owns or closes it.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    *DEVICE_FD.lock().unwrap_or_else(|e| e.into_inner()) = Some((device, fd));
+}
+
+/// The file the TUI passed for `device`, if it passed one.
+pub fn inherited(device: &str) -> Option<std::fs::File> {
+    let mut passed = DEVICE_FD.lock().unwrap_or_else(|e| e.into_inner());
+    if passed.as_ref()?.0 != device {
+        return None;
+    }
+    passed.take().map(|(_, fd)| std::fs::File::from(fd))
+}
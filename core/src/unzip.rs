@@ -0,0 +1,213 @@
+//! Streaming the first disk image out of a ZIP archive, so zipped images are
+//! written while they download. Only the local file headers in front of each
+//! entry are read; the central directory at the end is never needed.
+
+use anyhow::{Context, Result, bail};
+use async_compression::tokio::bufread::DeflateDecoder;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const ZIP64_EXTRA: u16 = 0x0001;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// General purpose flags: the entry is encrypted, and its sizes follow the
+/// data instead of being in the header.
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+const FLAG_DESCRIPTOR: u16 = 1 << 3;
+
+struct Entry {
+    name: String,
+    flags: u16,
+    method: u16,
+    /// Unknown (0) when the sizes follow in a data descriptor.
+    compressed_size: u64,
+    zip64: bool,
+}
+
+/// The contents of the first `.img` entry, skipping whatever comes before it
+/// (READMEs, licenses, directories).
+pub async fn image_entry<R>(mut reader: R) -> Result<Box<dyn AsyncRead + Unpin + Send>>
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    loop {
+        let Some(entry) = next_entry(&mut reader).await? else {
+            bail!("The ZIP archive holds no .img image");
+        };
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            bail!("{} in the ZIP archive is encrypted", entry.name);
+        }
+        if !entry.name.to_lowercase().ends_with(".img") {
+            skip(&mut reader, &entry).await?;
+            continue;
+        }
+        return match entry.method {
+            DEFLATED => Ok(Box::new(DeflateDecoder::new(reader))),
+            STORED if entry.flags & FLAG_DESCRIPTOR == 0 => {
+                Ok(Box::new(reader.take(entry.compressed_size)))
+            }
+            STORED => bail!("{} is stored without a size in the ZIP archive", entry.name),
+            method => bail!(
+                "{} uses ZIP compression method {}, only deflate is supported",
+                entry.name,
+                method
+            ),
+        };
+    }
+}
+
+/// The next local file header, or None once the central directory starts.
+async fn next_entry<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Entry>> {
+    let signature = reader
+        .read_u32_le()
+        .await
+        .context("The ZIP archive ended early")?;
+    if signature != LOCAL_HEADER {
+        return Ok(None);
+    }
+    let mut header = [0u8; 26];
+    reader.read_exact(&mut header).await?;
+    let u16_at = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+
+    let flags = u16_at(2);
+    let method = u16_at(4);
+    let mut compressed_size = u64::from(u32_at(14));
+    let uncompressed_size = u32_at(18);
+    let mut name = vec![0u8; usize::from(u16_at(22))];
+    let mut extra = vec![0u8; usize::from(u16_at(24))];
+    reader.read_exact(&mut name).await?;
+    reader.read_exact(&mut extra).await?;
+
+    // Entries over 4 GiB keep their sizes in the zip64 extra field, the
+    // uncompressed size first
+    let mut zip64 = false;
+    let mut fields = extra.as_slice();
+    while fields.len() >= 4 {
+        let id = u16::from_le_bytes([fields[0], fields[1]]);
+        let len = usize::from(u16::from_le_bytes([fields[2], fields[3]]));
+        let data = &fields[4..(4 + len).min(fields.len())];
+        if id == ZIP64_EXTRA {
+            zip64 = true;
+            let mut values = data
+                .chunks_exact(8)
+                .map(|v| u64::from_le_bytes(v.try_into().unwrap()));
+            if uncompressed_size == u32::MAX {
+                values.next();
+            }
+            if compressed_size == u64::from(u32::MAX)
+                && let Some(size) = values.next()
+            {
+                compressed_size = size;
+            }
+        }
+        fields = &fields[(4 + len).min(fields.len())..];
+    }
+
+    Ok(Some(Entry {
+        name: String::from_utf8_lossy(&name).into_owned(),
+        flags,
+        method,
+        compressed_size,
+        zip64,
+    }))
+}
+
+/// Reads past an entry that isn't the image.
+async fn skip<R: AsyncBufRead + Unpin>(reader: &mut R, entry: &Entry) -> Result<()> {
+    let mut sink = tokio::io::sink();
+    if entry.flags & FLAG_DESCRIPTOR == 0 {
+        tokio::io::copy(&mut (&mut *reader).take(entry.compressed_size), &mut sink).await?;
+        return Ok(());
+    }
+    // The size is only known after the data, so the entry is inflated to
+    // find its end
+    if entry.method != DEFLATED {
+        bail!(
+            "Can't skip {} in the ZIP archive, its size is unknown",
+            entry.name
+        );
+    }
+    tokio::io::copy(&mut DeflateDecoder::new(&mut *reader), &mut sink).await?;
+    // crc32 and both sizes, after an optional signature
+    let mut descriptor = [0u8; 4];
+    reader.read_exact(&mut descriptor).await?;
+    let rest = if entry.zip64 { 16 } else { 8 };
+    let rest = if u32::from_le_bytes(descriptor) == DATA_DESCRIPTOR {
+        rest + 4
+    } else {
+        rest
+    };
+    tokio::io::copy(&mut (&mut *reader).take(rest), &mut sink).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::write::DeflateEncoder;
+    use tokio::io::{AsyncWriteExt, BufReader};
+
+    async fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new());
+        encoder.write_all(data).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        encoder.into_inner()
+    }
+
+    fn local_header(name: &str, flags: u16, method: u16, size: usize) -> Vec<u8> {
+        let mut header = LOCAL_HEADER.to_le_bytes().to_vec();
+        header.extend_from_slice(&20u16.to_le_bytes());
+        header.extend_from_slice(&flags.to_le_bytes());
+        header.extend_from_slice(&method.to_le_bytes());
+        header.extend_from_slice(&[0; 8]); // time, date, crc32
+        let size = if flags & FLAG_DESCRIPTOR == 0 {
+            size
+        } else {
+            0
+        };
+        header.extend_from_slice(&(size as u32).to_le_bytes());
+        header.extend_from_slice(&(size as u32).to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header
+    }
+
+    async fn extract(archive: Vec<u8>) -> Result<Vec<u8>> {
+        let mut entry = image_entry(BufReader::new(std::io::Cursor::new(archive))).await?;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    #[tokio::test]
+    async fn streams_the_first_image_entry() {
+        let image: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let readme = b"Write os.img to a card".to_vec();
+        let notes = deflate(b"release notes").await;
+
+        let mut archive = local_header("README.txt", 0, STORED, readme.len());
+        archive.extend_from_slice(&readme);
+        // Streamed by its creator, so the size is only in the descriptor
+        archive.extend_from_slice(&local_header("notes.txt", FLAG_DESCRIPTOR, DEFLATED, 0));
+        archive.extend_from_slice(&notes);
+        archive.extend_from_slice(&DATA_DESCRIPTOR.to_le_bytes());
+        archive.extend_from_slice(&[0; 12]);
+        let compressed = deflate(&image).await;
+        archive.extend_from_slice(&local_header("os.IMG", 0, DEFLATED, compressed.len()));
+        archive.extend_from_slice(&compressed);
+        archive.extend_from_slice(&local_header("other.img", 0, STORED, 1));
+        archive.push(0);
+
+        assert!(extract(archive).await.unwrap() == image);
+
+        let mut archive = local_header("README.txt", 0, STORED, readme.len());
+        archive.extend_from_slice(&readme);
+        archive.extend_from_slice(b"PK\x01\x02");
+        assert!(extract(archive).await.is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! Disks on Windows: parsing what `Get-Disk` and `Get-Partition` report,
+//! the counterpart of [`crate::lsblk`] and [`crate::diskutil`], and opening
+//! `\\.\PhysicalDriveN` for writing.
+//!
+//! PowerShell prints enum and char properties differently across versions
+//! (BusType as 7 or "USB", DriveLetter as 67 or "C"), and a lone object
+//! instead of an array of one, so both forms are accepted.
+//!
+//! Windows refuses writes to sectors of a mounted volume, so the volumes on
+//! the disk are locked and dismounted before it is opened, and stay locked
+//! until the write is done ([`Unlock`]).
+
+// The backend only runs on Windows, the parsing is tested everywhere
+#![cfg_attr(not(windows), allow(dead_code))]
+
+use serde::{Deserialize, Deserializer};
+
+/// One JSON document with every disk and partition.
+pub const SCRIPT: &str = "@{ \
+    disks = @(Get-Disk | Select-Object Number, FriendlyName, SerialNumber, Size, BusType, \
+        IsBoot, IsSystem, IsReadOnly); \
+    partitions = @(Get-Partition | Select-Object DiskNumber, DriveLetter) \
+    } | ConvertTo-Json -Depth 3 -Compress";
+
+const PHYSICAL_DRIVE: &str = r"\\.\PhysicalDrive";
+
+/// The output of [`SCRIPT`].
+/// A disk as `Get-Disk` reports it.
+#[derive(Debug, Deserialize)]
+pub struct Disks {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub disks: Vec<Disk>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub partitions: Vec<Partition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Disk {
+    pub number: u32,
+    #[serde(default)]
+    pub friendly_name: Option<String>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, deserialize_with = "bus_type")]
+    pub bus_type: String,
+    #[serde(default)]
+    pub is_boot: bool,
+    #[serde(default)]
+    pub is_system: bool,
+    #[serde(default)]
+    pub is_read_only: bool,
+}
+
+/// A partition as `Get-Partition` reports it.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Partition {
+    pub disk_number: u32,
+    #[serde(default, deserialize_with = "drive_letter")]
+    pub drive_letter: Option<char>,
+}
+
+impl Disk {
+    /// `\\.\PhysicalDriveN`, the path the disk is opened at.
+    pub fn path(&self) -> String {
+        format!("{}{}", PHYSICAL_DRIVE, self.number)
+    }
+
+    /// Card readers and USB sticks; Windows has no removable flag per disk.
+    pub fn is_removable(&self) -> bool {
+        matches!(self.bus_type.as_str(), "USB" | "SD" | "MMC")
+    }
+}
+
+impl Disks {
+    /// "C:\" for each lettered volume on the disk.
+    pub fn mountpoints(&self, disk: &Disk) -> Vec<String> {
+        self.partitions
+            .iter()
+            .filter(|p| p.disk_number == disk.number)
+            .filter_map(|p| p.drive_letter)
+            .map(|letter| format!("{}:\\", letter))
+            .collect()
+    }
+}
+
+/// Whether `path` names a whole disk.
+pub fn is_physical_drive(path: &str) -> bool {
+    path.get(..PHYSICAL_DRIVE.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(PHYSICAL_DRIVE))
+}
+
+/// Parses the output of [`SCRIPT`].
+pub fn parse(json: &str) -> serde_json::Result<Disks> {
+    serde_json::from_str(json)
+}
+
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+        None,
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(items) => items,
+        OneOrMany::One(item) => vec![item],
+        OneOrMany::None => Vec::new(),
+    })
+}
+
+/// The MSFT_Disk BusType values that matter here, by name.
+fn bus_type<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => s,
+        serde_json::Value::Number(n) => match n.as_u64() {
+            Some(7) => "USB",
+            Some(8) => "RAID",
+            Some(11) => "SATA",
+            Some(12) => "SD",
+            Some(13) => "MMC",
+            Some(17) => "NVMe",
+            _ => "Unknown",
+        }
+        .to_string(),
+        _ => String::new(),
+    })
+}
+
+/// Partitions without a letter report "\0" or 0.
+fn drive_letter<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<char>, D::Error> {
+    let letter = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => s.chars().next(),
+        serde_json::Value::Number(n) => n.as_u64().and_then(|c| char::from_u32(c as u32)),
+        _ => None,
+    };
+    Ok(letter.filter(char::is_ascii_alphabetic))
+}
+
+/// Opens the physical drive at `path` for writing, after locking and
+/// dismounting its volumes.
+#[cfg(windows)]
+pub async fn open(path: &str) -> std::io::Result<tokio::fs::File> {
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || raw::open(&path))
+        .await
+        .map_err(std::io::Error::other)?
+        .map(tokio::fs::File::from_std)
+}
+
+/// Locks and dismounts the volumes at `mountpoints`, e.g. "E:\\".
+#[cfg(windows)]
+pub fn dismount(mountpoints: &[String]) -> std::io::Result<()> {
+    raw::dismount(mountpoints)
+}
+
+/// Releases the volume locks when dropped, so Windows mounts the volumes of
+/// the written card again. The writer holds one until the image is written,
+/// whether it succeeds or not.
+#[cfg(windows)]
+pub struct Unlock;
+
+#[cfg(windows)]
+impl Drop for Unlock {
+    fn drop(&mut self) {
+        raw::LOCKED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+#[cfg(windows)]
+mod raw {
+    use std::ffi::c_void;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::Mutex;
+
+    const FILE_SHARE_READ: u32 = 0x1;
+    const FILE_SHARE_WRITE: u32 = 0x2;
+    const FSCTL_LOCK_VOLUME: u32 = 0x0009_0018;
+    const FSCTL_DISMOUNT_VOLUME: u32 = 0x0009_0020;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn DeviceIoControl(
+            device: *mut c_void,
+            code: u32,
+            in_buffer: *const c_void,
+            in_size: u32,
+            out_buffer: *mut c_void,
+            out_size: u32,
+            returned: *mut u32,
+            overlapped: *mut c_void,
+        ) -> i32;
+    }
+
+    /// Locks are released when their handles close, so they are kept here
+    /// with the volume they lock, until [`super::Unlock`] drops them.
+    pub static LOCKED: Mutex<Vec<(String, File)>> = Mutex::new(Vec::new());
+
+    pub fn open(path: &str) -> io::Result<File> {
+        let drives = crate::drivelist::get_drives().map_err(|e| io::Error::other(e.to_string()))?;
+        let volumes = drives
+            .into_iter()
+            .find(|d| d.name.eq_ignore_ascii_case(path))
+            .map(|d| d.mountpoints)
+            .unwrap_or_default();
+        dismount(&volumes)?;
+        shared(path)
+    }
+
+    /// Volumes locked already, e.g. before the device is opened, are left
+    /// as they are.
+    pub fn dismount(mountpoints: &[String]) -> io::Result<()> {
+        let mut locked = LOCKED.lock().unwrap_or_else(|e| e.into_inner());
+        for volume in mountpoints.iter().filter_map(|mp| mp.strip_suffix('\\')) {
+            if locked.iter().any(|(v, _)| v.eq_ignore_ascii_case(volume)) {
+                continue;
+            }
+            let handle = shared(&format!(r"\\.\{}", volume))?;
+            control(&handle, FSCTL_LOCK_VOLUME)
+                .map_err(|e| io::Error::other(format!("Failed to lock {}: {}", volume, e)))?;
+            control(&handle, FSCTL_DISMOUNT_VOLUME)
+                .map_err(|e| io::Error::other(format!("Failed to dismount {}: {}", volume, e)))?;
+            locked.push((volume.to_string(), handle));
+        }
+        Ok(())
+    }
+
+    fn shared(path: &str) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+            .open(path)
+    }
+
+    fn control(file: &File, code: u32) -> io::Result<()> {
+        let mut returned = 0u32;
+        // SAFETY: no buffers are passed, the handle is open for the call
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                code,
+                std::ptr::null(),
+                0,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
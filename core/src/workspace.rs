@@ -0,0 +1,185 @@
+//! Temporary artifacts (mount points, debug images) of a single operation.
+//! Everything is cleaned up by `Drop`, and also registered globally so that
+//! [`cleanup_all`] can release it when the process is interrupted or panics
+//! and destructors won't run.
+//!
+//! Cleanup never deletes recursively: a directory that is still a mount point
+//! is left alone rather than risking the contents of the card.
+
+use anyhow::{Context, Result, anyhow};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Artifact {
+    Dir(PathBuf),
+    File(PathBuf),
+    Mount(PathBuf),
+}
+
+static TRACKED: Mutex<Vec<Artifact>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn track(artifact: Artifact) {
+    let mut tracked = TRACKED.lock().unwrap_or_else(|e| e.into_inner());
+    if !tracked.contains(&artifact) {
+        tracked.push(artifact);
+    }
+}
+
+fn untrack(artifact: &Artifact) {
+    TRACKED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|a| a != artifact);
+}
+
+/// Registers a file that must not outlive the process.
+pub fn track_file(path: impl Into<PathBuf>) {
+    track(Artifact::File(path.into()));
+}
+
+/// Releases every artifact still registered, newest first. Called on
+/// interruption, panic and exit; mounts are detached lazily since the
+/// thread using them may still be running.
+pub fn cleanup_all() {
+    let artifacts = std::mem::take(&mut *TRACKED.lock().unwrap_or_else(|e| e.into_inner()));
+    for artifact in artifacts.iter().rev() {
+        match artifact {
+            Artifact::Mount(path) => {
+                let _ = Command::new("umount").arg("-l").arg(path).status();
+                let _ = std::fs::remove_dir(path);
+            }
+            Artifact::File(path) => {
+                let _ = std::fs::remove_file(path);
+            }
+            Artifact::Dir(path) => {
+                let _ = std::fs::remove_dir(path);
+            }
+        }
+    }
+}
+
+/// A private directory under the system temp dir for mount points.
+pub struct Workspace {
+    dir: PathBuf,
+}
+
+impl Workspace {
+    /// Creates the directory; it is removed when dropped.
+    pub fn new() -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "rpi-imager-tui-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        track(Artifact::Dir(dir.clone()));
+        Ok(Self { dir })
+    }
+
+    /// Mounts `source` on a fresh directory inside the workspace.
+    pub fn mount(&self, source: &str) -> Result<Mount<'_>> {
+        self.mount_with(source, &[])
+    }
+
+    /// Like [`Self::mount`], for looking without touching anything.
+    pub fn mount_read_only(&self, source: &str) -> Result<Mount<'_>> {
+        self.mount_with(source, &["-o", "ro"])
+    }
+
+    fn mount_with(&self, source: &str, args: &[&str]) -> Result<Mount<'_>> {
+        let path = self
+            .dir
+            .join(format!("mnt-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+        std::fs::create_dir(&path).context("Failed to create temp mount point")?;
+
+        let status = Command::new("mount")
+            .args(args)
+            .arg(source)
+            .arg(&path)
+            .status()
+            .with_context(|| format!("Failed to mount {}", source));
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                let _ = std::fs::remove_dir(&path);
+                return Err(anyhow!(
+                    "Failed to mount {}. Exit code: {:?}",
+                    source,
+                    status.code()
+                ));
+            }
+            Err(e) => {
+                let _ = std::fs::remove_dir(&path);
+                return Err(e);
+            }
+        }
+
+        track(Artifact::Mount(path.clone()));
+        Ok(Mount {
+            path,
+            mounted: true,
+            _workspace: self,
+        })
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if std::fs::remove_dir(&self.dir).is_ok() {
+            untrack(&Artifact::Dir(self.dir.clone()));
+        }
+    }
+}
+
+/// A mounted filesystem, unmounted when dropped. Borrowing the workspace
+/// guarantees it is unmounted before the workspace directory goes away.
+pub struct Mount<'a> {
+    path: PathBuf,
+    mounted: bool,
+    _workspace: &'a Workspace,
+}
+
+impl Mount<'_> {
+    /// Where the partition is mounted.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Unmounts explicitly, reporting failure (e.g. a busy filesystem)
+    /// instead of ignoring it like `Drop` has to.
+    pub fn unmount(mut self) -> Result<()> {
+        self.mounted = false;
+        let status = Command::new("umount")
+            .arg(&self.path)
+            .status()
+            .context("Failed to unmount boot partition")?;
+        if !status.success() {
+            // Leave the tracked mount for cleanup_all to detach lazily
+            return Err(anyhow!("Failed to unmount. Check if busy."));
+        }
+        let _ = std::fs::remove_dir(&self.path);
+        untrack(&Artifact::Mount(self.path.clone()));
+        Ok(())
+    }
+}
+
+impl Drop for Mount<'_> {
+    fn drop(&mut self) {
+        if !self.mounted {
+            return;
+        }
+        let unmounted = Command::new("umount")
+            .arg(&self.path)
+            .status()
+            .is_ok_and(|s| s.success());
+        if unmounted {
+            let _ = std::fs::remove_dir(&self.path);
+            untrack(&Artifact::Mount(self.path.clone()));
+        }
+    }
+}
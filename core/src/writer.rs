@@ -0,0 +1,1415 @@
+use crate::compression::Settings;
+use crate::customization::{CustomizationOptions, Verification};
+use crate::drivelist::{Drive, TargetProfile};
+use crate::error::{ErrorKind, Tag};
+use crate::image_size::Counting;
+use crate::ipc::{ProgressEvent, WorkerCommand, WritingPhase};
+use crate::os_list::OsListItem;
+use crate::post_process::{apply_customization, finalize_ssd};
+use crate::target::Target;
+use anyhow::{Context, Result, anyhow};
+use futures::{StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter,
+};
+use tokio::sync::{Notify, mpsc};
+use tokio_util::io::StreamReader;
+
+/// Commands from the worker's parent (see [`WorkerCommand`]), checked by
+/// [`write_image`] between buffers.
+#[derive(Default)]
+pub struct Control {
+    aborted: AtomicBool,
+    paused: AtomicBool,
+    skip_verify: AtomicBool,
+    changed: Notify,
+}
+
+impl Control {
+    /// Passes `command` on to the running write.
+    pub fn apply(&self, command: WorkerCommand) {
+        match command {
+            WorkerCommand::Abort => self.aborted.store(true, Ordering::SeqCst),
+            WorkerCommand::SkipVerify => self.skip_verify.store(true, Ordering::SeqCst),
+            WorkerCommand::Pause => self.paused.store(true, Ordering::SeqCst),
+            WorkerCommand::Resume => self.paused.store(false, Ordering::SeqCst),
+        }
+        self.changed.notify_waiters();
+    }
+
+    /// Waits while paused, and fails once aborted.
+    async fn checkpoint(&self) -> Result<()> {
+        loop {
+            // Registered before checking, so a resume in between isn't missed
+            let changed = self.changed.notified();
+            if self.aborted.load(Ordering::SeqCst) {
+                return Err(anyhow!("Aborted by user")).tag(ErrorKind::Cancelled);
+            }
+            if !self.paused.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            changed.await;
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    fn skips_verify(&self) -> bool {
+        self.skip_verify.load(Ordering::SeqCst)
+    }
+}
+
+/// What [`write_image`] writes where, and how.
+#[derive(Debug, Clone)]
+pub struct WriteJob {
+    /// The image. Its `url` is a URL or a local path; `extract_sha256`, if
+    /// known, is checked against what was written.
+    pub os: OsListItem,
+    /// Where the image goes: a block device, an image file, or `-` for
+    /// stdout ([`Target`]).
+    pub drive: Drive,
+    /// The customization written onto the boot partition, and how the image
+    /// is downloaded, decompressed and verified.
+    pub options: CustomizationOptions,
+    /// Only write the chunks that differ from what the card holds, for a
+    /// card that has an earlier build of the image. Needs a target that can
+    /// be read back.
+    pub delta: bool,
+    /// A download of the image in the cache ([`crate::cache`]): read instead
+    /// of the URL once complete, continued from where it ends otherwise.
+    pub cache: Option<PathBuf>,
+}
+
+impl WriteJob {
+    /// A full write of `os` to `drive`, downloading the image.
+    pub fn new(os: OsListItem, drive: Drive, options: CustomizationOptions) -> Self {
+        Self {
+            os,
+            drive,
+            options,
+            delta: false,
+            cache: None,
+        }
+    }
+}
+
+/// Downloads or reads the image of `job`, decompresses it onto the target,
+/// verifies it and applies the customization. Progress goes to `tx` as
+/// [`ProgressEvent`]s, ending with [`ProgressEvent::Finished`], or with
+/// [`ProgressEvent::CustomizationFailed`] when the image was written but
+/// customizing it failed. `control` pauses or aborts the write from another
+/// task. Errors are tagged with an [`ErrorKind`].
+pub async fn write_image(
+    job: WriteJob,
+    control: Arc<Control>,
+    tx: mpsc::Sender<ProgressEvent>,
+) -> Result<()> {
+    let WriteJob {
+        os,
+        drive,
+        options,
+        delta,
+        cache,
+    } = job;
+    let url = os
+        .url
+        .as_deref()
+        .ok_or_else(|| anyhow!("No URL provided for the selected OS"))
+        .tag(ErrorKind::Download)?;
+
+    let extract_size = os.extract_size.unwrap_or(0);
+    let extract_sha256 = os.extract_sha256.as_deref();
+
+    let target = Target::detect(&drive.name);
+    if delta && !target.can_read_back() {
+        return Err(anyhow!(
+            "{} can't be read back, so it can't be written in delta mode",
+            drive.name
+        ))
+        .tag(ErrorKind::DeviceOpen);
+    }
+
+    // Send 0% progress
+    let _ = tx.send(progress(WritingPhase::Downloading, 0.0)).await;
+    let _ = tx
+        .send(ProgressEvent::Status("Starting download...".to_string()))
+        .await;
+
+    // A complete prefetched download is read like a local file
+    let local_path = match &cache {
+        Some(path) if !crate::cache::is_partial(path) => path.to_string_lossy().to_string(),
+        _ => url.to_string(),
+    };
+
+    let mut downloading = false;
+    // Start Download or Open Local File
+    let (reader, total_size): (Box<dyn AsyncRead + Unpin + Send>, Option<u64>) =
+        if local_path.starts_with("http://") || local_path.starts_with("https://") {
+            let client = crate::http::client(&options).tag(ErrorKind::Download)?;
+
+            // Continue after the prefetched part, if the server supports ranges
+            let prefix = match &cache {
+                Some(path) => tokio::fs::File::open(path).await.ok(),
+                None => None,
+            };
+            let offset = match &prefix {
+                Some(f) => f.metadata().await.map(|m| m.len()).unwrap_or(0),
+                None => 0,
+            };
+            let res = crate::http::get_from(&client, url, offset)
+                .await
+                .tag(ErrorKind::Download)?;
+            if let Some(host) = res.url().host_str() {
+                let _ = tx.send(ProgressEvent::Source(host.to_string())).await;
+                downloading = true;
+            }
+            let resumed = offset > 0 && res.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+
+            // The whole file, not just what is left after the prefetched part
+            let size = res
+                .content_length()
+                .map(|len| if resumed { len + offset } else { len });
+
+            // The rest over parallel ranged requests, to where the redirects led
+            let connections = options.download_connections.unwrap_or(1) as usize;
+            let stream = match size {
+                Some(end) if connections > 1 && crate::http::supports_ranges(&res) => {
+                    let start = if resumed { offset } else { 0 };
+                    crate::http::parallel_download(client, res, start, end, connections)
+                        .map_err(|e| ErrorKind::Download.io(e))
+                        .boxed()
+                }
+                _ => {
+                    let start = if resumed { offset } else { 0 };
+                    let status = tx.clone();
+                    let resumed = move |at: u64| {
+                        let _ = status.try_send(ProgressEvent::Status(format!(
+                            "Connection lost, continuing at {}...",
+                            crate::drivelist::format_size(at)
+                        )));
+                    };
+                    crate::http::resuming(client, res, start, resumed)
+                        .map_err(|e| ErrorKind::Download.io(e))
+                        .boxed()
+                }
+            };
+
+            // Convert reqwest stream to AsyncRead
+            let stream_reader = StreamReader::new(stream);
+            let reader: Box<dyn AsyncRead + Unpin + Send> = match prefix {
+                Some(prefix) if resumed => {
+                    let _ = tx
+                        .send(ProgressEvent::Status(
+                            "Continuing prefetched download...".to_string(),
+                        ))
+                        .await;
+                    Box::new(prefix.chain(stream_reader))
+                }
+                _ => Box::new(stream_reader),
+            };
+            (
+                Box::new(BufReader::with_capacity(1024 * 1024, reader)),
+                size,
+            )
+        } else {
+            let f = tokio::fs::File::open(&local_path)
+                .await
+                .context(format!("Failed to open local file {}", local_path))
+                .tag(ErrorKind::Download)?;
+            let metadata = f.metadata().await.tag(ErrorKind::Download)?;
+            (
+                Box::new(BufReader::with_capacity(1024 * 1024, f)),
+                Some(metadata.len()),
+            )
+        };
+
+    // Without an extract_size, the decompressed size is read from the image
+    // or extrapolated from how far into the download the decoder is
+    let header_size = if extract_size == 0 && !local_path.starts_with("http") {
+        crate::image_size::from_file(std::path::Path::new(&local_path))
+    } else {
+        None
+    };
+    let consumed = Arc::new(AtomicU64::new(0));
+    let reader: Box<dyn AsyncRead + Unpin + Send> =
+        Box::new(Counting::new(reader, consumed.clone()));
+    let expected_size = |written: u64| -> Option<u64> {
+        if extract_size > 0 {
+            return Some(extract_size);
+        }
+        header_size
+            .or_else(|| {
+                crate::image_size::extrapolate(
+                    written,
+                    consumed.load(Ordering::Relaxed),
+                    total_size?,
+                )
+            })
+            .map(|size| size.max(written))
+    };
+
+    // The first bytes tell the compression, whatever the name says
+    let mut reader = BufReader::new(reader);
+    let decompressor = crate::compression::for_image(
+        reader
+            .fill_buf()
+            .await
+            .context("Failed to read the start of the image")
+            .tag(ErrorKind::Download)?,
+    );
+    let compressed = decompressor.is_compressed();
+    let settings = Settings {
+        threads: xz_threads(options.decompression_threads),
+    };
+    let mut decoder = decompressor
+        .decode(Box::new(reader), settings)
+        .await
+        .tag(ErrorKind::Decompress)?;
+
+    // Windows keeps the volumes of the card locked from unmounting until
+    // the image is written, also if writing fails
+    #[cfg(windows)]
+    let unlock = crate::windisk::Unlock;
+
+    if target == Target::BlockDevice {
+        if drive.mountpoints.iter().any(|mp| mp != "/") {
+            let _ = tx
+                .send(ProgressEvent::Status(
+                    "Unmounting partitions...".to_string(),
+                ))
+                .await;
+            crate::in_use::unmount(&drive.name, &drive.mountpoints).tag(ErrorKind::DeviceOpen)?;
+        }
+        crate::in_use::check(&drive.name).tag(ErrorKind::DeviceOpen)?;
+    }
+
+    // Open target device for writing, unless udisks2 already opened it
+    #[cfg(unix)]
+    let inherited = crate::udisks::inherited(&drive.name);
+    #[cfg(not(unix))]
+    let inherited: Option<std::fs::File> = None;
+    let mut device_file = match inherited {
+        Some(file) => tokio::fs::File::from_std(file),
+        None => target.open(&drive.name).await.tag(ErrorKind::DeviceOpen)?,
+    };
+
+    let _ = tx.send(progress(WritingPhase::Writing, 0.0)).await;
+
+    // Delta mode compares against what is on the card, so it can't be wiped
+    if options.wipe_device && !delta && target.can_read_back() {
+        let _ = tx
+            .send(ProgressEvent::Status(
+                "Wiping old partition tables...".to_string(),
+            ))
+            .await;
+        wipe_signatures(&mut device_file)
+            .await
+            .tag(ErrorKind::DeviceWrite)?;
+    }
+
+    // A copy of exactly the bytes going to the card
+    let mut tee = match &options.tee_file {
+        Some(path) => {
+            let file = tokio::fs::File::create(path)
+                .await
+                .context(format!("Failed to create {}", path))
+                .tag(ErrorKind::TeeFile)?;
+            give_to_invoking_user(path);
+            Some(BufWriter::with_capacity(1024 * 1024, file))
+        }
+        None => None,
+    };
+
+    // 4MB buffer for SD cards, larger for SSDs
+    let buffer_size = drive.profile.buffer_size();
+    let mut buffer = vec![0u8; buffer_size];
+    let mut total_written = 0u64;
+    let mut total_unchanged = 0u64;
+    let mut hasher = Sha256::new();
+    // Scratch space for reading back existing card contents in delta mode
+    let mut existing = if delta {
+        vec![0u8; buffer.len()]
+    } else {
+        Vec::new()
+    };
+
+    // Wrap device_file in BufWriter for better write performance
+    let mut buf_writer = BufWriter::with_capacity(buffer_size, device_file);
+
+    let start_time = Instant::now();
+    let mut last_update = Instant::now();
+    let mut last_sync = 0u64;
+    // Download speed over the last SLOW_WINDOW, while still downloading
+    let mut window = (Instant::now(), 0u64);
+
+    loop {
+        if control.is_paused() {
+            // What was decoded so far goes to the card while the pipeline waits
+            buf_writer
+                .flush()
+                .await
+                .context("Failed to flush write buffer")
+                .tag(ErrorKind::DeviceWrite)?;
+            let _ = tx.send(ProgressEvent::Status("Paused".to_string())).await;
+        }
+        let paused = Instant::now();
+        control.checkpoint().await?;
+        if paused.elapsed() >= std::time::Duration::from_secs(1) {
+            // Time spent paused doesn't count against the download
+            window = (Instant::now(), consumed.load(Ordering::Relaxed));
+        }
+        let n = if delta {
+            // Whole chunks keep the comparison aligned with the card contents
+            read_full(&mut decoder, &mut buffer)
+                .await
+                .context("Failed to read/decompress image stream")
+                .tag(ErrorKind::Decompress)?
+        } else {
+            decoder
+                .read(&mut buffer)
+                .await
+                .context("Failed to read/decompress image stream")
+                .tag(ErrorKind::Decompress)?
+        };
+
+        if n == 0 {
+            break;
+        }
+
+        if delta {
+            // Nothing goes through the BufWriter in delta mode, so the file
+            // position is always exactly `total_written`.
+            let device = buf_writer.get_mut();
+            let existing_n = read_full(device, &mut existing[..n])
+                .await
+                .context("Failed to read existing contents from device")
+                .tag(ErrorKind::DeviceWrite)?;
+
+            if existing_n == n && existing[..n] == buffer[..n] {
+                total_unchanged += n as u64;
+            } else {
+                device
+                    .seek(SeekFrom::Start(total_written))
+                    .await
+                    .context("Failed to seek on storage device")
+                    .tag(ErrorKind::DeviceWrite)?;
+                device
+                    .write_all(&buffer[..n])
+                    .await
+                    .context("Failed to write to storage device")
+                    .tag(ErrorKind::DeviceWrite)?;
+            }
+        } else {
+            buf_writer
+                .write_all(&buffer[..n])
+                .await
+                .context("Failed to write to storage device")
+                .tag(ErrorKind::DeviceWrite)?;
+        }
+
+        if let Some(tee) = &mut tee {
+            tee.write_all(&buffer[..n])
+                .await
+                .context("Failed to write the image copy")
+                .tag(ErrorKind::TeeFile)?;
+        }
+
+        // Update checksum
+        hasher.update(&buffer[..n]);
+
+        total_written += n as u64;
+
+        // Only what a sync returned for is known to be on the card
+        if target.can_sync() && total_written - last_sync >= SYNC_INTERVAL {
+            buf_writer
+                .flush()
+                .await
+                .context("Failed to flush write buffer")
+                .tag(ErrorKind::DeviceWrite)?;
+            buf_writer
+                .get_ref()
+                .sync_data()
+                .await
+                .context("Failed to sync data to device")
+                .tag(ErrorKind::DeviceWrite)?;
+            last_sync = total_written;
+            if let Some(size) = expected_size(total_written) {
+                let fraction = (total_written as f64 / size as f64).min(1.0);
+                let _ = tx.send(ProgressEvent::Synced(fraction)).await;
+            }
+        }
+
+        // Update progress every 500ms
+        if last_update.elapsed().as_millis() > 500 {
+            let elapsed_secs = start_time.elapsed().as_secs_f64();
+            let speed_mb_s = if elapsed_secs > 0.0 {
+                (total_written as f64 / 1024.0 / 1024.0) / elapsed_secs
+            } else {
+                0.0
+            };
+
+            // How fast the compressed input is consumed, so a decoder that
+            // can't keep up with the card shows
+            let mut details = String::new();
+            if compressed && elapsed_secs > 0.0 {
+                let input_mb = consumed.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0;
+                details.push_str(&format!(
+                    ", decompressing {:.1} MB/s",
+                    input_mb / elapsed_secs
+                ));
+            }
+            if downloading && window.0.elapsed() >= SLOW_WINDOW {
+                let received = consumed.load(Ordering::Relaxed);
+                let rate = (received - window.1) as f64 / window.0.elapsed().as_secs_f64();
+                if rate < SLOW_DOWNLOAD_RATE as f64 {
+                    let _ = tx.send(ProgressEvent::SlowDownload).await;
+                    // Once is enough to offer another mirror
+                    downloading = false;
+                }
+                window = (Instant::now(), received);
+            }
+            if delta {
+                details.push_str(&format!(
+                    ", {:.0}% unchanged",
+                    total_unchanged as f64 / total_written as f64 * 100.0
+                ));
+            }
+
+            if let Some(size) = expected_size(total_written) {
+                let fraction = (total_written as f64 / size as f64).min(1.0);
+                let _ = tx.send(progress(WritingPhase::Writing, fraction)).await;
+                let remaining_mb = size.saturating_sub(total_written) as f64 / 1024.0 / 1024.0;
+                let eta = if speed_mb_s > 0.0 {
+                    let secs = (remaining_mb / speed_mb_s) as u64;
+                    format!(", ETA {}:{:02}", secs / 60, secs % 60)
+                } else {
+                    String::new()
+                };
+                let _ = tx
+                    .send(ProgressEvent::Status(format!(
+                        "Writing... {}{:.1}% ({:.1} MB/s{}{})",
+                        if extract_size > 0 { "" } else { "~" },
+                        fraction * 100.0,
+                        speed_mb_s,
+                        eta,
+                        details
+                    )))
+                    .await;
+            } else {
+                let _ = tx
+                    .send(ProgressEvent::Status(format!(
+                        "Writing... {} MB ({:.1} MB/s{})",
+                        total_written / 1024 / 1024,
+                        speed_mb_s,
+                        details
+                    )))
+                    .await;
+            }
+            last_update = Instant::now();
+        }
+    }
+
+    // Flush buffer
+    buf_writer
+        .flush()
+        .await
+        .context("Failed to flush write buffer")
+        .tag(ErrorKind::DeviceWrite)?;
+
+    if let Some(mut tee) = tee {
+        tee.flush()
+            .await
+            .context("Failed to write the image copy")
+            .tag(ErrorKind::TeeFile)?;
+    }
+
+    let _ = tx.send(progress(WritingPhase::Writing, 1.0)).await;
+    let _ = tx.send(progress(WritingPhase::Syncing, 0.0)).await;
+    let _ = tx
+        .send(ProgressEvent::Status("Syncing to disk...".to_string()))
+        .await;
+
+    // Retrieve underlying file to sync and seek
+    let mut device_file = buf_writer.into_inner();
+
+    // Ensure all data is physically written to disk. Most of the image may
+    // still sit in the page cache, so report how much of it is left.
+    let dirty_at_start = dirty_bytes();
+    if target.can_sync() {
+        let mut sync = std::pin::pin!(device_file.sync_all());
+        loop {
+            tokio::select! {
+                result = &mut sync => {
+                    result
+                        .context("Failed to sync data to device")
+                        .tag(ErrorKind::DeviceWrite)?;
+                    break;
+                }
+                _ = tokio::time::sleep(std::time::Duration::from_millis(500)) => {
+                    if let (Some(start), Some(now)) = (dirty_at_start, dirty_bytes())
+                        && start > 0
+                    {
+                        let fraction = 1.0 - (now as f64 / start as f64).min(1.0);
+                        let _ = tx.send(progress(WritingPhase::Syncing, fraction)).await;
+                    }
+                }
+            }
+        }
+    }
+    let _ = tx.send(progress(WritingPhase::Syncing, 1.0)).await;
+    let _ = tx.send(ProgressEvent::Synced(1.0)).await;
+
+    let _ = tx.send(progress(WritingPhase::Verifying, 0.0)).await;
+
+    let _ = tx
+        .send(ProgressEvent::Status("Verifying download...".to_string()))
+        .await;
+
+    // Calculate source hash
+    let source_hash = hasher.finalize();
+    let source_hash_hex = hex::encode(source_hash);
+
+    // Verify download integrity if expected hash is provided
+    if let Some(expected_hash) = extract_sha256
+        && source_hash_hex.to_lowercase() != expected_hash.to_lowercase()
+    {
+        return Err(anyhow!(
+            "Download verification failed!\nExpected: {}\nCalculated: {}",
+            expected_hash,
+            source_hash_hex
+        ))
+        .tag(ErrorKind::Download);
+    }
+
+    // Verify write integrity by reading back from device, unless turned off
+    if options.verification != Verification::Never && !target.can_read_back() {
+        let _ = tx
+            .send(ProgressEvent::Status(format!(
+                "Not verifying: {} can't be read back",
+                drive.name
+            )))
+            .await;
+    } else if options.verification != Verification::Never {
+        let _ = tx
+            .send(ProgressEvent::Status(
+                "Verifying write (reading back)...".to_string(),
+            ))
+            .await;
+
+        device_file
+            .seek(SeekFrom::Start(0))
+            .await
+            .context("Failed to seek to start of device for verification")
+            .tag(ErrorKind::Verify)?;
+
+        let mut verify_hasher = Sha256::new();
+        let mut total_read = 0u64;
+        // Only a skip before the last read; a finished read-back still counts
+        let mut skipped = false;
+        let start_time = Instant::now();
+        let mut last_update = Instant::now();
+
+        loop {
+            if control.is_paused() {
+                let _ = tx.send(ProgressEvent::Status("Paused".to_string())).await;
+            }
+            control.checkpoint().await?;
+            let remaining = total_written - total_read;
+            if remaining == 0 {
+                break;
+            }
+            if control.skips_verify() {
+                let _ = tx
+                    .send(ProgressEvent::Status("Verification skipped".to_string()))
+                    .await;
+                let _ = tx.send(ProgressEvent::VerificationSkipped).await;
+                skipped = true;
+                break;
+            }
+
+            let to_read = std::cmp::min(buffer.len() as u64, remaining) as usize;
+            let n = device_file
+                .read(&mut buffer[..to_read])
+                .await
+                .context("Failed to read from device for verification")
+                .tag(ErrorKind::Verify)?;
+
+            if n == 0 {
+                return Err(anyhow!("Unexpected EOF during verification")).tag(ErrorKind::Verify);
+            }
+
+            verify_hasher.update(&buffer[..n]);
+            total_read += n as u64;
+
+            if last_update.elapsed().as_millis() > 500 {
+                let elapsed_secs = start_time.elapsed().as_secs_f64();
+                let speed_mb_s = if elapsed_secs > 0.0 {
+                    (total_read as f64 / 1024.0 / 1024.0) / elapsed_secs
+                } else {
+                    0.0
+                };
+
+                // The size is known exactly by now
+                let fraction = total_read as f64 / total_written as f64;
+                let _ = tx.send(progress(WritingPhase::Verifying, fraction)).await;
+                let _ = tx
+                    .send(ProgressEvent::Status(format!(
+                        "Verifying... {:.1}% ({:.1} MB/s)",
+                        fraction * 100.0,
+                        speed_mb_s
+                    )))
+                    .await;
+                last_update = Instant::now();
+            }
+        }
+
+        let on_disk_hash_hex = hex::encode(verify_hasher.finalize());
+
+        if !skipped && on_disk_hash_hex != source_hash_hex {
+            return Err(anyhow!(
+                "Write verification failed!\nSource hash: {}\nOn-disk hash: {}",
+                source_hash_hex,
+                on_disk_hash_hex
+            ))
+            .tag(ErrorKind::Verify);
+        }
+    }
+
+    // The image is written; Windows can mount its volumes again
+    #[cfg(windows)]
+    drop(unlock);
+
+    if drive.profile == TargetProfile::Ssd && target.has_partitions() {
+        let _ = tx
+            .send(ProgressEvent::Status(
+                "Finalizing SSD (GPT, TRIM)...".to_string(),
+            ))
+            .await;
+
+        let drive_name = drive.name.clone();
+        tokio::task::spawn_blocking(move || finalize_ssd(&drive_name, total_written))
+            .await
+            .context("Failed to join SSD finalization task")?;
+    }
+
+    // Taken by the process that has the card open, as the interface may
+    // not be allowed to read it; customization doesn't touch this part
+    if target != Target::Stdout {
+        let mut start = vec![0; FINGERPRINT_BYTES];
+        if device_file.seek(SeekFrom::Start(0)).await.is_ok()
+            && device_file.read_exact(&mut start).await.is_ok()
+        {
+            let _ = tx
+                .send(ProgressEvent::Fingerprint(fingerprint(&start)))
+                .await;
+        }
+    }
+
+    let is_bootloader = crate::eeprom::is_bootloader_image(url);
+
+    // Apply bootloader settings to EEPROM recovery images
+    if is_bootloader && !options.bootloader.is_default() {
+        if !target.has_partitions() {
+            return Err(anyhow!(
+                "Bootloader settings can only be applied to a block device, not {}",
+                drive.name
+            ))
+            .tag(ErrorKind::Customize);
+        }
+        let _ = tx
+            .send(ProgressEvent::Status(
+                "Applying bootloader configuration...".to_string(),
+            ))
+            .await;
+
+        let drive_name = drive.name.clone();
+        let config = options.bootloader.clone();
+        tokio::task::spawn_blocking(move || {
+            crate::post_process::with_boot_partition(&drive_name, |boot| config.apply(boot))
+        })
+        .await
+        .context("Failed to join bootloader configuration task")?
+        .tag(ErrorKind::Customize)?;
+    }
+
+    // Apply Customization (if any)
+    if !is_bootloader && options.needs_customization() && !target.has_partitions() {
+        let _ = tx
+            .send(ProgressEvent::CustomizationFailed(format!(
+                "{} has no partitions to customize; only block devices can be customized",
+                drive.name
+            )))
+            .await;
+        return Ok(());
+    }
+    if !is_bootloader && options.needs_customization() {
+        let _ = tx
+            .send(ProgressEvent::Status(
+                "Applying customization options...".to_string(),
+            ))
+            .await;
+
+        let _ = tx.send(progress(WritingPhase::Customizing, 0.0)).await;
+        let drive_name = drive.name.clone();
+        let options_clone = options.clone();
+        let init_format = os.customization_format();
+
+        // Run blocking mount/io operations in a separate thread. The image is
+        // already on the card at this point, so a failure here is reported
+        // separately and can be retried without rewriting.
+        let result = tokio::task::spawn_blocking(move || {
+            apply_customization(&drive_name, &options_clone, init_format.as_deref())
+        })
+        .await
+        .context("Failed to join customization task")?;
+        let failure = match result {
+            Ok((checks, changes)) => {
+                let failure = crate::first_boot::failures(&checks);
+                if !changes.is_empty() {
+                    let _ = tx.send(ProgressEvent::BootChanges(changes)).await;
+                }
+                let _ = tx.send(ProgressEvent::Checklist(checks)).await;
+                failure
+            }
+            Err(e) => Some(format!("{:#}", e)),
+        };
+        if let Some(failure) = failure {
+            let _ = tx.send(ProgressEvent::CustomizationFailed(failure)).await;
+            return Ok(());
+        }
+    }
+
+    if options.eject_finished && target.has_partitions() {
+        let _ = tx
+            .send(ProgressEvent::Status("Ejecting...".to_string()))
+            .await;
+        // Closed first, or the drive is still in use
+        drop(device_file);
+        let drive_name = drive.name.clone();
+        let ejected = tokio::task::spawn_blocking(move || crate::eject::eject(&drive_name))
+            .await
+            .context("Failed to join eject task")
+            .and_then(|result| result);
+        let _ = tx
+            .send(match ejected {
+                Ok(()) => ProgressEvent::Ejected,
+                // The image is fine; the card just has to be ejected by hand
+                Err(e) => ProgressEvent::Status(format!("{:#}", e)),
+            })
+            .await;
+    }
+
+    // Send completion
+    let _ = tx.send(ProgressEvent::Finished).await;
+
+    Ok(())
+}
+
+/// How much of the start of a card [`fingerprint`] hashes: the partition
+/// table and the gap before the first partition. First boot grows the root
+/// partition, so a card that has been booted no longer matches.
+pub const FINGERPRINT_BYTES: usize = 1 << 20;
+
+/// A hash of the first [`FINGERPRINT_BYTES`] of a card, to tell whether the
+/// card in a reader is still the one that was written.
+pub fn fingerprint(start: &[u8]) -> String {
+    hex::encode(Sha256::digest(start))
+}
+
+/// How much is written between two fdatasync checkpoints. Keeps the page
+/// cache from holding most of the image, so the writing gauge doesn't run far
+/// ahead of the card and leave minutes of syncing at the end.
+const SYNC_INTERVAL: u64 = 256 * 1024 * 1024;
+
+/// A download slower than SLOW_DOWNLOAD_RATE bytes per second over a whole
+/// SLOW_WINDOW is reported, so another mirror can be offered.
+const SLOW_WINDOW: std::time::Duration = std::time::Duration::from_secs(30);
+const SLOW_DOWNLOAD_RATE: u64 = 512 * 1024;
+
+/// How much of each end of the device [`wipe_signatures`] zeroes.
+const WIPE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Zeroes the start and the end of the device, like `wipefs -a`: a smaller
+/// image doesn't overwrite the backup GPT header at the end of the card, or
+/// superblocks of old filesystems past its own partitions.
+async fn wipe_signatures(device: &mut tokio::fs::File) -> Result<()> {
+    let size = device.seek(SeekFrom::End(0)).await?;
+    let zeros = vec![0u8; WIPE_SIZE.min(size) as usize];
+    for offset in [0, size.saturating_sub(WIPE_SIZE)] {
+        device.seek(SeekFrom::Start(offset)).await?;
+        device
+            .write_all(&zeros)
+            .await
+            .context("Failed to wipe the device")?;
+    }
+    device.flush().await?;
+    device.seek(SeekFrom::Start(0)).await?;
+    Ok(())
+}
+
+/// The worker runs as root via sudo or pkexec; files it creates for the
+/// user should belong to the user. Best effort.
+#[cfg(unix)]
+fn give_to_invoking_user(path: &str) {
+    let id = |name: &str| std::env::var(name).ok()?.parse::<u32>().ok();
+    let Some(uid) = id("SUDO_UID").or_else(|| id("PKEXEC_UID")) else {
+        return;
+    };
+    let gid = id("SUDO_GID").map(nix::unistd::Gid::from_raw);
+    let _ = nix::unistd::chown(path, Some(nix::unistd::Uid::from_raw(uid)), gid);
+}
+
+/// Files belong to whoever created them on Windows.
+#[cfg(not(unix))]
+fn give_to_invoking_user(_path: &str) {}
+
+/// The configured thread count, or one per core.
+fn xz_threads(configured: Option<u32>) -> NonZeroU32 {
+    configured
+        .and_then(NonZeroU32::new)
+        .or_else(|| {
+            std::thread::available_parallelism()
+                .ok()
+                .and_then(|n| NonZeroU32::new(n.get() as u32))
+        })
+        .unwrap_or(NonZeroU32::MIN)
+}
+
+fn progress(phase: WritingPhase, fraction: f64) -> ProgressEvent {
+    ProgressEvent::Progress { phase, fraction }
+}
+
+/// Bytes waiting to be written back system-wide (Dirty + Writeback in
+/// /proc/meminfo). Only used to estimate how far a sync has got.
+fn dirty_bytes() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kb = |key: &str| {
+        meminfo
+            .lines()
+            .find_map(|l| l.strip_prefix(key))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+    Some((kb("Dirty:")? + kb("Writeback:")?) * 1024)
+}
+
+/// Reads until `buf` is full or the reader reaches EOF.
+async fn read_full<R: AsyncRead + Unpin + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::bufread::{BzEncoder, GzipEncoder, XzEncoder, ZstdEncoder};
+    use std::io::{Read, Seek, Write};
+    use tempfile::NamedTempFile;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Not a multiple of the buffer size, so the last chunk is partial.
+    const IMAGE_SIZE: usize = 3 * 1024 * 1024 + 123;
+
+    fn image_data() -> Vec<u8> {
+        (0..IMAGE_SIZE).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    async fn compress(data: &[u8], extension: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        match extension {
+            "gz" => GzipEncoder::new(data).read_to_end(&mut out).await,
+            "xz" => XzEncoder::new(data).read_to_end(&mut out).await,
+            "zst" => ZstdEncoder::new(data).read_to_end(&mut out).await,
+            "bz2" => BzEncoder::new(data).read_to_end(&mut out).await,
+            _ => {
+                out.extend_from_slice(data);
+                Ok(0)
+            }
+        }
+        .unwrap();
+        out
+    }
+
+    async fn serve(body: Vec<u8>, file_name: &str) -> (MockServer, String) {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/{}", file_name)))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body))
+            .mount(&server)
+            .await;
+        let url = format!("{}/{}", server.uri(), file_name);
+        (server, url)
+    }
+
+    fn image(url: &str, sha256: Option<String>) -> OsListItem {
+        serde_json::from_value(serde_json::json!({
+            "name": "Test Image",
+            "url": url,
+            "extract_size": IMAGE_SIZE,
+            "extract_sha256": sha256,
+        }))
+        .unwrap()
+    }
+
+    fn target(contents: &[u8]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents).unwrap();
+        file
+    }
+
+    fn drive(target: &NamedTempFile) -> Drive {
+        Drive {
+            name: target.path().to_string_lossy().to_string(),
+            description: "Test target".to_string(),
+            model: None,
+            size: 0,
+            removable: true,
+            readonly: false,
+            mountpoints: Vec::new(),
+            serial: None,
+            profile: TargetProfile::SdCard,
+        }
+    }
+
+    /// Options that need no post-processing, whatever the host locale is.
+    fn plain_options() -> CustomizationOptions {
+        CustomizationOptions {
+            timezone: "Europe/London".to_string(),
+            keyboard_layout: "gb".to_string(),
+            locale: "en_GB.UTF-8".to_string(),
+            ..CustomizationOptions::default()
+        }
+    }
+
+    async fn run(os: OsListItem, drive: Drive, delta: bool) -> (Result<()>, Vec<ProgressEvent>) {
+        let (tx, mut rx) = mpsc::channel(1024);
+        let result = write_image(
+            WriteJob {
+                delta,
+                ..WriteJob::new(os, drive, plain_options())
+            },
+            Arc::default(),
+            tx,
+        )
+        .await;
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        (result, events)
+    }
+
+    fn contents(target: &mut NamedTempFile) -> Vec<u8> {
+        let mut data = Vec::new();
+        target.rewind().unwrap();
+        target.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[tokio::test]
+    async fn writes_and_verifies_every_compression_format() {
+        let data = image_data();
+        for extension in ["img", "gz", "xz", "zst", "bz2"] {
+            // The compression is told by magic bytes, not by the name
+            let file_name = format!("image-{}.img", extension);
+            let (_server, url) = serve(compress(&data, extension).await, &file_name).await;
+            let mut target = target(&[]);
+
+            let (result, events) =
+                run(image(&url, Some(sha256_hex(&data))), drive(&target), false).await;
+
+            result.unwrap_or_else(|e| panic!("{}: {:#}", extension, e));
+            assert!(matches!(events.last(), Some(ProgressEvent::Finished)));
+            assert!(events.iter().any(|e| matches!(
+                e,
+                ProgressEvent::Fingerprint(hash) if *hash == fingerprint(&data[..FINGERPRINT_BYTES])
+            )));
+            assert!(
+                events
+                    .iter()
+                    .any(|e| matches!(e, ProgressEvent::Source(host) if host == "127.0.0.1"))
+            );
+            assert!(
+                contents(&mut target) == data,
+                "{} image mismatch",
+                extension
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn tees_the_image_to_a_file() {
+        let data = image_data();
+        let (_server, url) = serve(compress(&data, "xz").await, "image.xz").await;
+        let mut target = target(&[]);
+        let dir = tempfile::tempdir().unwrap();
+        let copy = dir.path().join("image.img");
+        let (tx, _rx) = mpsc::channel(1024);
+        let options = CustomizationOptions {
+            tee_file: Some(copy.to_string_lossy().to_string()),
+            ..plain_options()
+        };
+
+        let result = write_image(
+            WriteJob::new(
+                image(&url, Some(sha256_hex(&data))),
+                drive(&target),
+                options,
+            ),
+            Arc::default(),
+            tx,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(contents(&mut target) == data);
+        assert!(std::fs::read(&copy).unwrap() == data);
+    }
+
+    #[tokio::test]
+    async fn rejects_checksum_mismatch() {
+        let (_server, url) = serve(image_data(), "image.img").await;
+        let target = target(&[]);
+
+        let (result, events) = run(
+            image(&url, Some(sha256_hex(b"something else"))),
+            drive(&target),
+            false,
+        )
+        .await;
+
+        let err = result.unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Download);
+        assert!(
+            err.to_string().contains("Download verification failed"),
+            "{}",
+            err
+        );
+        assert!(!events.iter().any(|e| matches!(e, ProgressEvent::Finished)));
+    }
+
+    #[tokio::test]
+    async fn fails_on_truncated_compressed_stream() {
+        let mut body = compress(&image_data(), "xz").await;
+        body.truncate(body.len() / 2);
+        let (_server, url) = serve(body, "image.xz").await;
+        let target = target(&[]);
+
+        let (result, _) = run(image(&url, None), drive(&target), false).await;
+
+        let err = result.unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Decompress);
+        let err = format!("{:#}", err);
+        assert!(err.contains("Failed to read/decompress"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn rejects_zip_archives_without_an_image() {
+        // An empty entry without a name, then the central directory
+        let mut archive = b"PK\x03\x04".to_vec();
+        archive.extend_from_slice(&[0; 26]);
+        archive.extend_from_slice(b"PK\x01\x02");
+        let (_server, url) = serve(archive, "image.zip").await;
+        let target = target(&[]);
+
+        let (result, _) = run(image(&url, None), drive(&target), false).await;
+
+        assert!(result.unwrap_err().to_string().contains("ZIP"));
+    }
+
+    #[tokio::test]
+    async fn delta_write_completes_a_partially_written_target() {
+        let data = image_data();
+        let (_server, url) = serve(data.clone(), "image.img").await;
+        // An interrupted earlier write: first half done, rest still stale
+        let mut stale = data[..IMAGE_SIZE / 2].to_vec();
+        stale.resize(IMAGE_SIZE, 0xAA);
+        let mut target = target(&stale);
+
+        let (result, _) = run(image(&url, Some(sha256_hex(&data))), drive(&target), true).await;
+
+        result.unwrap();
+        assert!(contents(&mut target) == data);
+    }
+
+    #[tokio::test]
+    async fn continues_a_partial_prefetch_with_a_range_request() {
+        let data = image_data();
+        let split = 1024 * 1024 + 7;
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("range", format!("bytes={}-", split).as_str()))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(data[split..].to_vec()))
+            .mount(&server)
+            .await;
+        let url = format!("{}/image.img", server.uri());
+
+        let mut prefix = tempfile::Builder::new().suffix(".part").tempfile().unwrap();
+        prefix.write_all(&data[..split]).unwrap();
+        let mut target = target(&[]);
+        let (tx, _rx) = mpsc::channel(1024);
+
+        let result = write_image(
+            WriteJob {
+                cache: Some(prefix.path().to_path_buf()),
+                ..WriteJob::new(
+                    image(&url, Some(sha256_hex(&data))),
+                    drive(&target),
+                    plain_options(),
+                )
+            },
+            Arc::default(),
+            tx,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(contents(&mut target), data);
+    }
+
+    /// Answers ranged requests with 206 and the slice asked for.
+    struct Ranges(Vec<u8>);
+
+    impl wiremock::Respond for Ranges {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let range = request
+                .headers
+                .get("range")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("bytes="))
+                .and_then(|v| v.split_once('-'));
+            let response = match range {
+                Some((from, to)) => {
+                    let from: usize = from.parse().unwrap();
+                    let to = to.parse::<usize>().map_or(self.0.len(), |to| to + 1);
+                    ResponseTemplate::new(206).set_body_bytes(self.0[from..to].to_vec())
+                }
+                None => ResponseTemplate::new(200).set_body_bytes(self.0.clone()),
+            };
+            response.insert_header("accept-ranges", "bytes")
+        }
+    }
+
+    #[tokio::test]
+    async fn downloads_over_parallel_ranges() {
+        let data: Vec<u8> = (0..20 * 1024 * 1024 + 5).map(|i| (i % 253) as u8).collect();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(Ranges(data.clone()))
+            .mount(&server)
+            .await;
+        let url = format!("{}/image.img", server.uri());
+        let mut target = target(&[]);
+        let (tx, _rx) = mpsc::channel(1024);
+        let options = CustomizationOptions {
+            download_connections: Some(3),
+            ..plain_options()
+        };
+        let os = OsListItem {
+            extract_size: Some(data.len() as u64),
+            ..image(&url, Some(sha256_hex(&data)))
+        };
+
+        let result = write_image(
+            WriteJob::new(os, drive(&target), options),
+            Arc::default(),
+            tx,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(contents(&mut target) == data);
+        // The first request, then one per 8 MiB chunk
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn skips_read_back_when_verification_is_off() {
+        let data = image_data();
+        let (_server, url) = serve(data.clone(), "image.img").await;
+        let mut target = target(&[]);
+        let (tx, mut rx) = mpsc::channel(1024);
+        let options = CustomizationOptions {
+            verification: Verification::Never,
+            ..plain_options()
+        };
+
+        let result = write_image(
+            WriteJob::new(
+                image(&url, Some(sha256_hex(&data))),
+                drive(&target),
+                options,
+            ),
+            Arc::default(),
+            tx,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(contents(&mut target), data);
+        while let Ok(event) = rx.try_recv() {
+            assert!(
+                !matches!(&event, ProgressEvent::Status(s) if s.contains("reading back")),
+                "{:?}",
+                event
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn wipes_the_end_of_a_larger_card() {
+        let data = image_data();
+        let (_server, url) = serve(data.clone(), "image.img").await;
+        let card_size = IMAGE_SIZE + 2 * WIPE_SIZE as usize;
+        let mut target = target(&vec![0xee; card_size]);
+        let (tx, _rx) = mpsc::channel(1024);
+        let options = CustomizationOptions {
+            wipe_device: true,
+            ..plain_options()
+        };
+
+        let result = write_image(
+            WriteJob::new(
+                image(&url, Some(sha256_hex(&data))),
+                drive(&target),
+                options,
+            ),
+            Arc::default(),
+            tx,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+        let written = contents(&mut target);
+        assert_eq!(written.len(), card_size);
+        assert_eq!(&written[..IMAGE_SIZE], &data[..]);
+        // Past the wiped head the old contents remain up to the wiped tail
+        assert_eq!(written[IMAGE_SIZE], 0);
+        assert_eq!(written[WIPE_SIZE as usize], 0xee);
+        assert!(
+            written[card_size - WIPE_SIZE as usize..]
+                .iter()
+                .all(|&b| b == 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn waits_while_paused_and_stops_on_abort() {
+        let data = image_data();
+        let (_server, url) = serve(data.clone(), "image.img").await;
+        let mut target = target(&[]);
+        let (tx, mut rx) = mpsc::channel(1024);
+        let control = Arc::new(Control::default());
+        control.apply(WorkerCommand::Pause);
+
+        let handle = tokio::spawn(write_image(
+            WriteJob::new(
+                image(&url, Some(sha256_hex(&data))),
+                drive(&target),
+                plain_options(),
+            ),
+            control.clone(),
+            tx,
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!handle.is_finished());
+        assert!(contents(&mut target).is_empty());
+
+        control.apply(WorkerCommand::Abort);
+        let err = handle.await.unwrap().unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Cancelled);
+        assert!(err.to_string().contains("Aborted"), "{}", err);
+        let mut paused = false;
+        while let Ok(event) = rx.try_recv() {
+            paused |= matches!(event, ProgressEvent::Status(s) if s == "Paused");
+        }
+        assert!(paused);
+    }
+
+    #[tokio::test]
+    async fn skipping_verification_still_finishes() {
+        let data = image_data();
+        let (_server, url) = serve(data.clone(), "image.img").await;
+        let mut target = target(&[]);
+        let (tx, mut rx) = mpsc::channel(1024);
+        let control = Arc::new(Control::default());
+        control.apply(WorkerCommand::SkipVerify);
+
+        write_image(
+            WriteJob::new(
+                image(&url, Some(sha256_hex(&data))),
+                drive(&target),
+                plain_options(),
+            ),
+            control,
+            tx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(contents(&mut target), data);
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, ProgressEvent::VerificationSkipped))
+        );
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, ProgressEvent::Progress { phase: WritingPhase::Verifying, fraction } if *fraction > 0.0))
+        );
+    }
+
+    #[tokio::test]
+    async fn aborting_stops_before_finishing() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(image_data())
+                    .set_delay(std::time::Duration::from_secs(30)),
+            )
+            .mount(&server)
+            .await;
+        let url = format!("{}/image.img", server.uri());
+        let target = target(&[]);
+        let (tx, mut rx) = mpsc::channel(1024);
+
+        let handle = tokio::spawn(write_image(
+            WriteJob::new(image(&url, None), drive(&target), plain_options()),
+            Arc::default(),
+            tx,
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        handle.abort();
+
+        assert!(handle.await.unwrap_err().is_cancelled());
+        while let Some(event) = rx.recv().await {
+            assert!(!matches!(event, ProgressEvent::Finished));
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! What this build is: version, commit and what it was built with. Shown by
+//! `--version`/`--about` and the About popup, sent as the HTTP user agent and
+//! attached to copied or saved error reports.
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_HASH: &str = env!("GIT_HASH");
+
+/// One line, e.g. "rpi-imager-tui 0.1.0 (3f2a9c1, linux x86_64)".
+pub fn version_line() -> String {
+    format!(
+        "rpi-imager-tui {} ({}, {} {})",
+        VERSION,
+        GIT_HASH,
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+/// Sent with every HTTP request, e.g. "rpi-imager-tui/0.1.0+3f2a9c1".
+pub fn user_agent() -> String {
+    format!("rpi-imager-tui/{}+{}", VERSION, GIT_HASH)
+}
+
+/// The version line followed by what was compiled in.
+pub fn details() -> Vec<String> {
+    vec![
+        version_line(),
+        "Decompression: xz (multi-threaded), gzip, zstd, bzip2".to_string(),
+        "Privileges: udisks2, sudo, pkexec".to_string(),
+        "First boot: firstrun.sh, cloud-init, custom.toml, Armbian, Home Assistant OS, LibreELEC"
+            .to_string(),
+        "TLS: rustls with built-in roots".to_string(),
+        format!(
+            "Debug build: {}",
+            if cfg!(debug_assertions) { "yes" } else { "no" }
+        ),
+    ]
+}
+
+/// An error as it goes into a bug report.
+pub fn bug_report(error: &str) -> String {
+    format!("{}\n\n{}", error, version_line())
+}
@@ -0,0 +1,69 @@
+use crate::customization::CustomizationOptions;
+use anyhow::{Context, Result, anyhow};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+/// sshd comes up a little after the Pi starts answering mDNS.
+const SSH_WAIT: Duration = Duration::from_secs(120);
+
+pub fn log_path() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .ok()
+        .map(|home| std::path::Path::new(&home).join(".config/rpi-imager-tui/bootstrap.log"))
+}
+
+/// Runs the configured bootstrap command (e.g. an ansible-playbook call)
+/// against a freshly booted Pi. The command runs through `sh -c` with the
+/// connection details in PI_HOST, PI_HOSTNAME, PI_USER and, if one was set,
+/// PI_PASSWORD. Output goes to the bootstrap log.
+pub async fn run(command: &str, ip: IpAddr, options: &CustomizationOptions) -> Result<PathBuf> {
+    wait_for_ssh(ip).await?;
+
+    let log = log_path().ok_or_else(|| anyhow!("HOME is not set"))?;
+    if let Some(parent) = log.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create config directory")?;
+    }
+    let stdout = std::fs::File::create(&log).context("Failed to create bootstrap log")?;
+    let stderr = stdout.try_clone()?;
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .env("PI_HOST", ip.to_string())
+        .env("PI_HOSTNAME", format!("{}.local", options.hostname))
+        .env("PI_USER", &options.user_name)
+        .stdin(std::process::Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr);
+    if let Some(password) = &options.password {
+        cmd.env("PI_PASSWORD", password);
+    }
+
+    let status = cmd
+        .status()
+        .await
+        .context("Failed to run bootstrap command")?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Bootstrap command failed. Exit code: {:?}. See {}",
+            status.code(),
+            log.display()
+        ));
+    }
+    Ok(log)
+}
+
+async fn wait_for_ssh(ip: IpAddr) -> Result<()> {
+    let addr = SocketAddr::new(ip, 22);
+    let deadline = Instant::now() + SSH_WAIT;
+    while Instant::now() < deadline {
+        let connect = tokio::net::TcpStream::connect(addr);
+        if let Ok(Ok(_)) = tokio::time::timeout(Duration::from_secs(3), connect).await {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+    Err(anyhow!("SSH did not become available on {}", addr))
+}
@@ -0,0 +1,268 @@
+//! The command line. Without a subcommand the interface starts; the worker
+//! the interface spawns for privileged jobs is a hidden subcommand of the
+//! same binary. Shell completion is dynamic: the shell calls back into the
+//! binary with COMPLETE set, so drive arguments complete to the drives
+//! attached right now.
+
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use clap_complete::env::Shells;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The environment variable the completion scripts set when calling back.
+const COMPLETE_VAR: &str = "COMPLETE";
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "rpi-imager-tui",
+    about = "Raspberry Pi Imager for the terminal",
+    disable_version_flag = true,
+    group(ArgGroup::new("image_source").args(["image", "image_url"]))
+)]
+pub struct Cli {
+    /// Local image to write instead of one from the OS list; like --image
+    pub image: Option<PathBuf>,
+
+    /// Raspberry Pi model to select, by name or tag (e.g. pi5); a path is
+    /// taken as --drive
+    #[arg(long, value_name = "MODEL")]
+    pub device: Option<String>,
+
+    /// Operating system to select, by name
+    #[arg(long, value_name = "NAME")]
+    pub os: Option<String>,
+
+    /// Storage device to select; with a device and OS, the interface starts
+    /// on the customization screen
+    #[arg(long, value_name = "PATH", add = ArgValueCandidates::new(drive_candidates))]
+    pub drive: Option<String>,
+
+    /// Image URL or file to write. With --drive it is written without the
+    /// interface, otherwise the interface starts with it selected
+    #[arg(long = "image", id = "image_url", value_name = "URL|PATH")]
+    pub image_url: Option<String>,
+
+    /// Don't ask before erasing the drive
+    #[arg(short, long, requires = "image_source")]
+    pub yes: bool,
+
+    /// Print progress as JSON lines, like the worker does
+    #[arg(long, requires = "image_source")]
+    pub json: bool,
+
+    /// Show the state of the interface and extra drive details
+    #[arg(long)]
+    pub debug: bool,
+
+    /// Continue the session handed over on standard input when the app
+    /// restarted itself with privileges
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Redraw at most this many times a second
+    #[arg(long, value_name = "FPS")]
+    pub max_fps: Option<u32>,
+
+    /// Image protocol for OS and device icons, instead of guessing it
+    #[arg(long, value_parser = ["kitty", "iterm2", "sixel", "none"])]
+    pub graphics: Option<String>,
+
+    /// Print the version and exit
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// Print the version and build details and exit
+    #[arg(long)]
+    pub about: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Export the configuration (profiles, history, settings) to an archive
+    ExportConfig { file: PathBuf },
+    /// Import a configuration archive exported on another machine
+    ImportConfig { file: PathBuf },
+    /// Print the completion script for a shell
+    Completions { shell: Shell },
+    /// Privileged job spawned by the interface
+    #[command(hide = true)]
+    Worker(WorkerArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct WorkerArgs {
+    #[arg(long)]
+    pub image: Option<String>,
+    #[arg(long, add = ArgValueCandidates::new(drive_candidates))]
+    pub device: Option<String>,
+    #[arg(long)]
+    pub sha256: Option<String>,
+    #[arg(long)]
+    pub size: Option<u64>,
+    #[arg(long)]
+    pub delta: bool,
+    /// Device opened by the interface through udisks2
+    #[arg(long)]
+    pub device_fd: Option<i32>,
+    #[arg(long)]
+    pub inspect: bool,
+    #[arg(long)]
+    pub browse: bool,
+    #[arg(long)]
+    pub rpiboot: bool,
+    #[arg(long)]
+    pub customize: bool,
+    #[arg(long)]
+    pub profile: Option<String>,
+    #[arg(long)]
+    pub cache: Option<PathBuf>,
+    #[arg(long)]
+    pub init_format: Option<String>,
+    /// Base64 encoded customization options
+    #[arg(long)]
+    pub options: Option<String>,
+}
+
+impl Cli {
+    /// Answers a completion request from the shell and exits, or parses the
+    /// command line.
+    pub fn parse_args() -> Self {
+        clap_complete::CompleteEnv::with_factory(Self::command)
+            .var(COMPLETE_VAR)
+            .complete();
+        let cli = Self::parse();
+        DEBUG.store(cli.debug, Ordering::Relaxed);
+        crate::drivelist::set_fake_drive(cli.debug);
+        cli
+    }
+}
+
+impl Cli {
+    /// `--device`, unless it names a path.
+    pub fn pi_model(&self) -> Option<&str> {
+        self.device.as_deref().filter(|d| !d.starts_with('/'))
+    }
+
+    /// `--image`, or the image given as a path.
+    pub fn image_location(&self) -> Option<String> {
+        self.image_url.clone().or_else(|| {
+            self.image
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string())
+        })
+    }
+
+    /// `--drive`, or `--device` given a path, as in `--device /dev/sdb`.
+    pub fn target_drive(&self) -> Option<&str> {
+        self.drive
+            .as_deref()
+            .or_else(|| self.device.as_deref().filter(|d| d.starts_with('/')))
+    }
+}
+
+/// Whether `--debug` was given.
+pub fn debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+/// The script registering completions for `shell`, to be sourced from the
+/// shell's startup file.
+pub fn completion_script(shell: Shell) -> std::io::Result<String> {
+    let shells = Shells::builtins();
+    let name = shell.to_possible_value().map(|v| v.get_name().to_string());
+    let completer = name
+        .as_deref()
+        .and_then(|name| shells.completer(name))
+        .ok_or_else(|| std::io::Error::other("unsupported shell"))?;
+    let exe = std::env::current_exe()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "rpi-imager-tui".to_string());
+    let bin = Cli::command().get_name().to_string();
+    let mut script = Vec::new();
+    completer.write_registration(COMPLETE_VAR, &bin, &bin, &exe, &mut script)?;
+    Ok(String::from_utf8_lossy(&script).to_string())
+}
+
+/// Drives attached right now, described like in the storage list.
+pub fn drive_candidates() -> Vec<CompletionCandidate> {
+    crate::drivelist::get_drives()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|drive| CompletionCandidate::new(&drive.name).help(Some(drive.description.into())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_worker_jobs() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::parse_from([
+            "rpi-imager-tui",
+            "worker",
+            "--device",
+            "/dev/sdb",
+            "--size",
+            "1024",
+            "--delta",
+            "--init-format",
+            "cloudinit",
+            "--device-fd",
+            "7",
+        ]);
+        let Some(Command::Worker(worker)) = cli.command else {
+            panic!("not a worker job: {:?}", cli.command);
+        };
+        assert_eq!(worker.device.as_deref(), Some("/dev/sdb"));
+        assert_eq!(worker.size, Some(1024));
+        assert_eq!(worker.device_fd, Some(7));
+        assert!(worker.delta && !worker.inspect);
+
+        let cli = Cli::parse_from(["rpi-imager-tui", "--max-fps", "30", "image.img.xz"]);
+        assert_eq!(cli.image, Some(PathBuf::from("image.img.xz")));
+        assert_eq!(cli.max_fps, Some(30));
+        assert!(cli.command.is_none());
+
+        let cli = Cli::parse_from(["rpi-imager-tui", "--device", "pi5", "--drive", "/dev/sda"]);
+        assert_eq!(cli.pi_model(), Some("pi5"));
+        assert_eq!(cli.target_drive(), Some("/dev/sda"));
+
+        let cli = Cli::parse_from([
+            "rpi-imager-tui",
+            "--image",
+            "https://example.com/os.img.xz",
+            "--device",
+            "/dev/sdb",
+            "--yes",
+        ]);
+        assert!(cli.yes && cli.image.is_none());
+        assert_eq!(cli.pi_model(), None);
+        assert_eq!(cli.target_drive(), Some("/dev/sdb"));
+        assert!(Cli::try_parse_from(["rpi-imager-tui", "--yes"]).is_err());
+
+        // A positional image is written like --image
+        let cli = Cli::parse_from(["rpi-imager-tui", "os.img", "--drive", "/dev/sdb", "-y"]);
+        assert_eq!(cli.image_location().as_deref(), Some("os.img"));
+        assert_eq!(cli.target_drive(), Some("/dev/sdb"));
+        assert!(Cli::try_parse_from(["rpi-imager-tui", "os.img", "--image", "other.img"]).is_err());
+
+        let script = completion_script(Shell::Bash).unwrap();
+        assert!(script.contains("COMPLETE"));
+    }
+}
@@ -0,0 +1,163 @@
+//! Settings the selected image accepts but won't apply as expected. The OS
+//! list doesn't say what a release's first boot can do, so Raspberry Pi OS
+//! releases are told apart by the Debian codename in their name or, failing
+//! that, by their release date.
+
+use crate::customization::{CustomizationOptions, CustomizationSection};
+use crate::os_list::OsListItem;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Release {
+    Stretch,
+    Buster,
+    Bullseye,
+    Bookworm,
+    Trixie,
+}
+
+/// The first image of each release after Stretch.
+const FIRST_IMAGES: [(Release, &str); 4] = [
+    (Release::Buster, "2019-06-20"),
+    (Release::Bullseye, "2021-10-30"),
+    (Release::Bookworm, "2023-10-10"),
+    (Release::Trixie, "2025-10-01"),
+];
+
+/// Images from this date on have no default user, and set one up with
+/// userconf-pi.
+const USERCONF_RELEASE: &str = "2022-04-04";
+
+impl Release {
+    fn of(os: &OsListItem) -> Option<Self> {
+        let text = format!("{} {}", os.name, os.description).to_lowercase();
+        let named = [
+            (Self::Trixie, "trixie"),
+            (Self::Bookworm, "bookworm"),
+            (Self::Bullseye, "bullseye"),
+            (Self::Buster, "buster"),
+            (Self::Stretch, "stretch"),
+        ];
+        if let Some((release, _)) = named.iter().find(|(_, name)| text.contains(name)) {
+            return Some(*release);
+        }
+        // ISO dates compare as strings
+        let date = os.release_date.as_deref()?;
+        Some(
+            FIRST_IMAGES
+                .iter()
+                .rev()
+                .find(|(_, first)| date >= *first)
+                .map_or(Self::Stretch, |(release, _)| *release),
+        )
+    }
+}
+
+/// Why settings of `section` may be ignored when written to `os`. Only
+/// images customized through firstrun.sh are known well enough to judge.
+pub fn warnings(
+    os: &OsListItem,
+    options: &CustomizationOptions,
+    section: CustomizationSection,
+) -> Vec<String> {
+    if os.init_format.as_deref() != Some("systemd") || !os.supports(section) {
+        return Vec::new();
+    }
+    let Some(release) = Release::of(os) else {
+        return Vec::new();
+    };
+    if release == Release::Stretch {
+        return vec![
+            "Stretch and older releases can't run firstrun.sh (it needs systemd 233), \
+             so these settings are ignored."
+                .to_string(),
+        ];
+    }
+
+    let mut warnings = Vec::new();
+    match section {
+        CustomizationSection::User => {
+            let sets_user = !options.user_name.is_empty()
+                && options.password.as_deref().is_some_and(|p| !p.is_empty());
+            let date = os.release_date.as_deref();
+            if sets_user && date.is_some_and(|d| d < USERCONF_RELEASE) && options.user_name != "pi"
+            {
+                warnings.push(format!(
+                    "Released before userconf-pi (April 2022): the default user pi is renamed \
+                     to {} instead of being created.",
+                    options.user_name
+                ));
+            }
+            if !sets_user && date.is_some_and(|d| d >= USERCONF_RELEASE) {
+                warnings.push(
+                    "This release has no default user. Without a username and password it \
+                     asks for one on first boot, which needs a screen and keyboard."
+                        .to_string(),
+                );
+            }
+        }
+        CustomizationSection::Wifi
+            if release >= Release::Bookworm && options.section_modified(section) =>
+        {
+            warnings.push(
+                "Bookworm and later manage Wi-Fi with NetworkManager and ignore \
+                 wpa_supplicant.conf; the network is only set up through imager_custom."
+                    .to_string(),
+            );
+        }
+        _ => {}
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(name: &str, release_date: &str) -> OsListItem {
+        OsListItem {
+            url: Some("https://example.com/os.img.xz".to_string()),
+            release_date: Some(release_date.to_string()),
+            init_format: Some("systemd".to_string()),
+            ..OsListItem::from_location(name)
+        }
+    }
+
+    #[test]
+    fn warns_by_release() {
+        let mut options = CustomizationOptions {
+            user_name: "alice".to_string(),
+            password: Some("secret".to_string()),
+            wifi_ssid: "home".to_string(),
+            ..CustomizationOptions::default()
+        };
+        let buster = image("Raspberry Pi OS (Legacy)", "2021-05-07");
+        let bookworm = image("Raspberry Pi OS Lite", "2024-11-19");
+        let stretch = image("Raspbian Stretch", "2030-01-01");
+
+        assert_eq!(
+            warnings(&buster, &options, CustomizationSection::User).len(),
+            1
+        );
+        assert!(warnings(&buster, &options, CustomizationSection::Wifi).is_empty());
+        assert!(warnings(&bookworm, &options, CustomizationSection::User).is_empty());
+        assert_eq!(
+            warnings(&bookworm, &options, CustomizationSection::Wifi).len(),
+            1
+        );
+        assert!(
+            warnings(&stretch, &options, CustomizationSection::Hostname)[0].contains("Stretch")
+        );
+
+        options.password = None;
+        assert!(
+            warnings(&bookworm, &options, CustomizationSection::User)[0]
+                .contains("no default user")
+        );
+
+        let cloud_init = OsListItem {
+            init_format: Some("cloudinit-rpi".to_string()),
+            ..bookworm
+        };
+        assert!(warnings(&cloud_init, &options, CustomizationSection::User).is_empty());
+    }
+}
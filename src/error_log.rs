@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn path() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .ok()
+        .map(|home| std::path::Path::new(&home).join(".config/rpi-imager-tui/errors.log"))
+}
+
+/// Where the boot partition edits of each customization are kept.
+pub fn changes_path() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .ok()
+        .map(|home| std::path::Path::new(&home).join(".config/rpi-imager-tui/changes.log"))
+}
+
+/// Appends the error, stamped with the Unix time, and returns the log path.
+pub fn append(message: &str) -> Result<PathBuf> {
+    append_to(path().context("HOME is not set")?, message)
+}
+
+/// Appends a report of boot partition edits, like [`append`].
+pub fn append_changes(report: &str) -> Result<PathBuf> {
+    append_to(changes_path().context("HOME is not set")?, report)
+}
+
+fn append_to(path: PathBuf, message: &str) -> Result<PathBuf> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    writeln!(file, "[{}] {}\n", timestamp, message)?;
+    Ok(path)
+}
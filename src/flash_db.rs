@@ -0,0 +1,261 @@
+use crate::writer::FINGERPRINT_BYTES;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlashRecord {
+    pub image_name: String,
+    pub image_sha256: Option<String>,
+    pub flashed_at: u64, // Unix timestamp (seconds)
+    /// [`crate::writer::fingerprint`] of the card right after the write,
+    /// as the worker reported it.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+/// How fast an image was written through a reader or drive model, averaged
+/// over the recent writes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedRecord {
+    pub model: String,
+    pub image_name: String,
+    pub bytes_per_sec: f64,
+    pub writes: u32,
+}
+
+/// Older writes stop counting after this many, so a new reader or a worn
+/// card shows up in the estimate soon.
+const SPEED_WINDOW: u32 = 10;
+
+/// [`crate::writer::fingerprint`] of the card in `device`, to tell whether
+/// it is still the one a record was made for. Block devices usually need
+/// root, so udisks2 is asked next. None if it can't be read.
+pub fn fingerprint(device: &str) -> Option<String> {
+    let mut start = vec![0; FINGERPRINT_BYTES];
+    open(device)?.read_exact(&mut start).ok()?;
+    Some(crate::writer::fingerprint(&start))
+}
+
+fn open(device: &str) -> Option<std::fs::File> {
+    match std::fs::File::open(device) {
+        Ok(file) => Some(file),
+        #[cfg(unix)]
+        Err(_) => crate::udisks::read_device(device)
+            .ok()
+            .map(std::fs::File::from),
+        #[cfg(not(unix))]
+        Err(_) => None,
+    }
+}
+
+/// Remembers what was last written to each card, keyed by device serial,
+/// and how fast writes to each drive model went.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FlashDatabase {
+    pub cards: HashMap<String, FlashRecord>,
+    #[serde(default)]
+    pub speeds: Vec<SpeedRecord>,
+}
+
+impl FlashDatabase {
+    pub fn path() -> Option<std::path::PathBuf> {
+        std::env::var("HOME").ok().map(|home| {
+            std::path::Path::new(&home).join(".config/rpi-imager-tui/flashed_cards.json")
+        })
+    }
+
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    /// Not once saving is disabled, like the config.
+    pub fn save(&self) {
+        if crate::customization::saving_disabled() {
+            return;
+        }
+        if let Some(path) = Self::path() {
+            if let Some(parent) = path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            if let Ok(file) = std::fs::File::create(path) {
+                let _ = serde_json::to_writer_pretty(file, self);
+            }
+        }
+    }
+
+    pub fn get(&self, serial: &str) -> Option<&FlashRecord> {
+        self.cards.get(serial)
+    }
+
+    pub fn record(&mut self, serial: &str, image_name: &str, image_sha256: Option<&str>) {
+        let flashed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.cards.insert(
+            serial.to_string(),
+            FlashRecord {
+                image_name: image_name.to_string(),
+                image_sha256: image_sha256.map(|h| h.to_lowercase()),
+                flashed_at,
+                fingerprint: None,
+            },
+        );
+        self.save();
+    }
+
+    /// Remembers the fingerprint the worker reported for the card last
+    /// written through `serial`.
+    pub fn set_fingerprint(&mut self, serial: &str, fingerprint: String) {
+        if let Some(record) = self.cards.get_mut(serial) {
+            record.fingerprint = Some(fingerprint);
+            self.save();
+        }
+    }
+
+    /// Adds a write of `bytes` that took `millis` (writing and syncing).
+    pub fn record_speed(&mut self, model: &str, image_name: &str, bytes: u64, millis: u64) {
+        if bytes > 0 && millis > 0 {
+            self.add_speed(model, image_name, bytes, millis);
+            self.save();
+        }
+    }
+
+    fn add_speed(&mut self, model: &str, image_name: &str, bytes: u64, millis: u64) {
+        let speed = bytes as f64 * 1000.0 / millis as f64;
+        match self
+            .speeds
+            .iter_mut()
+            .find(|s| s.model == model && s.image_name == image_name)
+        {
+            Some(record) => {
+                let weight = record.writes.min(SPEED_WINDOW - 1) as f64;
+                record.bytes_per_sec = (record.bytes_per_sec * weight + speed) / (weight + 1.0);
+                record.writes += 1;
+            }
+            None => self.speeds.push(SpeedRecord {
+                model: model.to_string(),
+                image_name: image_name.to_string(),
+                bytes_per_sec: speed,
+                writes: 1,
+            }),
+        }
+    }
+
+    /// How long writing `bytes` of the image to this drive model should take,
+    /// from earlier writes of the same image, or of any image if there are
+    /// none.
+    pub fn estimated_duration(
+        &self,
+        model: &str,
+        image_name: &str,
+        bytes: u64,
+    ) -> Option<Duration> {
+        let same_model = || self.speeds.iter().filter(|s| s.model == model);
+        let speed = match same_model().find(|s| s.image_name == image_name) {
+            Some(record) => record.bytes_per_sec,
+            None => {
+                let (total, writes) = same_model().fold((0.0, 0), |(total, writes), s| {
+                    (total + s.bytes_per_sec * s.writes as f64, writes + s.writes)
+                });
+                if writes == 0 {
+                    return None;
+                }
+                total / writes as f64
+            }
+        };
+        Some(Duration::from_secs_f64(bytes as f64 / speed))
+    }
+}
+
+impl FlashRecord {
+    /// Whether this record was written from exactly the given image, onto
+    /// a card that still starts with what was written then.
+    pub fn matches(&self, image_sha256: Option<&str>, fingerprint: Option<&str>) -> bool {
+        let same_image = match (&self.image_sha256, image_sha256) {
+            (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+            _ => false,
+        };
+        same_image && fingerprint.is_some() && self.fingerprint.as_deref() == fingerprint
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "currently contains: {} (flashed {})",
+            self.image_name,
+            format_date(self.flashed_at)
+        )
+    }
+}
+
+/// Formats a Unix timestamp as YYYY-MM-DD (UTC).
+pub fn format_date(secs: u64) -> String {
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_from_earlier_writes() {
+        let mut db = FlashDatabase::default();
+        let gib = 1 << 30;
+        // 20 MiB/s, then 40 MiB/s
+        db.add_speed("SD Reader", "Lite", gib, 51_200);
+        db.add_speed("SD Reader", "Lite", gib, 25_600);
+
+        let estimate = db.estimated_duration("SD Reader", "Lite", gib).unwrap();
+        assert_eq!(estimate.as_secs(), 34);
+        // Another image falls back to the model's average
+        assert!(db.estimated_duration("SD Reader", "Desktop", gib).is_some());
+        assert!(db.estimated_duration("Other Reader", "Lite", gib).is_none());
+    }
+
+    #[test]
+    fn matches_only_the_card_that_was_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let card = dir.path().join("card");
+        std::fs::write(&card, vec![0xaa; FINGERPRINT_BYTES * 2]).unwrap();
+        let card = card.to_str().unwrap();
+
+        let mut db = FlashDatabase::default();
+        db.cards.insert(
+            "reader".to_string(),
+            FlashRecord {
+                image_name: "Lite".to_string(),
+                image_sha256: Some("abc".to_string()),
+                flashed_at: 0,
+                fingerprint: fingerprint(card),
+            },
+        );
+        let record = db.get("reader").unwrap();
+        assert!(record.matches(Some("ABC"), fingerprint(card).as_deref()));
+        assert!(!record.matches(Some("def"), fingerprint(card).as_deref()));
+
+        // Another card in the same reader, or the card after its first boot
+        std::fs::write(dir.path().join("card"), vec![0x55; 4096 * 512]).unwrap();
+        assert!(!record.matches(Some("abc"), fingerprint(card).as_deref()));
+        // A card that can't be read is never taken for the written one
+        assert!(!record.matches(Some("abc"), None));
+        assert_eq!(
+            fingerprint(&format!("{}/missing", dir.path().display())),
+            None
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Writing without the interface, for provisioning cards from scripts, CI
+//! and kiosks: `rpi-imager-tui --image <url-or-path> --drive /dev/sdX --yes`.
+//! The write runs in this process through the same writer the worker uses,
+//! so it needs root, or a device udisks2 lets the user open. Progress goes to
+//! stdout as plain lines, or with `--json` as the worker's JSON lines.
+
+use crate::customization::{CustomizationOptions, CustomizationSection};
+use crate::drivelist::{Drive, TargetProfile};
+use crate::ipc::ProgressEvent;
+use crate::os_list::OsListItem;
+use crate::target::Target;
+use crate::writer::Control;
+use anyhow::{Result, anyhow, bail};
+use std::io::{BufRead, IsTerminal, Write};
+#[cfg(unix)]
+use std::os::fd::IntoRawFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+pub struct Job {
+    /// URL or path of the image.
+    pub image: String,
+    pub drive: String,
+    /// Skip the confirmation prompt.
+    pub yes: bool,
+    pub json: bool,
+}
+
+/// Repeats of a status, e.g. "Writing... 45.0% (30.1 MB/s)", are printed
+/// at most this often.
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Runs the write and returns the exit code: 0 once the image is written
+/// and verified, 1 if anything failed, verification included.
+pub async fn run(job: Job) -> i32 {
+    crate::worker::release_workspaces_on_exit();
+    let result = write(&job).await;
+    if job.json {
+        if let Err(e) = &result {
+            ProgressEvent::error(e).emit();
+        }
+        crate::ipc::emit_summary();
+    }
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            if !job.json {
+                eprintln!("Error: {:#}", e);
+            }
+            1
+        }
+    }
+}
+
+async fn write(job: &Job) -> Result<()> {
+    let target = Target::detect(&job.drive);
+    if target == Target::Stdout {
+        bail!("Writing to a stream needs the interface; give a drive or an image file");
+    }
+    let drive = find_drive(&job.drive, target)?;
+    if !job.yes {
+        confirm(&drive, &job.image)?;
+    }
+
+    // Without root, ask udisks2 for the device like the interface does
+    #[cfg(unix)]
+    if target == Target::BlockDevice
+        && !crate::customization::is_root()
+        && let Ok(fd) = crate::udisks::open_device(&drive.name)
+    {
+        crate::udisks::adopt(drive.name.clone(), fd.into_raw_fd());
+    }
+
+    if let Some(warning) = crate::power::Battery::read().and_then(|b| b.warning()) {
+        eprintln!("Warning: {}", warning);
+    }
+    let _awake = crate::power::Inhibitor::take("Writing an image to storage");
+    let (tx, mut rx) = mpsc::channel::<ProgressEvent>(100);
+    let os = OsListItem::from_location(&job.image);
+    // The tool settings of the config apply (proxy, trust, verification,
+    // threads), but the image is written as it is
+    let mut options = CustomizationOptions::load();
+    for section in CustomizationSection::ALL {
+        options.clear_section(section);
+    }
+    options.extra_files.clear();
+    let control = Arc::new(Control::default());
+    let mut station = options.stats_file.clone().map(crate::stats::Station::open);
+    if let Some(station) = &mut station {
+        station.started(&job.image, &drive.name);
+    }
+    let writer = tokio::spawn(async move {
+        if let Err(e) = crate::writer::write_image(
+            crate::writer::WriteJob::new(os, drive, options),
+            control,
+            tx.clone(),
+        )
+        .await
+        {
+            let _ = tx.send(ProgressEvent::error(&e)).await;
+        }
+    });
+
+    let mut printed: Option<(String, Instant)> = None;
+    let mut finished = false;
+    while let Some(event) = rx.recv().await {
+        if let Some(station) = &mut station {
+            station.observe(&event);
+        }
+        if job.json {
+            if event.is_final() {
+                // Errors are emitted by run(), along with the summary
+                match event {
+                    ProgressEvent::Error { message: e, .. }
+                    | ProgressEvent::CustomizationFailed(e) => return Err(anyhow!(e)),
+                    _ => {}
+                }
+                event.emit();
+                finished = true;
+                break;
+            }
+            event.emit();
+            continue;
+        }
+        match event {
+            ProgressEvent::Status(message) => {
+                let step = message.split("...").next().unwrap_or_default().to_string();
+                if printed
+                    .as_ref()
+                    .is_none_or(|(last, at)| *last != step || at.elapsed() >= STATUS_INTERVAL)
+                {
+                    println!("{}", message);
+                    printed = Some((step, Instant::now()));
+                }
+            }
+            ProgressEvent::Source(host) => println!("Downloading from {}", host),
+            ProgressEvent::Finished => {
+                println!("{} written to {}", job.image, job.drive);
+                finished = true;
+                break;
+            }
+            ProgressEvent::Error { message: e, .. } | ProgressEvent::CustomizationFailed(e) => {
+                return Err(anyhow!(e));
+            }
+            _ => {}
+        }
+    }
+    writer
+        .await
+        .map_err(|e| anyhow!("The writer stopped unexpectedly: {}", e))?;
+    if !finished {
+        bail!("The writer stopped before the image was written");
+    }
+    Ok(())
+}
+
+/// The drive as listed, so its profile and system drive check apply, or a
+/// bare entry for image files.
+fn find_drive(path: &str, target: Target) -> Result<Drive> {
+    let drives = crate::drivelist::get_drives().unwrap_or_default();
+    if let Some(i) = crate::preselect::find_drive(&drives, path) {
+        let drive = drives[i].clone();
+        if drive.is_system() {
+            bail!("{} holds the running system", drive.name);
+        }
+        return Ok(drive);
+    }
+    if target == Target::BlockDevice {
+        bail!("{} is not a drive lsblk knows", path);
+    }
+    Ok(Drive {
+        name: path.to_string(),
+        description: "Image file".to_string(),
+        model: None,
+        size: 0,
+        removable: false,
+        readonly: false,
+        mountpoints: Vec::new(),
+        serial: None,
+        profile: TargetProfile::SdCard,
+    })
+}
+
+/// Asks on the terminal before erasing the drive; scripts pass `--yes`.
+fn confirm(drive: &Drive, image: &str) -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        bail!("Refusing to write without --yes when not run from a terminal");
+    }
+    eprint!(
+        "Erase everything on {} ({}) and write {}? [y/N] ",
+        drive.name, drive.description, image
+    );
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        bail!("Cancelled");
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+//! Every write that ran to an end, for lab record-keeping: the image, the
+//! device, when it started, how long it took and how it went. Kept in
+//! `history.json` next to the config, oldest first, and exported as CSV or
+//! JSON from the History screen. The image is kept whole, so a job can be
+//! run again from the history.
+
+use crate::ipc::ProgressEvent;
+use crate::os_list::OsListItem;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Older jobs are dropped beyond this many.
+const MAX_JOBS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Written,
+    /// Written, but the OS customization couldn't be applied.
+    CustomizationFailed,
+    Failed,
+    Cancelled,
+}
+
+impl Outcome {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Written => "written",
+            Self::CustomizationFailed => "customization failed",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub os: OsListItem,
+    /// Device path, e.g. /dev/sdb.
+    pub device: String,
+    /// Of the card reader, to find the device again when its path changed.
+    #[serde(default)]
+    pub serial: Option<String>,
+    /// Unix timestamp (seconds).
+    pub started: u64,
+    pub duration_ms: u64,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A job as exported: the image by name and checksum rather than its whole
+/// OS list entry.
+#[derive(Serialize)]
+struct Record<'a> {
+    started: String,
+    image: &'a str,
+    url: &'a str,
+    sha256: &'a str,
+    device: &'a str,
+    duration_s: u64,
+    result: &'a str,
+    error: &'a str,
+}
+
+impl Job {
+    fn record(&self) -> Record<'_> {
+        Record {
+            started: format_time(self.started),
+            image: &self.os.name,
+            url: self.os.url.as_deref().unwrap_or_default(),
+            sha256: self
+                .os
+                .extract_sha256
+                .as_deref()
+                .or(self.os.image_download_sha256.as_deref())
+                .unwrap_or_default(),
+            device: &self.device,
+            duration_s: self.duration_ms.div_ceil(1000),
+            result: self.outcome.label(),
+            error: self.error.as_deref().unwrap_or_default(),
+        }
+    }
+
+    /// One line for the History screen.
+    pub fn summary(&self) -> String {
+        let secs = self.duration_ms.div_ceil(1000);
+        format!(
+            "{}  {} -> {}  {}m{:02}s",
+            format_time(self.started),
+            self.os.name,
+            self.device,
+            secs / 60,
+            secs % 60
+        )
+    }
+}
+
+/// The finished jobs, and the write in progress.
+#[derive(Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    pub jobs: Vec<Job>,
+    current: Option<(Job, Instant)>,
+}
+
+impl History {
+    pub fn path() -> Option<PathBuf> {
+        std::env::var("HOME")
+            .ok()
+            .map(|home| Path::new(&home).join(".config/rpi-imager-tui/history.json"))
+    }
+
+    /// The history kept at [`History::path`].
+    pub fn load() -> Self {
+        Self::path().map(Self::open).unwrap_or_default()
+    }
+
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let jobs = std::fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path: Some(path),
+            jobs,
+            current: None,
+        }
+    }
+
+    pub fn started(&mut self, os: &OsListItem, device: &str, serial: Option<&str>) {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let job = Job {
+            os: os.clone(),
+            device: device.to_string(),
+            serial: serial.map(str::to_string),
+            started,
+            duration_ms: 0,
+            outcome: Outcome::Written,
+            error: None,
+        };
+        self.current = Some((job, Instant::now()));
+    }
+
+    /// Records the outcome of the current write. Events of other worker
+    /// runs, like a customization retry, come without one and are ignored.
+    pub fn observe(&mut self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Finished => self.finished(Outcome::Written, None),
+            ProgressEvent::CustomizationFailed(err) => {
+                self.finished(Outcome::CustomizationFailed, Some(err.clone()))
+            }
+            ProgressEvent::Error { kind, message } => {
+                let outcome = if *kind == crate::error::ErrorKind::Cancelled {
+                    Outcome::Cancelled
+                } else {
+                    Outcome::Failed
+                };
+                self.finished(outcome, Some(message.clone()))
+            }
+            _ => {}
+        }
+    }
+
+    /// Ends the current write with `outcome`.
+    pub fn finished(&mut self, outcome: Outcome, error: Option<String>) {
+        let Some((mut job, started)) = self.current.take() else {
+            return;
+        };
+        job.duration_ms = started.elapsed().as_millis() as u64;
+        job.outcome = outcome;
+        job.error = error;
+        self.jobs.push(job);
+        let overflow = self.jobs.len().saturating_sub(MAX_JOBS);
+        self.jobs.drain(..overflow);
+        self.save();
+    }
+
+    /// Best effort, like the flash database: a history that can't be kept
+    /// mustn't stop the writes. Not once saving is disabled, like the config.
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if crate::customization::saving_disabled() {
+            return;
+        }
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Ok(json) = serde_json::to_vec_pretty(&self.jobs) {
+            let _ = std::fs::write(path, json);
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "started,image,url,sha256,device,duration_s,result,error\n".to_string();
+        for job in &self.jobs {
+            let record = job.record();
+            let duration = record.duration_s.to_string();
+            let fields = [
+                record.started.as_str(),
+                record.image,
+                record.url,
+                record.sha256,
+                record.device,
+                &duration,
+                record.result,
+                record.error,
+            ];
+            let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let records: Vec<Record> = self.jobs.iter().map(Job::record).collect();
+        Ok(serde_json::to_string_pretty(&records)?)
+    }
+
+    /// Writes the history to `rpi-imager-history.<csv|json>` in the home
+    /// directory and returns where.
+    pub fn export(&self, csv: bool) -> Result<PathBuf> {
+        let home = std::env::var("HOME").context("HOME is not set")?;
+        let (name, text) = if csv {
+            ("rpi-imager-history.csv", self.to_csv())
+        } else {
+            ("rpi-imager-history.json", self.to_json()?)
+        };
+        let path = Path::new(&home).join(name);
+        std::fs::write(&path, text)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Quoted when it holds a separator, a quote or a line break (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Formats a Unix timestamp as YYYY-MM-DD HH:MM:SS (UTC).
+fn format_time(secs: u64) -> String {
+    let time = secs % 86_400;
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        crate::flash_db::format_date(secs),
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn keeps_finished_writes_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.json");
+        let os = OsListItem::custom(
+            "Raspberry Pi OS, Lite".to_string(),
+            "https://example.com/os.img.xz".to_string(),
+        );
+
+        let mut history = History::open(&path);
+        history.started(&os, "/dev/sdb", Some("0123"));
+        history.observe(&ProgressEvent::Finished);
+        // A customization retry isn't a write of its own
+        history.observe(&ProgressEvent::Finished);
+        history.started(&os, "/dev/sdc", None);
+        history.observe(&ProgressEvent::Error {
+            kind: ErrorKind::DeviceWrite,
+            message: "Input/output error".to_string(),
+        });
+
+        let history = History::open(&path);
+        assert_eq!(history.jobs.len(), 2);
+        assert_eq!(history.jobs[0].serial.as_deref(), Some("0123"));
+        assert_eq!(history.jobs[1].outcome, Outcome::Failed);
+
+        let csv = history.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains(",\"Raspberry Pi OS, Lite\",https://example.com/os.img.xz,"));
+        assert!(lines[2].ends_with(",/dev/sdc,0,failed,Input/output error"));
+
+        let json: serde_json::Value = serde_json::from_str(&history.to_json().unwrap()).unwrap();
+        assert_eq!(json[1]["result"], "failed");
+    }
+}
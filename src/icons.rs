@@ -0,0 +1,273 @@
+//! OS and device icons drawn with terminal graphics protocols (kitty, iTerm2,
+//! sixel). Lists reserve a blank slot in front of each entry; after ratatui
+//! has drawn a frame the images are written straight to the terminal on top
+//! of those slots. Terminals without graphics support just get the text.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use crossterm::{cursor::MoveTo, queue};
+use ratatui::layout::Rect;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Columns taken by an icon.
+pub const WIDTH: u16 = 2;
+/// Blank space left in front of list entries for the icon and a gap.
+pub const PADDING: &str = "   ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Iterm2,
+    Sixel,
+}
+
+impl Protocol {
+    /// Picks the protocol from `--graphics <kitty|iterm2|sixel|none>`, or
+    /// guesses it from the environment the terminal sets.
+    pub fn detect(forced: Option<&str>) -> Option<Self> {
+        if let Some(name) = forced {
+            return match name {
+                "kitty" => Some(Self::Kitty),
+                "iterm2" => Some(Self::Iterm2),
+                "sixel" => Some(Self::Sixel),
+                _ => None,
+            };
+        }
+
+        let var = |name| std::env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+        if term == "xterm-kitty" || !var("KITTY_WINDOW_ID").is_empty() || program == "ghostty" {
+            Some(Self::Kitty)
+        } else if program == "iTerm.app" || program == "WezTerm" {
+            Some(Self::Iterm2)
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || !var("KONSOLE_VERSION").is_empty()
+        {
+            Some(Self::Sixel)
+        } else {
+            None
+        }
+    }
+}
+
+fn icon_path(url: &str) -> Option<PathBuf> {
+    let hash = hex::encode(Sha256::digest(url.as_bytes()));
+    Some(crate::cache::cache_dir()?.join("icons").join(&hash[..16]))
+}
+
+/// Downloads an icon, or reads it from the icon cache.
+pub async fn fetch(client: &Client, url: &str) -> Result<Vec<u8>> {
+    let path = icon_path(url);
+    if let Some(path) = &path
+        && let Ok(bytes) = tokio::fs::read(path).await
+    {
+        return Ok(bytes);
+    }
+
+    let bytes = crate::http::get(client, url)
+        .await?
+        .bytes()
+        .await
+        .with_context(|| format!("Failed to download {}", url))?
+        .to_vec();
+    if let Some(path) = &path
+        && let Some(dir) = path.parent()
+        && tokio::fs::create_dir_all(dir).await.is_ok()
+    {
+        let _ = tokio::fs::write(path, &bytes).await;
+    }
+    Ok(bytes)
+}
+
+enum Entry {
+    Loading,
+    Failed,
+    /// The escape sequence that draws the icon at the cursor.
+    Ready(String),
+}
+
+pub struct Icons {
+    protocol: Option<Protocol>,
+    entries: HashMap<String, Entry>,
+    /// Where icons go in the frame being drawn.
+    slots: Vec<(Rect, String)>,
+    /// What is on the screen now.
+    drawn: Vec<(Rect, String)>,
+}
+
+impl Icons {
+    pub fn new(protocol: Option<Protocol>) -> Self {
+        Self {
+            protocol,
+            entries: HashMap::new(),
+            slots: Vec::new(),
+            drawn: Vec::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.protocol.is_some()
+    }
+
+    /// Called at the start of every frame.
+    pub fn clear_slots(&mut self) {
+        self.slots.clear();
+    }
+
+    /// Reserves `area` for the icon at `url` in this frame.
+    pub fn place(&mut self, area: Rect, url: &str) {
+        if self.enabled() && area.width >= WIDTH && area.height > 0 {
+            self.slots.push((area, url.to_string()));
+        }
+    }
+
+    /// Icons placed in this frame that haven't been requested yet. They
+    /// are marked as loading, so each is only fetched once.
+    pub fn take_missing(&mut self) -> Vec<String> {
+        let mut missing = Vec::new();
+        for (_, url) in &self.slots {
+            if !self.entries.contains_key(url) {
+                self.entries.insert(url.clone(), Entry::Loading);
+                missing.push(url.clone());
+            }
+        }
+        missing
+    }
+
+    pub fn loaded(&mut self, url: String, bytes: Result<Vec<u8>, String>) {
+        let entry = match (self.protocol, bytes) {
+            (Some(protocol), Ok(bytes)) => {
+                encode(protocol, &bytes).map_or(Entry::Failed, Entry::Ready)
+            }
+            _ => Entry::Failed,
+        };
+        self.entries.insert(url, entry);
+        // Redraw everything so the new icon shows up
+        self.drawn.clear();
+    }
+
+    /// Forgets what is on screen, after the terminal was cleared.
+    pub fn invalidate(&mut self) {
+        self.drawn.clear();
+    }
+
+    /// Writes the icons of the last frame, if they changed.
+    pub fn draw(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        let Some(protocol) = self.protocol else {
+            return Ok(());
+        };
+        if self.slots == self.drawn {
+            return Ok(());
+        }
+
+        // Kitty keeps images apart from the text, so drop the old placements.
+        // Other protocols paint the cells, so blank the slots that went away;
+        // ratatui believes they are blank already and won't repaint them.
+        if protocol == Protocol::Kitty {
+            write!(out, "\x1b_Ga=d,d=a,q=2\x1b\\")?;
+        } else {
+            for (area, _) in &self.drawn {
+                if !self.slots.iter().any(|(a, _)| a == area) {
+                    queue!(out, MoveTo(area.x, area.y))?;
+                    write!(out, "{:width$}", "", width = area.width as usize)?;
+                }
+            }
+        }
+
+        for (area, url) in &self.slots {
+            if let Some(Entry::Ready(sequence)) = self.entries.get(url) {
+                queue!(out, MoveTo(area.x, area.y))?;
+                out.write_all(sequence.as_bytes())?;
+            }
+        }
+        out.flush()?;
+        self.drawn = self.slots.clone();
+        Ok(())
+    }
+}
+
+fn encode(protocol: Protocol, bytes: &[u8]) -> Option<String> {
+    let image = image::load_from_memory(bytes).ok()?;
+    match protocol {
+        Protocol::Kitty => {
+            // Kitty only takes PNG, so re-encode whatever was downloaded
+            let mut png = Vec::new();
+            image
+                .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+                .ok()?;
+            let data = STANDARD.encode(png);
+            let chunks: Vec<&str> = data
+                .as_bytes()
+                .chunks(4096)
+                .map(|c| std::str::from_utf8(c).unwrap_or_default())
+                .collect();
+            let mut out = String::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = u8::from(i + 1 < chunks.len());
+                if i == 0 {
+                    out.push_str(&format!(
+                        "\x1b_Ga=T,f=100,c={},r=1,C=1,q=2,m={};{}\x1b\\",
+                        WIDTH, more, chunk
+                    ));
+                } else {
+                    out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+                }
+            }
+            Some(out)
+        }
+        Protocol::Iterm2 => Some(format!(
+            "\x1b]1337;File=inline=1;size={};width={};height=1;preserveAspectRatio=1:{}\x07",
+            bytes.len(),
+            WIDTH,
+            STANDARD.encode(bytes)
+        )),
+        Protocol::Sixel => {
+            let (cell_width, cell_height) = cell_size();
+            let image = image.resize(
+                cell_width * WIDTH as u32,
+                cell_height,
+                image::imageops::FilterType::Triangle,
+            );
+            // Sixel has no alpha channel, blend onto black
+            let rgba = image.to_rgba8();
+            let rgb: Vec<u8> = rgba
+                .pixels()
+                .flat_map(|p| {
+                    let [r, g, b, a] = p.0;
+                    [r, g, b].map(|c| (c as u16 * a as u16 / 255) as u8)
+                })
+                .collect();
+            icy_sixel::sixel_string(
+                &rgb,
+                rgba.width() as i32,
+                rgba.height() as i32,
+                icy_sixel::PixelFormat::RGB888,
+                icy_sixel::DiffusionMethod::Auto,
+                icy_sixel::MethodForLargest::Auto,
+                icy_sixel::MethodForRep::Auto,
+                icy_sixel::Quality::AUTO,
+            )
+            .ok()
+        }
+    }
+}
+
+/// Pixel size of a terminal cell, assuming 10x20 when the terminal
+/// doesn't report it.
+fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns) as u32,
+            (size.height / size.rows) as u32,
+        ),
+        _ => (10, 20),
+    }
+}
@@ -1,52 +1,97 @@
-mod customization;
-mod drivelist;
-mod os_list;
-mod post_process;
-mod static_data;
+mod about;
+mod bootstrap;
+mod cli;
+mod compat;
+mod config_archive;
+mod error_log;
+mod flash_db;
+mod headless;
+mod history;
+mod icons;
+mod metadata;
+mod pi_detect;
+mod power;
+mod preselect;
+mod presets;
+mod privileges;
+mod qr;
+mod queue;
+mod reachability;
+mod repositories;
+mod rpiboot;
+mod session;
+mod ssh_import;
+mod stats;
+mod telemetry;
+mod theme;
+mod troubleshoot;
+mod widgets;
 mod worker;
-mod writer;
+
+// The imaging engine lives in the library, shared with other tools
+#[cfg(unix)]
+use rpi_imager_core::udisks;
+use rpi_imager_core::{
+    boot_diff, cache, customization, drivelist, eeprom, error, first_boot, host_locale, http,
+    in_use, inspect, ipc, keyboards, os_list, post_process, static_data, target, workspace, writer,
+};
 
 use std::{error::Error, io};
 
 use base64::Engine;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+        MouseButton, MouseEventKind,
+    },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{
     Frame, Terminal,
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph},
 };
-use reqwest::Client;
-use tokio::io::AsyncBufReadExt;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 use tokio::process::Command;
 use tokio::sync::mpsc;
 
+use crate::boot_diff::DiffLine;
+use crate::cache::PrefetchProgress;
 use crate::customization::{
-    CustomizationOptions, CustomizationTab, CustomizationUiState, InputMode,
+    CustomizationOptions, CustomizationSection, CustomizationTab, History, StaticIp, Verification,
 };
-use crate::drivelist::Drive;
+use crate::drivelist::{Drive, TargetProfile};
+use crate::error::ErrorKind;
+use crate::first_boot::Check;
+use crate::flash_db::{FlashDatabase, FlashRecord};
+use crate::inspect::BootFile;
+use crate::ipc::{ProgressEvent, Summary, WorkerCommand, WritingPhase};
 use crate::os_list::{Device, OsList, OsListItem};
+use crate::privileges::DeviceAccess;
+use crate::qr::QrKind;
+use crate::queue::{JobStatus, WriteQueue};
+use crate::session::Session;
+use crate::widgets::{InputEvent, Picker, PickerEvent, TextInput};
 
 enum AppMessage {
     OsListLoaded(Result<OsList, String>),
-    WriteProgress(f64),
-    VerifyProgress(f64),
-    WriteStatus(String),
-    WriteFinished,
-    WriteError(String),
-    WritingPhase(WritingPhase),
-}
-
-#[derive(PartialEq, Clone, Copy, Debug)]
-pub enum WritingPhase {
-    Writing,
-    Verifying,
+    /// The OS list came from the cache because fetching it failed.
+    OsListDegraded(String),
+    Worker(ProgressEvent),
+    /// Progress of the queued job at this index.
+    QueueJob(usize, ProgressEvent),
+    HostReachable(Result<String, String>),
+    BootstrapFinished(Result<String, String>),
+    Prefetch(Result<PrefetchProgress, String>),
+    IconLoaded(String, Result<Vec<u8>, String>),
+    Metadata(crate::metadata::Fetched),
+    SshKeysImported(Result<Vec<String>, String>),
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -60,17 +105,65 @@ enum CurrentView {
     Writing,
     AbortConfirmation,
     Finished,
+    BootBrowser,
+    BootloaderConfig,
+    Queue,
+    Repositories,
+    Cache,
+    History,
+    Presets,
+    Setup,
+    Unlock,
 }
 
+/// The wizard steps listed in the sidebar. The number keys and clicks jump
+/// to the first four once they have been reached.
+const STEPS: [(&str, CurrentView); 6] = [
+    ("Device", CurrentView::DeviceSelection),
+    ("OS", CurrentView::OsSelection),
+    ("Storage", CurrentView::StorageSelection),
+    ("Customization", CurrentView::Customization),
+    ("Writing", CurrentView::Writing),
+    ("Done", CurrentView::Finished),
+];
+
 enum PopupType {
     Timezone,
     Keyboard,
     Locale,
     SshKey,
+    WifiCountry,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum InputMode {
+    Navigation,
+    Editing,
+}
+
+struct CustomizationUiState {
+    current_tab: CustomizationTab,
+    selected_field_index: usize,
+    input_mode: InputMode,
+    // Field being edited while in InputMode::Editing
+    input: TextInput,
+}
+
+impl Default for CustomizationUiState {
+    fn default() -> Self {
+        Self {
+            current_tab: CustomizationTab::General,
+            selected_field_index: 0,
+            input_mode: InputMode::Navigation,
+            input: TextInput::default(),
+        }
+    }
 }
 
 struct App {
     pub os_list: Option<OsList>,
+    /// Set when the OS list is a cached copy, saying why.
+    pub os_list_notice: Option<String>,
     pub is_loading: bool,
     pub should_quit: bool,
     pub error_message: Option<String>,
@@ -83,7 +176,12 @@ struct App {
     pub drive_list_state: ListState,
     pub selected_os: Option<OsListItem>,
     pub selected_drive: Option<Drive>,
+    // Fraction done of each phase, from 0.0 to 1.0
     pub write_progress: f64,
+    pub sync_progress: f64,
+    /// How much of the image is known to be on the card, see
+    /// [`ProgressEvent::Synced`].
+    pub synced_progress: f64,
     pub verify_progress: f64,
     pub write_status: String,
     pub write_phase: Option<WritingPhase>,
@@ -93,31 +191,176 @@ struct App {
 
     // Customization
     pub customization_options: CustomizationOptions,
+    /// The image settings as last saved as default, what the next run starts
+    /// with. Edits and presets apply to the writes of this run only.
+    pub saved_defaults: CustomizationOptions,
     pub customization_ui: CustomizationUiState,
     pub customization_menu_state: ListState,
     pub customization_sub_menu_state: ListState,
+    pub customization_history: History,
     pub in_customization_submenu: bool,
 
     // Device selection
     pub selected_device: Option<Device>,
+    // The OS list narrowed down to what the selected device supports
+    pub device_os_list: Option<Vec<OsListItem>>,
+    // Category entries and checksums fetched for the highlighted OS entry
+    pub metadata: crate::metadata::Metadata,
+    // A category chosen before its entries arrived, opened once they do
+    pub open_on_load: Option<String>,
     pub device_list_state: ListState,
     pub debug_mode: bool,
+    pub device_hint: Option<String>,
 
     // Popup
     pub popup: Option<PopupType>,
-    pub popup_list_state: ListState,
-    pub popup_items: Vec<String>,
-    pub popup_filter: String,
+    pub picker: Picker,
+
+    // Previously flashed cards
+    pub flash_db: FlashDatabase,
+    /// [`flash_db::fingerprint`] of the selected card, read when it was
+    /// selected, or as the worker reported it after writing.
+    pub card_fingerprint: Option<String>,
+
+    // Boot partition browser
+    pub boot_files: Vec<BootFile>,
+    pub boot_files_state: ListState,
+
+    // Fixed (non-removable) disks need a second confirmation
+    pub fixed_disk_confirmed: bool,
+
+    // Read-back verification chosen on the confirmation screen, when the
+    // setting is "ask"
+    pub verify_choice: Option<bool>,
+    // Copy of the decompressed image written alongside the card, toggled on
+    // the confirmation screen
+    pub tee_file: Option<String>,
+    // What the running write puts where, for the speed history; None for
+    // delta writes, which skip most of the card
+    pub timed_write: Option<(Drive, OsListItem)>,
+
+    // EEPROM bootloader editor
+    pub bootloader_menu_state: ListState,
+
+    // Compute Modules waiting in USB device-boot mode
+    pub usbboot_devices: Vec<String>,
+
+    // QR code shown on the Finished screen
+    pub qr_code: Option<QrKind>,
+    pub show_about: bool,
+
+    // Post-flash "wait for device" check
+    pub reachability: Option<String>,
+    pub reachability_task: Option<tokio::task::AbortHandle>,
+
+    // Block device permissions, checked on every drive refresh
+    pub device_access: Option<DeviceAccess>,
+
+    // Redraw cap, from --max-fps
+    pub max_fps: u32,
+
+    // Error detail view: scroll offset and the result of copy/save
+    pub error_scroll: u16,
+    pub error_notice: Option<String>,
+    // The last error was permission-related, so offer to re-run elevated
+    pub permission_error: bool,
+    // What the last write failed at, deciding whether it can be retried
+    pub error_kind: Option<ErrorKind>,
+    // Command to restart with after the TUI has shut down, and the session
+    // it continues
+    pub reexec: Option<(Vec<String>, Session)>,
+
+    // Background download of the selected image into the cache
+    pub prefetch: Option<String>,
+    /// Stops the running prefetch, and its task to wait for.
+    pub prefetch_task: Option<(
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<()>,
+    )>,
+    /// Whether the selected image is written from the cache, without network.
+    pub offline_flash: bool,
+
+    // Writes configured in the wizard, run one after another
+    pub queue: WriteQueue,
+    pub queue_state: ListState,
+    pub repositories_state: ListState,
+    // Repositories were switched on or off, so the OS list is fetched again
+    pub repositories_changed: bool,
+    // Counters for a station dashboard, when a stats file is configured
+    pub station: Option<crate::stats::Station>,
+    // Downloads listed on the Cache screen
+    pub cached_images: Vec<crate::cache::CachedImage>,
+    pub cache_state: ListState,
+    // Finished writes, listed newest first on the History screen
+    pub history: crate::history::History,
+    pub history_state: ListState,
+    // Where the history was exported to, or why it couldn't be
+    pub history_notice: Option<String>,
+    // Queue index of the job in `worker_args`, if it is one
+    pub worker_job: Option<usize>,
+    // Where the sidebar was last drawn, for clicks on its steps
+    pub sidebar_area: Rect,
+
+    // Commands for the running write's worker (see WorkerCommand)
+    pub worker_commands: Option<mpsc::UnboundedSender<WorkerCommand>>,
+    pub write_paused: bool,
+    // Host serving the running download, and whether it has been slow
+    pub download_host: Option<String>,
+    pub slow_download: bool,
+    // Index into download_mirrors plus one; 0 downloads from the listed URL
+    pub mirror: usize,
+
+    // Set when the image was written but customizing it failed
+    pub customization_error: Option<String>,
+    // Boot partition checklist of the last customization
+    pub boot_checks: Vec<Check>,
+    // Edits the customization made to cmdline.txt and config.txt
+    pub boot_changes: Vec<crate::boot_diff::FileDiff>,
+    // The user skipped reading the card back
+    pub verification_skipped: bool,
+    // The card was ejected after the write
+    pub ejected: bool,
+
+    // First-run setup of the tool itself
+    pub setup_state: ListState,
+
+    // Passphrase prompt for encrypted passwords: where to go afterwards,
+    // why the last attempt failed, and the preset it is for (None for the
+    // config)
+    pub unlock_return: CurrentView,
+    pub unlock_error: Option<String>,
+    pub unlock_preset: Option<CustomizationOptions>,
+
+    // Presets screen: the saved names, where Esc goes, and the preset being
+    // renamed while a name is typed (None for a new one)
+    pub preset_names: Vec<String>,
+    pub presets_state: ListState,
+    pub presets_return: CurrentView,
+    pub preset_renaming: Option<String>,
+
+    // Importing SSH keys from GitHub or GitLab: the id to fetch for, when
+    // the fetch started (for the spinner), and how the last one went
+    pub ssh_import_id: Option<String>,
+    pub ssh_import_started: Option<std::time::Instant>,
+    pub ssh_import_status: Option<String>,
+
+    // OS and device icons, when the terminal can show images
+    pub icons: crate::icons::Icons,
+
+    // Selections from the command line, made once the OS list is loaded
+    pub preselect: crate::preselect::Preselect,
 }
 
 impl App {
     fn new() -> App {
-        let debug_mode = std::env::args().any(|arg| arg == "--debug");
+        let debug_mode = crate::cli::debug();
+        let customization_options = CustomizationOptions::load();
         App {
             os_list: None,
+            os_list_notice: None,
             is_loading: true,
             should_quit: false,
-            error_message: None,
+            error_message: crate::customization::config_error(),
             list_state: ListState::default(),
             navigation_stack: Vec::new(),
             breadcrumbs: Vec::new(),
@@ -128,35 +371,272 @@ impl App {
             selected_os: None,
             selected_drive: None,
             write_progress: 0.0,
+            sync_progress: 0.0,
+            synced_progress: 0.0,
             verify_progress: 0.0,
             write_status: String::new(),
             write_phase: None,
             write_task: None,
             abort_handle: None,
             worker_args: None,
-            customization_options: CustomizationOptions::load(),
+            saved_defaults: customization_options.clone(),
+            customization_options,
             customization_ui: CustomizationUiState::default(),
             customization_menu_state: ListState::default(),
             customization_sub_menu_state: ListState::default(),
+            customization_history: History::default(),
             in_customization_submenu: false,
             selected_device: None,
+            device_os_list: None,
+            metadata: crate::metadata::Metadata::default(),
+            open_on_load: None,
             device_list_state: ListState::default(),
             debug_mode,
+            device_hint: None,
             popup: None,
-            popup_list_state: ListState::default(),
-            popup_items: Vec::new(),
-            popup_filter: String::new(),
+            show_about: false,
+            picker: Picker::default(),
+            flash_db: FlashDatabase::load(),
+            card_fingerprint: None,
+            boot_files: Vec::new(),
+            boot_files_state: ListState::default(),
+            usbboot_devices: Vec::new(),
+            bootloader_menu_state: ListState::default(),
+            fixed_disk_confirmed: false,
+            verify_choice: None,
+            tee_file: None,
+            timed_write: None,
+            qr_code: None,
+            reachability: None,
+            reachability_task: None,
+            device_access: None,
+            max_fps: 10,
+            error_scroll: 0,
+            error_notice: None,
+            permission_error: false,
+            error_kind: None,
+            reexec: None,
+            prefetch: None,
+            prefetch_task: None,
+            offline_flash: false,
+            queue: WriteQueue::default(),
+            queue_state: ListState::default(),
+            repositories_state: ListState::default(),
+            station: None,
+            cached_images: Vec::new(),
+            cache_state: ListState::default(),
+            history: crate::history::History::load(),
+            history_state: ListState::default(),
+            history_notice: None,
+            repositories_changed: false,
+            worker_job: None,
+            sidebar_area: Rect::default(),
+            worker_commands: None,
+            write_paused: false,
+            download_host: None,
+            slow_download: false,
+            mirror: 0,
+            customization_error: None,
+            boot_checks: Vec::new(),
+            boot_changes: Vec::new(),
+            verification_skipped: false,
+            ejected: false,
+            setup_state: ListState::default(),
+            unlock_return: CurrentView::DeviceSelection,
+            preset_names: Vec::new(),
+            presets_state: ListState::default(),
+            presets_return: CurrentView::StorageSelection,
+            preset_renaming: None,
+            ssh_import_id: None,
+            ssh_import_started: None,
+            ssh_import_status: None,
+            unlock_error: None,
+            unlock_preset: None,
+            icons: crate::icons::Icons::new(None),
+            preselect: Default::default(),
+        }
+    }
+
+    /// The last write through `drive`, by its serial. For a USB card reader
+    /// that is the reader's serial, the same for every card put in, so
+    /// only the fingerprint tells whether the card is still that one.
+    fn drive_record(&self, drive: &Drive) -> Option<&FlashRecord> {
+        drive
+            .serial
+            .as_deref()
+            .and_then(|serial| self.flash_db.get(serial))
+    }
+
+    /// The selected card already holds exactly the selected image, checked
+    /// against the card itself.
+    fn can_skip_write(&self) -> bool {
+        match (&self.selected_drive, &self.selected_os) {
+            (Some(drive), Some(os)) => self.drive_record(drive).is_some_and(|r| {
+                r.matches(
+                    os.extract_sha256.as_deref(),
+                    self.card_fingerprint.as_deref(),
+                )
+            }),
+            _ => false,
+        }
+    }
+
+    /// The selected card may hold an earlier build of the same image, so most
+    /// chunks are likely unchanged and only the differences need writing.
+    /// Delta writes compare against the card itself, so a different card in
+    /// the same reader only makes it slower.
+    fn can_delta_write(&self) -> bool {
+        match (&self.selected_drive, &self.selected_os) {
+            (Some(drive), Some(os)) => self
+                .drive_record(drive)
+                .is_some_and(|r| r.image_name == os.name),
+            _ => false,
+        }
+    }
+
+    fn skip_writing(&mut self) {
+        // The card may have been swapped since it was selected
+        self.card_fingerprint = self
+            .selected_drive
+            .as_ref()
+            .and_then(|d| crate::flash_db::fingerprint(&d.name));
+        if !self.can_skip_write() {
+            self.error_message =
+                Some("The card changed since it was selected; write it instead".to_string());
+            return;
+        }
+        self.write_status = "Skipped".to_string();
+        self.current_view = CurrentView::Finished;
+    }
+
+    /// Reports the written image to the official stats endpoint if the user
+    /// opted in. Local images are never reported.
+    fn send_telemetry(&self) {
+        if let Some(os) = &self.selected_os {
+            let category = self.breadcrumbs.last().cloned().unwrap_or_default();
+            self.send_telemetry_for(os, category);
+        }
+    }
+
+    fn send_telemetry_for(&self, os: &OsListItem, category: String) {
+        let Some(url) = os.url.clone().filter(|u| u.starts_with("http")) else {
+            return;
+        };
+        if !self.customization_options.telemetry {
+            return;
+        }
+        let Ok(client) = crate::http::client(&self.customization_options) else {
+            return;
+        };
+        let name = os.name.clone();
+        tokio::spawn(async move {
+            let _ = crate::telemetry::send(&client, &url, &category, &name).await;
+        });
+    }
+
+    fn record_flash(&mut self) {
+        if let (Some(drive), Some(os)) = (&self.selected_drive, &self.selected_os)
+            && let Some(serial) = &drive.serial
+        {
+            self.flash_db
+                .record(serial, &os.name, os.extract_sha256.as_deref());
+        }
+    }
+
+    /// Whether the selected image can honour the customization menu entry.
+    fn menu_section_supported(&self, menu_idx: usize) -> bool {
+        match (
+            CustomizationSection::from_menu_index(menu_idx),
+            &self.selected_os,
+        ) {
+            (Some(section), Some(os)) => os.supports(section),
+            _ => true,
+        }
+    }
+
+    /// Whether the customization menu entry differs from its defaults.
+    fn menu_section_modified(&self, menu_idx: usize) -> bool {
+        match CustomizationSection::from_menu_index(menu_idx) {
+            Some(section) => self.customization_options.section_modified(section),
+            None if menu_idx == 6 => self.customization_options.tool_options_modified(),
+            None => false,
+        }
+    }
+
+    /// Saves the tool options as they are, and the image settings as they
+    /// were last saved as default, so a preset or an edit for one write
+    /// doesn't replace the defaults.
+    fn save_options(&self) {
+        let mut saved = self.customization_options.clone();
+        for section in CustomizationSection::ALL {
+            saved.copy_section(&self.saved_defaults, section);
+        }
+        saved.save();
+    }
+
+    /// The current image settings become the defaults.
+    fn save_as_default(&mut self) {
+        if let Some(error) = crate::customization::config_error() {
+            self.error_message = Some(error);
+            return;
+        }
+        self.saved_defaults = self.customization_options.clone();
+        self.save_options();
+    }
+
+    /// Whether the image settings differ from the saved defaults.
+    fn differs_from_defaults(&self) -> bool {
+        CustomizationSection::ALL.into_iter().any(|section| {
+            let mut saved = self.customization_options.clone();
+            saved.copy_section(&self.saved_defaults, section);
+            saved != self.customization_options
+        })
+    }
+
+    /// Ctrl+Z and Ctrl+Y: steps back or forward through the edits.
+    fn undo_customization(&mut self, redo: bool) {
+        let history = &mut self.customization_history;
+        let changed = if redo {
+            history.redo(&mut self.customization_options)
+        } else {
+            history.undo(&mut self.customization_options)
+        };
+        if changed {
+            self.save_options();
         }
     }
 
+    fn reset_menu_section(&mut self, menu_idx: usize) {
+        match CustomizationSection::from_menu_index(menu_idx) {
+            Some(section) => self.customization_options.reset_section(section),
+            None if menu_idx == 6 => self.customization_options.reset_tool_options(),
+            None => return,
+        }
+        self.save_options();
+    }
+
+    /// The interface and field of a Network sub-item: those of eth0 first,
+    /// then those of wlan0.
+    fn network_field(&self, sub_idx: usize) -> (&StaticIp, usize) {
+        let fields = StaticIp::FIELDS.len();
+        let ip = if sub_idx < fields {
+            &self.customization_options.eth0
+        } else {
+            &self.customization_options.wlan0
+        };
+        (ip, sub_idx % fields)
+    }
+
     fn customization_sub_item_count(&self) -> usize {
         match self.customization_menu_state.selected().unwrap_or(0) {
-            0 => 1, // Hostname
-            1 => 3, // Localization (Timezone, Keyboard, Locale)
-            2 => 2, // User
-            3 => 3, // Wi-Fi
-            4 => 3, // Remote Access
-            5 => 1, // Reset Settings
+            0 => 1,  // Hostname
+            1 => 3,  // Localization (Timezone, Keyboard, Locale)
+            2 => 2,  // User
+            3 => 4,  // Wi-Fi
+            4 => 4,  // Remote Access
+            5 => 10, // Network (the same five fields for eth0 and wlan0)
+            6 => 7,  // Options
+            7 => 1,  // Reset Settings
             _ => 0,
         }
     }
@@ -165,218 +645,319 @@ impl App {
         let menu_idx = self.customization_menu_state.selected().unwrap_or(0);
         let sub_idx = self.customization_sub_menu_state.selected().unwrap_or(0);
 
-        match menu_idx {
-            0 => match sub_idx {
-                // Hostname
-                0 => self.start_editing(self.customization_options.hostname.clone()),
-                _ => {}
-            },
-            1 => match sub_idx {
-                // Localization
-                0 => self.open_popup(PopupType::Timezone),
-                1 => self.open_popup(PopupType::Keyboard),
-                2 => self.open_popup(PopupType::Locale),
-                _ => {}
-            },
-            2 => match sub_idx {
-                // User
-                0 => self.start_editing(self.customization_options.user_name.clone()),
-                1 => self.start_editing(
+        match (menu_idx, sub_idx) {
+            // Hostname
+            (0, 0) => self.start_editing(
+                TextInput::new(&self.customization_options.hostname)
+                    .validator(crate::customization::validate_hostname),
+            ),
+            // Localization
+            (1, 0) => self.open_popup(PopupType::Timezone),
+            (1, 1) => self.open_popup(PopupType::Keyboard),
+            (1, 2) => self.open_popup(PopupType::Locale),
+            // User
+            (2, 0) => self.start_editing(
+                TextInput::new(&self.customization_options.user_name)
+                    .validator(crate::customization::validate_username),
+            ),
+            (2, 1) => self.start_editing(
+                TextInput::new(
                     self.customization_options
                         .password
                         .clone()
                         .unwrap_or_default(),
-                ),
-                _ => {}
-            },
-            3 => match sub_idx {
-                // Wi-Fi
-                0 => self.start_editing(self.customization_options.wifi_ssid.clone()),
-                1 => self.start_editing(self.customization_options.wifi_password.clone()),
-                2 => {
-                    self.customization_options.wifi_hidden = !self.customization_options.wifi_hidden
-                }
-                _ => {}
-            },
-            4 => match sub_idx {
-                // Remote Access
-                0 => {
-                    self.customization_options.ssh_enabled = !self.customization_options.ssh_enabled
-                }
-                1 => {
-                    self.customization_options.ssh_password_auth =
-                        !self.customization_options.ssh_password_auth
+                )
+                .masked()
+                .placeholder("new password"),
+            ),
+            // Wi-Fi
+            (3, 0) => self.start_editing(
+                TextInput::new(&self.customization_options.wifi_ssid).placeholder("network name"),
+            ),
+            (3, 1) => self
+                .start_editing(TextInput::new(&self.customization_options.wifi_password).masked()),
+            (3, 2) => {
+                self.customization_options.wifi_hidden = !self.customization_options.wifi_hidden
+            }
+            (3, 3) => self.open_popup(PopupType::WifiCountry),
+            // Remote Access
+            (4, 0) => {
+                self.customization_options.ssh_enabled = !self.customization_options.ssh_enabled
+            }
+            (4, 1) => {
+                self.customization_options.ssh_password_auth =
+                    !self.customization_options.ssh_password_auth
+            }
+            (4, 2) => self.open_popup(PopupType::SshKey),
+            (4, 3) if self.ssh_import_started.is_none() => self.start_editing(
+                TextInput::new("")
+                    .placeholder("gh:username or gl:username")
+                    .validator(crate::ssh_import::validate_id),
+            ),
+            // Network
+            (5, i) => {
+                let (ip, field) = self.network_field(i);
+                self.start_editing(
+                    TextInput::new(ip.field(field)).validator(StaticIp::FIELDS[field].1),
+                )
+            }
+            // Options
+            (6, 0) => self.customization_options.telemetry = !self.customization_options.telemetry,
+            (6, 1) => {
+                self.customization_options.remember_secrets =
+                    !self.customization_options.remember_secrets
+            }
+            (6, 2) => {
+                self.customization_options.verification =
+                    self.customization_options.verification.next()
+            }
+            (6, 3) => {
+                if self.customization_options.encrypts_secrets() {
+                    self.customization_options.passphrase = None;
+                    self.customization_options.sealed_secrets = None;
+                } else {
+                    self.start_editing(TextInput::new("").masked().placeholder("passphrase"));
+                    return;
                 }
-                2 => self.open_popup(PopupType::SshKey),
-                _ => {}
-            },
-            5 => {
-                // Reset Settings
+            }
+            (6, 4) => {
+                self.customization_options.wipe_device = !self.customization_options.wipe_device
+            }
+            (6, 5) => {
+                self.customization_options.eject_finished =
+                    !self.customization_options.eject_finished
+            }
+            (6, 6) => {
+                self.customization_options.require_ac_power =
+                    !self.customization_options.require_ac_power
+            }
+            // Reset Settings
+            (7, _) => {
                 self.customization_options = CustomizationOptions::default();
+                self.saved_defaults = CustomizationOptions::default();
             }
             _ => {}
         }
-        self.customization_options.save();
+        self.save_options();
     }
 
-    fn start_editing(&mut self, current_value: String) {
-        self.customization_ui.input_buffer = current_value;
-        self.customization_ui.input_mode = InputMode::Editing;
+    /// Shows the setup wizard, used when there is no config file yet.
+    fn start_setup(&mut self) {
+        self.current_view = CurrentView::Setup;
+        self.setup_state.select(Some(0));
     }
 
-    fn open_popup(&mut self, popup_type: PopupType) {
-        self.popup = Some(popup_type);
-        self.popup_filter.clear();
-        self.popup_list_state.select(Some(0));
-        self.update_popup_items();
+    fn handle_setup_enter(&mut self) {
+        let options = &mut self.customization_options;
+        match self.setup_state.selected().unwrap_or(0) {
+            0 => {
+                let current = options
+                    .cache_dir
+                    .clone()
+                    .or_else(|| {
+                        crate::cache::default_cache_dir().map(|d| d.to_string_lossy().to_string())
+                    })
+                    .unwrap_or_default();
+                self.start_editing(TextInput::new(current).placeholder("~/.cache/rpi-imager-tui"));
+            }
+            1 => {
+                options.theme = options.theme.next();
+                crate::theme::set(options.theme);
+            }
+            2 => self.open_popup(PopupType::SshKey),
+            3 => options.telemetry = !options.telemetry,
+            _ => self.finish_setup(),
+        }
     }
 
-    fn update_popup_items(&mut self) {
-        if let Some(popup_type) = &self.popup {
-            let filter = self.popup_filter.to_lowercase();
-            match popup_type {
-                PopupType::Timezone => {
-                    self.popup_items = crate::static_data::get_timezones()
-                        .into_iter()
-                        .filter(|tz| tz.to_lowercase().contains(&filter))
-                        .map(|s| s.to_string())
-                        .collect();
-                }
-                PopupType::Keyboard => {
-                    self.popup_items = crate::static_data::get_keyboards()
-                        .into_iter()
-                        .filter(|(code, name)| {
-                            code.to_lowercase().contains(&filter)
-                                || name.to_lowercase().contains(&filter)
-                        })
-                        .map(|(code, name)| format!("{} - {}", code, name))
-                        .collect();
-                }
-                PopupType::Locale => {
-                    self.popup_items = crate::static_data::get_locales()
-                        .into_iter()
-                        .filter(|l| l.to_lowercase().contains(&filter))
-                        .map(|s| s.to_string())
-                        .collect();
-                }
-                PopupType::SshKey => {
-                    let keys = crate::customization::discover_ssh_keys();
-                    // Just show the whole key? They are long. Show comment if possible?
-                    // ssh keys format: "ssh-rsa AAAA... comment"
-                    // We can filter by the whole line.
-                    self.popup_items = keys
-                        .into_iter()
-                        .filter(|k| k.to_lowercase().contains(&filter))
-                        .collect();
-                    self.popup_items.insert(0, "<Enter Manually>".to_string());
-                }
-            }
-            if self.popup_items.is_empty() {
-                self.popup_list_state.select(None);
-            } else {
-                self.popup_list_state.select(Some(0));
+    fn apply_setup_edit(&mut self, value: String) {
+        match self.setup_state.selected().unwrap_or(0) {
+            0 => {
+                let value = value.trim();
+                let default = crate::cache::default_cache_dir();
+                // Keep following XDG_CACHE_HOME unless a different place was typed
+                self.customization_options.cache_dir = (!value.is_empty()
+                    && default.as_deref() != Some(std::path::Path::new(value)))
+                .then(|| value.to_string());
+                crate::cache::set_cache_dir(
+                    self.customization_options
+                        .cache_dir
+                        .as_ref()
+                        .map(std::path::PathBuf::from),
+                );
             }
+            2 => self.customization_options.ssh_public_keys = value,
+            _ => {}
         }
     }
 
-    fn popup_next(&mut self) {
-        if self.popup_items.is_empty() {
-            return;
+    /// Saves the setup choices, which also keeps the wizard from showing up
+    /// again, and continues to the normal flow.
+    fn finish_setup(&mut self) {
+        if !self.customization_options.ssh_public_keys.is_empty() {
+            self.customization_options.ssh_enabled = true;
         }
-        let i = match self.popup_list_state.selected() {
-            Some(i) => {
-                if i >= self.popup_items.len().saturating_sub(1) {
-                    0
-                } else {
-                    i + 1
-                }
-            }
-            None => 0,
-        };
-        self.popup_list_state.select(Some(i));
+        self.save_as_default();
+        self.current_view = CurrentView::DeviceSelection;
+    }
+
+    /// Asks for the passphrase of the encrypted passwords before continuing
+    /// to the current view.
+    fn start_unlock(&mut self) {
+        self.unlock_return = self.current_view;
+        self.current_view = CurrentView::Unlock;
+        self.start_editing(TextInput::new("").masked().placeholder("passphrase"));
     }
 
-    fn popup_previous(&mut self) {
-        if self.popup_items.is_empty() {
+    fn unlock(&mut self, passphrase: String) {
+        if let Some(preset) = self.unlock_preset.take() {
+            self.take_preset(preset, Some(&passphrase));
             return;
         }
-        let i = match self.popup_list_state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.popup_items.len().saturating_sub(1)
-                } else {
-                    i - 1
-                }
+        match self.customization_options.unlock(passphrase.clone()) {
+            Ok(()) => {
+                let _ = self.saved_defaults.unlock(passphrase);
+                self.unlock_error = None;
+                self.current_view = self.unlock_return;
             }
-            None => 0,
+            Err(e) => {
+                self.unlock_error = Some(format!("{:#}", e));
+                self.start_editing(TextInput::new("").masked().placeholder("passphrase"));
+            }
+        }
+    }
+
+    fn start_editing(&mut self, input: TextInput) {
+        self.customization_ui.input = input;
+        self.customization_ui.input_mode = InputMode::Editing;
+    }
+
+    fn open_popup(&mut self, popup_type: PopupType) {
+        self.picker = match popup_type {
+            PopupType::Timezone => Picker::new(
+                "Select Timezone",
+                crate::static_data::get_timezones()
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+            )
+            .current(&self.customization_options.timezone),
+            PopupType::Keyboard => Picker::new(
+                "Select Keyboard Layout",
+                crate::keyboards::available()
+                    .iter()
+                    .map(|k| format!("{} - {}", k.code(), k.name))
+                    .collect(),
+            )
+            .current(&crate::keyboards::join_code(
+                &self.customization_options.keyboard_layout,
+                &self.customization_options.keyboard_variant,
+            )),
+            PopupType::Locale => Picker::new(
+                "Select Locale",
+                crate::static_data::get_locales()
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+            )
+            .current(&self.customization_options.locale),
+            // ssh keys format: "ssh-rsa AAAA... comment"; filtering matches the whole line
+            PopupType::SshKey => {
+                Picker::new("Select SSH Key", crate::customization::discover_ssh_keys())
+                    .pinned("<Enter Manually>")
+            }
+            PopupType::WifiCountry => Picker::new(
+                "Select Wi-Fi Country",
+                crate::static_data::get_countries()
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+            )
+            .pinned("<Enter Manually>")
+            .current(&self.customization_options.wifi_country),
         };
-        self.popup_list_state.select(Some(i));
+        self.popup = Some(popup_type);
     }
 
-    fn popup_select(&mut self) {
-        if let (Some(i), Some(popup_type)) = (self.popup_list_state.selected(), &self.popup) {
-            if let Some(selection) = self.popup_items.get(i) {
-                match popup_type {
-                    PopupType::Timezone => {
-                        self.customization_options.timezone = selection.clone();
-                    }
-                    PopupType::Keyboard => {
-                        // Format: "gb - United Kingdom"
-                        if let Some(code) = selection.split(" - ").next() {
-                            self.customization_options.keyboard_layout = code.to_string();
-                        }
+    fn popup_select(&mut self, selection: String) {
+        if let Some(popup_type) = &self.popup {
+            match popup_type {
+                PopupType::Timezone => {
+                    self.customization_options.timezone = selection;
+                }
+                PopupType::Keyboard => {
+                    // Format: "gb - English (UK)" or "us(dvorak) - English (Dvorak)"
+                    if let Some(code) = selection.split(" - ").next() {
+                        let (layout, variant) = crate::keyboards::split_code(code);
+                        self.customization_options.keyboard_layout = layout;
+                        self.customization_options.keyboard_variant = variant;
                     }
-                    PopupType::Locale => {
-                        self.customization_options.locale = selection.clone();
+                }
+                PopupType::Locale => {
+                    self.customization_options.locale = selection;
+                }
+                PopupType::SshKey => {
+                    if selection == "<Enter Manually>" {
+                        self.popup = None;
+                        self.start_editing(
+                            TextInput::new(&self.customization_options.ssh_public_keys)
+                                .placeholder("ssh-ed25519 AAAA...")
+                                .validator(crate::customization::validate_ssh_keys),
+                        );
+                        return;
                     }
-                    PopupType::SshKey => {
-                        if selection == "<Enter Manually>" {
-                            self.popup = None;
-                            self.start_editing(self.customization_options.ssh_public_keys.clone());
-                            return;
-                        }
-                        self.customization_options.ssh_public_keys = selection.clone();
+                    self.customization_options.ssh_public_keys = selection;
+                }
+                PopupType::WifiCountry => {
+                    if selection == "<Enter Manually>" {
+                        self.popup = None;
+                        self.start_editing(
+                            TextInput::new(&self.customization_options.wifi_country)
+                                .validator(crate::customization::validate_wifi_country),
+                        );
+                        return;
                     }
+                    self.customization_options.wifi_country = selection;
                 }
-                self.customization_options.save();
             }
+            self.save_options();
         }
         self.popup = None;
     }
 
-    fn apply_customization_edit(&mut self) {
+    fn apply_customization_edit(&mut self, value: String) {
         let menu_idx = self.customization_menu_state.selected().unwrap_or(0);
         let sub_idx = self.customization_sub_menu_state.selected().unwrap_or(0);
-        let value = self.customization_ui.input_buffer.clone();
 
-        match menu_idx {
-            0 => match sub_idx {
-                0 => self.customization_options.hostname = value,
-                _ => {}
-            },
-            1 => match sub_idx {
-                0 => self.customization_options.timezone = value,
-                1 => self.customization_options.keyboard_layout = value,
-                2 => self.customization_options.locale = value,
-                _ => {}
-            },
-            2 => match sub_idx {
-                0 => self.customization_options.user_name = value,
-                1 => self.customization_options.password = Some(value),
-                _ => {}
-            },
-            3 => match sub_idx {
-                0 => self.customization_options.wifi_ssid = value,
-                1 => self.customization_options.wifi_password = value,
-                _ => {}
-            },
-            4 => match sub_idx {
-                2 => self.customization_options.ssh_public_keys = value,
-                _ => {}
-            },
+        match (menu_idx, sub_idx) {
+            (0, 0) => self.customization_options.hostname = value,
+            (1, 0) => self.customization_options.timezone = value,
+            (1, 1) => {
+                let (layout, variant) = crate::keyboards::split_code(&value);
+                self.customization_options.keyboard_layout = layout;
+                self.customization_options.keyboard_variant = variant;
+            }
+            (1, 2) => self.customization_options.locale = value,
+            (2, 0) => self.customization_options.user_name = value,
+            (2, 1) => self.customization_options.password = Some(value),
+            (3, 0) => self.customization_options.wifi_ssid = value,
+            (3, 1) => self.customization_options.wifi_password = value,
+            (3, 3) => self.customization_options.wifi_country = value.trim().to_uppercase(),
+            (4, 2) => self.customization_options.ssh_public_keys = value,
+            (4, 3) => self.ssh_import_id = Some(value),
+            (5, i) => {
+                let field = i % StaticIp::FIELDS.len();
+                let ip = if i < StaticIp::FIELDS.len() {
+                    &mut self.customization_options.eth0
+                } else {
+                    &mut self.customization_options.wlan0
+                };
+                *ip.field_mut(field) = value.trim().to_string();
+            }
+            (6, 3) if !value.is_empty() => self.customization_options.passphrase = Some(value),
             _ => {}
         }
-        self.customization_options.save();
+        self.save_options();
     }
 
     fn get_devices(&self) -> &[Device] {
@@ -388,6 +969,7 @@ impl App {
     }
 
     fn next_device(&mut self) {
+        self.device_hint = None;
         let i = match self.device_list_state.selected() {
             Some(i) => {
                 if i >= self.get_devices().len().saturating_sub(1) {
@@ -402,6 +984,7 @@ impl App {
     }
 
     fn previous_device(&mut self) {
+        self.device_hint = None;
         let i = match self.device_list_state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -415,23 +998,94 @@ impl App {
         self.device_list_state.select(Some(i));
     }
 
-    fn select_device(&mut self) {
-        if let Some(i) = self.device_list_state.selected() {
-            if let Some(device) = self.get_devices().get(i) {
-                self.selected_device = Some(device.clone());
-                self.current_view = CurrentView::OsSelection;
-                self.list_state.select(Some(0));
-                // Reset OS navigation
-                self.navigation_stack.clear();
-                self.breadcrumbs.clear();
-                self.selection_stack.clear();
+    /// Looks for a Pi attached in USB gadget mode and preselects its entry.
+    fn autodetect_device(&mut self) {
+        let Some(detected) = crate::pi_detect::detect_attached_pi() else {
+            self.device_hint = Some(
+                "No Raspberry Pi found in USB gadget/serial mode. Select your device manually."
+                    .to_string(),
+            );
+            return;
+        };
+        match crate::pi_detect::suggest_device(self.get_devices(), &detected) {
+            Some(i) => {
+                self.device_list_state.select(Some(i));
+                self.device_hint = Some(format!(
+                    "Detected {} ({}). Press Enter to use the highlighted device.",
+                    detected.model, detected.product
+                ));
+            }
+            None => {
+                self.device_hint = Some(format!(
+                    "Detected {} ({}), but it is not in the device list.",
+                    detected.model, detected.product
+                ));
             }
         }
     }
 
+    fn select_device(&mut self) {
+        if let Some(i) = self.device_list_state.selected()
+            && let Some(device) = self.get_devices().get(i)
+        {
+            self.selected_device = Some(device.clone());
+            self.filter_os_list();
+            self.current_view = CurrentView::OsSelection;
+            self.list_state.select(Some(0));
+            // Reset OS navigation
+            self.navigation_stack.clear();
+            self.breadcrumbs.clear();
+            self.selection_stack.clear();
+        }
+    }
+
+    /// Starts fetching what the highlighted OS entry lacks, and cancels the
+    /// fetch for the entry highlighted before.
+    fn fetch_metadata(&mut self, tx: &mpsc::Sender<AppMessage>) {
+        let wanted = match self.current_view {
+            CurrentView::OsSelection => self
+                .list_state
+                .selected()
+                .and_then(|i| self.current_items().get(i))
+                .and_then(crate::metadata::Request::for_item),
+            _ => None,
+        };
+        // The category waiting to be opened is no longer highlighted
+        if !matches!(&wanted, Some(crate::metadata::Request::Children(url))
+            if self.open_on_load.as_ref() == Some(url))
+        {
+            self.open_on_load = None;
+        }
+        let Some(request) = self.metadata.highlight(wanted) else {
+            return;
+        };
+        let Ok(client) = crate::http::client(&self.customization_options) else {
+            return;
+        };
+        let tx = tx.clone();
+        let handle = tokio::spawn({
+            let request = request.clone();
+            async move {
+                let fetched = request.fetch(&client).await;
+                let _ = tx.send(AppMessage::Metadata(fetched)).await;
+            }
+        });
+        self.metadata.started(request, handle.abort_handle());
+    }
+
+    /// Hides the images the selected device can't run.
+    fn filter_os_list(&mut self) {
+        self.device_os_list = match (&self.selected_device, &self.os_list) {
+            (Some(device), Some(os_list)) => Some(device.filter(&os_list.os_list)),
+            _ => None,
+        };
+    }
+
     fn current_items(&self) -> &[OsListItem] {
         if let Some(items) = self.navigation_stack.last() {
             items
+        } else if let Some(items) = &self.device_os_list {
+            items
         } else if let Some(os_list) = &self.os_list {
             &os_list.os_list
         } else {
@@ -467,29 +1121,73 @@ impl App {
         self.list_state.select(Some(i));
     }
 
-    fn select(&mut self) {
+    fn select(&mut self, tx: mpsc::Sender<AppMessage>) {
         if let Some(i) = self.list_state.selected() {
             let item = self.current_items().get(i).cloned();
-            if let Some(item) = item {
-                if !item.subitems.is_empty() {
+            if let Some(mut item) = item {
+                if let Some(url) = item.subitems_url.clone()
+                    && item.subitems.is_empty()
+                {
+                    match self.metadata.children(&url) {
+                        Some(Ok(children)) => {
+                            let children = match &self.selected_device {
+                                Some(device) => device.filter(children),
+                                None => children.clone(),
+                            };
+                            self.selection_stack.push(i);
+                            self.navigation_stack.push(children);
+                            self.breadcrumbs.push(item.name);
+                            self.list_state.select(Some(0));
+                        }
+                        Some(Err(e)) => {
+                            self.error_message =
+                                Some(format!("Failed to load {}: {}", item.name, e))
+                        }
+                        None => self.open_on_load = Some(url),
+                    }
+                } else if !item.subitems.is_empty() {
                     self.selection_stack.push(i);
                     self.navigation_stack.push(item.subitems);
                     self.breadcrumbs.push(item.name);
                     self.list_state.select(Some(0));
+                } else if item.is_custom_entry() {
+                    self.start_editing(
+                        TextInput::new("")
+                            .placeholder("path/to/image.img.xz")
+                            .validator(validate_image_path),
+                    );
                 } else {
+                    if item.image_download_sha256.is_none()
+                        && let Some(hash) =
+                            item.url.as_deref().and_then(|u| self.metadata.checksum(u))
+                    {
+                        item.image_download_sha256 = Some(hash.to_string());
+                    }
                     self.selected_os = Some(item);
                     self.current_view = CurrentView::StorageSelection;
                     self.refresh_drives();
+                    self.start_prefetch(tx);
                 }
             }
         }
     }
 
+    /// Selects an image that isn't in the OS list, given as a path or URL,
+    /// and moves on to the storage selection.
+    fn select_custom_image(&mut self, location: &str) {
+        self.selected_os = Some(OsListItem::from_location(location));
+        self.current_view = CurrentView::StorageSelection;
+        self.refresh_drives();
+    }
+
     fn refresh_drives(&mut self) {
+        self.usbboot_devices = crate::rpiboot::detect_compute_modules();
         match crate::drivelist::get_drives() {
             Ok(drives) => {
                 self.drive_list = drives.into_iter().filter(|d| !d.is_system()).collect();
-                self.drive_list_state.select(Some(0));
+                self.drive_list_state
+                    .select(Some(self.sole_candidate().unwrap_or(0)));
+                self.device_access = Some(DeviceAccess::check(&self.drive_list));
             }
             Err(e) => {
                 self.error_message = Some(format!("Failed to list drives: {}", e));
@@ -497,23 +1195,206 @@ impl App {
         }
     }
 
+    /// The index of the only removable, writable drive, when there is exactly
+    /// one. It is preselected, so a single card reader takes one Enter.
+    fn sole_candidate(&self) -> Option<usize> {
+        let mut candidates = self
+            .drive_list
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.removable && !d.readonly && !d.is_system());
+        match (candidates.next(), candidates.next()) {
+            (Some((i, _)), None) => Some(i),
+            _ => None,
+        }
+    }
+
     fn select_drive(&mut self) {
-        if let Some(i) = self.drive_list_state.selected() {
-            if let Some(drive) = self.drive_list.get(i) {
-                self.selected_drive = Some(drive.clone());
+        if let Some(i) = self.drive_list_state.selected()
+            && let Some(drive) = self.drive_list.get(i)
+        {
+            self.selected_drive = Some(drive.clone());
+            self.card_fingerprint = crate::flash_db::fingerprint(&drive.name);
+            self.fixed_disk_confirmed = false;
+            if self.selected_is_bootloader() {
+                // OS customization does not apply to EEPROM recovery images
+                self.current_view = CurrentView::BootloaderConfig;
+                self.bootloader_menu_state.select(Some(0));
+            } else if crate::presets::Presets::open().is_some_and(|p| !p.names().is_empty()) {
+                self.customization_menu_state.select(Some(0));
+                self.open_presets(CurrentView::StorageSelection);
+            } else {
                 self.current_view = CurrentView::Customization;
                 self.customization_menu_state.select(Some(0));
             }
         }
     }
 
-    fn next_drive(&mut self) {
-        let i = match self.drive_list_state.selected() {
-            Some(i) => {
-                if i >= self.drive_list.len().saturating_sub(1) {
-                    0
-                } else {
-                    i + 1
+    /// Jumps back (or forward again) to a step of the wizard in the sidebar.
+    /// Only steps whose earlier choices are still in place can be reached,
+    /// and choices made after the step are kept: going back to pick another
+    /// OS keeps the drive and customization.
+    fn jump_to_step(&mut self, step: usize) {
+        if !self.can_jump_to_step() {
+            return;
+        }
+        match step {
+            0 => self.current_view = CurrentView::DeviceSelection,
+            1 if self.selected_device.is_some() => self.current_view = CurrentView::OsSelection,
+            2 if self.selected_os.is_some() => {
+                self.current_view = CurrentView::StorageSelection;
+                self.refresh_drives();
+                if let Some(i) = self.selected_drive_index() {
+                    self.drive_list_state.select(Some(i));
+                }
+            }
+            // The drive may have been unplugged since it was chosen
+            3 if self.selected_os.is_some() && self.selected_drive_index().is_some() => {
+                self.current_view = if self.selected_is_bootloader() {
+                    CurrentView::BootloaderConfig
+                } else {
+                    CurrentView::Customization
+                };
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether a wizard step is shown and no text is being edited.
+    fn can_jump_to_step(&self) -> bool {
+        let in_wizard = matches!(
+            self.current_view,
+            CurrentView::DeviceSelection
+                | CurrentView::OsSelection
+                | CurrentView::StorageSelection
+                | CurrentView::Customization
+                | CurrentView::BootloaderConfig
+                | CurrentView::WriteConfirmation
+        );
+        in_wizard && self.customization_ui.input_mode == InputMode::Navigation
+    }
+
+    fn selected_drive_index(&self) -> Option<usize> {
+        let selected = self.selected_drive.as_ref()?;
+        self.drive_list.iter().position(|d| d.name == selected.name)
+    }
+
+    /// The sidebar step drawn at a terminal cell, three rows per step.
+    fn sidebar_step_at(&self, column: u16, row: u16) -> Option<usize> {
+        let area = self.sidebar_area;
+        let inside = column >= area.x
+            && column < area.x + area.width
+            && row >= area.y
+            && row < area.y + area.height;
+        let step = usize::from(row.checked_sub(area.y)? / 3);
+        (inside && step < STEPS.len()).then_some(step)
+    }
+
+    /// Makes the selections given on the command line and moves on to the
+    /// first screen that still needs a choice. The setup wizard and the
+    /// passphrase prompt stay in front.
+    fn apply_preselect(&mut self, tx: mpsc::Sender<AppMessage>) {
+        let preselect = std::mem::take(&mut self.preselect);
+        if preselect.is_empty() {
+            return;
+        }
+        let shown = self.current_view;
+        let mut missing = Vec::new();
+
+        if let Some(query) = &preselect.device {
+            match crate::preselect::find_device(self.get_devices(), query) {
+                Some(i) => {
+                    self.device_list_state.select(Some(i));
+                    self.select_device();
+                }
+                None => missing.push(format!("no device matches \"{}\"", query)),
+            }
+        }
+        if let Some(query) = &preselect.os
+            && self.selected_os.is_none()
+        {
+            let items = self.os_list.as_ref().map_or(&[][..], |l| &l.os_list);
+            match crate::preselect::find_os(items, query) {
+                Some(os) => {
+                    self.selected_os = Some(os);
+                    self.current_view = CurrentView::StorageSelection;
+                    self.refresh_drives();
+                    self.start_prefetch(tx);
+                }
+                None => missing.push(format!("no operating system matches \"{}\"", query)),
+            }
+        }
+        if let Some(query) = &preselect.drive
+            && self.selected_os.is_some()
+        {
+            match crate::preselect::find_drive(&self.drive_list, query) {
+                Some(i) => {
+                    self.drive_list_state.select(Some(i));
+                    self.select_drive();
+                }
+                None => missing.push(format!(
+                    "{} is not attached, or holds the running system",
+                    query
+                )),
+            }
+        }
+
+        match shown {
+            CurrentView::Unlock => {
+                self.unlock_return = self.current_view;
+                self.current_view = shown;
+            }
+            CurrentView::Setup => self.current_view = shown,
+            _ => {}
+        }
+        if !missing.is_empty() {
+            self.error_message = Some(format!("Command line selection: {}", missing.join("; ")));
+        }
+    }
+
+    fn selected_is_bootloader(&self) -> bool {
+        self.selected_os
+            .as_ref()
+            .and_then(|os| os.url.as_deref())
+            .is_some_and(crate::eeprom::is_bootloader_image)
+    }
+
+    const BOOTLOADER_MENU_LEN: usize = 7;
+
+    fn handle_bootloader_enter(&mut self) {
+        let config = &mut self.customization_options.bootloader;
+        match self.bootloader_menu_state.selected().unwrap_or(0) {
+            0 => {
+                let input = TextInput::new(&config.boot_order)
+                    .validator(crate::eeprom::BootloaderConfig::validate_boot_order);
+                self.start_editing(input);
+                return;
+            }
+            1 => config.power_off_on_halt = !config.power_off_on_halt,
+            2 => config.boot_uart = !config.boot_uart,
+            3 => config.wake_on_gpio = !config.wake_on_gpio,
+            4 => config.net_install_enabled = !config.net_install_enabled,
+            5 => *config = crate::eeprom::BootloaderConfig::default(),
+            _ => {
+                self.current_view = CurrentView::WriteConfirmation;
+                return;
+            }
+        }
+        self.save_options();
+    }
+
+    fn apply_bootloader_edit(&mut self, value: String) {
+        self.customization_options.bootloader.boot_order = value.trim().to_lowercase();
+        self.save_options();
+    }
+
+    fn next_drive(&mut self) {
+        let i = match self.drive_list_state.selected() {
+            Some(i) => {
+                if i >= self.drive_list.len().saturating_sub(1) {
+                    0
+                } else {
+                    i + 1
                 }
             }
             None => 0,
@@ -535,43 +1416,881 @@ impl App {
         self.drive_list_state.select(Some(i));
     }
 
+    fn needs_fixed_disk_confirmation(&self) -> bool {
+        self.selected_drive.as_ref().is_some_and(|d| !d.removable) && !self.fixed_disk_confirmed
+    }
+
+    fn confirm_write(&mut self, tx: mpsc::Sender<AppMessage>) {
+        if let Some(access) = self.device_access
+            && !access.can_write()
+        {
+            self.error_message = access.banner();
+        } else if let Some(Err(e)) = self
+            .selected_drive
+            .as_ref()
+            .filter(|d| {
+                crate::target::Target::detect(&d.name) == crate::target::Target::BlockDevice
+            })
+            .map(|d| crate::in_use::check(&d.name))
+        {
+            self.error_message = Some(format!("{:#}", e));
+        } else if let Some(refusal) =
+            crate::power::refuses_battery(self.customization_options.require_ac_power)
+        {
+            self.error_message = Some(refusal);
+        } else if self.needs_fixed_disk_confirmation() {
+            self.fixed_disk_confirmed = true;
+        } else {
+            self.mirror = 0;
+            self.start_writing(tx);
+        }
+    }
+
+    /// The next configured mirror of the image being written, if it has one.
+    fn next_mirror_url(&self) -> Option<String> {
+        let url = self.selected_os.as_ref()?.url.as_deref()?;
+        let base = self
+            .customization_options
+            .download_mirrors
+            .get(self.mirror)?;
+        crate::http::mirror_url(url, base)
+    }
+
+    /// Stops the slow download and starts the write over from the next
+    /// mirror, keeping every selection.
+    fn switch_mirror(&mut self, tx: mpsc::Sender<AppMessage>) {
+        if self.next_mirror_url().is_none() {
+            return;
+        }
+        self.send_worker_command(WorkerCommand::Abort);
+        self.worker_commands = None;
+        if let Some(handle) = self.abort_handle.take() {
+            handle.abort();
+        }
+        self.write_task = None;
+        self.mirror += 1;
+        self.start_writing(tx);
+    }
+
+    /// How long writing the selected image should take, from earlier writes
+    /// to the same drive model.
+    fn estimated_write_time(&self) -> Option<std::time::Duration> {
+        let (drive, os) = (self.selected_drive.as_ref()?, self.selected_os.as_ref()?);
+        self.flash_db
+            .estimated_duration(drive.model.as_deref()?, &os.name, os.extract_size?)
+    }
+
+    /// Turns saving a copy of the image next to the write on or off.
+    fn toggle_tee_file(&mut self) {
+        self.tee_file = match (&self.tee_file, &self.selected_os) {
+            (None, Some(os)) => default_tee_file(os),
+            _ => None,
+        };
+    }
+
+    /// Whether the next write reads the card back.
+    fn verify_write(&self) -> bool {
+        match self.customization_options.verification {
+            Verification::Always => true,
+            Verification::Ask => self.verify_choice.unwrap_or(true),
+            Verification::Never => false,
+        }
+    }
+
+    /// The customization to send to the worker, base64-encoded, without the
+    /// sections the image can't honour.
+    fn encoded_options(&self, os: &OsListItem) -> String {
+        let mut options = self.customization_options.clone();
+        options.sealed_secrets = None;
+        // The worker can't ask, so settle the verification choice here
+        options.verification = if self.verify_write() {
+            Verification::Always
+        } else {
+            Verification::Never
+        };
+        options.tee_file = self.tee_file.clone();
+        for section in CustomizationSection::ALL {
+            if !os.supports(section) {
+                options.clear_section(section);
+            }
+        }
+        let options_json = serde_json::to_string(&options).unwrap_or_default();
+        base64::engine::general_purpose::STANDARD.encode(options_json)
+    }
+
+    /// Worker command line writing the selected image to the selected drive.
+    fn write_args(&self) -> Option<Vec<String>> {
+        let (os, drive) = (self.selected_os.as_ref()?, self.selected_drive.as_ref()?);
+        let exe = std::env::current_exe().unwrap_or_else(|_| "rpi-imager-tui".into());
+
+        let mut args = vec![
+            exe.to_string_lossy().to_string(),
+            "worker".to_string(),
+            "--device".to_string(),
+            drive.name.clone(),
+            "--options".to_string(),
+            self.encoded_options(os),
+        ];
+
+        if let Some(url) = &os.url {
+            let mirrored = self.mirror.checked_sub(1).and_then(|i| {
+                crate::http::mirror_url(url, self.customization_options.download_mirrors.get(i)?)
+            });
+            args.push("--image".to_string());
+            args.push(mirrored.unwrap_or_else(|| url.clone()));
+        }
+        if let Some(hash) = &os.extract_sha256 {
+            args.push("--sha256".to_string());
+            args.push(hash.clone());
+        }
+        if let Some(size) = os.extract_size {
+            args.push("--size".to_string());
+            args.push(size.to_string());
+        }
+        if let Some(format) = os.customization_format() {
+            args.push("--init-format".to_string());
+            args.push(format);
+        }
+        if self.can_delta_write() {
+            args.push("--delta".to_string());
+        }
+        let cached =
+            crate::cache::offline_image(os.url.as_deref(), os.image_download_sha256.as_deref())
+                .or_else(|| {
+                    let url = os.url.as_deref()?;
+                    crate::cache::lookup(url, os.image_download_sha256.as_deref())
+                });
+        if let Some(cached) = cached {
+            args.push("--cache".to_string());
+            args.push(cached.to_string_lossy().to_string());
+        }
+        args.push("--profile".to_string());
+        args.push(drive.profile.as_str().to_string());
+        Some(args)
+    }
+
     fn start_writing(&mut self, _tx: mpsc::Sender<AppMessage>) {
-        if let (Some(os), Some(drive)) = (self.selected_os.clone(), self.selected_drive.clone()) {
-            let options = self.customization_options.clone();
+        // The worker continues from the prefetched part
+        self.stop_prefetch();
+        self.boot_checks.clear();
+        self.boot_changes.clear();
+        self.verification_skipped = false;
+        self.ejected = false;
+        self.download_host = None;
+        self.slow_download = false;
+        if let Some(args) = self.write_args() {
+            if let (Some(station), Some(drive), Some(os)) =
+                (&mut self.station, &self.selected_drive, &self.selected_os)
+            {
+                station.started(&os.name, &drive.name);
+            }
+            if let (Some(drive), Some(os)) = (&self.selected_drive, &self.selected_os) {
+                self.history
+                    .started(os, &drive.name, drive.serial.as_deref());
+            }
+            self.timed_write = match (&self.selected_drive, &self.selected_os) {
+                (Some(drive), Some(os)) if !args.iter().any(|a| a == "--delta") => {
+                    Some((drive.clone(), os.clone()))
+                }
+                _ => None,
+            };
+            self.worker_args = Some(args);
+            self.current_view = CurrentView::Authenticating;
+        }
+    }
 
-            // Prepare arguments
-            let exe = std::env::current_exe().unwrap_or_else(|_| "rpi-imager-tui".into());
+    /// Queues the configured write and returns to the start of the wizard,
+    /// so the next one can be configured while earlier ones run.
+    fn queue_write(&mut self) {
+        self.stop_prefetch();
+        let (Some(args), Some(os), Some(drive)) = (
+            self.write_args(),
+            self.selected_os.clone(),
+            self.selected_drive.clone(),
+        ) else {
+            return;
+        };
+        let category = self.breadcrumbs.last().cloned().unwrap_or_default();
+        match self.queue.push(os, drive, category, args) {
+            Ok(()) => {
+                self.reset_wizard();
+                self.open_queue();
+            }
+            Err(e) => self.error_message = Some(e),
+        }
+    }
+
+    fn open_queue(&mut self) {
+        self.current_view = CurrentView::Queue;
+        if self.queue_state.selected().is_none() {
+            self.queue_state.select(Some(0));
+        }
+    }
+
+    fn open_repositories(&mut self) {
+        self.current_view = CurrentView::Repositories;
+        self.repositories_state.select(Some(0));
+    }
+
+    fn open_cache(&mut self) {
+        self.current_view = CurrentView::Cache;
+        self.cached_images = crate::cache::list();
+        self.cache_state.select(Some(0));
+    }
+
+    fn open_presets(&mut self, back: CurrentView) {
+        self.presets_return = back;
+        self.preset_names = crate::presets::Presets::open()
+            .map(|p| p.names())
+            .unwrap_or_default();
+        self.presets_state.select(Some(0));
+        self.current_view = CurrentView::Presets;
+    }
+
+    /// The preset highlighted on the Presets screen, whose first row keeps
+    /// the current settings.
+    fn selected_preset(&self) -> Option<&String> {
+        let i = self.presets_state.selected()?;
+        self.preset_names.get(i.checked_sub(1)?)
+    }
+
+    /// Takes the image settings of the highlighted preset, or keeps the
+    /// current ones, and continues with the customization.
+    fn apply_preset(&mut self) {
+        if let Some(name) = self.selected_preset().cloned()
+            && let Some(presets) = crate::presets::Presets::open()
+        {
+            match presets.load(&name) {
+                Ok(preset) => {
+                    let passphrase = self.customization_options.passphrase.clone();
+                    self.take_preset(preset, passphrase.as_deref());
+                }
+                Err(e) => self.error_message = Some(format!("{:#}", e)),
+            }
+            return;
+        }
+        self.current_view = CurrentView::Customization;
+    }
 
-            let options_json = serde_json::to_string(&options).unwrap_or_default();
-            let options_b64 = base64::engine::general_purpose::STANDARD.encode(options_json);
+    /// Applies `preset` and continues with the customization, or asks for
+    /// the passphrase of its passwords, again after a wrong one.
+    fn take_preset(&mut self, preset: CustomizationOptions, passphrase: Option<&str>) {
+        let mut options = self.customization_options.clone();
+        match crate::presets::apply(&mut options, preset.clone(), passphrase) {
+            Ok(()) => {
+                self.customization_options = options;
+                self.save_options();
+                self.unlock_error = None;
+                self.current_view = CurrentView::Customization;
+            }
+            // Only a typed passphrase is reported wrong; the config's may
+            // just not be the preset's
+            Err(e) if self.current_view == CurrentView::Unlock => {
+                self.unlock_error = Some(format!("{:#}", e));
+                self.unlock_preset = Some(preset);
+                self.start_editing(TextInput::new("").masked().placeholder("passphrase"));
+            }
+            Err(_) => {
+                self.unlock_preset = Some(preset);
+                self.start_unlock();
+            }
+        }
+    }
+
+    fn start_naming_preset(&mut self, renaming: Option<String>) {
+        let input = TextInput::new(renaming.clone().unwrap_or_default())
+            .placeholder("preset name")
+            .validator(crate::presets::validate_name);
+        self.preset_renaming = renaming;
+        self.start_editing(input);
+    }
+
+    /// Saves the current settings as `name`, or renames the preset being
+    /// renamed to it.
+    fn name_preset(&mut self, name: String) {
+        let Some(presets) = crate::presets::Presets::open() else {
+            return;
+        };
+        let name = name.trim();
+        let result = match self.preset_renaming.take() {
+            Some(old) => presets.rename(&old, name),
+            None => presets.save(name, &self.customization_options),
+        };
+        if let Err(e) = result {
+            self.error_message = Some(format!("{:#}", e));
+        }
+        self.preset_names = presets.names();
+        if let Some(i) = self.preset_names.iter().position(|n| n == name) {
+            self.presets_state.select(Some(i + 1));
+        }
+    }
+
+    fn delete_preset(&mut self) {
+        let (Some(name), Some(presets)) = (
+            self.selected_preset().cloned(),
+            crate::presets::Presets::open(),
+        ) else {
+            return;
+        };
+        if let Err(e) = presets.delete(&name) {
+            self.error_message = Some(format!("{:#}", e));
+        }
+        self.preset_names = presets.names();
+        let last = self.preset_names.len();
+        self.presets_state
+            .select(self.presets_state.selected().map(|i| i.min(last)));
+    }
+
+    /// Fetches the keys asked for in the Remote Access submenu.
+    fn start_ssh_import(&mut self, tx: &mpsc::Sender<AppMessage>) {
+        let Some(id) = self.ssh_import_id.take() else {
+            return;
+        };
+        let client = match crate::http::client(&self.customization_options) {
+            Ok(client) => client,
+            Err(e) => {
+                self.error_message = Some(format!("{:#}", e));
+                return;
+            }
+        };
+        self.ssh_import_started = Some(std::time::Instant::now());
+        self.ssh_import_status = None;
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let result = crate::ssh_import::fetch(&client, &id)
+                .await
+                .map_err(|e| format!("{:#}", e));
+            let _ = tx.send(AppMessage::SshKeysImported(result)).await;
+        });
+    }
+
+    fn open_history(&mut self) {
+        self.current_view = CurrentView::History;
+        self.history_notice = None;
+        self.history_state.select(Some(0));
+    }
+
+    /// The job highlighted on the History screen, which lists the newest
+    /// first.
+    fn selected_history_job(&self) -> Option<&crate::history::Job> {
+        let i = self.history_state.selected()?;
+        let jobs = &self.history.jobs;
+        jobs.get(jobs.len().checked_sub(i + 1)?)
+    }
+
+    /// Picks the image and card of the highlighted job again and continues
+    /// with the customization, as if both had been chosen in the wizard.
+    fn rerun_job(&mut self) {
+        let Some(job) = self.selected_history_job().cloned() else {
+            return;
+        };
+        self.refresh_drives();
+        let found = self
+            .drive_list
+            .iter()
+            .position(|d| match (&job.serial, &d.serial) {
+                (Some(serial), Some(other)) => serial == other,
+                _ => d.name == job.device,
+            });
+        let Some(i) = found else {
+            self.history_notice = Some(format!("{} is not connected", job.device));
+            return;
+        };
+        self.selected_os = Some(job.os);
+        self.drive_list_state.select(Some(i));
+        self.select_drive();
+    }
+
+    fn export_history(&mut self, csv: bool) {
+        self.history_notice = Some(match self.history.export(csv) {
+            Ok(path) => format!("Exported to {}", path.display()),
+            Err(e) => format!("{:#}", e),
+        });
+    }
 
+    /// Deletes the selected download, or all of them.
+    fn remove_cached(&mut self, all: bool) {
+        let selected = self.cache_state.selected().unwrap_or(0);
+        let images: Vec<_> = if all {
+            std::mem::take(&mut self.cached_images)
+        } else if selected < self.cached_images.len() {
+            vec![self.cached_images.remove(selected)]
+        } else {
+            Vec::new()
+        };
+        for image in &images {
+            if let Err(e) = crate::cache::remove(image) {
+                self.error_message = Some(format!("{:#}", e));
+            }
+        }
+        self.cached_images = crate::cache::list();
+        self.cache_state.select(Some(
+            selected.min(self.cached_images.len().saturating_sub(1)),
+        ));
+    }
+
+    fn toggle_repository(&mut self) {
+        if let Some(repository) = self
+            .repositories_state
+            .selected()
+            .and_then(|i| crate::repositories::BUILTIN.get(i))
+        {
+            repository.toggle(&mut self.customization_options.os_sources);
+            self.save_options();
+            self.repositories_changed = true;
+        }
+    }
+
+    /// Back to the device selection, reloading the OS list if the enabled
+    /// repositories changed.
+    fn close_repositories(&mut self, tx: mpsc::Sender<AppMessage>) {
+        self.current_view = CurrentView::DeviceSelection;
+        if std::mem::take(&mut self.repositories_changed) {
+            self.navigation_stack.clear();
+            self.selection_stack.clear();
+            self.breadcrumbs.clear();
+            self.is_loading = true;
+            self.os_list_notice = None;
+            spawn_os_list_fetch(tx, self.customization_options.clone());
+        }
+    }
+
+    /// Hands the next pending job to the worker spawner, if none is running.
+    fn start_queue(&mut self) {
+        if let Some(index) = self.queue.start_next() {
+            let job = &self.queue.jobs[index];
+            if let Some(station) = &mut self.station {
+                station.started(&job.os.name, &job.drive.name);
+            }
+            self.history
+                .started(&job.os, &job.drive.name, job.drive.serial.as_deref());
+            self.worker_args = Some(self.queue.jobs[index].args.clone());
+            self.worker_job = Some(index);
+        }
+    }
+
+    fn finish_queue_job(&mut self, index: usize) {
+        let job = &self.queue.jobs[index];
+        if job.status == JobStatus::Done {
+            if let Some(serial) = &job.drive.serial {
+                self.flash_db
+                    .record(serial, &job.os.name, job.os.extract_sha256.as_deref());
+            }
+            self.send_telemetry_for(&job.os, job.category.clone());
+        }
+        self.start_queue();
+    }
+
+    /// Starts downloading the selected image into the cache while the user
+    /// picks storage and customization. Failures only show in the status bar;
+    /// the write downloads whatever is missing anyway.
+    fn start_prefetch(&mut self, tx: mpsc::Sender<AppMessage>) {
+        self.stop_prefetch();
+        self.offline_flash = false;
+        let Some(os) = self
+            .selected_os
+            .as_ref()
+            .filter(|os| os.url.as_deref().is_some_and(|u| u.starts_with("http")))
+        else {
+            return;
+        };
+        // Already downloaded, possibly from an older URL: nothing to fetch
+        self.offline_flash =
+            crate::cache::offline_image(os.url.as_deref(), os.image_download_sha256.as_deref())
+                .is_some();
+        let url = os.url.clone().unwrap_or_default();
+        let sha256 = os.image_download_sha256.clone();
+        if self.offline_flash {
+            self.prefetch = Some("Offline flash: image in cache".to_string());
+            return;
+        }
+        let Ok(client) = crate::http::client(&self.customization_options) else {
+            return;
+        };
+        self.prefetch = Some("Prefetch: starting".to_string());
+        let (stop, stopped) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(async move {
+            let progress_tx = tx.clone();
+            let result =
+                crate::cache::prefetch(&client, &url, sha256.as_deref(), stopped, |progress| {
+                    let _ = progress_tx.try_send(AppMessage::Prefetch(Ok(progress)));
+                })
+                .await;
+            if let Err(e) = result {
+                let _ = tx.send(AppMessage::Prefetch(Err(format!("{:#}", e)))).await;
+            }
+        });
+        self.prefetch_task = Some((stop, handle));
+    }
+
+    /// Stops the prefetch, leaving what was downloaded for the worker. Waits
+    /// until the last chunk is on disk, as the worker continues from the
+    /// length of the partial download.
+    fn stop_prefetch(&mut self) {
+        if let Some((stop, handle)) = self.prefetch_task.take() {
+            let _ = stop.send(());
+            tokio::task::block_in_place(|| {
+                let _ = tokio::runtime::Handle::current().block_on(handle);
+            });
+        }
+        self.prefetch = None;
+    }
+
+    /// Back to the first wizard step, keeping the loaded OS list.
+    fn reset_wizard(&mut self) {
+        self.stop_prefetch();
+        self.offline_flash = false;
+        self.current_view = CurrentView::DeviceSelection;
+        self.selected_os = None;
+        self.selected_drive = None;
+        self.verify_choice = None;
+        self.tee_file = None;
+        self.navigation_stack.clear();
+        self.breadcrumbs.clear();
+        self.list_state.select(Some(0));
+        self.selected_device = None;
+        self.device_os_list = None;
+        self.device_list_state.select(Some(0));
+    }
+
+    /// Re-runs only the customization step on the card that was just written.
+    fn retry_customization(&mut self) {
+        self.boot_checks.clear();
+        self.boot_changes.clear();
+        if let (Some(os), Some(drive)) = (&self.selected_os, &self.selected_drive) {
+            let exe = std::env::current_exe().unwrap_or_else(|_| "rpi-imager-tui".into());
             let mut args = vec![
                 exe.to_string_lossy().to_string(),
-                "--worker".to_string(),
+                "worker".to_string(),
+                "--customize".to_string(),
                 "--device".to_string(),
                 drive.name.clone(),
                 "--options".to_string(),
-                options_b64,
+                self.encoded_options(os),
             ];
-
-            if let Some(url) = os.url {
-                args.push("--image".to_string());
-                args.push(url.clone());
+            if let Some(format) = os.customization_format() {
+                args.push("--init-format".to_string());
+                args.push(format);
             }
-            if let Some(hash) = os.extract_sha256 {
-                args.push("--sha256".to_string());
-                args.push(hash.clone());
+            self.worker_args = Some(args);
+            self.current_view = CurrentView::Authenticating;
+        }
+    }
+
+    /// Asks the privileged worker to read the customization back from the
+    /// selected drive's boot partition.
+    fn inspect_drive(&mut self) {
+        if let Some(drive) = self
+            .drive_list_state
+            .selected()
+            .and_then(|i| self.drive_list.get(i))
+        {
+            let exe = std::env::current_exe().unwrap_or_else(|_| "rpi-imager-tui".into());
+            self.worker_args = Some(vec![
+                exe.to_string_lossy().to_string(),
+                "worker".to_string(),
+                "--inspect".to_string(),
+                "--device".to_string(),
+                drive.name.clone(),
+            ]);
+            self.current_view = CurrentView::Authenticating;
+        }
+    }
+
+    /// Asks the privileged worker for a read-only listing of the boot
+    /// partition that was just written.
+    fn browse_boot_partition(&mut self) {
+        if let Some(drive) = &self.selected_drive {
+            let exe = std::env::current_exe().unwrap_or_else(|_| "rpi-imager-tui".into());
+            self.worker_args = Some(vec![
+                exe.to_string_lossy().to_string(),
+                "worker".to_string(),
+                "--browse".to_string(),
+                "--device".to_string(),
+                drive.name.clone(),
+            ]);
+            self.current_view = CurrentView::Authenticating;
+        }
+    }
+
+    /// Runs rpiboot through the privileged worker so an attached Compute
+    /// Module's eMMC shows up as a regular storage device.
+    fn provision_compute_module(&mut self) {
+        let exe = std::env::current_exe().unwrap_or_else(|_| "rpi-imager-tui".into());
+        self.worker_args = Some(vec![
+            exe.to_string_lossy().to_string(),
+            "worker".to_string(),
+            "--rpiboot".to_string(),
+        ]);
+        self.current_view = CurrentView::Authenticating;
+    }
+
+    fn next_boot_file(&mut self) {
+        let i = match self.boot_files_state.selected() {
+            Some(i) if i + 1 < self.boot_files.len() => i + 1,
+            _ => 0,
+        };
+        self.boot_files_state.select(Some(i));
+    }
+
+    fn previous_boot_file(&mut self) {
+        let i = match self.boot_files_state.selected() {
+            Some(0) | None => self.boot_files.len().saturating_sub(1),
+            Some(i) => i - 1,
+        };
+        self.boot_files_state.select(Some(i));
+    }
+
+    fn apply_inspected_customization(&mut self, inspected: CustomizationOptions) {
+        // Only the image customization comes from the card; keep local tool settings.
+        let current = &self.customization_options;
+        self.customization_options = CustomizationOptions {
+            telemetry: current.telemetry,
+            eject_finished: current.eject_finished,
+            remember_secrets: current.remember_secrets,
+            sealed_secrets: current.sealed_secrets.clone(),
+            passphrase: current.passphrase.clone(),
+            verification: current.verification,
+            wipe_device: current.wipe_device,
+            require_ac_power: current.require_ac_power,
+            theme: current.theme,
+            cache_dir: current.cache_dir.clone(),
+            decompression_threads: current.decompression_threads,
+            stats_file: current.stats_file.clone(),
+            http_proxy: current.http_proxy.clone(),
+            ca_bundle: current.ca_bundle.clone(),
+            pinned_certificates: current.pinned_certificates.clone(),
+            bootloader: current.bootloader.clone(),
+            os_sources: current.os_sources.clone(),
+            download_mirrors: current.download_mirrors.clone(),
+            download_connections: current.download_connections,
+            bootstrap_command: current.bootstrap_command.clone(),
+            extra_files: current.extra_files.clone(),
+            ..inspected
+        };
+        self.save_options();
+        self.write_status.clear();
+        self.current_view = CurrentView::Customization;
+        self.customization_menu_state.select(Some(0));
+        self.in_customization_submenu = false;
+    }
+
+    /// Shows the QR code of the given kind, or hides it if already shown.
+    /// Kinds the customization doesn't configure are ignored.
+    fn toggle_qr_code(&mut self, kind: QrKind) {
+        if self.qr_code == Some(kind) {
+            self.qr_code = None;
+        } else if kind.payload(&self.customization_options).is_some() {
+            self.qr_code = Some(kind);
+        }
+    }
+
+    /// Starts watching for the flashed Pi's hostname to come online.
+    fn wait_for_device(&mut self, tx: mpsc::Sender<AppMessage>) {
+        if self.reachability_task.is_some() {
+            return;
+        }
+        let options = self.customization_options.clone();
+        self.reachability = Some(format!(
+            "Waiting for {}.local to come online (boot the Pi now)...",
+            options.hostname
+        ));
+        let handle = tokio::spawn(async move {
+            let found = match crate::reachability::wait_for_host(
+                &options.hostname,
+                std::time::Duration::from_secs(600),
+            )
+            .await
+            {
+                Ok(found) => found,
+                Err(e) => {
+                    let _ = tx
+                        .send(AppMessage::HostReachable(Err(format!("{:#}", e))))
+                        .await;
+                    return;
+                }
+            };
+            let ip = found.ip();
+            let reachable = match found {
+                crate::reachability::Found::Named(_) => format!(
+                    "Your Pi is reachable at {} ({}.local)",
+                    ip, options.hostname
+                ),
+                crate::reachability::Found::Neighbour(_) => format!(
+                    "A Raspberry Pi came online at {} ({}.local doesn't resolve, so it may be \
+                     another Pi)",
+                    ip, options.hostname
+                ),
+            };
+
+            // Optionally hand the Pi over to the configured bootstrap command
+            let Some(command) = options.bootstrap_command.clone() else {
+                let _ = tx.send(AppMessage::HostReachable(Ok(reachable))).await;
+                return;
+            };
+            let _ = tx
+                .send(AppMessage::HostReachable(Ok(format!(
+                    "{}. Running bootstrap command...",
+                    reachable
+                ))))
+                .await;
+            let result = crate::bootstrap::run(&command, ip, &options)
+                .await
+                .map(|log| format!("{}. Bootstrap finished, log: {}", reachable, log.display()))
+                .map_err(|e| format!("{}. {:#}", reachable, e));
+            let _ = tx.send(AppMessage::BootstrapFinished(result)).await;
+        });
+        self.reachability_task = Some(handle.abort_handle());
+    }
+
+    fn stop_waiting_for_device(&mut self) {
+        if let Some(handle) = self.reachability_task.take() {
+            handle.abort();
+        }
+        self.reachability = None;
+    }
+
+    /// Whether the error screen offers `action` for the current error.
+    fn offers(&self, action: crate::troubleshoot::Action) -> bool {
+        match (self.error_kind, &self.error_message) {
+            (Some(kind), Some(message)) => {
+                crate::troubleshoot::actions(kind, message).contains(&action)
             }
-            if let Some(size) = os.extract_size {
-                args.push("--size".to_string());
-                args.push(size.to_string());
+            _ => false,
+        }
+    }
+
+    fn dismiss_error(&mut self) {
+        self.error_message = None;
+        self.error_scroll = 0;
+        self.error_notice = None;
+        self.permission_error = false;
+        self.error_kind = None;
+    }
+
+    /// Copies the error through the terminal (OSC 52), which also works over SSH.
+    fn copy_error(&mut self) {
+        let Some(err) = &self.error_message else {
+            return;
+        };
+        let result = execute!(
+            io::stdout(),
+            crossterm::clipboard::CopyToClipboard::to_clipboard_from(crate::about::bug_report(err))
+        );
+        self.error_notice = Some(match result {
+            Ok(()) => "Copied to clipboard".to_string(),
+            Err(e) => format!("Copy failed: {}", e),
+        });
+    }
+
+    fn save_error(&mut self) {
+        let Some(err) = &self.error_message else {
+            return;
+        };
+        self.error_notice = Some(
+            match crate::error_log::append(&crate::about::bug_report(err)) {
+                Ok(path) => format!("Saved to {}", path.display()),
+                Err(e) => format!("Save failed: {:#}", e),
+            },
+        );
+    }
+
+    /// Quits with the wizard state, so that main() can restart the app
+    /// under sudo/pkexec and resume from the same step.
+    fn restart_elevated(&mut self) {
+        let session = Session {
+            device: self.selected_device.clone(),
+            os: self.selected_os.clone(),
+            drive: self.selected_drive.as_ref().map(|d| d.name.clone()),
+            customization: self.customization_options.clone(),
+        };
+        let tool = self
+            .device_access
+            .and_then(|a| a.escalation)
+            .unwrap_or("sudo");
+        let exe = std::env::current_exe().unwrap_or_else(|_| "rpi-imager-tui".into());
+        let command = vec![
+            tool.to_string(),
+            exe.to_string_lossy().to_string(),
+            "--resume".to_string(),
+        ];
+        self.reexec = Some((command, session));
+        self.should_quit = true;
+    }
+
+    /// Restores the wizard state saved by [`App::restart_elevated`].
+    fn resume(&mut self, session: Session) {
+        self.selected_device = session.device;
+        self.selected_os = session.os;
+        self.customization_options = session.customization;
+        self.refresh_drives();
+        self.selected_drive = session
+            .drive
+            .and_then(|name| self.drive_list.iter().find(|d| d.name == name).cloned());
+        self.card_fingerprint = self
+            .selected_drive
+            .as_ref()
+            .and_then(|d| crate::flash_db::fingerprint(&d.name));
+        self.current_view = if self.selected_drive.is_some() {
+            CurrentView::WriteConfirmation
+        } else {
+            CurrentView::StorageSelection
+        };
+    }
+
+    fn set_progress(&mut self, phase: WritingPhase, fraction: f64) {
+        self.write_phase = Some(phase);
+        match phase {
+            WritingPhase::Downloading => {
+                // A new write starts over from the first segment
+                self.write_progress = 0.0;
+                self.sync_progress = 0.0;
+                self.synced_progress = 0.0;
+                self.verify_progress = 0.0;
             }
+            WritingPhase::Writing => self.write_progress = fraction,
+            WritingPhase::Syncing => self.sync_progress = fraction,
+            WritingPhase::Verifying => self.verify_progress = fraction,
+            WritingPhase::Customizing => {}
+        }
+    }
 
-            self.worker_args = Some(args);
-            self.current_view = CurrentView::Authenticating;
+    fn send_worker_command(&self, command: WorkerCommand) {
+        if let Some(commands) = &self.worker_commands {
+            let _ = commands.send(command);
+        }
+    }
+
+    /// Pauses or resumes the running write.
+    fn toggle_pause(&mut self) {
+        self.write_paused = !self.write_paused;
+        if self.write_paused {
+            // The worker confirms once it stopped; until then this is the status
+            self.write_status = "Pausing...".to_string();
+        }
+        self.send_worker_command(if self.write_paused {
+            WorkerCommand::Pause
+        } else {
+            WorkerCommand::Resume
+        });
+    }
+
+    /// During verification the worker only skips it; otherwise it stops.
+    fn confirm_abort(&mut self) {
+        self.write_paused = false;
+        if self.write_phase == Some(WritingPhase::Verifying) && self.worker_commands.is_some() {
+            self.send_worker_command(WorkerCommand::SkipVerify);
+            self.send_worker_command(WorkerCommand::Resume);
+            self.current_view = CurrentView::Writing;
+        } else {
+            self.abort_writing();
         }
     }
+
     fn abort_writing(&mut self) {
+        // Lets the worker stop between buffers instead of dying mid-write
+        self.send_worker_command(WorkerCommand::Abort);
+        self.worker_commands = None;
         if let Some(handle) = &self.abort_handle {
             handle.abort();
         }
@@ -580,6 +2299,12 @@ impl App {
         self.current_view = CurrentView::Finished;
         self.write_status = "Aborted".to_string();
         self.error_message = Some("Operation cancelled by user.".to_string());
+        self.error_kind = Some(ErrorKind::Cancelled);
+        if let Some(station) = &mut self.station {
+            station.failed(ErrorKind::Cancelled);
+        }
+        self.history
+            .finished(crate::history::Outcome::Cancelled, None);
     }
 
     fn back(&mut self) {
@@ -596,24 +2321,136 @@ impl App {
             self.list_state.select(Some(0));
         }
     }
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = std::env::args().collect();
+}
+
+/// Remembers how long a finished write took, without verification, keyed by
+/// the drive model.
+fn record_write_speed(db: &mut FlashDatabase, drive: &Drive, os: &OsListItem, summary: &Summary) {
+    let written = matches!(
+        summary.outcome.as_deref(),
+        Some("Finished" | "CustomizationFailed")
+    );
+    let (Some(model), Some(bytes)) = (&drive.model, os.extract_size) else {
+        return;
+    };
+    let millis = summary
+        .phases
+        .iter()
+        .filter(|(phase, _)| matches!(phase, WritingPhase::Writing | WritingPhase::Syncing))
+        .map(|(_, ms)| ms)
+        .sum();
+    if written {
+        db.record_speed(model, &os.name, bytes, millis);
+    }
+}
+
+/// Where the image copy goes by default: the image's file name without the
+/// compression suffix, in the working directory. None for an uncompressed
+/// local image there, which already is that file.
+fn default_tee_file(os: &OsListItem) -> Option<String> {
+    let url = os.url.as_deref()?;
+    let name = url.rsplit('/').next()?;
+    let stem = [".xz", ".gz", ".zst", ".bz2"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name);
+    let path = std::env::current_dir()
+        .ok()?
+        .join(if stem.ends_with(".img") {
+            stem.to_string()
+        } else {
+            format!("{}.img", stem)
+        });
+    let source = std::fs::canonicalize(url).ok();
+    (source.as_deref() != Some(&path)).then(|| path.to_string_lossy().to_string())
+}
+
+/// Validator for the path typed after choosing "Use custom image".
+fn validate_image_path(value: &str) -> Result<(), String> {
+    let path = std::path::Path::new(value.trim());
+    if value.trim().is_empty() {
+        Err("Enter the path of an image file".to_string())
+    } else if !path.is_file() {
+        Err("No such file".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = crate::cli::Cli::parse_args();
+    crate::http::set_user_agent(crate::about::user_agent());
+
+    if cli.version {
+        println!("{}", crate::about::version_line());
+        return Ok(());
+    }
+    if cli.about {
+        println!("{}", crate::about::details().join("\n"));
+        return Ok(());
+    }
+
+    match cli.command {
+        // Worker Mode
+        Some(crate::cli::Command::Worker(args)) => {
+            worker::run_worker(args).await;
+            return Ok(());
+        }
+        Some(crate::cli::Command::Completions { shell }) => {
+            match crate::cli::completion_script(shell) {
+                Ok(script) => print!("{}", script),
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        // Configuration transfer runs without the interface
+        Some(crate::cli::Command::ExportConfig { file }) => {
+            config_transfer(
+                crate::config_archive::export(&file)
+                    .map(|()| format!("Configuration exported to {}", file.display())),
+            );
+            return Ok(());
+        }
+        Some(crate::cli::Command::ImportConfig { file }) => {
+            config_transfer(
+                crate::config_archive::import(&file)
+                    .map(|()| format!("Configuration imported from {}", file.display())),
+            );
+            return Ok(());
+        }
+        None => {}
+    }
 
-    // Worker Mode
-    if args.iter().any(|a| a == "--worker") {
-        worker::run_worker(args).await;
-        return Ok(());
+    // Headless write, when there is a target to write to
+    if let Some(image) = cli.image_location()
+        && let Some(drive) = cli.target_drive()
+    {
+        let job = crate::headless::Job {
+            image,
+            drive: drive.to_string(),
+            yes: cli.yes,
+            json: cli.json,
+        };
+        std::process::exit(crate::headless::run(job).await);
     }
 
-    // Check for root (prevent running as root)
-    if nix::unistd::Uid::effective().is_root() {
-        eprintln!(
-            "Error: Please run as a normal user. The application will request privileges when needed."
-        );
-        std::process::exit(1);
+    // Read before the terminal is set up, which then goes through /dev/tty
+    let session = cli.resume.then(|| Session::receive(io::stdin().lock()));
+
+    // Check for root (prevent running as root), unless the user chose to
+    // restart elevated after a permission error
+    if crate::customization::is_root() {
+        if !cli.resume {
+            eprintln!(
+                "Error: Please run as a normal user. The application will request privileges when needed."
+            );
+            std::process::exit(1);
+        }
+        crate::customization::disable_saving();
     }
 
     // Setup terminal
@@ -623,89 +2460,59 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
+    // No config file yet, nor the config.json of older versions, means this
+    // is the first launch
+    let first_run = CustomizationOptions::config_path()
+        .is_some_and(|path| !path.exists() && !path.with_extension("json").exists());
+
     // Create App
     let mut app = App::new();
+    crate::theme::set(app.customization_options.theme);
+    crate::cache::set_cache_dir(
+        app.customization_options
+            .cache_dir
+            .as_ref()
+            .map(std::path::PathBuf::from),
+    );
+    app.station = app
+        .customization_options
+        .stats_file
+        .as_deref()
+        .map(crate::stats::Station::open);
+    if first_run && !cli.resume {
+        app.start_setup();
+    }
+    if let Some(fps) = cli.max_fps {
+        app.max_fps = fps;
+    }
+    app.icons = crate::icons::Icons::new(crate::icons::Protocol::detect(cli.graphics.as_deref()));
 
-    // Check for local image argument
-    for arg in args.iter().skip(1) {
-        if !arg.starts_with("--") {
-            // Assume this is an image path
-            let path = std::path::Path::new(arg);
-            let abs_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
-            let name = abs_path
-                .file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_else(|| "Custom Image".to_string());
-
-            let item = OsListItem {
-                name: name.clone(),
-                description: format!("Local Image: {}", abs_path.display()),
-                url: Some(abs_path.to_string_lossy().to_string()),
-                icon: None,
-                extract_size: None,
-                extract_sha256: None,
-                release_date: None,
-                subitems: Vec::new(),
-                // Defaults for missing fields
-                random: false,
-                image_download_size: None,
-                image_download_sha256: None,
-                init_format: None,
-                devices: Vec::new(),
-                capabilities: Vec::new(),
-                website: None,
-                tooltip: None,
-                architecture: None,
-                enable_rpi_connect: false,
-            };
-
-            app.selected_os = Some(item);
-            app.current_view = CurrentView::StorageSelection;
-            app.refresh_drives();
-            break;
+    if let Some(session) = session {
+        match session {
+            Ok(session) => app.resume(session),
+            Err(e) => app.error_message = Some(format!("{:#}", e)),
         }
     }
 
-    // Create a channel to communicate between the async fetch and the sync UI loop
-    let (tx, mut rx) = mpsc::channel::<AppMessage>(100);
+    // An image given on the command line skips the OS selection
+    if let Some(location) = cli.image_location() {
+        app.select_custom_image(&location);
+    }
 
-    // Spawn the fetch task
-    let tx_os = tx.clone();
-    tokio::spawn(async move {
-        // Try local file first
-        let local_path = "os_list_imagingutility_v4.json";
-        if let Ok(file) = std::fs::File::open(local_path) {
-            let reader = std::io::BufReader::new(file);
-            if let Ok(data) = serde_json::from_reader(reader) {
-                let _ = tx_os.send(AppMessage::OsListLoaded(Ok(data))).await;
-                return;
-            }
-        }
+    app.preselect = crate::preselect::Preselect {
+        device: cli.pi_model().map(str::to_string),
+        os: cli.os.clone(),
+        drive: cli.target_drive().map(str::to_string),
+    };
 
-        let client = Client::builder()
-            .user_agent("rpi-imager-tui/0.1")
-            .build()
-            .unwrap_or_else(|_| Client::new());
+    if app.customization_options.sealed_secrets.is_some() && !cli.resume {
+        app.start_unlock();
+    }
 
-        let url = "https://downloads.raspberrypi.com/os_list_imagingutility_v4.json";
-        match client.get(url).send().await {
-            Ok(resp) => match resp.json::<OsList>().await {
-                Ok(data) => {
-                    let _ = tx_os.send(AppMessage::OsListLoaded(Ok(data))).await;
-                }
-                Err(e) => {
-                    let _ = tx_os
-                        .send(AppMessage::OsListLoaded(Err(e.to_string())))
-                        .await;
-                }
-            },
-            Err(e) => {
-                let _ = tx_os
-                    .send(AppMessage::OsListLoaded(Err(e.to_string())))
-                    .await;
-            }
-        }
-    });
+    // Create a channel to communicate between the async fetch and the sync UI loop
+    let (tx, mut rx) = mpsc::channel::<AppMessage>(100);
+
+    spawn_os_list_fetch(tx.clone(), app.customization_options.clone());
 
     // Run the application
     let res = run_app(&mut terminal, &mut app, &mut rx, tx).await;
@@ -723,18 +2530,125 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("{:?}", err);
     }
 
+    crate::workspace::cleanup_all();
+
+    if let Some((command, session)) = app.reexec.take() {
+        match session.hand_over(&command) {
+            Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+            Err(e) => eprintln!("Failed to restart with {}: {:#}", command[0], e),
+        }
+    }
+
     Ok(())
 }
 
+/// Loads the OS list, with the configured extra sources, in the background
+/// and reports it as [`AppMessage::OsListLoaded`].
+fn spawn_os_list_fetch(tx_os: mpsc::Sender<AppMessage>, http_options: CustomizationOptions) {
+    tokio::spawn(async move {
+        // Try local file first
+        let local_path = "os_list_imagingutility_v4.json";
+        if let Ok(json) = std::fs::read(local_path)
+            && let Ok(data) = crate::os_list::parse(&json)
+        {
+            let _ = tx_os.send(AppMessage::OsListLoaded(Ok(data))).await;
+            return;
+        }
+
+        let result = match crate::http::client(&http_options) {
+            Ok(client) => crate::os_list::fetch_all(&client, &http_options.os_sources).await,
+            Err(e) => Err(e),
+        };
+        let result = result.map(|(list, degraded)| {
+            if let Some(notice) = degraded {
+                let _ = tx_os.try_send(AppMessage::OsListDegraded(notice));
+            }
+            list
+        });
+        let _ = tx_os
+            .send(AppMessage::OsListLoaded(
+                result.map_err(|e| format!("{:#}", e)),
+            ))
+            .await;
+    });
+}
+
+/// The device of a worker job, opened through udisks2, if it can run
+/// unprivileged. rpiboot talks to USB directly and always needs root.
+#[cfg(unix)]
+fn udisks_device(args: &[String]) -> Option<std::os::fd::OwnedFd> {
+    if args.iter().any(|a| a == "--rpiboot") {
+        return None;
+    }
+    let device = args.iter().position(|a| a == "--device")?;
+    let fd = crate::udisks::open_device(args.get(device + 1)?).ok()?;
+    crate::udisks::inheritable(&fd).ok()?;
+    Some(fd)
+}
+
+fn spawn_elevated(args: &[String]) -> std::io::Result<tokio::process::Child> {
+    let mut cmd = Command::new("sudo");
+    cmd.args(args);
+    cmd.stdout(std::process::Stdio::piped());
+    cmd.stderr(std::process::Stdio::inherit()); // Allow prompt to show
+    cmd.stdin(std::process::Stdio::piped()); // Worker commands; sudo asks on the tty
+    match cmd.spawn() {
+        Ok(c) => Ok(c),
+        Err(e) => {
+            // Fallback to pkexec if sudo is missing or fails to spawn
+            let mut cmd = Command::new("pkexec");
+            cmd.args(args);
+            cmd.stdout(std::process::Stdio::piped());
+            cmd.stderr(std::process::Stdio::inherit());
+            cmd.stdin(std::process::Stdio::piped());
+            cmd.spawn().map_err(|_| e) // Return original error if fallback also fails
+        }
+    }
+}
+
 async fn run_app<B: Backend + std::io::Write>(
     terminal: &mut Terminal<B>,
     app: &mut App,
     rx: &mut mpsc::Receiver<AppMessage>,
     tx: mpsc::Sender<AppMessage>,
 ) -> io::Result<()> {
+    // Redraw only after something changed, and at most max_fps times a
+    // second, so slow remote terminals aren't flooded with frames.
+    let frame_interval = std::time::Duration::from_secs_f64(1.0 / app.max_fps.max(1) as f64);
+    let mut needs_redraw = true;
+    let mut last_draw: Option<std::time::Instant> = None;
+
     loop {
+        // Whatever the last key changed in the Customization view can be undone
+        app.customization_history.observe(
+            &app.customization_options,
+            app.current_view == CurrentView::Customization,
+        );
+
+        // Category entries and checksums for the highlighted OS entry
+        app.fetch_metadata(&tx);
+
+        app.start_ssh_import(&tx);
+        if app.ssh_import_started.is_some() {
+            // Keeps the spinner turning
+            needs_redraw = true;
+        }
+
         // Handle Authentication / Worker Spawning
         if let Some(args) = app.worker_args.take() {
+            needs_redraw = true;
+            let job = app.worker_job.take();
+            // Inspect/browse run in the background of the view that requested them,
+            // queued jobs in the background of whatever the user is doing
+            let background_view = if job.is_some() {
+                Some(app.current_view)
+            } else if args.iter().any(|a| a == "--inspect" || a == "--rpiboot") {
+                Some(CurrentView::StorageSelection)
+            } else if args.iter().any(|a| a == "--browse") {
+                Some(CurrentView::Finished)
+            } else {
+                None
+            };
             // Suspend UI
             disable_raw_mode()?;
             execute!(
@@ -745,24 +2659,24 @@ async fn run_app<B: Backend + std::io::Write>(
             terminal.show_cursor()?;
 
             // Spawn Process
-            // We prioritize sudo for TUI/CLI usage as it is more standard for terminal environments.
-            let mut cmd = Command::new("sudo");
-            cmd.args(&args);
-            cmd.stdout(std::process::Stdio::piped());
-            cmd.stderr(std::process::Stdio::inherit()); // Allow prompt to show
-            cmd.stdin(std::process::Stdio::inherit()); // Allow input
-            let spawn_result = match cmd.spawn() {
-                Ok(c) => Ok(c),
-                Err(e) => {
-                    // Fallback to pkexec if sudo is missing or fails to spawn
-                    let mut cmd = Command::new("pkexec");
-                    cmd.args(&args);
+            // Without root if udisks2 lets us open the device, otherwise
+            // we prioritize sudo for TUI/CLI usage as it is more standard for terminal environments.
+            #[cfg(unix)]
+            let spawn_result = match udisks_device(&args) {
+                Some(fd) => {
+                    let mut cmd = Command::new(&args[0]);
+                    cmd.args(&args[1..])
+                        .arg("--device-fd")
+                        .arg(fd.as_raw_fd().to_string());
                     cmd.stdout(std::process::Stdio::piped());
                     cmd.stderr(std::process::Stdio::inherit());
-                    cmd.stdin(std::process::Stdio::inherit());
-                    cmd.spawn().map_err(|_| e) // Return original error if fallback also fails
+                    cmd.stdin(std::process::Stdio::piped()); // Worker commands
+                    cmd.spawn()
                 }
+                None => spawn_elevated(&args),
             };
+            #[cfg(not(unix))]
+            let spawn_result = spawn_elevated(&args);
 
             // Restore UI
             execute!(
@@ -771,108 +2685,306 @@ async fn run_app<B: Backend + std::io::Write>(
                 EnableMouseCapture
             )?;
             enable_raw_mode()?;
+            // The screen was left for the password prompt, so repaint all of it
+            terminal.clear()?;
+            app.icons.invalidate();
 
             match spawn_result {
                 Ok(mut child) => {
+                    if let Some(mut stdin) = child.stdin.take()
+                        && job.is_none()
+                    {
+                        let (commands, mut rx) = mpsc::unbounded_channel::<WorkerCommand>();
+                        tokio::spawn(async move {
+                            while let Some(command) = rx.recv().await {
+                                if stdin.write_all(command.to_line().as_bytes()).await.is_err() {
+                                    break;
+                                }
+                            }
+                        });
+                        app.worker_commands = Some(commands);
+                    }
                     if let Some(stdout) = child.stdout.take() {
-                        app.current_view = CurrentView::Writing;
-                        app.write_status = "Starting worker...".to_string();
+                        if let Some(view) = background_view {
+                            app.current_view = view;
+                        } else {
+                            app.current_view = CurrentView::Writing;
+                            app.write_status = "Starting worker...".to_string();
+                        }
 
+                        // Writes keep the machine from sleeping until the worker exits
+                        let inhibitor = if background_view.is_none() || job.is_some() {
+                            crate::power::Inhibitor::take("Writing an image to storage")
+                        } else {
+                            None
+                        };
                         let tx_clone = tx.clone();
+                        let message = move |event| match job {
+                            Some(index) => AppMessage::QueueJob(index, event),
+                            None => AppMessage::Worker(event),
+                        };
                         let handle = tokio::spawn(async move {
                             let mut reader = tokio::io::BufReader::new(stdout).lines();
                             while let Ok(Some(line)) = reader.next_line().await {
-                                if let Ok(msg) =
-                                    serde_json::from_str::<worker::WorkerMessage>(&line)
-                                {
-                                    let app_msg = match msg {
-                                        worker::WorkerMessage::Progress(p) => {
-                                            AppMessage::WriteProgress(p)
-                                        }
-                                        worker::WorkerMessage::VerifyProgress(p) => {
-                                            AppMessage::VerifyProgress(p)
-                                        }
-                                        worker::WorkerMessage::Status(s) => {
-                                            AppMessage::WriteStatus(s)
-                                        }
-                                        worker::WorkerMessage::Phase(p) => {
-                                            AppMessage::WritingPhase(match p.as_str() {
-                                                "Verifying" => WritingPhase::Verifying,
-                                                _ => WritingPhase::Writing,
-                                            })
-                                        }
-                                        worker::WorkerMessage::Error(e) => {
-                                            AppMessage::WriteError(e)
-                                        }
-                                        worker::WorkerMessage::Finished => {
-                                            AppMessage::WriteFinished
-                                        }
-                                    };
-                                    let _ = tx_clone.send(app_msg).await;
+                                if let Some(event) = ProgressEvent::parse(&line) {
+                                    let _ = tx_clone.send(message(event)).await;
                                 }
                             }
                             // Check exit status
-                            if let Ok(status) = child.wait().await {
-                                if !status.success() {
-                                    let _ = tx_clone
-                                        .send(AppMessage::WriteError(format!(
-                                            "Worker process exited with code {}",
-                                            status.code().unwrap_or(-1)
-                                        )))
-                                        .await;
-                                }
+                            if let Ok(status) = child.wait().await
+                                && !status.success()
+                            {
+                                let _ = tx_clone
+                                    .send(message(ProgressEvent::other_error(format!(
+                                        "Worker process exited with code {}",
+                                        status.code().unwrap_or(-1)
+                                    ))))
+                                    .await;
                             }
+                            drop(inhibitor);
                         });
-                        app.abort_handle = Some(handle.abort_handle()); // Note: this abort handle kills the reader, not the child.
-                        app.write_task = Some(handle);
+                        if job.is_none() {
+                            app.abort_handle = Some(handle.abort_handle()); // Note: this abort handle kills the reader, not the child.
+                            app.write_task = Some(handle);
+                        }
+                    } else if let Some(index) = job {
+                        app.queue.update(
+                            index,
+                            ProgressEvent::other_error(
+                                "Failed to capture stdout of worker".to_string(),
+                            ),
+                        );
                     } else {
                         app.error_message = Some("Failed to capture stdout of worker".to_string());
                         app.current_view = CurrentView::StorageSelection;
                     }
                 }
                 Err(e) => {
-                    app.error_message = Some(format!("Failed to spawn privileged process: {}", e));
-                    app.current_view = CurrentView::StorageSelection;
+                    let err = format!("Failed to spawn privileged process: {}", e);
+                    if let Some(index) = job {
+                        app.queue.update(index, ProgressEvent::other_error(err));
+                    } else {
+                        app.error_message = Some(err);
+                        app.current_view = CurrentView::StorageSelection;
+                    }
                 }
             }
         }
 
         // Check for updates from fetch task or write task
-        match rx.try_recv() {
+        let message = rx.try_recv();
+        if let (Ok(AppMessage::Worker(event) | AppMessage::QueueJob(_, event)), Some(station)) =
+            (&message, &mut app.station)
+        {
+            station.observe(event);
+        }
+        if let Ok(AppMessage::Worker(event) | AppMessage::QueueJob(_, event)) = &message {
+            app.history.observe(event);
+        }
+        if message.is_ok() {
+            needs_redraw = true;
+        }
+        match message {
             Ok(AppMessage::OsListLoaded(result)) => match result {
-                Ok(data) => {
+                Ok(mut data) => {
+                    data.os_list.push(OsListItem::custom_entry());
                     app.os_list = Some(data);
+                    app.filter_os_list();
                     app.is_loading = false;
                     app.list_state.select(Some(0));
                     app.device_list_state.select(Some(0));
+                    app.apply_preselect(tx.clone());
                 }
                 Err(msg) => {
                     app.error_message = Some(msg);
                     app.is_loading = false;
                 }
             },
-            Ok(AppMessage::WriteProgress(p)) => {
-                app.write_progress = p;
+            Ok(AppMessage::OsListDegraded(notice)) => {
+                app.os_list_notice = Some(notice);
+            }
+            Ok(AppMessage::Worker(ProgressEvent::Progress { phase, fraction })) => {
+                app.set_progress(phase, fraction);
+            }
+            Ok(AppMessage::Worker(ProgressEvent::Source(host))) => {
+                app.download_host = Some(host);
             }
-            Ok(AppMessage::VerifyProgress(p)) => {
-                app.verify_progress = p;
+            Ok(AppMessage::Worker(ProgressEvent::SlowDownload)) => {
+                app.slow_download = true;
             }
-            Ok(AppMessage::WritingPhase(phase)) => {
-                app.write_phase = Some(phase);
+            Ok(AppMessage::Worker(ProgressEvent::Synced(fraction))) => {
+                app.synced_progress = fraction;
             }
-            Ok(AppMessage::WriteStatus(msg)) => {
+            Ok(AppMessage::Worker(ProgressEvent::Status(msg))) => {
                 app.write_status = msg;
             }
-            Ok(AppMessage::WriteFinished) => {
-                app.write_progress = 100.0;
-                app.verify_progress = 100.0;
+            Ok(AppMessage::Worker(ProgressEvent::VerificationSkipped)) => {
+                app.verification_skipped = true;
+            }
+            Ok(AppMessage::Worker(ProgressEvent::Ejected)) => {
+                app.ejected = true;
+            }
+            Ok(AppMessage::Worker(ProgressEvent::Finished)) => {
+                app.worker_commands = None;
+                app.write_paused = false;
+                app.write_progress = 1.0;
+                app.sync_progress = 1.0;
+                app.verify_progress = 1.0;
+                app.write_status = "Finished".to_string();
+                app.current_view = CurrentView::Finished;
+                app.write_phase = None;
+                // A successful retry follows a write that was already recorded
+                if app.customization_error.take().is_none() {
+                    app.record_flash();
+                    app.send_telemetry();
+                }
+            }
+            Ok(AppMessage::Worker(ProgressEvent::CustomizationFailed(err))) => {
+                app.worker_commands = None;
+                app.write_paused = false;
+                app.write_progress = 1.0;
+                app.sync_progress = 1.0;
+                app.verify_progress = 1.0;
                 app.write_status = "Finished".to_string();
                 app.current_view = CurrentView::Finished;
                 app.write_phase = None;
+                if app.customization_error.replace(err).is_none() {
+                    app.record_flash();
+                    app.send_telemetry();
+                }
+            }
+            Ok(AppMessage::Worker(ProgressEvent::Summary(summary))) => {
+                if let Some((drive, os)) = app.timed_write.take() {
+                    record_write_speed(&mut app.flash_db, &drive, &os, &summary);
+                }
+                // Follows Finished, which recorded the write
+                if let Some(fingerprint) = summary.fingerprint
+                    && let Some(serial) = app.selected_drive.as_ref().and_then(|d| d.serial.clone())
+                {
+                    app.flash_db.set_fingerprint(&serial, fingerprint.clone());
+                    app.card_fingerprint = Some(fingerprint);
+                }
+            }
+            Ok(AppMessage::Worker(ProgressEvent::Checklist(checks))) => {
+                if let Some(failure) = crate::first_boot::failures(&checks) {
+                    let list: Vec<String> = checks
+                        .iter()
+                        .map(|c| format!("[{}] {}", if c.passed { "x" } else { " " }, c.label))
+                        .collect();
+                    let _ = crate::error_log::append(&format!("{}\n{}", failure, list.join("\n")));
+                }
+                app.boot_checks = checks;
+            }
+            Ok(AppMessage::Worker(ProgressEvent::BootChanges(changes))) => {
+                let device = app.selected_drive.as_ref().map_or("", |d| d.name.as_str());
+                let diffs: Vec<String> = changes.iter().map(|c| c.to_text()).collect();
+                let _ =
+                    crate::error_log::append_changes(&format!("{}\n{}", device, diffs.join("")));
+                app.boot_changes = changes;
+            }
+            Ok(AppMessage::Worker(ProgressEvent::Customization(options))) => {
+                app.apply_inspected_customization(*options);
+            }
+            // The worker prints it as part of the Summary
+            Ok(AppMessage::Worker(ProgressEvent::Fingerprint(_))) => {}
+            Ok(AppMessage::Worker(ProgressEvent::EmmcReady)) => {
+                app.write_status.clear();
+                app.refresh_drives();
+            }
+            Ok(AppMessage::Worker(ProgressEvent::BootFiles(files))) => {
+                app.boot_files = files;
+                app.boot_files_state.select(Some(0));
+                app.current_view = CurrentView::BootBrowser;
+            }
+            Ok(AppMessage::QueueJob(index, event)) => {
+                if let ProgressEvent::Summary(summary) = &event
+                    && let Some(job) = app.queue.jobs.get(index)
+                    && !job.args.iter().any(|a| a == "--delta")
+                {
+                    record_write_speed(&mut app.flash_db, &job.drive, &job.os, summary);
+                }
+                if let ProgressEvent::Summary(summary) = &event
+                    && let Some(fingerprint) = &summary.fingerprint
+                    && let Some(serial) = app
+                        .queue
+                        .jobs
+                        .get(index)
+                        .and_then(|job| job.drive.serial.clone())
+                {
+                    app.flash_db.set_fingerprint(&serial, fingerprint.clone());
+                }
+                if app.queue.update(index, event) {
+                    app.finish_queue_job(index);
+                }
+            }
+            Ok(AppMessage::Prefetch(result)) => {
+                if app.prefetch_task.is_some() {
+                    app.prefetch = Some(match result {
+                        Ok((done, Some(total))) if done >= total => "Prefetch: done".to_string(),
+                        Ok((done, Some(total))) => format!(
+                            "Prefetch: {:.1}%",
+                            done as f64 / total.max(1) as f64 * 100.0
+                        ),
+                        Ok((done, None)) => format!("Prefetch: {} MB", done / (1024 * 1024)),
+                        Err(_) => "Prefetch: failed".to_string(),
+                    });
+                }
+            }
+            Ok(AppMessage::IconLoaded(url, result)) => app.icons.loaded(url, result),
+            Ok(AppMessage::Metadata(fetched)) => {
+                let opens = matches!(&fetched, crate::metadata::Fetched::Children(url, _)
+                    if app.open_on_load.as_ref() == Some(url));
+                app.metadata.finished(fetched);
+                if opens {
+                    app.open_on_load = None;
+                    app.select(tx.clone());
+                }
+            }
+            Ok(AppMessage::SshKeysImported(result)) => {
+                app.ssh_import_started = None;
+                match result {
+                    Ok(keys) => {
+                        let options = &mut app.customization_options;
+                        let (merged, added) =
+                            crate::ssh_import::append(&options.ssh_public_keys, &keys);
+                        options.ssh_public_keys = merged;
+                        options.ssh_enabled = true;
+                        app.save_options();
+                        app.ssh_import_status = Some(format!("{} of {} added", added, keys.len()));
+                    }
+                    Err(e) => app.error_message = Some(e),
+                }
+            }
+            Ok(AppMessage::HostReachable(result)) => {
+                if result.is_err() || app.customization_options.bootstrap_command.is_none() {
+                    app.reachability_task = None;
+                }
+                app.reachability = Some(result.unwrap_or_else(|e| e));
+            }
+            Ok(AppMessage::BootstrapFinished(result)) => {
+                app.reachability_task = None;
+                app.reachability = Some(result.unwrap_or_else(|e| e));
             }
-            Ok(AppMessage::WriteError(err)) => {
-                app.error_message = Some(err);
-                app.current_view = CurrentView::StorageSelection;
+            // abort_writing() already said so
+            Ok(AppMessage::Worker(ProgressEvent::Error {
+                kind: ErrorKind::Cancelled,
+                ..
+            })) => {
+                app.worker_commands = None;
+                app.write_paused = false;
+            }
+            Ok(AppMessage::Worker(ProgressEvent::Error { kind, message })) => {
+                app.worker_commands = None;
+                app.write_paused = false;
+                app.permission_error = crate::privileges::is_permission_error(&message);
+                app.error_message = Some(message);
+                app.error_kind = Some(kind);
+                // A failed browse or customization retry leaves the finished write intact
+                if app.customization_error.is_some() {
+                    app.current_view = CurrentView::Finished;
+                } else if app.current_view != CurrentView::Finished {
+                    app.current_view = CurrentView::StorageSelection;
+                }
             }
             Err(mpsc::error::TryRecvError::Empty) => {
                 // No messages
@@ -886,230 +2998,552 @@ async fn run_app<B: Backend + std::io::Write>(
             }
         }
 
-        terminal.draw(|f| ui(f, app))?;
+        if needs_redraw && last_draw.is_none_or(|t| t.elapsed() >= frame_interval) {
+            terminal.draw(|f| ui(f, app))?;
+            app.icons.draw(terminal.backend_mut())?;
+            needs_redraw = false;
+            last_draw = Some(std::time::Instant::now());
+
+            for url in app.icons.take_missing() {
+                let tx = tx.clone();
+                let client = crate::http::client(&app.customization_options);
+                tokio::spawn(async move {
+                    let result = match client {
+                        Ok(client) => crate::icons::fetch(&client, &url).await,
+                        Err(e) => Err(e),
+                    };
+                    let _ = tx
+                        .send(AppMessage::IconLoaded(
+                            url,
+                            result.map_err(|e| format!("{:#}", e)),
+                        ))
+                        .await;
+                });
+            }
+        }
 
         // Poll for events
         // We use a timeout to ensure we keep checking the channel if no keys are pressed
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    if app.error_message.is_some() {
-                        app.error_message = None;
-                        continue;
+        let event = if event::poll(std::time::Duration::from_millis(100).min(frame_interval))? {
+            Some(event::read()?)
+        } else {
+            None
+        };
+        // Any input (including resizes) may change what's on screen
+        if event.is_some() {
+            needs_redraw = true;
+        }
+        if let Some(Event::Resize(..)) = event {
+            // ratatui clears the screen on resize, taking the icons with it
+            app.icons.invalidate();
+        }
+        if let Some(Event::Mouse(mouse)) = event
+            && mouse.kind == MouseEventKind::Down(MouseButton::Left)
+            && app.error_message.is_none()
+            && app.popup.is_none()
+            && !app.show_about
+            && let Some(step) = app.sidebar_step_at(mouse.column, mouse.row)
+        {
+            app.jump_to_step(step);
+        }
+        if let Some(Event::Key(key)) = event
+            && key.kind == KeyEventKind::Press
+        {
+            if app.error_message.is_some() {
+                match key.code {
+                    KeyCode::Up => app.error_scroll = app.error_scroll.saturating_sub(1),
+                    KeyCode::Down => app.error_scroll = app.error_scroll.saturating_add(1),
+                    KeyCode::PageUp => app.error_scroll = app.error_scroll.saturating_sub(10),
+                    KeyCode::PageDown => app.error_scroll = app.error_scroll.saturating_add(10),
+                    KeyCode::Char('y') => app.copy_error(),
+                    KeyCode::Char('l') => app.save_error(),
+                    KeyCode::Char('e') if app.permission_error => {
+                        app.dismiss_error();
+                        app.restart_elevated();
+                    }
+                    KeyCode::Char('r') if app.error_kind.is_some_and(ErrorKind::is_retryable) => {
+                        app.dismiss_error();
+                        app.start_writing(tx.clone());
                     }
+                    KeyCode::Char('d') if app.offers(crate::troubleshoot::Action::Rescan) => {
+                        app.dismiss_error();
+                        app.refresh_drives();
+                    }
+                    _ => app.dismiss_error(),
+                }
+                continue;
+            }
+
+            if app.show_about {
+                app.show_about = false;
+                continue;
+            }
+
+            if app.popup.is_some() {
+                match app.picker.handle_key(key.code) {
+                    PickerEvent::Selected(selection) => app.popup_select(selection),
+                    PickerEvent::Cancelled => app.popup = None,
+                    PickerEvent::Pending => {}
+                }
+                continue;
+            }
 
-                    if app.popup.is_some() {
+            if let KeyCode::Char(digit @ '1'..='6') = key.code
+                && app.can_jump_to_step()
+            {
+                app.jump_to_step(digit as usize - '1' as usize);
+                continue;
+            }
+
+            match app.current_view {
+                CurrentView::Unlock => match app.customization_ui.input.handle_key(key.code) {
+                    InputEvent::Submitted(passphrase) => app.unlock(passphrase),
+                    InputEvent::Cancelled => {
+                        // Carry on without the remembered passwords, or
+                        // back to the presets without the preset
+                        app.customization_ui.input_mode = InputMode::Navigation;
+                        app.unlock_error = None;
+                        app.unlock_preset = None;
+                        app.current_view = app.unlock_return;
+                    }
+                    InputEvent::Pending => {}
+                },
+                CurrentView::Setup => {
+                    if app.customization_ui.input_mode == InputMode::Editing {
+                        match app.customization_ui.input.handle_key(key.code) {
+                            InputEvent::Submitted(value) => {
+                                app.apply_setup_edit(value);
+                                app.customization_ui.input_mode = InputMode::Navigation;
+                            }
+                            InputEvent::Cancelled => {
+                                app.customization_ui.input_mode = InputMode::Navigation;
+                            }
+                            InputEvent::Pending => {}
+                        }
+                    } else {
                         match key.code {
-                            KeyCode::Esc => app.popup = None,
-                            KeyCode::Enter => app.popup_select(),
-                            KeyCode::Up => app.popup_previous(),
-                            KeyCode::Down => app.popup_next(),
-                            KeyCode::Char(c) => {
-                                app.popup_filter.push(c);
-                                app.update_popup_items();
+                            KeyCode::Char('q') => app.should_quit = true,
+                            KeyCode::Down => {
+                                let i = app.setup_state.selected().map_or(0, |i| (i + 1) % 5);
+                                app.setup_state.select(Some(i));
                             }
-                            KeyCode::Backspace => {
-                                app.popup_filter.pop();
-                                app.update_popup_items();
+                            KeyCode::Up => {
+                                let i = app.setup_state.selected().map_or(0, |i| (i + 4) % 5);
+                                app.setup_state.select(Some(i));
                             }
+                            KeyCode::Enter | KeyCode::Char(' ') => app.handle_setup_enter(),
+                            KeyCode::Esc => app.finish_setup(),
                             _ => {}
                         }
-                        continue;
                     }
-
-                    match app.current_view {
-                        CurrentView::DeviceSelection => match key.code {
-                            KeyCode::Char('q') => app.should_quit = true,
-                            KeyCode::Down => app.next_device(),
-                            KeyCode::Up => app.previous_device(),
-                            KeyCode::Enter => app.select_device(),
-                            _ => {}
-                        },
-                        CurrentView::OsSelection => match key.code {
-                            KeyCode::Char('q') => app.should_quit = true,
-                            KeyCode::Esc => {
-                                if !app.navigation_stack.is_empty() {
-                                    app.back();
-                                } else {
-                                    // Go back to device selection
-                                    app.current_view = CurrentView::DeviceSelection;
-                                    app.selected_os = None;
-                                    app.breadcrumbs.clear();
-                                }
+                }
+                CurrentView::DeviceSelection => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Char('?') => app.show_about = true,
+                    KeyCode::Down => app.next_device(),
+                    KeyCode::Up => app.previous_device(),
+                    KeyCode::Enter => app.select_device(),
+                    KeyCode::Char('a') => app.autodetect_device(),
+                    KeyCode::Char('u') if !app.queue.jobs.is_empty() => app.open_queue(),
+                    KeyCode::Char('r') => app.open_repositories(),
+                    KeyCode::Char('c') => app.open_cache(),
+                    KeyCode::Char('h') => app.open_history(),
+                    _ => {}
+                },
+                CurrentView::OsSelection
+                    if app.customization_ui.input_mode == InputMode::Editing =>
+                {
+                    match app.customization_ui.input.handle_key(key.code) {
+                        InputEvent::Submitted(path) => {
+                            app.customization_ui.input_mode = InputMode::Navigation;
+                            app.select_custom_image(path.trim());
+                        }
+                        InputEvent::Cancelled => {
+                            app.customization_ui.input_mode = InputMode::Navigation;
+                        }
+                        InputEvent::Pending => {}
+                    }
+                }
+                CurrentView::OsSelection => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Char('?') => app.show_about = true,
+                    KeyCode::Esc => {
+                        if !app.navigation_stack.is_empty() {
+                            app.back();
+                        } else {
+                            // Go back to device selection
+                            app.current_view = CurrentView::DeviceSelection;
+                            app.selected_os = None;
+                            app.breadcrumbs.clear();
+                        }
+                    }
+                    KeyCode::Down => app.next(),
+                    KeyCode::Up => app.previous(),
+                    KeyCode::Enter => app.select(tx.clone()),
+                    KeyCode::Left | KeyCode::Backspace => app.back(),
+                    _ => {}
+                },
+                CurrentView::StorageSelection => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Esc | KeyCode::Left | KeyCode::Backspace => {
+                        app.current_view = CurrentView::OsSelection;
+                        app.drive_list.clear();
+                        app.selected_os = None;
+                    }
+                    KeyCode::Down => app.next_drive(),
+                    KeyCode::Up => app.previous_drive(),
+                    KeyCode::Enter => app.select_drive(),
+                    KeyCode::Char('r') => app.refresh_drives(),
+                    KeyCode::Char('i') => app.inspect_drive(),
+                    KeyCode::Char('c') if !app.usbboot_devices.is_empty() => {
+                        app.provision_compute_module()
+                    }
+                    KeyCode::Char('o') => {
+                        app.current_view = CurrentView::Customization;
+                        app.customization_ui.current_tab = CustomizationTab::General;
+                        app.customization_ui.selected_field_index = 0;
+                    }
+                    _ => {}
+                },
+                CurrentView::Customization => {
+                    if app.customization_ui.input_mode == InputMode::Navigation
+                        && key.modifiers.contains(KeyModifiers::CONTROL)
+                        && matches!(key.code, KeyCode::Char('z') | KeyCode::Char('y'))
+                    {
+                        app.undo_customization(key.code == KeyCode::Char('y'));
+                    } else if app.customization_ui.input_mode == InputMode::Editing {
+                        match app.customization_ui.input.handle_key(key.code) {
+                            InputEvent::Submitted(value) => {
+                                app.apply_customization_edit(value);
+                                app.customization_ui.input_mode = InputMode::Navigation;
                             }
-                            KeyCode::Down => app.next(),
-                            KeyCode::Up => app.previous(),
-                            KeyCode::Enter => app.select(),
-                            KeyCode::Left | KeyCode::Backspace => app.back(),
-                            _ => {}
-                        },
-                        CurrentView::StorageSelection => match key.code {
-                            KeyCode::Char('q') => app.should_quit = true,
-                            KeyCode::Esc | KeyCode::Left | KeyCode::Backspace => {
-                                app.current_view = CurrentView::OsSelection;
-                                app.drive_list.clear();
-                                app.selected_os = None;
+                            InputEvent::Cancelled => {
+                                app.customization_ui.input_mode = InputMode::Navigation;
                             }
-                            KeyCode::Down => app.next_drive(),
-                            KeyCode::Up => app.previous_drive(),
-                            KeyCode::Enter => app.select_drive(),
-                            KeyCode::Char('r') => app.refresh_drives(),
-                            KeyCode::Char('o') => {
-                                app.current_view = CurrentView::Customization;
-                                app.customization_ui.current_tab = CustomizationTab::General;
-                                app.customization_ui.selected_field_index = 0;
+                            InputEvent::Pending => {}
+                        }
+                    } else if app.in_customization_submenu {
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Left => {
+                                app.in_customization_submenu = false;
+                                app.customization_sub_menu_state.select(None);
                             }
-                            _ => {}
-                        },
-                        CurrentView::Customization => {
-                            if app.customization_ui.input_mode == InputMode::Editing {
-                                match key.code {
-                                    KeyCode::Enter => {
-                                        app.apply_customization_edit();
-                                        app.customization_ui.input_mode = InputMode::Navigation;
-                                    }
-                                    KeyCode::Esc => {
-                                        app.customization_ui.input_mode = InputMode::Navigation;
-                                        app.customization_ui.input_buffer.clear();
-                                    }
-                                    KeyCode::Backspace => {
-                                        app.customization_ui.input_buffer.pop();
-                                    }
-                                    KeyCode::Char(c) => {
-                                        app.customization_ui.input_buffer.push(c);
-                                    }
-                                    _ => {}
-                                }
-                            } else if app.in_customization_submenu {
-                                match key.code {
-                                    KeyCode::Esc | KeyCode::Left => {
-                                        app.in_customization_submenu = false;
-                                        app.customization_sub_menu_state.select(None);
-                                    }
-                                    KeyCode::Down => {
-                                        let max_idx =
-                                            app.customization_sub_item_count().saturating_sub(1);
-                                        let i = match app.customization_sub_menu_state.selected() {
-                                            Some(i) => {
-                                                if i >= max_idx {
-                                                    0
-                                                } else {
-                                                    i + 1
-                                                }
-                                            }
-                                            None => 0,
-                                        };
-                                        app.customization_sub_menu_state.select(Some(i));
-                                    }
-                                    KeyCode::Up => {
-                                        let max_idx =
-                                            app.customization_sub_item_count().saturating_sub(1);
-                                        let i = match app.customization_sub_menu_state.selected() {
-                                            Some(i) => {
-                                                if i == 0 {
-                                                    max_idx
-                                                } else {
-                                                    i - 1
-                                                }
-                                            }
-                                            None => 0,
-                                        };
-                                        app.customization_sub_menu_state.select(Some(i));
-                                    }
-                                    KeyCode::Enter | KeyCode::Char(' ') => {
-                                        app.handle_customization_enter();
-                                    }
-                                    _ => {}
-                                }
-                            } else {
-                                match key.code {
-                                    KeyCode::Char('q') => app.should_quit = true,
-                                    KeyCode::Esc => {
-                                        app.current_view = CurrentView::StorageSelection;
-                                    }
-                                    KeyCode::Down => {
-                                        let i = match app.customization_menu_state.selected() {
-                                            Some(i) => {
-                                                if i >= 6 {
-                                                    0
-                                                } else {
-                                                    i + 1
-                                                }
-                                            }
-                                            None => 0,
-                                        };
-                                        app.customization_menu_state.select(Some(i));
-                                    }
-                                    KeyCode::Up => {
-                                        let i = match app.customization_menu_state.selected() {
-                                            Some(i) => {
-                                                if i == 0 {
-                                                    6
-                                                } else {
-                                                    i - 1
-                                                }
-                                            }
-                                            None => 0,
-                                        };
-                                        app.customization_menu_state.select(Some(i));
+                            KeyCode::Down => {
+                                let max_idx = app.customization_sub_item_count().saturating_sub(1);
+                                let i = match app.customization_sub_menu_state.selected() {
+                                    Some(i) => {
+                                        if i >= max_idx {
+                                            0
+                                        } else {
+                                            i + 1
+                                        }
                                     }
-                                    KeyCode::Enter | KeyCode::Right => {
-                                        if let Some(6) = app.customization_menu_state.selected() {
-                                            // NEXT selected
-                                            app.current_view = CurrentView::WriteConfirmation;
+                                    None => 0,
+                                };
+                                app.customization_sub_menu_state.select(Some(i));
+                            }
+                            KeyCode::Up => {
+                                let max_idx = app.customization_sub_item_count().saturating_sub(1);
+                                let i = match app.customization_sub_menu_state.selected() {
+                                    Some(i) => {
+                                        if i == 0 {
+                                            max_idx
                                         } else {
-                                            app.in_customization_submenu = true;
-                                            app.customization_sub_menu_state.select(Some(0));
+                                            i - 1
                                         }
                                     }
-                                    _ => {}
-                                }
+                                    None => 0,
+                                };
+                                app.customization_sub_menu_state.select(Some(i));
+                            }
+                            KeyCode::Enter | KeyCode::Char(' ') => {
+                                app.handle_customization_enter();
                             }
+                            KeyCode::Char('d') => app.reset_menu_section(
+                                app.customization_menu_state.selected().unwrap_or(0),
+                            ),
+                            _ => {}
                         }
-                        CurrentView::WriteConfirmation => match key.code {
+                    } else {
+                        match key.code {
                             KeyCode::Char('q') => app.should_quit = true,
                             KeyCode::Esc => {
                                 app.current_view = CurrentView::StorageSelection;
-                                app.selected_drive = None;
                             }
-                            KeyCode::Char('y') | KeyCode::Enter => app.start_writing(tx.clone()),
-                            KeyCode::Char('n') => {
-                                app.current_view = CurrentView::StorageSelection;
-                                app.selected_drive = None;
+                            KeyCode::Down => {
+                                let i = match app.customization_menu_state.selected() {
+                                    Some(i) => {
+                                        if i >= 8 {
+                                            0
+                                        } else {
+                                            i + 1
+                                        }
+                                    }
+                                    None => 0,
+                                };
+                                app.customization_menu_state.select(Some(i));
+                            }
+                            KeyCode::Up => {
+                                let i = match app.customization_menu_state.selected() {
+                                    Some(i) => {
+                                        if i == 0 {
+                                            8
+                                        } else {
+                                            i - 1
+                                        }
+                                    }
+                                    None => 0,
+                                };
+                                app.customization_menu_state.select(Some(i));
+                            }
+                            KeyCode::Enter | KeyCode::Right => {
+                                let selected = app.customization_menu_state.selected();
+                                if let Some(8) = selected {
+                                    // NEXT selected
+                                    app.current_view = CurrentView::WriteConfirmation;
+                                } else if app.menu_section_supported(selected.unwrap_or(0)) {
+                                    app.in_customization_submenu = true;
+                                    app.customization_sub_menu_state.select(Some(0));
+                                }
                             }
+                            KeyCode::Char('d') => app.reset_menu_section(
+                                app.customization_menu_state.selected().unwrap_or(0),
+                            ),
+                            KeyCode::Char('p') => app.open_presets(CurrentView::Customization),
+                            KeyCode::Char('s') => app.save_as_default(),
                             _ => {}
-                        },
-                        CurrentView::Writing => {
-                            if key.code == KeyCode::Esc {
-                                app.current_view = CurrentView::AbortConfirmation;
+                        }
+                    }
+                }
+                CurrentView::WriteConfirmation => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Esc => {
+                        app.current_view = CurrentView::StorageSelection;
+                        app.selected_drive = None;
+                    }
+                    KeyCode::Char('y') | KeyCode::Enter => app.confirm_write(tx.clone()),
+                    KeyCode::Char('s') if app.can_skip_write() => app.skip_writing(),
+                    KeyCode::Char('u') => app.queue_write(),
+                    KeyCode::Char('v')
+                        if app.customization_options.verification == Verification::Ask =>
+                    {
+                        app.verify_choice = Some(!app.verify_write());
+                    }
+                    KeyCode::Char('t') => app.toggle_tee_file(),
+                    KeyCode::Char('n') => {
+                        app.current_view = CurrentView::StorageSelection;
+                        app.selected_drive = None;
+                    }
+                    _ => {}
+                },
+                CurrentView::Writing => match key.code {
+                    KeyCode::Esc => app.current_view = CurrentView::AbortConfirmation,
+                    KeyCode::Char('p') if app.worker_commands.is_some() => app.toggle_pause(),
+                    KeyCode::Char('m') if app.slow_download => app.switch_mirror(tx.clone()),
+                    _ => {}
+                },
+                CurrentView::AbortConfirmation => match key.code {
+                    KeyCode::Char('y') | KeyCode::Enter => app.confirm_abort(),
+                    KeyCode::Char('n') | KeyCode::Esc => {
+                        app.current_view = CurrentView::Writing;
+                    }
+                    _ => {}
+                },
+                CurrentView::Finished => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => {
+                        app.reset_wizard();
+                        app.qr_code = None;
+                        app.customization_error = None;
+                        app.stop_waiting_for_device();
+                    }
+                    KeyCode::Char('r') if app.customization_error.is_some() => {
+                        app.retry_customization()
+                    }
+                    KeyCode::Char('b') if app.write_status != "Skipped" => {
+                        app.browse_boot_partition()
+                    }
+                    KeyCode::Char('s') => app.toggle_qr_code(QrKind::Ssh),
+                    KeyCode::Char('w') => app.toggle_qr_code(QrKind::Wifi),
+                    KeyCode::Char('c') if app.write_status != "Aborted" => {
+                        app.wait_for_device(tx.clone())
+                    }
+                    _ => {}
+                },
+                CurrentView::BootloaderConfig => {
+                    if app.customization_ui.input_mode == InputMode::Editing {
+                        match app.customization_ui.input.handle_key(key.code) {
+                            InputEvent::Submitted(value) => {
+                                app.apply_bootloader_edit(value);
+                                app.customization_ui.input_mode = InputMode::Navigation;
+                            }
+                            InputEvent::Cancelled => {
+                                app.customization_ui.input_mode = InputMode::Navigation;
+                            }
+                            InputEvent::Pending => {}
+                        }
+                    } else {
+                        match key.code {
+                            KeyCode::Char('q') => app.should_quit = true,
+                            KeyCode::Esc => {
+                                app.current_view = CurrentView::StorageSelection;
+                                app.selected_drive = None;
                             }
-                        }
-                        CurrentView::AbortConfirmation => match key.code {
-                            KeyCode::Char('y') | KeyCode::Enter => app.abort_writing(),
-                            KeyCode::Char('n') | KeyCode::Esc => {
-                                app.current_view = CurrentView::Writing;
+                            KeyCode::Down => {
+                                let i = match app.bootloader_menu_state.selected() {
+                                    Some(i) if i + 1 < App::BOOTLOADER_MENU_LEN => i + 1,
+                                    _ => 0,
+                                };
+                                app.bootloader_menu_state.select(Some(i));
                             }
-                            _ => {}
-                        },
-                        CurrentView::Finished => match key.code {
-                            KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => {
-                                // Reset navigation but keep OS list
-                                app.current_view = CurrentView::DeviceSelection;
-                                app.selected_os = None;
-                                app.selected_drive = None;
-                                app.navigation_stack.clear();
-                                app.breadcrumbs.clear();
-                                app.list_state.select(Some(0));
-                                app.selected_device = None;
-                                app.device_list_state.select(Some(0));
+                            KeyCode::Up => {
+                                let i = match app.bootloader_menu_state.selected() {
+                                    Some(0) | None => App::BOOTLOADER_MENU_LEN - 1,
+                                    Some(i) => i - 1,
+                                };
+                                app.bootloader_menu_state.select(Some(i));
                             }
+                            KeyCode::Enter | KeyCode::Char(' ') => app.handle_bootloader_enter(),
                             _ => {}
-                        },
-                        CurrentView::Authenticating => {
-                            // Ignore all input while authenticating
                         }
                     }
                 }
+                CurrentView::Queue => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Esc => app.current_view = CurrentView::DeviceSelection,
+                    KeyCode::Down => {
+                        let i = match app.queue_state.selected() {
+                            Some(i) if i + 1 < app.queue.jobs.len() => i + 1,
+                            _ => 0,
+                        };
+                        app.queue_state.select(Some(i));
+                    }
+                    KeyCode::Up => {
+                        let i = match app.queue_state.selected() {
+                            Some(0) | None => app.queue.jobs.len().saturating_sub(1),
+                            Some(i) => i - 1,
+                        };
+                        app.queue_state.select(Some(i));
+                    }
+                    KeyCode::Enter | KeyCode::Char('s') => app.start_queue(),
+                    KeyCode::Char('x') => {
+                        app.queue.clear_completed();
+                        app.queue_state.select(Some(0));
+                    }
+                    _ => {}
+                },
+                CurrentView::Repositories => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Esc => app.close_repositories(tx.clone()),
+                    KeyCode::Down => {
+                        let count = crate::repositories::BUILTIN.len();
+                        let i = app
+                            .repositories_state
+                            .selected()
+                            .map_or(0, |i| (i + 1) % count);
+                        app.repositories_state.select(Some(i));
+                    }
+                    KeyCode::Up => {
+                        let count = crate::repositories::BUILTIN.len();
+                        let i = app
+                            .repositories_state
+                            .selected()
+                            .map_or(0, |i| (i + count - 1) % count);
+                        app.repositories_state.select(Some(i));
+                    }
+                    KeyCode::Enter | KeyCode::Char(' ') => app.toggle_repository(),
+                    _ => {}
+                },
+                CurrentView::Cache => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Esc => app.current_view = CurrentView::DeviceSelection,
+                    KeyCode::Down if !app.cached_images.is_empty() => {
+                        let count = app.cached_images.len();
+                        let i = app.cache_state.selected().map_or(0, |i| (i + 1) % count);
+                        app.cache_state.select(Some(i));
+                    }
+                    KeyCode::Up if !app.cached_images.is_empty() => {
+                        let count = app.cached_images.len();
+                        let i = app
+                            .cache_state
+                            .selected()
+                            .map_or(0, |i| (i + count - 1) % count);
+                        app.cache_state.select(Some(i));
+                    }
+                    KeyCode::Char('d') | KeyCode::Delete => app.remove_cached(false),
+                    KeyCode::Char('x') => app.remove_cached(true),
+                    _ => {}
+                },
+                CurrentView::Presets if app.customization_ui.input_mode == InputMode::Editing => {
+                    match app.customization_ui.input.handle_key(key.code) {
+                        InputEvent::Submitted(name) => {
+                            app.customization_ui.input_mode = InputMode::Navigation;
+                            app.name_preset(name);
+                        }
+                        InputEvent::Cancelled => {
+                            app.customization_ui.input_mode = InputMode::Navigation;
+                            app.preset_renaming = None;
+                        }
+                        InputEvent::Pending => {}
+                    }
+                }
+                CurrentView::Presets => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Esc => app.current_view = app.presets_return,
+                    KeyCode::Down => {
+                        let count = app.preset_names.len() + 1;
+                        let i = app.presets_state.selected().map_or(0, |i| (i + 1) % count);
+                        app.presets_state.select(Some(i));
+                    }
+                    KeyCode::Up => {
+                        let count = app.preset_names.len() + 1;
+                        let i = app
+                            .presets_state
+                            .selected()
+                            .map_or(0, |i| (i + count - 1) % count);
+                        app.presets_state.select(Some(i));
+                    }
+                    KeyCode::Enter => app.apply_preset(),
+                    KeyCode::Char('n') => app.start_naming_preset(None),
+                    KeyCode::Char('r') => {
+                        if let Some(name) = app.selected_preset().cloned() {
+                            app.start_naming_preset(Some(name));
+                        }
+                    }
+                    KeyCode::Char('d') | KeyCode::Delete => app.delete_preset(),
+                    _ => {}
+                },
+                CurrentView::History => match key.code {
+                    KeyCode::Char('q') => app.should_quit = true,
+                    KeyCode::Esc => app.current_view = CurrentView::DeviceSelection,
+                    KeyCode::Down if !app.history.jobs.is_empty() => {
+                        let count = app.history.jobs.len();
+                        let i = app.history_state.selected().map_or(0, |i| (i + 1) % count);
+                        app.history_state.select(Some(i));
+                    }
+                    KeyCode::Up if !app.history.jobs.is_empty() => {
+                        let count = app.history.jobs.len();
+                        let i = app
+                            .history_state
+                            .selected()
+                            .map_or(0, |i| (i + count - 1) % count);
+                        app.history_state.select(Some(i));
+                    }
+                    KeyCode::Enter => app.rerun_job(),
+                    KeyCode::Char('e') => app.export_history(true),
+                    KeyCode::Char('j') => app.export_history(false),
+                    _ => {}
+                },
+                CurrentView::BootBrowser => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc | KeyCode::Left => {
+                        app.current_view = CurrentView::Finished;
+                    }
+                    KeyCode::Down => app.next_boot_file(),
+                    KeyCode::Up => app.previous_boot_file(),
+                    _ => {}
+                },
+                CurrentView::Authenticating => {
+                    // Ignore all input while authenticating
+                }
             }
         }
 
@@ -1120,6 +3554,7 @@ async fn run_app<B: Backend + std::io::Write>(
 }
 
 fn ui(f: &mut Frame, app: &mut App) {
+    app.icons.clear_slots();
     let main_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
@@ -1143,21 +3578,42 @@ fn ui(f: &mut Frame, app: &mut App) {
         .style(
             Style::default()
                 .fg(Color::White)
-                .bg(Color::Magenta)
+                .bg(crate::theme::accent())
                 .add_modifier(Modifier::BOLD),
         )
         .alignment(ratatui::layout::Alignment::Center)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .style(Style::default().fg(Color::Magenta)),
+                .style(Style::default().fg(crate::theme::accent())),
         );
     f.render_widget(title, main_chunks[0]);
 
     // Footer: Description
+    let drive_info = match app
+        .drive_list_state
+        .selected()
+        .and_then(|i| app.drive_list.get(i))
+    {
+        Some(drive) => match app.drive_record(drive) {
+            Some(record) => format!("{} - {}", drive.description, record.summary()),
+            None => drive.description.clone(),
+        },
+        None => String::new(),
+    };
+    let writing_info = match &app.download_host {
+        Some(host) if app.slow_download && app.next_mirror_url().is_some() => format!(
+            "{}\nDownloading from {} is slow; press 'm' to continue from the next mirror.",
+            app.write_status, host
+        ),
+        Some(host) => format!("{}\nDownloading from {}", app.write_status, host),
+        None => app.write_status.clone(),
+    };
     let description = match app.current_view {
         CurrentView::DeviceSelection => {
-            if let Some(i) = app.device_list_state.selected() {
+            if let Some(hint) = &app.device_hint {
+                hint.as_str()
+            } else if let Some(i) = app.device_list_state.selected() {
                 app.get_devices()
                     .get(i)
                     .map(|d| d.description.as_str())
@@ -1167,7 +3623,9 @@ fn ui(f: &mut Frame, app: &mut App) {
             }
         }
         CurrentView::OsSelection => {
-            if let Some(i) = app.list_state.selected() {
+            if app.open_on_load.is_some() {
+                "Loading the entries of this category..."
+            } else if let Some(i) = app.list_state.selected() {
                 app.current_items()
                     .get(i)
                     .map(|os| os.description.as_str())
@@ -1176,27 +3634,56 @@ fn ui(f: &mut Frame, app: &mut App) {
                 ""
             }
         }
-        CurrentView::StorageSelection => {
-            if let Some(i) = app.drive_list_state.selected() {
-                app.drive_list
-                    .get(i)
-                    .map(|d| d.description.as_str())
-                    .unwrap_or("")
-            } else {
-                ""
-            }
+        CurrentView::StorageSelection => drive_info.as_str(),
+        CurrentView::Customization if app.differs_from_defaults() => {
+            "Edit image customization options. Changes apply to this run; press 's' to save them as the default."
         }
         CurrentView::Customization => "Edit image customization options.",
         CurrentView::WriteConfirmation => "Confirm write operation.",
         CurrentView::Authenticating => {
             "Authenticating... Please check terminal for password prompt."
         }
-        CurrentView::Writing => app.write_status.as_str(),
+        CurrentView::Writing if app.write_paused => "Paused. Press 'p' to resume.",
+        CurrentView::Writing => writing_info.as_str(),
         CurrentView::AbortConfirmation => match app.write_phase {
             Some(WritingPhase::Verifying) => "Skip verification?",
             _ => "Abort writing operation?",
         },
-        CurrentView::Finished => "Write complete.",
+        CurrentView::Finished => {
+            if app.customization_error.is_some() {
+                "Image written, but the customization could not be applied."
+            } else {
+                "Write complete."
+            }
+        }
+        CurrentView::BootloaderConfig => {
+            "EEPROM boot.conf settings embedded into the recovery image after writing."
+        }
+        CurrentView::BootBrowser => "Read-only view of the boot partition on the written card.",
+        CurrentView::Queue => "Queued writes run one after another in the background.",
+        CurrentView::Cache => {
+            "Downloaded images are written from here when the same image is selected again, \
+             and partial downloads continue where they stopped."
+        }
+        CurrentView::Presets => {
+            "Presets keep the hostname, localization, user, Wi-Fi and SSH settings under a \
+             name. Pick one to start the customization from, or save the current settings as one."
+        }
+        CurrentView::History => app.selected_history_job().map_or(
+            "Writes are listed here once they finish or fail.",
+            |job| {
+                job.error
+                    .as_deref()
+                    .unwrap_or("Enter runs this write again.")
+            },
+        ),
+        CurrentView::Repositories => app
+            .repositories_state
+            .selected()
+            .and_then(|i| crate::repositories::BUILTIN.get(i))
+            .map_or("", |r| r.description),
+        CurrentView::Setup => "Welcome! Pick a few defaults; all of them can be changed later.",
+        CurrentView::Unlock => "Remembered passwords are encrypted.",
     };
 
     let desc = Paragraph::new(description)
@@ -1204,7 +3691,7 @@ fn ui(f: &mut Frame, app: &mut App) {
             Block::default().borders(Borders::ALL).title(Span::styled(
                 "Description",
                 Style::default()
-                    .fg(Color::Magenta)
+                    .fg(crate::theme::accent())
                     .add_modifier(Modifier::BOLD),
             )),
         )
@@ -1214,25 +3701,92 @@ fn ui(f: &mut Frame, app: &mut App) {
 
     // Footer: Keys
     let keys = match app.current_view {
-        CurrentView::DeviceSelection => "↑/↓: Navigate | Enter: Select | q: Quit",
-        CurrentView::OsSelection => "↑/↓: Navigate | Enter: Select | Esc: Back | q: Quit",
+        CurrentView::DeviceSelection => {
+            if app.queue.jobs.is_empty() {
+                "↑/↓: Navigate | Enter: Select | a: Auto-detect | r: Repositories | c: Cache | h: History | ?: About | q: Quit"
+            } else {
+                "↑/↓: Navigate | Enter: Select | a: Auto-detect | r: Repositories | c: Cache | h: History | u: Queue | ?: About | q: Quit"
+            }
+        }
+        CurrentView::OsSelection => {
+            if app.customization_ui.input_mode == InputMode::Editing {
+                "Enter: Use image | Esc: Cancel"
+            } else {
+                "↑/↓: Navigate | Enter: Select | Esc: Back | ?: About | q: Quit"
+            }
+        }
         CurrentView::StorageSelection => {
-            "↑/↓: Navigate | Enter: Select | o: Options | r: Refresh | Esc: Back | q: Quit"
+            if app.usbboot_devices.is_empty() {
+                "↑/↓: Navigate | Enter: Select | o: Options | i: Inspect | r: Refresh | Esc: Back | q: Quit"
+            } else {
+                "↑/↓: Navigate | Enter: Select | c: Compute Module | i: Inspect | r: Refresh | Esc: Back | q: Quit"
+            }
         }
         CurrentView::Customization => {
             if app.customization_ui.input_mode == InputMode::Editing {
                 "Enter: Save | Esc: Cancel"
             } else if app.in_customization_submenu {
-                "Enter: Edit | Esc: Back to Menu"
+                "Enter: Edit | d: Reset to defaults | Ctrl+Z/Y: Undo/Redo | Esc: Back to Menu"
+            } else {
+                "↑/↓: Navigate | Enter/→: Select | d: Reset to defaults | s: Save as default | p: Presets | Ctrl+Z/Y: Undo/Redo | Esc: Back"
+            }
+        }
+        CurrentView::WriteConfirmation => {
+            if app.can_skip_write() {
+                "y/Enter: Confirm | s: Skip | u: Add to queue | n/Esc: Cancel | q: Quit"
             } else {
-                "↑/↓: Navigate | Enter/→: Select | Esc: Back"
+                "y/Enter: Confirm | u: Add to queue | n/Esc: Cancel | q: Quit"
             }
         }
-        CurrentView::WriteConfirmation => "y/Enter: Confirm | n/Esc: Cancel | q: Quit",
         CurrentView::Authenticating => "Please wait...",
+        CurrentView::Writing if app.slow_download && app.next_mirror_url().is_some() => {
+            "Esc: Cancel/Skip | p: Pause/Resume | m: Next mirror"
+        }
+        CurrentView::Writing if app.worker_commands.is_some() => {
+            "Esc: Cancel/Skip | p: Pause/Resume"
+        }
         CurrentView::Writing => "Esc: Cancel/Skip",
         CurrentView::AbortConfirmation => "y/Enter: Confirm | n/Esc: Continue",
-        CurrentView::Finished => "Enter/Esc: Done | q: Quit",
+        CurrentView::Finished => {
+            if app.customization_error.is_some() {
+                "r: Retry customization | b: Browse boot partition | Enter/Esc: Done | q: Quit"
+            } else {
+                "b: Browse boot partition | c: Wait for Pi | s/w: SSH/Wi-Fi QR code | Enter/Esc: Done | q: Quit"
+            }
+        }
+        CurrentView::BootBrowser => "↑/↓: Navigate | Esc: Back",
+        CurrentView::Queue => {
+            "↑/↓: Navigate | Enter/s: Start | x: Clear completed | Esc: Back | q: Quit"
+        }
+        CurrentView::Repositories => {
+            "↑/↓: Navigate | Enter/Space: Enable/Disable | Esc: Back | q: Quit"
+        }
+        CurrentView::Cache => "↑/↓: Navigate | d: Delete | x: Delete all | Esc: Back | q: Quit",
+        CurrentView::Presets => {
+            if app.customization_ui.input_mode == InputMode::Editing {
+                "Enter: Save | Esc: Cancel"
+            } else {
+                "↑/↓: Navigate | Enter: Use | n: Save current as new | r: Rename | d: Delete | Esc: Back | q: Quit"
+            }
+        }
+        CurrentView::History => {
+            "↑/↓: Navigate | Enter: Run again | e: Export CSV | j: Export JSON | Esc: Back | q: Quit"
+        }
+        CurrentView::Unlock => "Enter: Unlock | Esc: Continue without saved passwords",
+        CurrentView::Setup => {
+            if app.customization_ui.input_mode == InputMode::Editing {
+                "Enter: Save | Esc: Cancel"
+            } else {
+                "↑/↓: Navigate | Enter: Change | Esc: Skip | q: Quit"
+            }
+        }
+        CurrentView::BootloaderConfig => {
+            if app.customization_ui.input_mode == InputMode::Editing {
+                "Enter: Save | Esc: Cancel"
+            } else {
+                "↑/↓: Navigate | Enter/Space: Edit/Toggle | Esc: Back | q: Quit"
+            }
+        }
     };
     let keys_para = Paragraph::new(keys).style(
         Style::default()
@@ -1241,17 +3795,83 @@ fn ui(f: &mut Frame, app: &mut App) {
             .add_modifier(Modifier::BOLD),
     );
     f.render_widget(keys_para, main_chunks[3]);
+    if let Some(prefetch) = &app.prefetch {
+        let status = Paragraph::new(format!("{} ", prefetch))
+            .style(Style::default().fg(Color::DarkGray).bg(Color::Cyan))
+            .alignment(ratatui::layout::Alignment::Right);
+        f.render_widget(status, main_chunks[3]);
+    }
 
-    if app.is_loading {
+    // The OS list keeps loading in the background of the setup wizard
+    if app.is_loading && !matches!(app.current_view, CurrentView::Setup | CurrentView::Unlock) {
         let loading = Paragraph::new("Loading OS List from raspberrypi.com...")
             .style(Style::default().fg(Color::Yellow))
             .block(Block::default().borders(Borders::ALL));
         f.render_widget(loading, main_chunks[1]);
         return;
     } else if let Some(err) = &app.error_message {
-        let error = Paragraph::new(format!("Error: {}", err))
+        let hint = if app.permission_error {
+            Some("Press 'e' to restart with administrator rights, keeping your selections.")
+        } else {
+            app.error_kind.and_then(ErrorKind::hint)
+        };
+        let kind = app.error_kind.unwrap_or(ErrorKind::Other);
+        let mut lines: Vec<Line> = err
+            .lines()
+            .map(|line| Line::from(line.to_string()))
+            .collect();
+        if let Some(hint) = hint {
+            lines.push(Line::default());
+            lines.push(Line::from(hint));
+        }
+        let checks = crate::troubleshoot::checklist(kind, err);
+        if !checks.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::from(Span::styled(
+                "Things to check:",
+                Style::default().fg(Color::White),
+            )));
+        }
+        for check in checks {
+            lines.push(if check.likely {
+                Line::from(Span::styled(
+                    format!(" ! {}", check.text),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ))
+            } else {
+                Line::from(Span::styled(
+                    format!(" - {}", check.text),
+                    Style::default().fg(Color::Gray),
+                ))
+            });
+        }
+        let actions: String = crate::troubleshoot::actions(kind, err)
+            .iter()
+            .map(|a| format!("{}: {} | ", a.key(), a.label()))
+            .collect();
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Error ")
+            .title_bottom(format!(
+                " ↑/↓/PgUp/PgDn: Scroll | y: Copy | l: Save to log | {}Enter/Esc: Dismiss ",
+                actions
+            ));
+        if let Some(notice) = &app.error_notice {
+            block = block.title_bottom(
+                Line::from(Span::styled(
+                    format!(" {} ", notice),
+                    Style::default().fg(Color::Yellow),
+                ))
+                .right_aligned(),
+            );
+        }
+        let error = Paragraph::new(lines)
             .style(Style::default().fg(Color::Red))
-            .block(Block::default().borders(Borders::ALL));
+            .block(block)
+            .wrap(ratatui::widgets::Wrap { trim: false })
+            .scroll((app.error_scroll, 0));
         f.render_widget(error, main_chunks[1]);
         return;
     }
@@ -1262,25 +3882,22 @@ fn ui(f: &mut Frame, app: &mut App) {
         .split(main_chunks[1]);
 
     // Render Sidebar
-    let steps = vec![
-        ("Device", CurrentView::DeviceSelection),
-        ("OS", CurrentView::OsSelection),
-        ("Storage", CurrentView::StorageSelection),
-        ("Customization", CurrentView::Customization),
-        ("Writing", CurrentView::Writing),
-        ("Done", CurrentView::Finished),
-    ];
-
-    let items: Vec<ListItem> = steps
+    let items: Vec<ListItem> = STEPS
         .iter()
-        .map(|(label, view)| {
+        .enumerate()
+        .map(|(i, (label, view))| {
             let is_active = app.current_view == *view
                 || (app.current_view == CurrentView::WriteConfirmation
-                    && *label == "Customization");
+                    && *label == "Customization")
+                || (app.current_view == CurrentView::BootBrowser && *label == "Done")
+                || (matches!(
+                    app.current_view,
+                    CurrentView::BootloaderConfig | CurrentView::Presets
+                ) && *label == "Customization");
 
             let style = if is_active {
                 Style::default()
-                    .fg(Color::Magenta)
+                    .fg(crate::theme::accent())
                     .add_modifier(Modifier::BOLD)
             } else {
                 Style::default().fg(Color::Gray)
@@ -1288,7 +3905,7 @@ fn ui(f: &mut Frame, app: &mut App) {
 
             ListItem::new(vec![
                 Line::from(""),
-                Line::from(Span::styled(format!("  {}", label), style)),
+                Line::from(Span::styled(format!(" {} {}", i + 1, label), style)),
                 Line::from(""),
             ])
         })
@@ -1305,23 +3922,30 @@ fn ui(f: &mut Frame, app: &mut App) {
             ),
     );
     f.render_widget(sidebar, content_chunks[0]);
+    app.sidebar_area = content_chunks[0];
 
     // Render Main Content
     match app.current_view {
         CurrentView::DeviceSelection => {
+            let pad = if app.icons.enabled() {
+                crate::icons::PADDING
+            } else {
+                ""
+            };
             let devices = app.get_devices();
+            let icons: Vec<Option<String>> = devices.iter().map(|d| d.icon.clone()).collect();
             let items: Vec<ListItem> = devices
                 .iter()
                 .map(|d| {
                     ListItem::new(vec![
                         Line::from(Span::styled(
-                            d.name.clone(),
+                            format!("{}{}", pad, d.name),
                             Style::default()
                                 .fg(Color::Cyan)
                                 .add_modifier(Modifier::BOLD),
                         )),
                         Line::from(Span::styled(
-                            d.description.clone(),
+                            format!("{}{}", pad, d.description),
                             Style::default().fg(Color::Gray),
                         )),
                         Line::from(""),
@@ -1329,34 +3953,62 @@ fn ui(f: &mut Frame, app: &mut App) {
                 })
                 .collect();
 
+            let mut block = Block::default().borders(Borders::ALL).title(Span::styled(
+                "Select your Raspberry Pi device",
+                Style::default()
+                    .fg(crate::theme::accent())
+                    .add_modifier(Modifier::BOLD),
+            ));
+            if let Some(notice) = &app.os_list_notice {
+                block = block.title_bottom(Span::styled(
+                    format!(" {} ", notice),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
             let list = List::new(items)
-                .block(
-                    Block::default().borders(Borders::ALL).title(Span::styled(
-                        "Select your Raspberry Pi device",
-                        Style::default()
-                            .fg(Color::Magenta)
-                            .add_modifier(Modifier::BOLD),
-                    )),
-                )
+                .block(block)
                 .highlight_style(
                     Style::default()
-                        .bg(Color::Magenta)
+                        .bg(crate::theme::accent())
                         .fg(Color::White)
                         .add_modifier(Modifier::BOLD),
                 )
                 .highlight_symbol(">> ");
 
             f.render_stateful_widget(list, content_chunks[1], &mut app.device_list_state);
+            place_icons(
+                app,
+                content_chunks[1],
+                &icons,
+                3,
+                app.device_list_state.offset(),
+            );
         }
         CurrentView::OsSelection => {
+            let pad = if app.icons.enabled() {
+                crate::icons::PADDING
+            } else {
+                ""
+            };
+            let icons: Vec<Option<String>> = app
+                .current_items()
+                .iter()
+                .map(|os| os.icon.clone())
+                .collect();
+            let editing = app.customization_ui.input_mode == InputMode::Editing;
             let items: Vec<ListItem> = app
                 .current_items()
                 .iter()
                 .map(|os| {
+                    if editing && os.is_custom_entry() {
+                        return ListItem::new(app.customization_ui.input.line());
+                    }
                     let title = if os.subitems.is_empty() {
-                        os.name.clone()
+                        format!("{}{}", pad, os.name)
                     } else {
-                        format!("{} >", os.name)
+                        format!("{}{} >", pad, os.name)
                     };
                     ListItem::new(Line::from(Span::raw(title)))
                 })
@@ -1373,26 +4025,33 @@ fn ui(f: &mut Frame, app: &mut App) {
                     Block::default().borders(Borders::ALL).title(Span::styled(
                         title,
                         Style::default()
-                            .fg(Color::Magenta)
+                            .fg(crate::theme::accent())
                             .add_modifier(Modifier::BOLD),
                     )),
                 )
                 .highlight_style(
                     Style::default()
-                        .bg(Color::Magenta)
+                        .bg(crate::theme::accent())
                         .fg(Color::White)
                         .add_modifier(Modifier::BOLD),
                 )
                 .highlight_symbol(">> ");
 
             f.render_stateful_widget(list, content_chunks[1], &mut app.list_state);
+            place_icons(app, content_chunks[1], &icons, 1, app.list_state.offset());
         }
         CurrentView::StorageSelection => {
-            let title = if let Some(os) = &app.selected_os {
+            let mut title = if let Some(os) = &app.selected_os {
                 format!("Select Storage Device for {}", os.name)
             } else {
                 "Select Storage Device".to_string()
             };
+            if let Some(cm) = app.usbboot_devices.first() {
+                title.push_str(&format!(
+                    " | {} in USB boot mode: press 'c' to expose its eMMC",
+                    cm
+                ));
+            }
 
             let items: Vec<ListItem> = app
                 .drive_list
@@ -1409,6 +4068,11 @@ fn ui(f: &mut Frame, app: &mut App) {
                         },
                         if drive.is_system() { " [SYSTEM]" } else { "" }
                     );
+                    let info = if drive.profile == TargetProfile::Ssd {
+                        format!("{} [SSD]", info)
+                    } else {
+                        info
+                    };
                     let style = if drive.is_system() {
                         Style::default().fg(Color::Red)
                     } else {
@@ -1418,24 +4082,78 @@ fn ui(f: &mut Frame, app: &mut App) {
                 })
                 .collect();
 
+            let mut block = Block::default().borders(Borders::ALL).title(Span::styled(
+                title,
+                Style::default()
+                    .fg(crate::theme::accent())
+                    .add_modifier(Modifier::BOLD),
+            ));
+            if let Some(banner) = app.device_access.and_then(|a| a.banner()) {
+                block = block.title_bottom(Span::styled(
+                    banner,
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+
             let list = List::new(items)
-                .block(
-                    Block::default().borders(Borders::ALL).title(Span::styled(
-                        title,
-                        Style::default()
-                            .fg(Color::Magenta)
-                            .add_modifier(Modifier::BOLD),
-                    )),
-                )
+                .block(block)
                 .highlight_style(
                     Style::default()
-                        .bg(Color::Magenta)
+                        .bg(crate::theme::accent())
                         .fg(Color::White)
                         .add_modifier(Modifier::BOLD),
                 )
                 .highlight_symbol(">> ");
 
-            f.render_stateful_widget(list, content_chunks[1], &mut app.drive_list_state);
+            // A single candidate is preselected; show everything about it
+            let details = app
+                .sole_candidate()
+                .and_then(|i| app.drive_list.get(i))
+                .map(|drive| {
+                    let mut details = vec![
+                        Line::from(Span::styled(
+                            "Only one removable drive found. Press Enter to use it.",
+                            Style::default()
+                                .fg(Color::Green)
+                                .add_modifier(Modifier::BOLD),
+                        )),
+                        Line::from(format!("Device: {}", drive.name)),
+                        Line::from(format!("Model and size: {}", drive.description)),
+                        Line::from(format!(
+                            "Serial: {}",
+                            drive.serial.as_deref().unwrap_or("unknown")
+                        )),
+                        Line::from(if drive.mountpoints.is_empty() {
+                            "Not mounted".to_string()
+                        } else {
+                            format!("Mounted at: {}", drive.mountpoints.join(", "))
+                        }),
+                    ];
+                    if let Some(record) = app.drive_record(drive) {
+                        details.push(Line::from(format!("Last written: {}", record.summary())));
+                    }
+                    details
+                });
+            match details {
+                Some(details) => {
+                    let chunks = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([
+                            Constraint::Min(3),
+                            Constraint::Length(details.len() as u16 + 2),
+                        ])
+                        .split(content_chunks[1]);
+                    let details = Paragraph::new(details)
+                        .block(Block::default().borders(Borders::ALL).title("Drive"));
+                    f.render_stateful_widget(list, chunks[0], &mut app.drive_list_state);
+                    f.render_widget(details, chunks[1]);
+                }
+                None => {
+                    f.render_stateful_widget(list, content_chunks[1], &mut app.drive_list_state)
+                }
+            }
         }
         CurrentView::Customization => {
             let area = content_chunks[1];
@@ -1445,18 +4163,36 @@ fn ui(f: &mut Frame, app: &mut App) {
                 .split(area);
 
             // Left Menu
-            let menu_items_labels = vec![
+            let menu_items_labels = [
                 "Hostname",
                 "Localization",
                 "User",
                 "Wi-Fi",
                 "Remote Access",
+                "Network",
+                "Options",
                 "Reset Settings",
                 "NEXT >",
             ];
             let menu_items: Vec<ListItem> = menu_items_labels
                 .iter()
-                .map(|t| ListItem::new(Line::from(*t)))
+                .enumerate()
+                .map(|(i, t)| {
+                    // Mark categories that differ from the defaults, e.g. "Wi-Fi *"
+                    let label = if app.menu_section_modified(i) {
+                        format!("{} *", t)
+                    } else {
+                        t.to_string()
+                    };
+                    if app.menu_section_supported(i) {
+                        ListItem::new(Line::from(label))
+                    } else {
+                        ListItem::new(Line::from(Span::styled(
+                            label,
+                            Style::default().fg(Color::DarkGray),
+                        )))
+                    }
+                })
                 .collect();
 
             let menu_list = List::new(menu_items)
@@ -1468,7 +4204,7 @@ fn ui(f: &mut Frame, app: &mut App) {
                 )
                 .highlight_style(
                     Style::default()
-                        .bg(Color::Magenta)
+                        .bg(crate::theme::accent())
                         .fg(Color::White)
                         .add_modifier(Modifier::BOLD),
                 )
@@ -1478,36 +4214,74 @@ fn ui(f: &mut Frame, app: &mut App) {
 
             // Right Content
             let opts = &app.customization_options;
+            let host = crate::host_locale::detected();
+            let detected = |value: &str, host_value: &Option<String>| {
+                if host_value.as_deref() == Some(value) {
+                    " (detected)"
+                } else {
+                    ""
+                }
+            };
             let mut items = Vec::new();
             let selected_menu = app.customization_menu_state.selected().unwrap_or(0);
 
             match selected_menu {
+                _ if !app.menu_section_supported(selected_menu) => {
+                    items.push(
+                        "Not supported by the selected image; it will be skipped.".to_string(),
+                    );
+                }
                 0 => {
                     // Hostname
                     items.push(format!("Hostname: {}", opts.hostname));
                 }
                 1 => {
                     // Localization
-                    items.push(format!("Timezone: {}", opts.timezone));
-                    items.push(format!("Keyboard Layout: {}", opts.keyboard_layout));
-                    items.push(format!("Locale: {}", opts.locale));
+                    items.push(format!(
+                        "Timezone: {}{}",
+                        opts.timezone,
+                        detected(&opts.timezone, &host.timezone)
+                    ));
+                    let keyboard =
+                        crate::keyboards::join_code(&opts.keyboard_layout, &opts.keyboard_variant);
+                    items.push(format!(
+                        "Keyboard Layout: {}{}",
+                        keyboard,
+                        detected(&keyboard, &host.keyboard_layout)
+                    ));
+                    items.push(format!(
+                        "Locale: {}{}",
+                        opts.locale,
+                        detected(&opts.locale, &host.locale)
+                    ));
                 }
                 2 => {
                     // User
                     items.push(format!("Username: {}", opts.user_name));
                     items.push(format!(
                         "Password: {}",
-                        opts.password.as_deref().unwrap_or("******")
+                        opts.password
+                            .as_deref()
+                            .map(crate::widgets::text_input::mask)
+                            .unwrap_or_else(|| "******".to_string())
                     ));
                 }
                 3 => {
                     // Wi-Fi
                     items.push(format!("SSID: {}", opts.wifi_ssid));
-                    items.push(format!("Password: {}", opts.wifi_password));
+                    items.push(format!(
+                        "Password: {}",
+                        crate::widgets::text_input::mask(&opts.wifi_password)
+                    ));
                     items.push(format!(
                         "Hidden SSID: {}",
                         if opts.wifi_hidden { "[x]" } else { "[ ]" }
                     ));
+                    items.push(format!(
+                        "Country: {}{}",
+                        opts.wifi_country,
+                        detected(&opts.wifi_country, &host.wifi_country)
+                    ));
                 }
                 4 => {
                     // Remote Access
@@ -1524,33 +4298,119 @@ fn ui(f: &mut Frame, app: &mut App) {
                         items.push("Password Auth: [ ]".to_string());
                     }
                     items.push(format!("Public Key: {}", opts.ssh_public_keys));
+                    items.push(match (&app.ssh_import_started, &app.ssh_import_status) {
+                        (Some(started), _) => {
+                            const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+                            let frame = started.elapsed().as_millis() / 150;
+                            format!(
+                                "Import keys from GitHub/GitLab {}",
+                                FRAMES[frame as usize % FRAMES.len()]
+                            )
+                        }
+                        (None, Some(status)) => {
+                            format!("Import keys from GitHub/GitLab: {}", status)
+                        }
+                        (None, None) => "Import keys from GitHub/GitLab".to_string(),
+                    });
                 }
                 5 => {
+                    // Network
+                    for (interface, ip) in [("eth0", &opts.eth0), ("wlan0", &opts.wlan0)] {
+                        for (field, (label, _)) in StaticIp::FIELDS.iter().enumerate() {
+                            let value = match ip.field(field) {
+                                "" if field % 2 == 0 => "automatic",
+                                "" => "none",
+                                value => value,
+                            };
+                            items.push(format!("{} {}: {}", interface, label, value));
+                        }
+                    }
+                }
+                6 => {
+                    // Options
+                    items.push(format!(
+                        "Send anonymous download statistics: {}",
+                        if opts.telemetry { "[x]" } else { "[ ]" }
+                    ));
+                    items.push(format!(
+                        "Remember passwords between runs: {}",
+                        if opts.remember_secrets { "[x]" } else { "[ ]" }
+                    ));
+                    items.push(format!(
+                        "Verify after writing: {}",
+                        opts.verification.label()
+                    ));
+                    items.push(format!(
+                        "Encrypt remembered passwords: {}",
+                        if opts.encrypts_secrets() {
+                            "[x]"
+                        } else {
+                            "[ ]"
+                        }
+                    ));
+                    items.push(format!(
+                        "Wipe old partition tables before writing: {}",
+                        if opts.wipe_device { "[x]" } else { "[ ]" }
+                    ));
+                    items.push(format!(
+                        "Eject when finished: {}",
+                        if opts.eject_finished { "[x]" } else { "[ ]" }
+                    ));
+                    items.push(format!(
+                        "Only write on AC power: {}",
+                        if opts.require_ac_power { "[x]" } else { "[ ]" }
+                    ));
+                }
+                7 => {
                     // Reset
                     items.push("Press Enter to reset all settings to defaults.".to_string());
+                    let modified: Vec<&str> = menu_items_labels[..7]
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| app.menu_section_modified(*i))
+                        .map(|(_, t)| *t)
+                        .collect();
+                    if modified.is_empty() {
+                        items.push("All categories are at their defaults.".to_string());
+                    } else {
+                        items.push(format!("Changed: {}", modified.join(", ")));
+                    }
                 }
-                6 => {
+                8 => {
                     // Next
                     items.push("Press Enter to proceed to writing.".to_string());
                 }
                 _ => {}
             }
 
-            let list_items: Vec<ListItem> = items
+            let mut list_items: Vec<ListItem> = items
                 .iter()
                 .enumerate()
                 .map(|(i, val)| {
-                    let mut content = val.clone();
                     if app.in_customization_submenu
                         && app.customization_sub_menu_state.selected() == Some(i)
                         && app.customization_ui.input_mode == InputMode::Editing
                     {
-                        content = format!("> {}_", app.customization_ui.input_buffer);
+                        ListItem::new(app.customization_ui.input.line())
+                    } else {
+                        ListItem::new(Line::from(val.clone()))
                     }
-                    ListItem::new(Line::from(content))
                 })
                 .collect();
 
+            // Below the settings, out of reach of the selection
+            if let (Some(section), Some(os)) = (
+                CustomizationSection::from_menu_index(selected_menu),
+                &app.selected_os,
+            ) {
+                for warning in crate::compat::warnings(os, opts, section) {
+                    list_items.push(ListItem::new(Line::from(Span::styled(
+                        format!("! {}", warning),
+                        Style::default().fg(Color::Yellow),
+                    ))));
+                }
+            }
+
             let content_block = Block::default()
                 .borders(Borders::ALL)
                 .title(" Settings ")
@@ -1589,7 +4449,7 @@ fn ui(f: &mut Frame, app: &mut App) {
                 .map(|d| d.description.as_str())
                 .unwrap_or("Unknown Drive");
 
-            let text = vec![
+            let mut text = vec![
                 Line::from(Span::raw("Are you sure you want to write:")),
                 Line::from(Span::styled(
                     os_name,
@@ -1617,12 +4477,79 @@ fn ui(f: &mut Frame, app: &mut App) {
                 )),
             ];
 
+            if let Some(warning) = crate::power::Battery::read().and_then(|b| b.warning()) {
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    warning,
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                )));
+            }
+            if app.fixed_disk_confirmed {
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    "Fixed (non-removable) disk! Press 'y' again to overwrite it.",
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                )));
+            }
+            if app.can_skip_write() {
+                let mut skip_note =
+                    "This card already contains this image. Press 's' to skip writing".to_string();
+                if app.customization_options.needs_customization() {
+                    skip_note.push_str(" (customization will not be applied)");
+                }
+                skip_note.push('.');
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    skip_note,
+                    Style::default().fg(Color::Green),
+                )));
+            } else if app.can_delta_write() {
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    "Card holds an earlier build of this image; only changed blocks will be written.",
+                    Style::default().fg(Color::Green),
+                )));
+            }
+            if app.offline_flash {
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    "Offline flash: the image is written from the cache, no network needed.",
+                    Style::default().fg(Color::Green),
+                )));
+            }
+            if app.customization_options.verification == Verification::Ask {
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::raw(format!(
+                    "Verify after writing: {}. Press 'v' to change.",
+                    if app.verify_write() { "yes" } else { "no" }
+                ))));
+            }
+            if let Some(estimate) = app.estimated_write_time() {
+                let minutes = ((estimate.as_secs() + 30) / 60).max(1);
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::raw(format!(
+                    "~{} min based on previous writes to this {}.",
+                    minutes,
+                    match app.selected_drive.as_ref().map(|d| d.profile) {
+                        Some(TargetProfile::Ssd) => "drive",
+                        _ => "reader",
+                    }
+                ))));
+            }
+            text.push(Line::from(Span::raw("")));
+            text.push(Line::from(Span::raw(match &app.tee_file {
+                Some(path) => format!("Also saving the image to {}. Press 't' to stop.", path),
+                None => "Press 't' to also save the image to a file.".to_string(),
+            })));
+
             let vertical_layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(
                     [
                         Constraint::Min(1),
-                        Constraint::Length(10),
+                        Constraint::Length(text.len() as u16 + 2),
                         Constraint::Min(1),
                     ]
                     .as_ref(),
@@ -1694,76 +4621,62 @@ fn ui(f: &mut Frame, app: &mut App) {
         }
         CurrentView::Writing => {
             let vertical_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints(
-                    [
-                        Constraint::Min(1),
-                        Constraint::Length(3),
-                        Constraint::Length(1),
-                        Constraint::Length(3),
-                        Constraint::Min(1),
-                    ]
-                    .as_ref(),
-                )
-                .split(content_chunks[1]);
-
-            let horizontal_layout_write = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints(
-                    [
-                        Constraint::Percentage(10),
-                        Constraint::Percentage(80),
-                        Constraint::Percentage(10),
-                    ]
-                    .as_ref(),
-                )
-                .split(vertical_layout[1]);
-
-            let horizontal_layout_verify = Layout::default()
-                .direction(Direction::Horizontal)
+                .direction(Direction::Vertical)
                 .constraints(
                     [
-                        Constraint::Percentage(10),
-                        Constraint::Percentage(80),
-                        Constraint::Percentage(10),
+                        Constraint::Min(1),
+                        Constraint::Length(3),
+                        Constraint::Length(3),
+                        Constraint::Length(3),
+                        Constraint::Min(1),
                     ]
                     .as_ref(),
                 )
-                .split(vertical_layout[3]);
+                .split(content_chunks[1]);
 
-            let gauge_write = Gauge::default()
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title("Writing...")
-                        .border_style(Style::default().fg(Color::Green)),
-                )
-                .gauge_style(
-                    Style::default()
-                        .fg(Color::Green)
-                        .bg(Color::DarkGray)
-                        .add_modifier(Modifier::BOLD),
-                )
-                .percent(app.write_progress as u16)
-                .label(format!("{:.1}%", app.write_progress));
-            f.render_widget(gauge_write, horizontal_layout_write[1]);
+            let segments = [
+                ("Writing...", app.write_progress, Color::Green),
+                ("Syncing...", app.sync_progress, Color::Yellow),
+                ("Verifying...", app.verify_progress, Color::Cyan),
+            ];
+            for (i, (title, fraction, color)) in segments.into_iter().enumerate() {
+                let horizontal_layout = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints(
+                        [
+                            Constraint::Percentage(10),
+                            Constraint::Percentage(80),
+                            Constraint::Percentage(10),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(vertical_layout[i + 1]);
 
-            let gauge_verify = Gauge::default()
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title("Verifying...")
-                        .border_style(Style::default().fg(Color::Cyan)),
-                )
-                .gauge_style(
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .bg(Color::DarkGray)
-                        .add_modifier(Modifier::BOLD),
-                )
-                .percent(app.verify_progress as u16)
-                .label(format!("{:.1}%", app.verify_progress));
-            f.render_widget(gauge_verify, horizontal_layout_verify[1]);
+                let gauge = Gauge::default()
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .title(title)
+                            .border_style(Style::default().fg(color)),
+                    )
+                    .gauge_style(
+                        Style::default()
+                            .fg(color)
+                            .bg(Color::DarkGray)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                    .ratio(fraction.clamp(0.0, 1.0))
+                    .label(if i == 0 && app.synced_progress > 0.0 {
+                        format!(
+                            "{:.1}% processed, {:.1}% on device",
+                            fraction * 100.0,
+                            app.synced_progress * 100.0
+                        )
+                    } else {
+                        format!("{:.1}%", fraction * 100.0)
+                    });
+                f.render_widget(gauge, horizontal_layout[1]);
+            }
         }
         CurrentView::AbortConfirmation => {
             let title = match app.write_phase {
@@ -1830,31 +4743,104 @@ fn ui(f: &mut Frame, app: &mut App) {
             f.render_widget(p, horizontal_layout[1]);
         }
         CurrentView::Finished => {
-            let text = vec![
-                Line::from(Span::styled(
-                    "Write Successful!",
-                    Style::default()
-                        .fg(Color::Green)
-                        .add_modifier(Modifier::BOLD),
-                )),
-                Line::from(Span::raw("")),
+            let (headline, color) = if app.customization_error.is_some() {
+                ("Image Written - Customization Failed", Color::Yellow)
+            } else if app.write_status == "Skipped" {
+                ("Write Skipped - card already up to date", Color::Green)
+            } else {
+                ("Write Successful!", Color::Green)
+            };
+            let mut text = vec![
                 Line::from(Span::styled(
-                    "You can now remove the SD card.",
-                    Style::default().fg(Color::White),
+                    headline,
+                    Style::default().fg(color).add_modifier(Modifier::BOLD),
                 )),
                 Line::from(Span::raw("")),
-                Line::from(Span::styled(
-                    "Press Enter to continue.",
-                    Style::default().fg(Color::Gray),
-                )),
             ];
+            if let Some(err) = &app.customization_error {
+                text.push(Line::from(Span::styled(
+                    format!("Image written, customization failed: {}", err),
+                    Style::default().fg(Color::Red),
+                )));
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    "The card boots with default settings. Press 'r' to retry the customization only.",
+                    Style::default().fg(Color::Gray),
+                )));
+            } else {
+                if app.verification_skipped {
+                    text.push(Line::from(Span::styled(
+                        "Verification skipped: the card wasn't read back, so write errors may \
+                         have gone unnoticed.",
+                        Style::default().fg(Color::Yellow),
+                    )));
+                    text.push(Line::from(Span::raw("")));
+                }
+                text.push(if app.ejected {
+                    Line::from(Span::styled(
+                        "Safe to remove: the card has been ejected.",
+                        Style::default().fg(Color::Green),
+                    ))
+                } else {
+                    Line::from(Span::styled(
+                        "You can now remove the SD card.",
+                        Style::default().fg(Color::White),
+                    ))
+                });
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    "Press Enter to continue, or 'b' to browse the boot partition.",
+                    Style::default().fg(Color::Gray),
+                )));
+            }
+            if !app.boot_checks.is_empty() {
+                text.push(Line::from(Span::raw("")));
+                for check in &app.boot_checks {
+                    let (mark, color) = if check.passed {
+                        ("[x]", Color::Green)
+                    } else {
+                        ("[ ]", Color::Red)
+                    };
+                    text.push(Line::from(Span::styled(
+                        format!("{} {}", mark, check.label),
+                        Style::default().fg(color),
+                    )));
+                }
+            }
+            for change in &app.boot_changes {
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    format!("Changed {}:", change.file),
+                    Style::default().fg(Color::White),
+                )));
+                // Unchanged lines only go to the changes log
+                for line in &change.lines {
+                    let (text_line, color) = match line {
+                        DiffLine::Same(_) => continue,
+                        DiffLine::Removed(line) => (format!("- {}", line), Color::Red),
+                        DiffLine::Added(line) => (format!("+ {}", line), Color::Green),
+                    };
+                    text.push(Line::from(Span::styled(
+                        text_line,
+                        Style::default().fg(color),
+                    )));
+                }
+            }
+            if let Some(status) = &app.reachability {
+                text.push(Line::from(Span::raw("")));
+                text.push(Line::from(Span::styled(
+                    status.as_str(),
+                    Style::default().fg(Color::Cyan),
+                )));
+            }
+            let height = text.len() as u16 + 2;
 
             let vertical_layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(
                     [
                         Constraint::Min(1),
-                        Constraint::Length(7),
+                        Constraint::Length(height),
                         Constraint::Min(1),
                     ]
                     .as_ref(),
@@ -1883,44 +4869,522 @@ fn ui(f: &mut Frame, app: &mut App) {
                 .style(Style::default().fg(Color::White))
                 .alignment(ratatui::layout::Alignment::Center);
             f.render_widget(p, horizontal_layout[1]);
+
+            if let Some(kind) = app.qr_code
+                && let Some(payload) = kind.payload(&app.customization_options)
+            {
+                render_qr_code(f, kind, &payload, content_chunks[1]);
+            }
         }
+        CurrentView::BootBrowser => render_boot_browser(f, app, content_chunks[1]),
+        CurrentView::Queue => render_queue(f, app, content_chunks[1]),
+        CurrentView::Repositories => render_repositories(f, app, content_chunks[1]),
+        CurrentView::Cache => render_cache(f, app, content_chunks[1]),
+        CurrentView::History => render_history(f, app, content_chunks[1]),
+        CurrentView::Presets => render_presets(f, app, content_chunks[1]),
+        CurrentView::Setup => render_setup(f, app, content_chunks[1]),
+        CurrentView::Unlock => render_unlock(f, app, content_chunks[1]),
+        CurrentView::BootloaderConfig => render_bootloader_config(f, app, content_chunks[1]),
     }
 
-    if let Some(popup_type) = &app.popup {
-        let title = match popup_type {
-            PopupType::Timezone => "Select Timezone",
-            PopupType::Keyboard => "Select Keyboard Layout",
-            PopupType::Locale => "Select Locale",
-            PopupType::SshKey => "Select SSH Key",
-        };
-
-        let block = Block::default()
-            .borders(Borders::ALL)
-            .title(title)
-            .title_bottom(format!("Filter: {}", app.popup_filter))
-            .style(Style::default().fg(Color::Yellow));
-
+    if app.popup.is_some() {
         let area = centered_rect(60, 60, f.area());
-        f.render_widget(Clear, area); // Clear background
+        app.picker.render(f, area);
+    }
 
-        let items: Vec<ListItem> = app
-            .popup_items
-            .iter()
-            .map(|i| ListItem::new(Line::from(i.as_str())))
+    if app.show_about {
+        let lines: Vec<Line> = crate::about::details()
+            .into_iter()
+            .map(Line::from)
             .collect();
+        let area = centered_rect(70, 40, f.area());
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(" About ")
+                        .title_bottom(" Any key: Close "),
+                )
+                .wrap(ratatui::widgets::Wrap { trim: true }),
+            area,
+        );
+    }
+}
 
-        let list = List::new(items)
-            .block(block)
-            .highlight_style(
+/// Reserves icon slots for the visible rows of a bordered list with a
+/// ">> " highlight symbol, whose items are `height` lines tall.
+fn place_icons(
+    app: &mut App,
+    area: ratatui::layout::Rect,
+    icons: &[Option<String>],
+    height: u16,
+    offset: usize,
+) {
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    let x = inner.x + 3;
+    let mut y = inner.y;
+    for icon in icons.iter().skip(offset) {
+        if y >= inner.bottom() {
+            break;
+        }
+        if let Some(url) = icon {
+            let slot = ratatui::layout::Rect::new(x, y, crate::icons::WIDTH, 1).intersection(inner);
+            app.icons.place(slot, url);
+        }
+        y += height;
+    }
+}
+
+fn render_bootloader_config(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    let config = &app.customization_options.bootloader;
+    let check = |b: bool| if b { "[x]" } else { "[ ]" };
+    let editing = app.customization_ui.input_mode == InputMode::Editing;
+    let labels = [
+        format!("BOOT_ORDER: {}", config.boot_order),
+        format!("POWER_OFF_ON_HALT: {}", check(config.power_off_on_halt)),
+        format!("BOOT_UART: {}", check(config.boot_uart)),
+        format!("WAKE_ON_GPIO: {}", check(config.wake_on_gpio)),
+        format!("NET_INSTALL_ENABLED: {}", check(config.net_install_enabled)),
+        "Reset to defaults".to_string(),
+        "NEXT >".to_string(),
+    ];
+    let items: Vec<ListItem> = labels
+        .into_iter()
+        .enumerate()
+        .map(|(i, l)| {
+            if i == 0 && editing {
+                let mut line = app.customization_ui.input.line();
+                line.spans.insert(0, Span::raw("BOOT_ORDER: "));
+                ListItem::new(line)
+            } else {
+                ListItem::new(Line::from(l))
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default().borders(Borders::ALL).title(Span::styled(
+                "Bootloader Configuration (boot.conf)",
                 Style::default()
-                    .bg(Color::Yellow)
-                    .fg(Color::Black)
+                    .fg(crate::theme::accent())
                     .add_modifier(Modifier::BOLD),
-            )
-            .highlight_symbol("> ");
+            )),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(crate::theme::accent())
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(list, area, &mut app.bootloader_menu_state);
+}
+
+fn render_unlock(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    let prompt = if app.unlock_preset.is_some() {
+        "Enter the passphrase for the passwords of the preset:"
+    } else {
+        "Enter the passphrase for the remembered passwords:"
+    };
+    let mut text = vec![
+        Line::from(prompt),
+        Line::from(""),
+        app.customization_ui.input.line(),
+    ];
+    if let Some(err) = &app.unlock_error {
+        text.push(Line::from(""));
+        text.push(Line::from(Span::styled(
+            err.as_str(),
+            Style::default().fg(Color::Red),
+        )));
+    }
+    let popup = centered_rect(60, 30, area);
+    let p = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Unlock ")
+            .border_style(Style::default().fg(crate::theme::accent())),
+    );
+    f.render_widget(p, popup);
+}
+
+fn render_setup(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    let opts = &app.customization_options;
+    let cache_dir = opts.cache_dir.clone().unwrap_or_else(|| {
+        crate::cache::default_cache_dir()
+            .map(|d| d.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    let ssh_key = if opts.ssh_public_keys.is_empty() {
+        "none".to_string()
+    } else {
+        opts.ssh_public_keys.clone()
+    };
+    let values = [
+        format!("Cache directory: {}", cache_dir),
+        format!("Theme: {}", opts.theme.label()),
+        format!("SSH key for new images: {}", ssh_key),
+        format!(
+            "Send anonymous download statistics: {}",
+            if opts.telemetry { "[x]" } else { "[ ]" }
+        ),
+        "FINISH >".to_string(),
+    ];
+    let editing = app.customization_ui.input_mode == InputMode::Editing;
+    let items: Vec<ListItem> = values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| {
+            if editing && app.setup_state.selected() == Some(i) {
+                ListItem::new(app.customization_ui.input.line())
+            } else {
+                ListItem::new(Line::from(value))
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(Span::styled(
+                    " First-run Setup ",
+                    Style::default()
+                        .fg(crate::theme::accent())
+                        .add_modifier(Modifier::BOLD),
+                ))
+                .title_bottom(" Saved to the config file; an imported SSH key enables SSH "),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(crate::theme::accent())
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, area, &mut app.setup_state);
+}
+
+fn render_repositories(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    let items: Vec<ListItem> = crate::repositories::BUILTIN
+        .iter()
+        .map(|repository| {
+            let mark = if repository.is_enabled(&app.customization_options.os_sources) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            ListItem::new(vec![
+                Line::from(vec![
+                    Span::raw(format!("{} {} ", mark, repository.name)),
+                    Span::styled("third-party", Style::default().fg(Color::Yellow)),
+                ]),
+                Line::from(Span::styled(
+                    format!("    {}", repository.url),
+                    Style::default().fg(Color::Gray),
+                )),
+            ])
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Repositories ")
+                .title_bottom(
+                    " Maintained by their projects, not by Raspberry Pi; shown as extra OS categories ",
+                )
+                .border_style(Style::default().fg(Color::Cyan)),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(crate::theme::accent())
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, area, &mut app.repositories_state);
+}
+
+fn render_cache(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    let items: Vec<ListItem> = app
+        .cached_images
+        .iter()
+        .map(|image| {
+            let mut spans = vec![Span::raw(format!(
+                "{} ({}) ",
+                image.name,
+                crate::drivelist::format_size(image.size)
+            ))];
+            if image.partial {
+                spans.push(Span::styled("partial", Style::default().fg(Color::Yellow)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let total: u64 = app.cached_images.iter().map(|i| i.size).sum();
+    let dir = crate::cache::cache_dir()
+        .map(|d| d.display().to_string())
+        .unwrap_or_default();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" Cache: {} ", crate::drivelist::format_size(total)))
+                .title_bottom(format!(" {} ", dir))
+                .border_style(Style::default().fg(Color::Cyan)),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(crate::theme::accent())
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, area, &mut app.cache_state);
+}
+
+fn render_presets(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    let editing = app.customization_ui.input_mode == InputMode::Editing;
+    let renaming = app.preset_renaming.as_ref();
+    let mut items: Vec<ListItem> = vec![ListItem::new(Line::from(Span::styled(
+        "Current settings",
+        Style::default().add_modifier(Modifier::ITALIC),
+    )))];
+    items.extend(app.preset_names.iter().map(|name| {
+        if editing && renaming == Some(name) {
+            ListItem::new(app.customization_ui.input.line())
+        } else {
+            ListItem::new(Line::from(name.as_str()))
+        }
+    }));
+    if editing && renaming.is_none() {
+        items.push(ListItem::new(app.customization_ui.input.line()));
+    }
+    let dir = crate::presets::Presets::open()
+        .map(|p| p.dir().display().to_string())
+        .unwrap_or_default();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Presets ")
+                .title_bottom(format!(" {} ", dir))
+                .border_style(Style::default().fg(Color::Cyan)),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(crate::theme::accent())
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, area, &mut app.presets_state);
+}
+
+fn render_history(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    use crate::history::Outcome;
+    let items: Vec<ListItem> = app
+        .history
+        .jobs
+        .iter()
+        .rev()
+        .map(|job| {
+            let color = match job.outcome {
+                Outcome::Written => Color::Green,
+                Outcome::CustomizationFailed | Outcome::Cancelled => Color::Yellow,
+                Outcome::Failed => Color::Red,
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{}  ", job.summary())),
+                Span::styled(job.outcome.label(), Style::default().fg(color)),
+            ]))
+        })
+        .collect();
 
-        f.render_stateful_widget(list, area, &mut app.popup_list_state);
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" History ({}) ", app.history.jobs.len()))
+        .border_style(Style::default().fg(Color::Cyan));
+    if let Some(notice) = &app.history_notice {
+        block = block.title_bottom(format!(" {} ", notice));
     }
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(
+            Style::default()
+                .bg(crate::theme::accent())
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, area, &mut app.history_state);
+}
+
+fn render_queue(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    let items: Vec<ListItem> = app
+        .queue
+        .jobs
+        .iter()
+        .map(|job| {
+            let color = match job.status {
+                JobStatus::Pending => Color::Gray,
+                JobStatus::Running => Color::Yellow,
+                JobStatus::Done => Color::Green,
+                JobStatus::Failed(_) => Color::Red,
+            };
+            ListItem::new(vec![
+                Line::from(format!("{} -> {}", job.os.name, job.drive.name)),
+                Line::from(Span::styled(
+                    format!("   {}", job.summary()),
+                    Style::default().fg(color),
+                )),
+            ])
+        })
+        .collect();
+
+    let title = if app.queue.is_running() {
+        " Write Queue (running) "
+    } else {
+        " Write Queue "
+    };
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(Color::Cyan)),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(crate::theme::accent())
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, area, &mut app.queue_state);
+}
+
+fn render_boot_browser(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
+        .split(area);
+
+    let items: Vec<ListItem> = app
+        .boot_files
+        .iter()
+        .map(|file| {
+            let label = if file.is_dir {
+                format!("{}/", file.name)
+            } else {
+                file.name.clone()
+            };
+            ListItem::new(Line::from(label))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Boot Partition ")
+                .border_style(Style::default().fg(Color::Cyan)),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(crate::theme::accent())
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, chunks[0], &mut app.boot_files_state);
+
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(5), Constraint::Min(1)].as_ref())
+        .split(chunks[1]);
+
+    // Sanity checks for the customization we just applied
+    let has = |name: &str| app.boot_files.iter().any(|f| f.name == name);
+    let cmdline_runs_firstrun = app
+        .boot_files
+        .iter()
+        .find(|f| f.name == "cmdline.txt")
+        .and_then(|f| f.content.as_deref())
+        .is_some_and(|c| c.contains("systemd.run=/boot/firstrun.sh"));
+    let check = |ok: bool, label: &str| {
+        Line::from(Span::styled(
+            format!("{} {}", if ok { "[x]" } else { "[ ]" }, label),
+            Style::default().fg(if ok { Color::Green } else { Color::Gray }),
+        ))
+    };
+    let checks = vec![
+        check(has("firstrun.sh"), "firstrun.sh present"),
+        check(cmdline_runs_firstrun, "cmdline.txt runs firstrun.sh"),
+        check(has("ssh") || has("ssh.txt"), "ssh marker file"),
+    ];
+    let checks = Paragraph::new(checks).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Checks ")
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(checks, right[0]);
+
+    let selected = app
+        .boot_files_state
+        .selected()
+        .and_then(|i| app.boot_files.get(i));
+    let (title, content) = match selected {
+        Some(file) if file.is_dir => (file.name.clone(), "<directory>".to_string()),
+        Some(file) => (
+            format!("{} ({} bytes)", file.name, file.size),
+            file.content
+                .clone()
+                .unwrap_or_else(|| "<binary or too large to preview>".to_string()),
+        ),
+        None => (String::new(), String::new()),
+    };
+    let preview = Paragraph::new(content)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" {} ", title))
+                .border_style(Style::default().fg(Color::DarkGray)),
+        )
+        .style(Style::default().fg(Color::White))
+        .wrap(ratatui::widgets::Wrap { trim: false });
+    f.render_widget(preview, right[1]);
+}
+
+fn render_qr_code(f: &mut Frame, kind: QrKind, payload: &str, area: ratatui::layout::Rect) {
+    let lines: Vec<Line> = crate::qr::render(payload)
+        .into_iter()
+        .map(Line::from)
+        .collect();
+    let width = lines.iter().map(|l| l.width()).max().unwrap_or(0) as u16 + 2;
+    let height = lines.len() as u16 + 2;
+
+    let popup = ratatui::layout::Rect {
+        x: area.x + area.width.saturating_sub(width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width: width.min(area.width),
+        height: height.min(area.height),
+    };
+    f.render_widget(Clear, popup);
+    let p = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} ", kind.title())),
+    );
+    f.render_widget(p, popup);
 }
 
 fn centered_rect(
@@ -1952,3 +5416,14 @@ fn centered_rect(
         )
         .split(popup_layout[1])[1]
 }
+
+/// Prints the outcome of exporting or importing the configuration.
+fn config_transfer(result: anyhow::Result<String>) {
+    match result {
+        Ok(done) => println!("{}", done),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! What the OS list leaves out, fetched for the highlighted entry while the
+//! user looks at it: the entries of categories kept in a list of their own
+//! (`subitems_url`), and the `.sha256` files published next to images the
+//! list has no checksum for. Only the highlighted entry is fetched for;
+//! moving on cancels the request, so scrolling quickly through a long list
+//! never queues up downloads, and the interface never waits for any of it.
+
+use crate::os_list::OsListItem;
+use anyhow::{Context, Result, anyhow};
+use reqwest::Client;
+use std::collections::HashMap;
+use tokio::task::AbortHandle;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// The entries of a category, by the URL of their list.
+    Children(String),
+    /// The checksum of an image, by the image's URL.
+    Checksum(String),
+}
+
+/// The outcome of a [`Request`], for the same URL.
+pub enum Fetched {
+    Children(String, Result<Vec<OsListItem>, String>),
+    Checksum(String, Option<String>),
+}
+
+impl Fetched {
+    fn request(&self) -> Request {
+        match self {
+            Self::Children(url, _) => Request::Children(url.clone()),
+            Self::Checksum(url, _) => Request::Checksum(url.clone()),
+        }
+    }
+}
+
+impl Request {
+    /// What `item` lacks that can be fetched, if anything.
+    pub fn for_item(item: &OsListItem) -> Option<Self> {
+        if let Some(url) = &item.subitems_url
+            && item.subitems.is_empty()
+        {
+            return Some(Self::Children(url.clone()));
+        }
+        let url = item.url.as_deref().filter(|u| u.starts_with("http"))?;
+        if item.image_download_sha256.is_none() && item.extract_sha256.is_none() {
+            return Some(Self::Checksum(url.to_string()));
+        }
+        None
+    }
+
+    pub async fn fetch(self, client: &Client) -> Fetched {
+        match self {
+            Self::Children(url) => {
+                let items = crate::os_list::fetch_from(client, &url)
+                    .await
+                    .map(|list| list.os_list)
+                    .map_err(|e| format!("{:#}", e));
+                Fetched::Children(url, items)
+            }
+            Self::Checksum(url) => {
+                let hash = sidecar(client, &url).await.ok();
+                Fetched::Checksum(url, hash)
+            }
+        }
+    }
+}
+
+async fn sidecar(client: &Client, url: &str) -> Result<String> {
+    let sidecar = format!("{}.sha256", url);
+    let text = crate::http::get(client, &sidecar)
+        .await?
+        .text()
+        .await
+        .with_context(|| format!("Failed to download {}", sidecar))?;
+    parse_sidecar(&text).ok_or_else(|| anyhow!("{} holds no SHA-256 checksum", sidecar))
+}
+
+/// The hash from a `sha256sum` line, "<hex>  <file name>".
+fn parse_sidecar(text: &str) -> Option<String> {
+    let hash = text.split_whitespace().next()?;
+    (hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| hash.to_ascii_lowercase())
+}
+
+/// Results by URL, and the one request in flight. Failures are remembered
+/// too, so an entry without a sidecar isn't asked for again each time it is
+/// highlighted.
+#[derive(Default)]
+pub struct Metadata {
+    children: HashMap<String, Result<Vec<OsListItem>, String>>,
+    checksums: HashMap<String, Option<String>>,
+    pending: Option<(Request, AbortHandle)>,
+}
+
+impl Metadata {
+    /// Called whenever the highlighted entry may have changed, with what it
+    /// lacks ([`Request::for_item`]), or None when no OS list is shown.
+    /// Cancels the request for the entry that was highlighted before, and
+    /// returns `wanted` if it still has to be started.
+    pub fn highlight(&mut self, wanted: Option<Request>) -> Option<Request> {
+        let request = wanted.filter(|r| !self.is_done(r));
+        if let Some((pending, handle)) = &self.pending {
+            if request.as_ref() == Some(pending) {
+                return None;
+            }
+            handle.abort();
+            self.pending = None;
+        }
+        request
+    }
+
+    pub fn started(&mut self, request: Request, handle: AbortHandle) {
+        self.pending = Some((request, handle));
+    }
+
+    pub fn finished(&mut self, fetched: Fetched) {
+        if self.is_pending(&fetched.request()) {
+            self.pending = None;
+        }
+        match fetched {
+            Fetched::Children(url, items) => {
+                self.children.insert(url, items);
+            }
+            Fetched::Checksum(url, hash) => {
+                self.checksums.insert(url, hash);
+            }
+        }
+    }
+
+    fn is_done(&self, request: &Request) -> bool {
+        match request {
+            Request::Children(url) => self.children.contains_key(url),
+            Request::Checksum(url) => self.checksums.contains_key(url),
+        }
+    }
+
+    pub fn is_pending(&self, request: &Request) -> bool {
+        self.pending.as_ref().is_some_and(|(r, _)| r == request)
+    }
+
+    /// The entries of the category listed at `url`, or why they couldn't be
+    /// fetched; None until the request finished.
+    pub fn children(&self, url: &str) -> Option<&Result<Vec<OsListItem>, String>> {
+        self.children.get(url)
+    }
+
+    pub fn checksum(&self, url: &str) -> Option<&str> {
+        self.checksums.get(url)?.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sha256sum_lines() {
+        let hash = "3A".repeat(32);
+        assert_eq!(
+            parse_sidecar(&format!(
+                "{}  2024-11-19-raspios-bookworm-arm64.img.xz\n",
+                hash
+            )),
+            Some("3a".repeat(32))
+        );
+        assert_eq!(parse_sidecar("<html>Not Found</html>"), None);
+        assert_eq!(parse_sidecar(""), None);
+    }
+
+    #[tokio::test]
+    async fn cancels_the_request_of_the_previous_entry() {
+        let category = OsListItem {
+            subitems_url: Some("https://example.com/ubuntu.json".to_string()),
+            url: None,
+            ..OsListItem::custom("Ubuntu".to_string(), String::new())
+        };
+        let image = OsListItem::custom(
+            "Image".to_string(),
+            "https://example.com/os.img.xz".to_string(),
+        );
+        let mut metadata = Metadata::default();
+
+        let request = metadata.highlight(Request::for_item(&category)).unwrap();
+        let task = tokio::spawn(std::future::pending::<()>());
+        metadata.started(request.clone(), task.abort_handle());
+        // Still highlighted: nothing new to start
+        assert!(metadata.highlight(Request::for_item(&category)).is_none());
+
+        let next = metadata.highlight(Request::for_item(&image));
+        assert_eq!(
+            next,
+            Some(Request::Checksum(
+                "https://example.com/os.img.xz".to_string()
+            ))
+        );
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert!(!metadata.is_pending(&request));
+
+        assert!(metadata.highlight(Request::for_item(&image)).is_some());
+        metadata.finished(Fetched::Checksum(
+            "https://example.com/os.img.xz".to_string(),
+            None,
+        ));
+        assert!(metadata.highlight(Request::for_item(&image)).is_none());
+        assert_eq!(metadata.checksum("https://example.com/os.img.xz"), None);
+    }
+}
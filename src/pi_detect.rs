@@ -0,0 +1,119 @@
+use crate::os_list::Device;
+use std::path::Path;
+
+/// A Raspberry Pi found on the USB bus while running in gadget (Ethernet or
+/// serial) mode.
+pub struct DetectedPi {
+    pub model: &'static str,
+    pub product: String,
+}
+
+/// Linux USB gadgets report "<kernel> with <udc>" as their manufacturer
+/// string. The UDC's bus address identifies the SoC, which is enough to tell
+/// the Pi families apart.
+const UDC_MODELS: &[(&str, &str)] = &[
+    ("20980000.usb", "Raspberry Pi Zero"),
+    ("3f980000.usb", "Raspberry Pi Zero 2 W"),
+    ("fe980000.usb", "Raspberry Pi 4"),
+    ("1000480000.usb", "Raspberry Pi 5"),
+];
+
+pub fn detect_attached_pi() -> Option<DetectedPi> {
+    detect(Path::new("/sys"))
+}
+
+/// The first Pi gadget among the USB devices under the sysfs root `sys`.
+fn detect(sys: &Path) -> Option<DetectedPi> {
+    let entries = std::fs::read_dir(sys.join("bus/usb/devices")).ok()?;
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let Ok(manufacturer) = std::fs::read_to_string(path.join("manufacturer")) else {
+            continue;
+        };
+        if let Some((_, model)) = UDC_MODELS
+            .iter()
+            .find(|(udc, _)| manufacturer.trim().ends_with(udc))
+        {
+            let product = std::fs::read_to_string(path.join("product"))
+                .map(|p| p.trim().to_string())
+                .unwrap_or_else(|_| "USB gadget".to_string());
+            return Some(DetectedPi { model, product });
+        }
+    }
+    None
+}
+
+/// Index of the device entry best matching the detected model: an exact name
+/// match first, then the first entry whose name starts with it.
+pub fn suggest_device(devices: &[Device], detected: &DetectedPi) -> Option<usize> {
+    devices
+        .iter()
+        .position(|d| d.name == detected.model)
+        .or_else(|| {
+            devices
+                .iter()
+                .position(|d| d.name.starts_with(detected.model))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn device(name: &str) -> Device {
+        Device {
+            name: name.to_string(),
+            tags: Vec::new(),
+            icon: None,
+            description: String::new(),
+            matching_type: None,
+            capabilities: Vec::new(),
+            default: false,
+        }
+    }
+
+    #[test]
+    fn detects_a_pi_gadget_and_suggests_its_model() {
+        let root = tempfile::tempdir().unwrap();
+        let sys = root.path();
+        assert!(detect(sys).is_none());
+
+        let devices = sys.join("bus/usb/devices");
+        for (dir, manufacturer, product) in [
+            ("1-1", "Logitech", Some("USB Receiver")),
+            ("usb1", "Linux 6.6.31 xhci-hcd", None),
+            (
+                "1-2",
+                "Linux 6.6.31+rpt-rpi-2712 with 1000480000.usb\n",
+                Some("RNDIS/Ethernet Gadget\n"),
+            ),
+        ] {
+            fs::create_dir_all(devices.join(dir)).unwrap();
+            fs::write(devices.join(dir).join("manufacturer"), manufacturer).unwrap();
+            if let Some(product) = product {
+                fs::write(devices.join(dir).join("product"), product).unwrap();
+            }
+        }
+        let detected = detect(sys).unwrap();
+        assert_eq!(detected.model, "Raspberry Pi 5");
+        assert_eq!(detected.product, "RNDIS/Ethernet Gadget");
+
+        fs::remove_file(devices.join("1-2/product")).unwrap();
+        assert_eq!(detect(sys).unwrap().product, "USB gadget");
+
+        let list = [
+            device("Raspberry Pi 4"),
+            device("Raspberry Pi 500"),
+            device("Raspberry Pi 5"),
+        ];
+        assert_eq!(suggest_device(&list, &detected), Some(2));
+        let zero = DetectedPi {
+            model: "Raspberry Pi Zero",
+            product: String::new(),
+        };
+        let list = [device("Raspberry Pi 5"), device("Raspberry Pi Zero 2 W")];
+        assert_eq!(suggest_device(&list, &zero), Some(1));
+        assert_eq!(suggest_device(&list[..1], &zero), None);
+    }
+}
@@ -0,0 +1,308 @@
+//! Keeping the machine awake while writing. A laptop suspending on lid close
+//! or idle ruins a half written card, so sleep is blocked for as long as an
+//! [`Inhibitor`] lives. Dropping it releases the block, also when the write
+//! is cancelled or the TUI exits.
+//!
+//! - Linux: a blocking logind inhibitor for sleep, idle and the lid switch,
+//!   held until the file descriptor logind returns is closed.
+//! - macOS: `caffeinate` keeping the display, system and disks awake for as
+//!   long as it runs.
+//! - Windows: SetThreadExecutionState on a thread kept for it, since the
+//!   state belongs to the thread that set it.
+//!
+//! A laptop dying of an empty battery ruins the card just the same, so the
+//! [`Battery`] is checked before a write starts: read from
+//! /sys/class/power_supply on Linux, `pmset -g batt` on macOS and
+//! GetSystemPowerStatus on Windows.
+
+pub struct Inhibitor {
+    _held: platform::Held,
+}
+
+impl Inhibitor {
+    /// Takes the inhibitor, shown with `why` where the system lists them
+    /// (`systemd-inhibit --list`). None if the system won't give one.
+    pub fn take(why: &str) -> Option<Self> {
+        platform::take(why).map(|held| Self { _held: held })
+    }
+}
+
+/// Below this charge, a write on battery is warned about.
+pub const LOW_BATTERY: u8 = 20;
+
+/// The host's battery, on machines that have one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Battery {
+    pub percent: u8,
+    /// Plugged in, whether charging or full.
+    pub on_ac: bool,
+}
+
+impl Battery {
+    pub fn read() -> Option<Self> {
+        platform::battery()
+    }
+
+    /// What to tell before a write starts, if anything.
+    pub fn warning(self) -> Option<String> {
+        (!self.on_ac && self.percent < LOW_BATTERY).then(|| {
+            format!(
+                "Battery at {}% and not charging: connect the charger before writing.",
+                self.percent
+            )
+        })
+    }
+}
+
+/// Why a write can't start, when `require_ac` asks for it to be plugged in.
+pub fn refuses_battery(require_ac: bool) -> Option<String> {
+    let battery = Battery::read().filter(|b| require_ac && !b.on_ac)?;
+    Some(format!(
+        "Running on battery ({}%). Writing only starts on AC power, as set in the \
+         Options: connect the charger and confirm again.",
+        battery.percent
+    ))
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::Battery;
+    use std::path::Path;
+    use zbus::blocking::Connection;
+
+    /// What is inhibited, most first. Some polkit setups don't let users
+    /// block the lid switch, then sleep and idle are still worth blocking.
+    const WHAT: [&str; 2] = ["sleep:idle:handle-lid-switch", "sleep:idle"];
+
+    pub type Held = std::os::fd::OwnedFd;
+
+    pub fn take(why: &str) -> Option<Held> {
+        let conn = Connection::system().ok()?;
+        WHAT.iter().find_map(|what| {
+            let reply = conn
+                .call_method(
+                    Some("org.freedesktop.login1"),
+                    "/org/freedesktop/login1",
+                    Some("org.freedesktop.login1.Manager"),
+                    "Inhibit",
+                    &(*what, "rpi-imager-tui", why, "block"),
+                )
+                .ok()?;
+            let fd: zbus::zvariant::OwnedFd = reply.body().deserialize().ok()?;
+            Some(fd.into())
+        })
+    }
+
+    pub fn battery() -> Option<Battery> {
+        from_sysfs(Path::new("/sys/class/power_supply"))
+    }
+
+    /// The system batteries under `root` (not those of a mouse or headset,
+    /// which are scoped to their device), averaged, and whether a charger is
+    /// online. Without a charger to ask, a battery that isn't discharging is
+    /// taken to be plugged in.
+    pub fn from_sysfs(root: &Path) -> Option<Battery> {
+        let read = |dir: &Path, name: &str| {
+            std::fs::read_to_string(dir.join(name))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+        let mut charges = Vec::new();
+        let mut discharging = false;
+        let mut charger = None;
+        for entry in std::fs::read_dir(root).ok()?.flatten() {
+            let dir = entry.path();
+            match read(&dir, "type").as_str() {
+                "Battery" if read(&dir, "scope") != "Device" => {
+                    if let Ok(percent) = read(&dir, "capacity").parse::<u8>() {
+                        charges.push(percent);
+                    }
+                    discharging |= read(&dir, "status") == "Discharging";
+                }
+                "Mains" | "USB" | "USB_C" | "USB_PD" => {
+                    let online = read(&dir, "online") == "1";
+                    charger = Some(charger.unwrap_or(false) || online);
+                }
+                _ => {}
+            }
+        }
+        if charges.is_empty() {
+            return None;
+        }
+        let percent = charges.iter().map(|&p| p as usize).sum::<usize>() / charges.len();
+        Some(Battery {
+            percent: percent.min(100) as u8,
+            on_ac: charger.unwrap_or(!discharging),
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::{Child, Command, Stdio};
+
+    pub struct Held(Child);
+
+    impl Drop for Held {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    pub fn battery() -> Option<super::Battery> {
+        let output = Command::new("pmset").args(["-g", "batt"]).output().ok()?;
+        parse_pmset(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// "Now drawing from 'AC Power'" and " -InternalBattery-0 (id=...)
+    /// 85%; charging; ...", or no battery line on desktops.
+    fn parse_pmset(text: &str) -> Option<super::Battery> {
+        let percent = text
+            .lines()
+            .find(|line| line.contains("InternalBattery"))?
+            .split_whitespace()
+            .find_map(|word| word.strip_suffix("%;")?.parse().ok())?;
+        Some(super::Battery {
+            percent,
+            on_ac: text.contains("'AC Power'"),
+        })
+    }
+
+    pub fn take(_why: &str) -> Option<Held> {
+        // -w also ends it if this process dies without dropping the inhibitor
+        Command::new("caffeinate")
+            .args(["-dims", "-w", &std::process::id().to_string()])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()
+            .map(Held)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::mpsc;
+
+    const ES_CONTINUOUS: u32 = 0x8000_0000;
+    const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+    const ES_DISPLAY_REQUIRED: u32 = 0x0000_0002;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemPowerStatus {
+        ac_line_status: u8,
+        battery_flag: u8,
+        battery_life_percent: u8,
+        _system_status_flag: u8,
+        _battery_life_time: u32,
+        _battery_full_life_time: u32,
+    }
+
+    /// In `battery_flag`: the machine has no battery.
+    const NO_SYSTEM_BATTERY: u8 = 128;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+    }
+
+    pub fn battery() -> Option<super::Battery> {
+        let mut status = SystemPowerStatus::default();
+        // SAFETY: fills in the struct it is given
+        if unsafe { GetSystemPowerStatus(&mut status) } == 0
+            || status.battery_flag & NO_SYSTEM_BATTERY != 0
+            || status.battery_life_percent > 100
+        {
+            return None;
+        }
+        Some(super::Battery {
+            percent: status.battery_life_percent,
+            on_ac: status.ac_line_status == 1,
+        })
+    }
+
+    /// The thread holding the state exits once this is dropped.
+    pub struct Held {
+        _release: mpsc::Sender<()>,
+    }
+
+    pub fn take(_why: &str) -> Option<Held> {
+        let (release, released) = mpsc::channel::<()>();
+        let (taken, result) = mpsc::channel();
+        std::thread::spawn(move || {
+            // SAFETY: only sets flags on the calling thread
+            let previous = unsafe {
+                SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED | ES_DISPLAY_REQUIRED)
+            };
+            let _ = taken.send(previous != 0);
+            // Errors once the sender is dropped
+            let _ = released.recv();
+            // SAFETY: as above
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        });
+        result.recv().ok()?.then_some(Held { _release: release })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    pub type Held = ();
+
+    pub fn take(_why: &str) -> Option<Held> {
+        None
+    }
+
+    pub fn battery() -> Option<super::Battery> {
+        None
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_battery_from_sysfs() {
+        let root = tempfile::tempdir().unwrap();
+        let supply = |name: &str, files: &[(&str, &str)]| {
+            let dir = root.path().join(name);
+            std::fs::create_dir(&dir).unwrap();
+            for (file, value) in files {
+                std::fs::write(dir.join(file), format!("{}\n", value)).unwrap();
+            }
+        };
+        assert_eq!(platform::from_sysfs(root.path()), None);
+
+        supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("capacity", "14"),
+                ("status", "Discharging"),
+            ],
+        );
+        // A wireless mouse's battery isn't the laptop's
+        supply(
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "90")],
+        );
+        let battery = platform::from_sysfs(root.path()).unwrap();
+        assert_eq!(
+            battery,
+            Battery {
+                percent: 14,
+                on_ac: false
+            }
+        );
+        assert!(battery.warning().is_some());
+
+        supply("AC", &[("type", "Mains"), ("online", "1")]);
+        let battery = platform::from_sysfs(root.path()).unwrap();
+        assert!(battery.on_ac);
+        assert_eq!(battery.warning(), None);
+    }
+}
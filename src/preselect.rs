@@ -0,0 +1,126 @@
+//! Selections given on the command line, e.g. `--device pi5 --os "Raspberry
+//! Pi OS Lite (64-bit)" --drive /dev/sda`, so a scripted launch lands on the
+//! customization screen with everything but the review done. Names are
+//! matched loosely, since they are typed by hand.
+
+use crate::drivelist::Drive;
+use crate::os_list::{Device, OsListItem};
+use std::path::Path;
+
+#[derive(Debug, Default)]
+pub struct Preselect {
+    pub device: Option<String>,
+    pub os: Option<String>,
+    pub drive: Option<String>,
+}
+
+impl Preselect {
+    pub fn is_empty(&self) -> bool {
+        self.device.is_none() && self.os.is_none() && self.drive.is_none()
+    }
+}
+
+/// The device named `query`: the exact name, then a name ending in it with
+/// spaces and case ignored ("pi5" for "Raspberry Pi 5"), then a tag starting
+/// with it ("pi5" for "pi5-64bit").
+pub fn find_device(devices: &[Device], query: &str) -> Option<usize> {
+    let wanted = normalize(query);
+    devices
+        .iter()
+        .position(|d| d.name.eq_ignore_ascii_case(query))
+        .or_else(|| {
+            devices
+                .iter()
+                .position(|d| !wanted.is_empty() && normalize(&d.name).ends_with(&wanted))
+        })
+        .or_else(|| {
+            devices.iter().position(|d| {
+                d.tags
+                    .iter()
+                    .any(|tag| tag == query || tag.starts_with(&format!("{}-", query)))
+            })
+        })
+}
+
+/// The image named `query` anywhere in the list, by exact name first, then
+/// by the first name containing it. Categories are never picked.
+pub fn find_os(items: &[OsListItem], query: &str) -> Option<OsListItem> {
+    let mut images = Vec::new();
+    collect_images(items, &mut images);
+    let query = query.to_lowercase();
+    images
+        .iter()
+        .find(|item| item.name.to_lowercase() == query)
+        .or_else(|| {
+            images
+                .iter()
+                .find(|item| item.name.to_lowercase().contains(&query))
+        })
+        .map(|item| (*item).clone())
+}
+
+/// The drive at `query`, which may also be a symlink such as
+/// /dev/disk/by-id/usb-....
+pub fn find_drive(drives: &[Drive], query: &str) -> Option<usize> {
+    let canonical = std::fs::canonicalize(query).ok();
+    drives
+        .iter()
+        .position(|d| d.name == query || canonical.as_deref() == Some(Path::new(&d.name)))
+}
+
+fn collect_images<'a>(items: &'a [OsListItem], images: &mut Vec<&'a OsListItem>) {
+    for item in items {
+        if item.subitems.is_empty() {
+            // Skips "Use custom image", which only asks for a file
+            if item.url.is_some() {
+                images.push(item);
+            }
+        } else {
+            collect_images(&item.subitems, images);
+        }
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_typed_names() {
+        let devices: Vec<Device> = serde_json::from_value(serde_json::json!([
+            {"name": "Raspberry Pi 500", "tags": ["pi5-64bit"], "icon": null},
+            {"name": "Raspberry Pi 5", "tags": ["pi5-64bit"], "icon": null},
+            {"name": "Raspberry Pi Zero 2 W", "tags": ["pi3-64bit"], "icon": null},
+        ]))
+        .unwrap();
+        assert_eq!(find_device(&devices, "pi5"), Some(1));
+        assert_eq!(find_device(&devices, "raspberry pi 500"), Some(0));
+        assert_eq!(find_device(&devices, "pi3"), Some(2));
+        assert_eq!(find_device(&devices, "pi4"), None);
+
+        let mut items: Vec<OsListItem> = serde_json::from_value(serde_json::json!([
+            {"name": "Raspberry Pi OS (64-bit)", "icon": null, "url": "https://example.com/a.img.xz"},
+            {"name": "Raspberry Pi OS (other)", "icon": null, "subitems": [
+                {"name": "Raspberry Pi OS Lite (64-bit)", "icon": null, "url": "https://example.com/b.img.xz"},
+                {"name": "Raspberry Pi OS Full (64-bit)", "icon": null, "url": "https://example.com/c.img.xz"},
+            ]},
+        ]))
+        .unwrap();
+        items.push(OsListItem::custom_entry());
+        let os = find_os(&items, "raspberry pi os lite (64-bit)").unwrap();
+        assert_eq!(os.name, "Raspberry Pi OS Lite (64-bit)");
+        assert_eq!(
+            find_os(&items, "Full").unwrap().name,
+            "Raspberry Pi OS Full (64-bit)"
+        );
+        assert!(find_os(&items, "other").is_none());
+        assert!(find_os(&items, "custom").is_none());
+    }
+}
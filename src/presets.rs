@@ -0,0 +1,209 @@
+//! Named sets of image settings, like "office-wifi" or "headless-kiosk",
+//! offered on the Presets screen before the customization step. Each one is
+//! a TOML file in the presets directory next to the config, holding the
+//! options as they were when it was saved. Only the image settings are
+//! taken from it (hostname, localization, user, Wi-Fi and SSH), so the tool
+//! options stay as they are. Passwords go into a preset as into the config:
+//! only when remembered, and sealed when a passphrase is set.
+
+use crate::customization::{CustomizationOptions, CustomizationSection};
+use anyhow::{Context, Result, bail};
+use std::path::PathBuf;
+
+pub struct Presets {
+    dir: PathBuf,
+}
+
+impl Presets {
+    /// The presets next to the config file.
+    pub fn open() -> Option<Self> {
+        let config = CustomizationOptions::config_path()?;
+        Some(Self::at(config.with_file_name("presets")))
+    }
+
+    pub fn at(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+
+    /// The names of the saved presets, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_suffix(".toml").map(str::to_string)
+            })
+            .collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        names
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.toml", name))
+    }
+
+    /// Saves `options` as `name`, replacing a preset of that name.
+    pub fn save(&self, name: &str, options: &CustomizationOptions) -> Result<()> {
+        validate_name(name).map_err(anyhow::Error::msg)?;
+        if crate::customization::saving_disabled() {
+            bail!("Presets aren't saved while running as root");
+        }
+        let text = options.to_toml()?;
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.path(name);
+        std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn load(&self, name: &str) -> Result<CustomizationOptions> {
+        let path = self.path(name);
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        CustomizationOptions::from_toml(&text)
+            .with_context(|| format!("{} is not a valid preset", path.display()))
+    }
+
+    pub fn rename(&self, from: &str, to: &str) -> Result<()> {
+        validate_name(to).map_err(anyhow::Error::msg)?;
+        if from == to {
+            return Ok(());
+        }
+        if self.path(to).exists() {
+            bail!("A preset named {} already exists", to);
+        }
+        std::fs::rename(self.path(from), self.path(to))
+            .with_context(|| format!("Failed to rename the preset {}", from))
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        std::fs::remove_file(self.path(name))
+            .with_context(|| format!("Failed to delete the preset {}", name))
+    }
+}
+
+/// Preset names become file names, so they are kept to letters, digits,
+/// spaces, dashes, underscores and dots, and can't start with a dot.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Enter a name".to_string());
+    }
+    if name.starts_with('.') || name.chars().count() > 64 {
+        return Err("Use up to 64 characters, not starting with a dot".to_string());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+    {
+        return Err("Use letters, digits, spaces, '-', '_' and '.'".to_string());
+    }
+    Ok(())
+}
+
+/// Takes the image settings of `preset` into `options`. Passwords sealed in
+/// the preset are opened with `passphrase`; without one, or with a wrong
+/// one, nothing is taken. A preset without a password keeps the current one
+/// for the same user or network.
+pub fn apply(
+    options: &mut CustomizationOptions,
+    mut preset: CustomizationOptions,
+    passphrase: Option<&str>,
+) -> Result<()> {
+    if preset.sealed_secrets.is_some() {
+        let Some(passphrase) = passphrase else {
+            bail!("The passwords of this preset are encrypted; enter its passphrase");
+        };
+        preset
+            .unlock(passphrase.to_string())
+            .context("Failed to open the passwords of this preset")?;
+    }
+    if preset.password.is_none() && preset.user_name == options.user_name {
+        preset.password = options.password.clone();
+    }
+    if preset.wifi_password.is_empty() && preset.wifi_ssid == options.wifi_ssid {
+        preset.wifi_password = options.wifi_password.clone();
+    }
+    for section in CustomizationSection::ALL {
+        options.copy_section(&preset, section);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_renames_and_applies_presets() {
+        let dir = tempfile::tempdir().unwrap();
+        let presets = Presets::at(dir.path().join("presets"));
+        let kiosk = CustomizationOptions {
+            hostname: "kiosk".to_string(),
+            ssh_enabled: true,
+            wifi_password: "not remembered".to_string(),
+            download_connections: Some(8),
+            ..CustomizationOptions::default()
+        };
+        presets.save("headless-kiosk", &kiosk).unwrap();
+        presets
+            .save("home", &CustomizationOptions::default())
+            .unwrap();
+        assert!(presets.save("../escape", &kiosk).is_err());
+
+        presets.rename("headless-kiosk", "Kiosk").unwrap();
+        assert!(presets.rename("Kiosk", "home").is_err());
+        assert_eq!(presets.names(), ["home", "Kiosk"]);
+
+        let mut options = CustomizationOptions::default();
+        apply(&mut options, presets.load("Kiosk").unwrap(), None).unwrap();
+        assert_eq!(options.hostname, "kiosk");
+        assert!(options.ssh_enabled);
+        assert!(options.wifi_password.is_empty());
+        // Tool options aren't part of a preset
+        assert_eq!(options.download_connections, None);
+
+        presets.delete("home").unwrap();
+        assert_eq!(presets.names(), ["Kiosk"]);
+    }
+
+    #[test]
+    fn sealed_passwords_need_the_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let presets = Presets::at(dir.path());
+        let office = CustomizationOptions {
+            wifi_ssid: "office".to_string(),
+            wifi_password: "office-secret".to_string(),
+            remember_secrets: true,
+            passphrase: Some("open sesame".to_string()),
+            ..CustomizationOptions::default()
+        };
+        presets.save("office", &office).unwrap();
+        let current = CustomizationOptions {
+            user_name: "pi".to_string(),
+            password: Some("current".to_string()),
+            ..CustomizationOptions::default()
+        };
+
+        // Nothing is taken without the passphrase, or with a wrong one
+        for passphrase in [None, Some("wrong")] {
+            let mut options = current.clone();
+            let preset = presets.load("office").unwrap();
+            assert!(apply(&mut options, preset, passphrase).is_err());
+            assert_eq!(options.wifi_ssid, current.wifi_ssid);
+            assert_eq!(options.password, current.password);
+        }
+
+        let mut options = current.clone();
+        let preset = presets.load("office").unwrap();
+        apply(&mut options, preset, Some("open sesame")).unwrap();
+        assert_eq!(options.wifi_ssid, "office");
+        assert_eq!(options.wifi_password, "office-secret");
+        // The preset has no password for the same user
+        assert_eq!(options.password.as_deref(), Some("current"));
+    }
+}
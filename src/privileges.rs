@@ -0,0 +1,100 @@
+use crate::drivelist::Drive;
+use std::path::Path;
+
+/// Whether the current user can open the listed block devices, and how the
+/// privileged worker will be started. The TUI itself only runs as root when
+/// the user restarts it elevated after a permission error, and saves no
+/// settings then.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceAccess {
+    /// Every listed device could be opened for writing.
+    pub writable: bool,
+    /// The first of sudo/pkexec found on PATH.
+    pub escalation: Option<&'static str>,
+    /// udisks2 is running and may open devices without either.
+    pub udisks: bool,
+}
+
+impl DeviceAccess {
+    /// Only the permissions are looked at, without opening the devices:
+    /// closing a device opened for writing makes udev probe it again, and
+    /// this runs on every drive refresh.
+    pub fn check(drives: &[Drive]) -> Self {
+        let writable = !drives
+            .iter()
+            .filter(|d| d.name.starts_with("/dev/"))
+            .any(|d| denied(&d.name));
+        Self {
+            writable,
+            escalation: ["sudo", "pkexec"].into_iter().find(|t| in_path(t)),
+            #[cfg(unix)]
+            udisks: crate::udisks::available(),
+            #[cfg(not(unix))]
+            udisks: false,
+        }
+    }
+
+    /// Banner shown above the drive list, if the user should know about
+    /// elevation before picking a drive.
+    pub fn banner(&self) -> Option<String> {
+        match self.escalation {
+            // Mounting the boot partition needs root even with disk group access
+            None if !self.udisks => Some(
+                "Writing needs root, but neither sudo nor pkexec was found: re-run as root"
+                    .to_string(),
+            ),
+            _ if self.writable => None,
+            None => Some(
+                "Your user can't open block devices: writing will ask udisks2 for access"
+                    .to_string(),
+            ),
+            Some(tool) if self.udisks => Some(format!(
+                "Your user can't open block devices: writing will ask udisks2 for access, then {}",
+                tool
+            )),
+            Some(tool) => Some(format!(
+                "Your user can't open block devices: writing will ask for your password via {}",
+                tool
+            )),
+        }
+    }
+
+    /// Whether a worker can be started at all.
+    pub fn can_write(&self) -> bool {
+        self.escalation.is_some() || self.udisks
+    }
+}
+
+/// Whether the user may not open `path` for reading and writing.
+#[cfg(unix)]
+fn denied(path: &str) -> bool {
+    use nix::unistd::{AccessFlags, access};
+    matches!(
+        access(path, AccessFlags::R_OK | AccessFlags::W_OK),
+        Err(nix::errno::Errno::EACCES)
+    )
+}
+
+/// Windows drives have no /dev/ path, so this is never asked.
+#[cfg(not(unix))]
+fn denied(_path: &str) -> bool {
+    false
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| Path::new(&dir).join(program).is_file())
+    })
+}
+
+/// Whether a worker error looks like it was caused by missing privileges,
+/// in which case re-running the whole app elevated may help.
+pub fn is_permission_error(message: &str) -> bool {
+    [
+        "Permission denied",
+        "Operation not permitted",
+        "exited with code 126",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
@@ -0,0 +1,74 @@
+use crate::customization::CustomizationOptions;
+use qrcode::QrCode;
+use qrcode::render::unicode::Dense1x2;
+
+/// What the QR code on the Finished screen encodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QrKind {
+    Ssh,
+    Wifi,
+}
+
+impl QrKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            QrKind::Ssh => "SSH",
+            QrKind::Wifi => "Wi-Fi",
+        }
+    }
+
+    /// The payload for this kind, or None if the customization doesn't
+    /// configure it.
+    pub fn payload(&self, options: &CustomizationOptions) -> Option<String> {
+        match self {
+            QrKind::Ssh if options.ssh_enabled => Some(format!(
+                "ssh://{}@{}.local",
+                options.user_name, options.hostname
+            )),
+            QrKind::Wifi if !options.wifi_ssid.is_empty() => Some(wifi_payload(options)),
+            _ => None,
+        }
+    }
+}
+
+/// Wi-Fi network config in the format understood by Android and iOS cameras.
+fn wifi_payload(options: &CustomizationOptions) -> String {
+    let mut payload = format!("WIFI:S:{};", escape(&options.wifi_ssid));
+    if options.wifi_password.is_empty() {
+        payload.push_str("T:nopass;");
+    } else {
+        payload.push_str(&format!("T:WPA;P:{};", escape(&options.wifi_password)));
+    }
+    if options.wifi_hidden {
+        payload.push_str("H:true;");
+    }
+    payload.push(';');
+    payload
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Renders `data` as lines of half-block characters, two modules per line.
+/// Colours are inverted so the code reads correctly on a dark terminal.
+pub fn render(data: &str) -> Vec<String> {
+    let Ok(code) = QrCode::new(data.as_bytes()) else {
+        return Vec::new();
+    };
+    code.render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
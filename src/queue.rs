@@ -0,0 +1,133 @@
+use crate::drivelist::Drive;
+use crate::ipc::{ProgressEvent, WritingPhase};
+use crate::os_list::OsListItem;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Done,
+    Failed(String),
+}
+
+/// A write configured in the wizard, waiting for its turn.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub os: OsListItem,
+    pub drive: Drive,
+    /// OS list category, reported with telemetry.
+    pub category: String,
+    /// Worker command line, built when the job was queued.
+    pub args: Vec<String>,
+    pub status: JobStatus,
+    /// Fraction of the current phase done.
+    pub progress: f64,
+    pub phase: Option<WritingPhase>,
+    pub message: String,
+}
+
+impl Job {
+    /// One-line state for the queue view, e.g. "Writing 42.5%".
+    pub fn summary(&self) -> String {
+        match &self.status {
+            JobStatus::Pending => "Pending".to_string(),
+            JobStatus::Running => {
+                let phase = match self.phase {
+                    Some(WritingPhase::Downloading) => "Downloading",
+                    Some(WritingPhase::Syncing) => "Syncing",
+                    Some(WritingPhase::Customizing) => "Customizing",
+                    Some(WritingPhase::Verifying) => "Verifying",
+                    _ => "Writing",
+                };
+                format!("{} {:.1}%", phase, self.progress * 100.0)
+            }
+            JobStatus::Done if self.message.is_empty() => "Done".to_string(),
+            JobStatus::Done => format!("Done ({})", self.message),
+            JobStatus::Failed(err) => format!("Failed: {}", err),
+        }
+    }
+}
+
+/// Jobs run one at a time, in the order they were queued, so only one
+/// worker competes for bandwidth and the password prompt at a time.
+#[derive(Default)]
+pub struct WriteQueue {
+    pub jobs: Vec<Job>,
+}
+
+impl WriteQueue {
+    /// Queues a write, unless an unfinished job already targets the drive.
+    pub fn push(
+        &mut self,
+        os: OsListItem,
+        drive: Drive,
+        category: String,
+        args: Vec<String>,
+    ) -> Result<(), String> {
+        if self.jobs.iter().any(|j| {
+            j.drive.name == drive.name
+                && matches!(j.status, JobStatus::Pending | JobStatus::Running)
+        }) {
+            return Err(format!("{} already has a queued write", drive.name));
+        }
+        self.jobs.push(Job {
+            os,
+            drive,
+            category,
+            args,
+            status: JobStatus::Pending,
+            progress: 0.0,
+            phase: None,
+            message: String::new(),
+        });
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.jobs.iter().any(|j| j.status == JobStatus::Running)
+    }
+
+    /// Marks the next pending job as running and returns its index.
+    pub fn start_next(&mut self) -> Option<usize> {
+        if self.is_running() {
+            return None;
+        }
+        let index = self
+            .jobs
+            .iter()
+            .position(|j| j.status == JobStatus::Pending)?;
+        self.jobs[index].status = JobStatus::Running;
+        Some(index)
+    }
+
+    /// Applies a worker event to a job. Returns true once the job is over.
+    pub fn update(&mut self, index: usize, event: ProgressEvent) -> bool {
+        let Some(job) = self.jobs.get_mut(index) else {
+            return false;
+        };
+        match event {
+            ProgressEvent::Progress { phase, fraction } => {
+                job.phase = Some(phase);
+                job.progress = fraction;
+            }
+            ProgressEvent::Status(msg) => job.message = msg,
+            ProgressEvent::Finished => {
+                job.status = JobStatus::Done;
+                job.message.clear();
+            }
+            ProgressEvent::CustomizationFailed(err) => {
+                job.status = JobStatus::Done;
+                job.message = format!("customization failed: {}", err);
+            }
+            ProgressEvent::Error { message, .. } => job.status = JobStatus::Failed(message),
+            _ => {}
+        }
+        !matches!(job.status, JobStatus::Pending | JobStatus::Running)
+    }
+
+    /// Drops finished and failed jobs from the list.
+    pub fn clear_completed(&mut self) {
+        self.jobs
+            .retain(|j| matches!(j.status, JobStatus::Pending | JobStatus::Running));
+    }
+}
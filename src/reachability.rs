@@ -0,0 +1,137 @@
+use anyhow::{Result, anyhow};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The kernel's IPv4 neighbour table.
+const ARP_TABLE: &str = "/proc/net/arp";
+
+/// MAC prefixes Raspberry Pi Ltd and the Raspberry Pi Foundation assign.
+const PI_OUIS: [&str; 6] = [
+    "b8:27:eb", "dc:a6:32", "e4:5f:01", "28:cd:c1", "d8:3a:dd", "2c:cf:67",
+];
+
+/// How the Pi was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Found {
+    /// Its hostname resolved over mDNS.
+    Named(IpAddr),
+    /// A Raspberry Pi that wasn't there before showed up in the neighbour
+    /// table; it may be another Pi booting on the same network.
+    Neighbour(IpAddr),
+}
+
+impl Found {
+    pub fn ip(self) -> IpAddr {
+        match self {
+            Self::Named(ip) | Self::Neighbour(ip) => ip,
+        }
+    }
+}
+
+/// Waits for a freshly flashed Pi to announce `<hostname>.local` over mDNS.
+/// Networks that drop multicast never resolve the name, so a Pi appearing in
+/// the neighbour table counts too. First boot resizes the filesystem and
+/// reboots, so this can take minutes.
+pub async fn wait_for_host(hostname: &str, timeout: Duration) -> Result<Found> {
+    let name = format!("{}.local", hostname);
+    let deadline = Instant::now() + timeout;
+    // Pis already on the network aren't the one being booted
+    let known = neighbour_pis().await;
+
+    while Instant::now() < deadline {
+        if let Some(ip) = resolve(&name).await {
+            return Ok(Found::Named(ip));
+        }
+        if let Some(ip) = neighbour_pis()
+            .await
+            .into_iter()
+            .find(|ip| !known.contains(ip))
+        {
+            return Ok(Found::Neighbour(ip));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    Err(anyhow!(
+        "{} did not come online within {} minutes",
+        name,
+        timeout.as_secs() / 60
+    ))
+}
+
+/// Resolves through the system resolver (nss-mdns), falling back to avahi
+/// for systems without mDNS in nsswitch.conf.
+async fn resolve(name: &str) -> Option<IpAddr> {
+    if let Ok(mut addrs) = tokio::net::lookup_host((name, 22)).await
+        && let Some(addr) = addrs.find(|a| a.is_ipv4())
+    {
+        return Some(addr.ip());
+    }
+
+    let output = Command::new("avahi-resolve-host-name")
+        .arg("-4")
+        .arg(name)
+        .output()
+        .await
+        .ok()?;
+    // Output format: "<name>\t<address>"
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .nth(1)
+        .and_then(|ip| ip.parse().ok())
+}
+
+/// Raspberry Pis in the neighbour table; none where there is no such table.
+async fn neighbour_pis() -> Vec<IpAddr> {
+    tokio::fs::read_to_string(ARP_TABLE)
+        .await
+        .map(|table| raspberry_pis(&table))
+        .unwrap_or_default()
+}
+
+/// Addresses of the resolved entries with a Raspberry Pi MAC in the
+/// /proc/net/arp format: "<ip> <hw type> <flags> <mac> <mask> <device>".
+fn raspberry_pis(table: &str) -> Vec<IpAddr> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [ip, _, flags, mac, ..] = fields[..] else {
+                return None;
+            };
+            // ATF_COM: the MAC is known, not a pending request
+            let flags = u32::from_str_radix(flags.trim_start_matches("0x"), 16).ok()?;
+            let mac = mac.to_ascii_lowercase();
+            (flags & 0x2 != 0 && PI_OUIS.iter().any(|oui| mac.starts_with(oui)))
+                .then(|| ip.parse().ok())
+                .flatten()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_raspberry_pis_in_the_neighbour_table() {
+        let table = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         a4:91:b1:10:20:30     *        wlan0
+192.168.1.23     0x1         0x2         D8:3A:DD:01:02:03     *        wlan0
+192.168.1.24     0x1         0x0         2c:cf:67:01:02:03     *        wlan0
+192.168.1.40     0x1         0x6         b8:27:eb:aa:bb:cc     *        eth0
+";
+        assert_eq!(
+            raspberry_pis(table),
+            vec![
+                "192.168.1.23".parse::<IpAddr>().unwrap(),
+                "192.168.1.40".parse().unwrap()
+            ]
+        );
+        assert!(raspberry_pis("").is_empty());
+    }
+}
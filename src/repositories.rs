@@ -0,0 +1,75 @@
+//! Well-known alternative OS lists that can be switched on from the
+//! Repositories screen. They are maintained by their projects, not by
+//! Raspberry Pi, and are always shown with a third-party label. Enabled ones
+//! are stored as ordinary entries of `os_sources` in the config file.
+
+use crate::os_list::OsSource;
+
+pub struct Repository {
+    pub name: &'static str,
+    pub url: &'static str,
+    pub description: &'static str,
+}
+
+pub const BUILTIN: &[Repository] = &[
+    Repository {
+        name: "RetroPie",
+        url: "https://retropie.org.uk/os_list_imagingutility.json",
+        description: "Retro gaming on top of Raspberry Pi OS, with EmulationStation.",
+    },
+    Repository {
+        name: "Batocera",
+        url: "https://updates.batocera.org/os_list_imagingutility.json",
+        description: "Retro gaming distribution that boots straight into its front end.",
+    },
+    Repository {
+        name: "Recalbox",
+        url: "https://upgrade.recalbox.com/latest/rpi-imager/os_list_imagingutility.json",
+        description: "Retro gaming distribution aimed at TVs and arcade cabinets.",
+    },
+];
+
+/// Suffix of the source names, so the category in the OS list says where
+/// the images come from.
+const LABEL: &str = " (third-party)";
+
+impl Repository {
+    pub fn is_enabled(&self, sources: &[OsSource]) -> bool {
+        sources.iter().any(|s| s.url == self.url)
+    }
+
+    /// Adds the repository to `sources`, or removes it if it is there.
+    pub fn toggle(&self, sources: &mut Vec<OsSource>) {
+        if self.is_enabled(sources) {
+            sources.retain(|s| s.url != self.url);
+        } else {
+            sources.push(OsSource {
+                name: format!("{}{}", self.name, LABEL),
+                url: self.url.to_string(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggles_a_labelled_source() {
+        let own = OsSource {
+            name: "My boards".to_string(),
+            url: "https://example.com/os_list.json".to_string(),
+        };
+        let mut sources = vec![own];
+        let repository = &BUILTIN[0];
+
+        repository.toggle(&mut sources);
+        assert!(repository.is_enabled(&sources));
+        assert_eq!(sources[1].name, "RetroPie (third-party)");
+
+        repository.toggle(&mut sources);
+        assert!(!repository.is_enabled(&sources));
+        assert_eq!(sources.len(), 1);
+    }
+}
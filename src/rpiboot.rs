@@ -0,0 +1,80 @@
+use anyhow::{Context, Result, anyhow};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const BROADCOM_VENDOR_ID: &str = "0a5c";
+
+/// USB product IDs a Compute Module presents while waiting in device-boot mode.
+const USB_BOOT_PRODUCTS: &[(&str, &str)] = &[
+    ("2763", "Compute Module 1/3"),
+    ("2764", "Compute Module 1/3"),
+    ("2711", "Compute Module 4"),
+    ("2712", "Compute Module 5"),
+];
+
+/// Returns a label for every Compute Module currently attached in USB
+/// device-boot mode (nRPIBOOT jumper fitted).
+pub fn detect_compute_modules() -> Vec<String> {
+    let mut found = Vec::new();
+    let Ok(entries) = std::fs::read_dir("/sys/bus/usb/devices") else {
+        return found;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if read_attr(&path, "idVendor").as_deref() != Some(BROADCOM_VENDOR_ID) {
+            continue;
+        }
+        let Some(product) = read_attr(&path, "idProduct") else {
+            continue;
+        };
+        if let Some((_, label)) = USB_BOOT_PRODUCTS.iter().find(|(id, _)| *id == product) {
+            found.push(label.to_string());
+        }
+    }
+    found
+}
+
+fn read_attr(device: &Path, name: &str) -> Option<String> {
+    std::fs::read_to_string(device.join(name))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// Runs usbboot's `rpiboot` to expose the Compute Module's eMMC as a mass
+/// storage device, then waits for the new disk to show up. Needs root.
+pub fn expose_emmc() -> Result<()> {
+    let before = disk_names();
+
+    let status = Command::new("rpiboot").status().map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            anyhow!(
+                "rpiboot not found. Install usbboot (https://github.com/raspberrypi/usbboot) and try again."
+            )
+        } else {
+            anyhow!(e).context("Failed to run rpiboot")
+        }
+    })?;
+    if !status.success() {
+        return Err(anyhow!("rpiboot failed. Exit code: {:?}", status.code()));
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while Instant::now() < deadline {
+        if disk_names().iter().any(|d| !before.contains(d)) {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    Err(anyhow!(
+        "rpiboot completed but no new storage device appeared"
+    ))
+    .context("Compute Module eMMC did not show up")
+}
+
+fn disk_names() -> Vec<String> {
+    crate::drivelist::get_drives()
+        .map(|drives| drives.into_iter().map(|d| d.name).collect())
+        .unwrap_or_default()
+}
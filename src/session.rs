@@ -0,0 +1,80 @@
+//! Wizard state handed over when the app restarts itself with elevated
+//! privileges, so the user doesn't have to redo every step. It may hold
+//! passwords, so it goes to the new process through a pipe on its standard
+//! input and never touches the disk.
+
+use crate::customization::CustomizationOptions;
+use crate::os_list::{Device, OsListItem};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub device: Option<Device>,
+    pub os: Option<OsListItem>,
+    /// Device path of the selected drive, e.g. /dev/sdb.
+    pub drive: Option<String>,
+    pub customization: CustomizationOptions,
+}
+
+impl Session {
+    /// Runs `command`, sudo or pkexec starting the app with `--resume`,
+    /// with the session on its standard input, and waits for it to exit.
+    /// The terminal is reached through /dev/tty then, as sudo does to ask
+    /// for the password.
+    pub fn hand_over(&self, command: &[String]) -> Result<ExitStatus> {
+        let (program, args) = command
+            .split_first()
+            .context("No command to restart with")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", program))?;
+        let json = serde_json::to_vec(self)?;
+        // Fails if the password prompt was cancelled, which the exit status
+        // reports anyway
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(&json);
+        }
+        Ok(child.wait()?)
+    }
+
+    /// Reads the session handed over by [`Session::hand_over`].
+    pub fn receive(input: impl Read) -> Result<Self> {
+        serde_json::from_reader(input).context("Invalid session")
+    }
+}
+
+// The command is a shell, standing in for sudo
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_the_session_over_on_stdin() {
+        let session = Session {
+            device: None,
+            os: None,
+            drive: Some("/dev/sdb".to_string()),
+            customization: CustomizationOptions {
+                wifi_password: "long and precious".to_string(),
+                ..CustomizationOptions::default()
+            },
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let copy = dir.path().join("session.json");
+        let command = [
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("cat > {}", copy.display()),
+        ];
+        assert!(session.hand_over(&command).unwrap().success());
+
+        let received = Session::receive(std::fs::File::open(&copy).unwrap()).unwrap();
+        assert_eq!(received.drive.as_deref(), Some("/dev/sdb"));
+        assert_eq!(received.customization.wifi_password, "long and precious");
+    }
+}
@@ -0,0 +1,103 @@
+//! Public keys published by code hosts, imported like cloud-init's
+//! ssh_import_id does: "gh:alice", or just "alice", fetches
+//! https://github.com/alice.keys, and "gl:alice" the same from GitLab.
+
+use anyhow::{Context, Result, bail};
+use reqwest::Client;
+
+/// The host part of an id, and where it publishes a user's keys.
+const HOSTS: [(&str, &str, &str); 2] = [
+    ("gh", "GitHub", "https://github.com"),
+    ("gl", "GitLab", "https://gitlab.com"),
+];
+
+/// The host name and keys URL of `id`.
+fn parse_id(id: &str) -> Result<(&'static str, String), String> {
+    let (prefix, user) = id.trim().split_once(':').unwrap_or(("gh", id.trim()));
+    let Some((_, host, base)) = HOSTS.iter().find(|(p, ..)| *p == prefix) else {
+        return Err("Use gh:<user> for GitHub or gl:<user> for GitLab".to_string());
+    };
+    let valid = !user.is_empty()
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(format!("Not a {} username", host));
+    }
+    Ok((host, format!("{}/{}.keys", base, user)))
+}
+
+pub fn validate_id(id: &str) -> Result<(), String> {
+    parse_id(id).map(|_| ())
+}
+
+/// The keys `id` published, failing when there are none.
+pub async fn fetch(client: &Client, id: &str) -> Result<Vec<String>> {
+    let (host, url) = parse_id(id).map_err(anyhow::Error::msg)?;
+    let text = crate::http::get(client, &url)
+        .await
+        .with_context(|| format!("Failed to fetch the keys of {} from {}", id.trim(), host))?
+        .text()
+        .await?;
+    let keys = parse_keys(&text);
+    if keys.is_empty() {
+        bail!("{} has no public SSH keys on {}", id.trim(), host);
+    }
+    Ok(keys)
+}
+
+fn parse_keys(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && crate::customization::validate_ssh_keys(line).is_ok())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `existing` keys, one per line, with those of `keys` that aren't among
+/// them yet, and how many were added.
+pub fn append(existing: &str, keys: &[String]) -> (String, usize) {
+    let mut lines: Vec<&str> = existing
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let before = lines.len();
+    for key in keys {
+        if !lines.iter().any(|line| key_body(line) == key_body(key)) {
+            lines.push(key);
+        }
+    }
+    let added = lines.len() - before;
+    (lines.join("\n"), added)
+}
+
+/// The type and key of a key line; the comment after them may differ.
+fn key_body(line: &str) -> Vec<&str> {
+    line.split_whitespace().take(2).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_new_keys_by_id() {
+        assert_eq!(
+            parse_id("alice").unwrap(),
+            ("GitHub", "https://github.com/alice.keys".to_string())
+        );
+        assert_eq!(parse_id("gl:bob").unwrap().1, "https://gitlab.com/bob.keys");
+        assert!(validate_id("lp:carol").is_err());
+        assert!(validate_id("gh:../etc").is_err());
+
+        let keys = parse_keys("ssh-ed25519 AAAAC3Nza alice@laptop\n<html>\nssh-rsa AAAAB3Nza\n");
+        assert_eq!(keys.len(), 2);
+        let (merged, added) = append("ssh-ed25519 AAAAC3Nza old comment", &keys);
+        assert_eq!(added, 1);
+        assert_eq!(
+            merged,
+            "ssh-ed25519 AAAAC3Nza old comment\nssh-rsa AAAAB3Nza"
+        );
+    }
+}
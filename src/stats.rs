@@ -0,0 +1,192 @@
+//! Counters for provisioning stations, so a wall dashboard can watch one:
+//! cards flashed, failures by [`ErrorKind`], the average write time and
+//! what is being written right now. They are kept as JSON in the
+//! `stats_file` of the config, rewritten whole (through a temporary file, so
+//! readers never see half of it) whenever something changes. The counters
+//! are read back from the same file, so they keep counting across restarts
+//! and headless runs.
+
+use crate::error::ErrorKind;
+use crate::ipc::{ProgressEvent, WritingPhase};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Progress within a phase is written at most this often; everything else
+/// right away.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    pub flashed: u64,
+    pub failed: u64,
+    /// Failed writes by kind, e.g. "Download": 2. Cancelled writes are only
+    /// counted here.
+    pub failures: BTreeMap<String, u64>,
+    /// Of the writes that succeeded.
+    pub average_duration_ms: u64,
+    pub current: Option<Current>,
+    /// Milliseconds since the Unix epoch.
+    pub updated: u64,
+}
+
+/// The write in progress.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Current {
+    pub os: String,
+    pub drive: String,
+    pub phase: Option<WritingPhase>,
+    /// Of the phase, from 0.0 to 1.0.
+    pub progress: f64,
+    pub status: String,
+    pub started: u64,
+}
+
+/// The stats of this station and the file they are written to.
+pub struct Station {
+    path: PathBuf,
+    pub stats: Stats,
+    started: Option<Instant>,
+    last_saved: Option<Instant>,
+}
+
+impl Station {
+    /// Continues counting from what `path` holds, if anything.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let stats = std::fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            stats,
+            started: None,
+            last_saved: None,
+        }
+    }
+
+    pub fn started(&mut self, os: &str, drive: &str) {
+        self.started = Some(Instant::now());
+        self.stats.current = Some(Current {
+            os: os.to_string(),
+            drive: drive.to_string(),
+            phase: None,
+            progress: 0.0,
+            status: String::new(),
+            started: now_ms(),
+        });
+        self.save();
+    }
+
+    /// Counts the outcome of the current write. Events of other worker runs,
+    /// like a customization retry, come without one and are ignored.
+    pub fn observe(&mut self, event: &ProgressEvent) {
+        let Some(current) = &mut self.stats.current else {
+            return;
+        };
+        match event {
+            ProgressEvent::Progress { phase, fraction } => {
+                let entered = current.phase.replace(*phase) != Some(*phase);
+                current.progress = *fraction;
+                if entered
+                    || self
+                        .last_saved
+                        .is_none_or(|at| at.elapsed() >= PROGRESS_INTERVAL)
+                {
+                    self.save();
+                }
+            }
+            ProgressEvent::Status(status) => current.status = status.clone(),
+            ProgressEvent::Finished => {
+                let elapsed = self.started.take().map_or(0, |at| at.elapsed().as_millis());
+                let stats = &mut self.stats;
+                stats.flashed += 1;
+                stats.average_duration_ms =
+                    ((stats.average_duration_ms as u128 * (stats.flashed - 1) as u128 + elapsed)
+                        / stats.flashed as u128) as u64;
+                stats.current = None;
+                self.save();
+            }
+            ProgressEvent::Error { kind, .. } => self.failed(*kind),
+            ProgressEvent::CustomizationFailed(_) => self.failed(ErrorKind::Customize),
+            _ => {}
+        }
+    }
+
+    /// Ends the current write with a failure of `kind`.
+    pub fn failed(&mut self, kind: ErrorKind) {
+        if self.stats.current.take().is_none() {
+            return;
+        }
+        self.started = None;
+        if kind != ErrorKind::Cancelled {
+            self.stats.failed += 1;
+        }
+        *self
+            .stats
+            .failures
+            .entry(format!("{:?}", kind))
+            .or_default() += 1;
+        self.save();
+    }
+
+    /// Best effort: a dashboard that can't be fed mustn't stop the writes.
+    fn save(&mut self) {
+        self.last_saved = Some(Instant::now());
+        self.stats.updated = now_ms();
+        let Ok(json) = serde_json::to_vec_pretty(&self.stats) else {
+            return;
+        };
+        if let Some(dir) = self.path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        if std::fs::write(&temporary, json).is_ok() {
+            let _ = std::fs::rename(&temporary, &self.path);
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_writes_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.json");
+
+        let mut station = Station::open(&path);
+        station.started("Raspberry Pi OS Lite", "/dev/sdb");
+        station.observe(&ProgressEvent::Progress {
+            phase: WritingPhase::Writing,
+            fraction: 0.5,
+        });
+        let written: Stats = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let current = written.current.unwrap();
+        assert_eq!(current.phase, Some(WritingPhase::Writing));
+        assert_eq!(current.drive, "/dev/sdb");
+
+        station.observe(&ProgressEvent::Finished);
+        station.started("Raspberry Pi OS Lite", "/dev/sdb");
+        station.observe(&ProgressEvent::other_error("Worker exited".to_string()));
+        // A customization retry isn't a write of its own
+        station.observe(&ProgressEvent::Finished);
+
+        let stats = Station::open(&path).stats;
+        assert_eq!((stats.flashed, stats.failed), (1, 1));
+        assert_eq!(stats.failures["Other"], 1);
+        assert!(stats.current.is_none());
+    }
+}
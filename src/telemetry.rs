@@ -0,0 +1,30 @@
+use anyhow::Result;
+use reqwest::Client;
+
+/// Same endpoint and fields the official imager reports to, so image
+/// maintainers see downloads made with this tool in their statistics.
+const TELEMETRY_URL: &str = "https://rpi-imager-stats.raspberrypi.com/api/v1/stats";
+
+/// Reports which image was written. Only the image URL, its name and
+/// category, and the tool version and host OS are sent.
+pub async fn send(
+    client: &Client,
+    image_url: &str,
+    category: &str,
+    image_name: &str,
+) -> Result<()> {
+    let os_info = format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH);
+    client
+        .post(TELEMETRY_URL)
+        .form(&[
+            ("url", image_url),
+            ("os", category),
+            ("image", image_name),
+            ("imagerversion", env!("CARGO_PKG_VERSION")),
+            ("imagerosinfo", &os_info),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
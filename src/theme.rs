@@ -0,0 +1,26 @@
+//! Accent color of the interface, picked in the setup wizard.
+
+use crate::customization::Theme;
+use ratatui::style::Color;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn color(theme: Theme) -> Color {
+    match theme {
+        Theme::Raspberry => Color::Magenta,
+        Theme::Ocean => Color::Blue,
+        Theme::Forest => Color::Green,
+        Theme::Mono => Color::DarkGray,
+    }
+}
+
+// Global, so rendering code doesn't need to pass the options around
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set(theme: Theme) {
+    CURRENT.store(theme as usize, Ordering::Relaxed);
+}
+
+/// Color of titles and the selection highlight.
+pub fn accent() -> Color {
+    color(Theme::ALL[CURRENT.load(Ordering::Relaxed) % Theme::ALL.len()])
+}
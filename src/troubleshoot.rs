@@ -0,0 +1,190 @@
+//! What to check after a failed write. The kind of the failure says where
+//! to look, and the message, which carries the operating system's error,
+//! points at the likely cause among those; likely causes are listed first.
+//! Each failure also comes with the actions that may get the write through,
+//! bound to a key on the error screen.
+
+use crate::error::ErrorKind;
+
+/// A possible cause, and whether the error message points at it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Check {
+    pub text: &'static str,
+    pub likely: bool,
+}
+
+struct Cause {
+    text: &'static str,
+    shows_as: fn(&str) -> bool,
+}
+
+const DISCONNECTED: Cause = Cause {
+    text: "Is the card reader still connected, and the card fully inserted?",
+    shows_as: |m| {
+        m.contains("No such device")
+            || m.contains("No such file or directory")
+            || m.contains("os error 6)")
+    },
+};
+
+const LOCKED: Cause = Cause {
+    text: "Is the card locked? Slide the switch on its side away from \"Lock\".",
+    shows_as: |m| m.contains("Read-only file system") || m.contains("write-protected"),
+};
+
+const BUSY: Cause = Cause {
+    text: "Is one of its partitions mounted, or the card open in another program?",
+    shows_as: |m| m.contains("Device or resource busy") || m.contains("in use"),
+};
+
+const PERMISSION: Cause = Cause {
+    text: "Does the tool have administrator rights to write to the card?",
+    shows_as: crate::privileges::is_permission_error,
+};
+
+const TOO_SMALL: Cause = Cause {
+    text: "Is the card large enough for the image?",
+    shows_as: |m| m.contains("No space left") || m.contains("too small"),
+};
+
+const FAILING: Cause = Cause {
+    text: "Is the card worn out or counterfeit? Try writing to another one.",
+    shows_as: |m| m.contains("Input/output error") || m.contains("verification failed"),
+};
+
+const NETWORK: Cause = Cause {
+    text: "Is the network up? Behind a proxy, set it in the Options.",
+    shows_as: |m| {
+        m.contains("error sending request") || m.contains("dns error") || m.contains("timed out")
+    },
+};
+
+const GONE: Cause = Cause {
+    text: "Is the image still published at its URL? The OS list may be out of date.",
+    shows_as: |m| m.contains("404"),
+};
+
+const NO_ROOM: Cause = Cause {
+    text: "Is there room on this computer for the download?",
+    shows_as: |m| m.contains("No space left"),
+};
+
+const DAMAGED: Cause = Cause {
+    text: "Was the download cut off? Delete it on the Cache screen to fetch it again.",
+    shows_as: |m| m.contains("unexpected end") || m.contains("corrupt") || m.contains("checksum"),
+};
+
+const BOOT_FULL: Cause = Cause {
+    text: "Is the boot partition full? Fewer or smaller extra files may fit.",
+    shows_as: |m| m.contains("No space left") || m.contains("The boot partition has"),
+};
+
+const ALL: [&Cause; 11] = [
+    &DISCONNECTED,
+    &LOCKED,
+    &BUSY,
+    &PERMISSION,
+    &TOO_SMALL,
+    &FAILING,
+    &NETWORK,
+    &GONE,
+    &NO_ROOM,
+    &DAMAGED,
+    &BOOT_FULL,
+];
+
+/// The causes worth checking for a failure of `kind`, likely ones first.
+/// Untagged failures only list the causes the message points at.
+pub fn checklist(kind: ErrorKind, message: &str) -> Vec<Check> {
+    let causes: &[&Cause] = match kind {
+        ErrorKind::Download => &[&NETWORK, &GONE, &NO_ROOM],
+        ErrorKind::Decompress => &[&DAMAGED, &NO_ROOM],
+        ErrorKind::DeviceOpen => &[&DISCONNECTED, &LOCKED, &BUSY, &PERMISSION],
+        ErrorKind::DeviceWrite => &[&DISCONNECTED, &LOCKED, &TOO_SMALL, &FAILING],
+        ErrorKind::Verify => &[&FAILING, &DISCONNECTED],
+        ErrorKind::Customize => &[&BOOT_FULL, &LOCKED],
+        ErrorKind::TeeFile => &[&NO_ROOM],
+        ErrorKind::Other => &ALL,
+        ErrorKind::Cancelled => &[],
+    };
+    let mut checks: Vec<Check> = causes
+        .iter()
+        .map(|cause| Check {
+            text: cause.text,
+            likely: (cause.shows_as)(message),
+        })
+        .filter(|check| check.likely || kind != ErrorKind::Other)
+        .collect();
+    checks.sort_by_key(|check| !check.likely);
+    checks
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Retry,
+    /// Lists the drives again, for a reader that was plugged back in.
+    Rescan,
+    Elevate,
+}
+
+impl Action {
+    pub fn key(self) -> char {
+        match self {
+            Self::Retry => 'r',
+            Self::Rescan => 'd',
+            Self::Elevate => 'e',
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Retry => "Retry",
+            Self::Rescan => "Re-scan drives",
+            Self::Elevate => "Restart as administrator",
+        }
+    }
+}
+
+/// What may get the write through after a failure of `kind`.
+pub fn actions(kind: ErrorKind, message: &str) -> Vec<Action> {
+    let mut actions = Vec::new();
+    if kind.is_retryable() {
+        actions.push(Action::Retry);
+    }
+    if matches!(
+        kind,
+        ErrorKind::DeviceOpen | ErrorKind::DeviceWrite | ErrorKind::Verify
+    ) {
+        actions.push(Action::Rescan);
+    }
+    if crate::privileges::is_permission_error(message) {
+        actions.push(Action::Elevate);
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_likely_cause_first() {
+        let message = "Failed to open /dev/sdb: Read-only file system (os error 30)";
+        let checks = checklist(ErrorKind::DeviceOpen, message);
+        assert_eq!(checks.len(), 4);
+        assert_eq!(checks[0].text, LOCKED.text);
+        assert!(checks[0].likely);
+        assert!(checks[1..].iter().all(|c| !c.likely));
+        assert_eq!(
+            actions(ErrorKind::DeviceOpen, message),
+            vec![Action::Rescan]
+        );
+
+        let message = "Failed to open /dev/sdb: Permission denied (os error 13)";
+        let checks = checklist(ErrorKind::Other, message);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].text, PERMISSION.text);
+        assert_eq!(actions(ErrorKind::Other, message), vec![Action::Elevate]);
+        assert!(checklist(ErrorKind::Cancelled, message).is_empty());
+    }
+}
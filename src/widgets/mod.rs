@@ -0,0 +1,7 @@
+//! Reusable interactive components shared by the views in main.rs.
+
+pub mod picker;
+pub mod text_input;
+
+pub use picker::{Picker, PickerEvent};
+pub use text_input::{InputEvent, TextInput};
@@ -0,0 +1,254 @@
+use crossterm::event::KeyCode;
+use ratatui::Frame;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState};
+
+/// Entries skipped by PageUp and PageDown.
+const PAGE: usize = 10;
+
+/// Result of feeding a key to a [`Picker`].
+pub enum PickerEvent {
+    Selected(String),
+    Cancelled,
+    Pending,
+}
+
+/// Modal list picker with type-to-filter. Pinned entries (e.g. "<Enter
+/// Manually>") stay at the top regardless of the filter.
+#[derive(Default)]
+pub struct Picker {
+    title: String,
+    pinned: Vec<String>,
+    items: Vec<String>,
+    filter: String,
+    visible: Vec<String>,
+    state: ListState,
+}
+
+impl Picker {
+    pub fn new(title: impl Into<String>, items: Vec<String>) -> Self {
+        let mut picker = Self {
+            title: title.into(),
+            items,
+            ..Self::default()
+        };
+        picker.refilter();
+        picker
+    }
+
+    pub fn pinned(mut self, item: impl Into<String>) -> Self {
+        self.pinned.push(item.into());
+        self.refilter();
+        self
+    }
+
+    /// Starts on the entry for `value`, the setting as it is now: the entry
+    /// itself, or one naming it first ("gb - English (UK)" for "gb").
+    pub fn current(mut self, value: &str) -> Self {
+        let prefix = format!("{} ", value);
+        if !value.is_empty()
+            && let Some(i) = self
+                .visible
+                .iter()
+                .position(|item| item == value || item.starts_with(&prefix))
+        {
+            self.state.select(Some(i));
+        }
+        self
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> PickerEvent {
+        match key {
+            KeyCode::Esc => return PickerEvent::Cancelled,
+            KeyCode::Enter => {
+                if let Some(item) = self.state.selected().and_then(|i| self.visible.get(i)) {
+                    return PickerEvent::Selected(item.clone());
+                }
+            }
+            KeyCode::Down => self.next(),
+            KeyCode::Up => self.previous(),
+            KeyCode::PageDown => self.jump(PAGE as isize),
+            KeyCode::PageUp => self.jump(-(PAGE as isize)),
+            KeyCode::Home => self.jump(isize::MIN),
+            KeyCode::End => self.jump(isize::MAX),
+            KeyCode::Char(c) => {
+                self.filter.push(c);
+                self.refilter();
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.refilter();
+            }
+            _ => {}
+        }
+        PickerEvent::Pending
+    }
+
+    fn refilter(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.visible = self
+            .pinned
+            .iter()
+            .chain(
+                self.items
+                    .iter()
+                    .filter(|item| item.to_lowercase().contains(&filter)),
+            )
+            .cloned()
+            .collect();
+        self.state.select(if self.visible.is_empty() {
+            None
+        } else {
+            Some(0)
+        });
+    }
+
+    fn next(&mut self) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) if i + 1 < self.visible.len() => i + 1,
+            _ => 0,
+        };
+        self.state.select(Some(i));
+    }
+
+    fn previous(&mut self) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(0) | None => self.visible.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.state.select(Some(i));
+    }
+
+    /// Moves by `by` entries, stopping at either end instead of wrapping.
+    fn jump(&mut self, by: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let i = self.state.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        self.state
+            .select(Some(i.saturating_add(by).clamp(0, last) as usize));
+    }
+
+    pub fn render(&mut self, f: &mut Frame, area: Rect) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(self.title.as_str())
+            .title_bottom(format!("Filter: {}", self.filter))
+            .style(Style::default().fg(Color::Yellow));
+
+        f.render_widget(Clear, area); // Clear background
+
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|i| ListItem::new(Line::from(i.as_str())))
+            .collect();
+
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("> ");
+
+        f.render_stateful_widget(list, area, &mut self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timezones() -> Picker {
+        let items = (0..25)
+            .map(|i| format!("Etc/GMT+{}", i))
+            .chain(["Europe/London".to_string(), "Europe/Lisbon".to_string()]);
+        Picker::new("Timezone", items.collect()).pinned("<Enter Manually>")
+    }
+
+    fn select(picker: &mut Picker) -> Option<String> {
+        match picker.handle_key(KeyCode::Enter) {
+            PickerEvent::Selected(item) => Some(item),
+            _ => None,
+        }
+    }
+
+    fn type_filter(picker: &mut Picker, filter: &str) {
+        for c in filter.chars() {
+            picker.handle_key(KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn filters_case_insensitively_keeping_pinned_entries() {
+        let mut picker = timezones();
+        type_filter(&mut picker, "LON");
+        assert_eq!(picker.visible, ["<Enter Manually>", "Europe/London"]);
+
+        picker.handle_key(KeyCode::Backspace);
+        picker.handle_key(KeyCode::Backspace);
+        assert_eq!(
+            picker.visible,
+            ["<Enter Manually>", "Europe/London", "Europe/Lisbon"]
+        );
+        assert!(matches!(
+            picker.handle_key(KeyCode::Esc),
+            PickerEvent::Cancelled
+        ));
+    }
+
+    #[test]
+    fn keeps_the_selection_in_range_when_the_filter_narrows() {
+        let mut picker = timezones().current("Europe/Lisbon");
+        assert_eq!(picker.state.selected(), Some(27));
+
+        type_filter(&mut picker, "london");
+        picker.handle_key(KeyCode::Down);
+        assert_eq!(select(&mut picker).as_deref(), Some("Europe/London"));
+
+        type_filter(&mut picker, "x");
+        assert_eq!(picker.visible, ["<Enter Manually>"]);
+        assert_eq!(select(&mut picker).as_deref(), Some("<Enter Manually>"));
+
+        let mut unpinned = Picker::new("Keyboard", vec!["gb".to_string(), "us".to_string()]);
+        type_filter(&mut unpinned, "de");
+        assert_eq!(unpinned.state.selected(), None);
+        unpinned.handle_key(KeyCode::PageDown);
+        assert_eq!(select(&mut unpinned), None);
+    }
+
+    #[test]
+    fn pages_stop_at_either_end() {
+        let mut picker = timezones();
+        picker.handle_key(KeyCode::PageUp);
+        assert_eq!(picker.state.selected(), Some(0));
+
+        picker.handle_key(KeyCode::PageDown);
+        picker.handle_key(KeyCode::PageDown);
+        assert_eq!(picker.state.selected(), Some(2 * PAGE));
+        picker.handle_key(KeyCode::PageDown);
+        assert_eq!(picker.state.selected(), Some(27));
+        picker.handle_key(KeyCode::PageDown);
+        assert_eq!(select(&mut picker).as_deref(), Some("Europe/Lisbon"));
+
+        picker.handle_key(KeyCode::PageUp);
+        assert_eq!(picker.state.selected(), Some(27 - PAGE));
+        picker.handle_key(KeyCode::Home);
+        assert_eq!(picker.state.selected(), Some(0));
+        // Up still wraps around, unlike the page keys
+        picker.handle_key(KeyCode::Up);
+        assert_eq!(picker.state.selected(), Some(27));
+    }
+}
@@ -0,0 +1,172 @@
+use crossterm::event::KeyCode;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+
+pub type Validator = fn(&str) -> Result<(), String>;
+
+/// Result of feeding a key to a [`TextInput`].
+pub enum InputEvent {
+    /// Enter was pressed and the value passed validation.
+    Submitted(String),
+    Cancelled,
+    /// Still editing (a character was typed, or validation failed).
+    Pending,
+}
+
+/// Single-line text field with optional masking, placeholder and validation.
+#[derive(Default)]
+pub struct TextInput {
+    value: String,
+    masked: bool,
+    placeholder: &'static str,
+    validator: Option<Validator>,
+    error: Option<String>,
+}
+
+impl TextInput {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn masked(mut self) -> Self {
+        self.masked = true;
+        self
+    }
+
+    pub fn placeholder(mut self, placeholder: &'static str) -> Self {
+        self.placeholder = placeholder;
+        self
+    }
+
+    pub fn validator(mut self, validator: Validator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> InputEvent {
+        match key {
+            KeyCode::Enter => {
+                if let Some(validate) = self.validator
+                    && let Err(e) = validate(&self.value)
+                {
+                    self.error = Some(e);
+                    return InputEvent::Pending;
+                }
+                InputEvent::Submitted(std::mem::take(&mut self.value))
+            }
+            KeyCode::Esc => InputEvent::Cancelled,
+            KeyCode::Backspace => {
+                self.value.pop();
+                self.error = None;
+                InputEvent::Pending
+            }
+            KeyCode::Char(c) => {
+                self.value.push(c);
+                self.error = None;
+                InputEvent::Pending
+            }
+            _ => InputEvent::Pending,
+        }
+    }
+
+    /// The field as shown while editing: "> value_", followed by the
+    /// validation error if the last submit was rejected.
+    pub fn line(&self) -> Line<'static> {
+        let text = if self.value.is_empty() {
+            Span::styled(
+                self.placeholder.to_string(),
+                Style::default().fg(Color::DarkGray),
+            )
+        } else if self.masked {
+            Span::raw(mask(&self.value))
+        } else {
+            Span::raw(self.value.clone())
+        };
+
+        let mut spans = vec![Span::raw("> "), text, Span::raw("_")];
+        if let Some(error) = &self.error {
+            spans.push(Span::styled(
+                format!("  {}", error),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        Line::from(spans)
+    }
+}
+
+/// Replaces every character with '*'.
+pub fn mask(value: &str) -> String {
+    "*".repeat(value.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &Line) -> String {
+        line.spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect()
+    }
+
+    fn non_empty(value: &str) -> Result<(), String> {
+        if value.is_empty() {
+            Err("Required".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn masks_the_value_but_not_the_placeholder() {
+        let mut input = TextInput::new("").masked().placeholder("password");
+        assert_eq!(text(&input.line()), "> password_");
+        for c in "pässwörd".chars() {
+            input.handle_key(KeyCode::Char(c));
+        }
+        assert_eq!(text(&input.line()), "> ********_");
+        assert_eq!(mask("ab c"), "****");
+    }
+
+    #[test]
+    fn edits_at_the_cursor() {
+        let mut input = TextInput::new("pi");
+        input.handle_key(KeyCode::Char('4'));
+        assert_eq!(text(&input.line()), "> pi4_");
+        input.handle_key(KeyCode::Backspace);
+        input.handle_key(KeyCode::Backspace);
+        input.handle_key(KeyCode::Char('!'));
+        assert_eq!(text(&input.line()), "> p!_");
+        input.handle_key(KeyCode::Left);
+        assert_eq!(text(&input.line()), "> p!_");
+
+        let mut empty = TextInput::new("");
+        empty.handle_key(KeyCode::Backspace);
+        assert_eq!(text(&empty.line()), "> _");
+        assert!(matches!(
+            empty.handle_key(KeyCode::Esc),
+            InputEvent::Cancelled
+        ));
+    }
+
+    #[test]
+    fn shows_validation_errors_until_edited() {
+        let mut input = TextInput::new("").validator(non_empty);
+        assert!(matches!(
+            input.handle_key(KeyCode::Enter),
+            InputEvent::Pending
+        ));
+        assert_eq!(text(&input.line()), "> _  Required");
+
+        input.handle_key(KeyCode::Char('x'));
+        assert_eq!(text(&input.line()), "> x_");
+        let InputEvent::Submitted(value) = input.handle_key(KeyCode::Enter) else {
+            panic!("valid input not submitted");
+        };
+        assert_eq!(value, "x");
+    }
+}
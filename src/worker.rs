@@ -1,72 +1,61 @@
+use crate::cli::WorkerArgs;
 use crate::customization::CustomizationOptions;
-use crate::drivelist::Drive;
+use crate::drivelist::{Drive, TargetProfile};
+use crate::ipc::{ProgressEvent, WorkerCommand, WritingPhase};
 use crate::os_list::OsListItem;
-use crate::{AppMessage, WritingPhase};
+use crate::writer::Control;
 use base64::Engine;
-use serde::{Deserialize, Serialize};
 use std::process;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::mpsc;
 
-#[derive(Serialize, Deserialize)]
-#[serde(tag = "type", content = "data")]
-pub enum WorkerMessage {
-    Progress(f64),
-    VerifyProgress(f64),
-    Status(String),
-    Phase(String),
-    Error(String),
-    Finished,
+pub async fn run_worker(args: WorkerArgs) {
+    release_workspaces_on_exit();
+    run_job(args).await;
+    crate::ipc::emit_summary();
 }
 
-pub async fn run_worker(args: Vec<String>) {
-    // Parse arguments
-    let mut image_url = String::new();
-    let mut device_path = String::new();
-    let mut sha256 = None;
-    let mut size = None;
-    let mut options_b64 = String::new();
-
-    let mut i = 0;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--image" => {
-                i += 1;
-                if i < args.len() {
-                    image_url = args[i].clone();
-                }
-            }
-            "--device" => {
-                i += 1;
-                if i < args.len() {
-                    device_path = args[i].clone();
-                }
-            }
-            "--sha256" => {
-                i += 1;
-                if i < args.len() {
-                    sha256 = Some(args[i].clone());
-                }
-            }
-            "--size" => {
-                i += 1;
-                if i < args.len() {
-                    size = args[i].parse::<u64>().ok();
-                }
-            }
-            "--options" => {
-                i += 1;
-                if i < args.len() {
-                    options_b64 = args[i].clone();
-                }
-            }
-            _ => {}
-        }
-        i += 1;
+async fn run_job(args: WorkerArgs) {
+    let WorkerArgs {
+        image,
+        device,
+        sha256,
+        size,
+        delta,
+        device_fd,
+        inspect,
+        browse,
+        rpiboot,
+        customize,
+        profile,
+        cache,
+        init_format,
+        options,
+    } = args;
+    let image_url = image.unwrap_or_default();
+    let device_path = device.unwrap_or_default();
+    let options_b64 = options.unwrap_or_default();
+    let profile = profile
+        .as_deref()
+        .map(TargetProfile::parse)
+        .unwrap_or(TargetProfile::SdCard);
+
+    #[cfg(unix)]
+    if let Some(fd) = device_fd {
+        crate::udisks::adopt(device_path.clone(), fd);
     }
+    #[cfg(not(unix))]
+    let _ = device_fd;
 
-    if image_url.is_empty() || device_path.is_empty() {
-        eprintln!("Missing required arguments for worker");
-        process::exit(1);
+    if rpiboot {
+        run_rpiboot().await;
+        return;
+    }
+
+    if (inspect || browse) && !device_path.is_empty() {
+        run_inspect(device_path, browse).await;
+        return;
     }
 
     // Decode options
@@ -79,69 +68,177 @@ pub async fn run_worker(args: Vec<String>) {
         CustomizationOptions::default()
     };
 
+    if customize && !device_path.is_empty() {
+        run_customize(device_path, options, init_format).await;
+        return;
+    }
+
+    if image_url.is_empty() || device_path.is_empty() {
+        eprintln!("Missing required arguments for worker");
+        process::exit(1);
+    }
+
     // Construct objects
     let os = OsListItem {
-        name: "Worker Image".to_string(),
-        url: Some(image_url),
         extract_sha256: sha256,
         extract_size: size,
-        // Defaults
-        description: String::new(),
-        icon: None,
-        random: false,
-        subitems: Vec::new(),
-        image_download_size: None,
-        image_download_sha256: None,
-        release_date: None,
-        init_format: None,
-        devices: Vec::new(),
-        capabilities: Vec::new(),
-        website: None,
-        tooltip: None,
-        architecture: None,
-        enable_rpi_connect: false,
+        init_format,
+        ..OsListItem::custom("Worker Image".to_string(), image_url)
     };
 
     let drive = Drive {
         name: device_path,
         // Defaults
         description: "Target Drive".to_string(),
+        model: None,
         size: 0,
         removable: true,
         readonly: false,
         mountpoints: Vec::new(),
+        serial: None,
+        profile,
     };
 
-    let (tx, mut rx) = mpsc::channel::<AppMessage>(100);
+    let (tx, mut rx) = mpsc::channel::<ProgressEvent>(100);
+
+    // Commands from the parent arrive on stdin
+    let control = Arc::new(Control::default());
+    let commands = control.clone();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(command) = WorkerCommand::parse(&line) {
+                commands.apply(command);
+            }
+        }
+    });
 
     // Spawn writer
     tokio::spawn(async move {
-        if let Err(e) = crate::writer::write_image(os, drive, options, tx.clone()).await {
-            let _ = tx.send(AppMessage::WriteError(e.to_string())).await;
+        if let Err(e) = crate::writer::write_image(
+            crate::writer::WriteJob {
+                os,
+                drive,
+                options,
+                delta,
+                cache,
+            },
+            control,
+            tx.clone(),
+        )
+        .await
+        {
+            let _ = tx.send(ProgressEvent::error(&e)).await;
         }
     });
 
     // Loop and print JSON
-    while let Some(msg) = rx.recv().await {
-        let worker_msg = match msg {
-            AppMessage::WriteProgress(p) => WorkerMessage::Progress(p),
-            AppMessage::VerifyProgress(p) => WorkerMessage::VerifyProgress(p),
-            AppMessage::WriteStatus(s) => WorkerMessage::Status(s),
-            AppMessage::WritingPhase(p) => WorkerMessage::Phase(match p {
-                WritingPhase::Writing => "Writing".to_string(),
-                WritingPhase::Verifying => "Verifying".to_string(),
-            }),
-            AppMessage::WriteError(e) => WorkerMessage::Error(e),
-            AppMessage::WriteFinished => WorkerMessage::Finished,
-            AppMessage::OsListLoaded(_) => continue, // Should not happen
-        };
-
-        if let Ok(json) = serde_json::to_string(&worker_msg) {
-            println!("{}", json);
+    while let Some(event) = rx.recv().await {
+        event.emit();
+        if event.is_final() {
+            break;
         }
+    }
+}
 
-        if let WorkerMessage::Finished | WorkerMessage::Error(_) = worker_msg {
-            break;
+async fn run_inspect(device_path: String, browse: bool) {
+    let result = tokio::task::spawn_blocking(move || {
+        crate::post_process::with_boot_partition(&device_path, |boot| {
+            if browse {
+                crate::inspect::list_boot_files(boot).map(ProgressEvent::BootFiles)
+            } else {
+                crate::inspect::read_customization(boot)
+                    .map(|options| ProgressEvent::Customization(Box::new(options)))
+            }
+        })
+    })
+    .await;
+
+    match result {
+        Ok(Ok(event)) => event,
+        Ok(Err(e)) => ProgressEvent::error(&e),
+        Err(e) => ProgressEvent::other_error(e.to_string()),
+    }
+    .emit();
+}
+
+/// Re-applies the customization to an already written card.
+async fn run_customize(
+    device_path: String,
+    options: CustomizationOptions,
+    init_format: Option<String>,
+) {
+    ProgressEvent::Progress {
+        phase: WritingPhase::Customizing,
+        fraction: 0.0,
+    }
+    .emit();
+    ProgressEvent::Status("Applying customization options...".to_string()).emit();
+
+    match tokio::task::spawn_blocking(move || {
+        crate::post_process::apply_customization(&device_path, &options, init_format.as_deref())
+    })
+    .await
+    {
+        Ok(Ok((checks, changes))) => {
+            let failure = crate::first_boot::failures(&checks);
+            if !changes.is_empty() {
+                ProgressEvent::BootChanges(changes).emit();
+            }
+            ProgressEvent::Checklist(checks).emit();
+            match failure {
+                Some(failure) => ProgressEvent::CustomizationFailed(failure),
+                None => ProgressEvent::Finished,
+            }
         }
+        Ok(Err(e)) => ProgressEvent::CustomizationFailed(format!("{:#}", e)),
+        Err(e) => ProgressEvent::CustomizationFailed(e.to_string()),
+    }
+    .emit();
+}
+
+async fn run_rpiboot() {
+    ProgressEvent::Status("Running rpiboot to expose Compute Module eMMC...".to_string()).emit();
+
+    match tokio::task::spawn_blocking(crate::rpiboot::expose_emmc).await {
+        Ok(Ok(())) => ProgressEvent::EmmcReady,
+        Ok(Err(e)) => ProgressEvent::error(&e),
+        Err(e) => ProgressEvent::other_error(e.to_string()),
     }
+    .emit();
+}
+
+/// Unmounts and removes temp artifacts when the worker is interrupted, or
+/// panics (e.g. writing to stdout after the TUI went away), since
+/// destructors on other threads won't run in either case.
+pub fn release_workspaces_on_exit() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        crate::workspace::cleanup_all();
+        default_hook(info);
+    }));
+
+    tokio::spawn(async {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+            let (Ok(mut term), Ok(mut hangup)) = (
+                signal(SignalKind::terminate()),
+                signal(SignalKind::hangup()),
+            ) else {
+                return;
+            };
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+                _ = hangup.recv() => {}
+            }
+        }
+        #[cfg(not(unix))]
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        crate::workspace::cleanup_all();
+        process::exit(130);
+    });
 }